@@ -0,0 +1,83 @@
+//! Compares drawing the same string every frame with `draw_string_with_options`
+//! against preparing it once with `prepare_commands` and only running `execute_commands` every frame.
+//! Run with `cargo bench --bench draw_commands`.
+
+use sext::blit::blend_rgba_over;
+use sext::colours::TextColour;
+use sext::options::{BlendMode, DrawOptions};
+use sext::{PasteSurface, Rect, StoreSurface, TextRenderer};
+use std::hint::black_box;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 2000;
+const TEXT: &str = "FPS: 60  entities: 1024  draw calls: 87  frame time: 16.6ms";
+
+/// A cached glyph, shared instead of copied whenever the renderer hands one out.
+#[derive(Clone)]
+struct Glyph {
+    width: usize,
+    height: usize,
+    data: Arc<Vec<u8>>,
+}
+
+impl StoreSurface for Glyph {
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        Glyph { width, height, data: Arc::new(data.to_vec()) }
+    }
+}
+
+struct Canvas {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl PasteSurface<Glyph> for Canvas {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Glyph) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_blended(&mut self, x: usize, y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Glyph, _mode: BlendMode) {
+        let clip = Rect::new(x as i32, y as i32, width, height);
+        blend_rgba_over(
+            &mut self.data, self.width, self.height, x as i32 - src_x as i32, y as i32 - src_y as i32,
+            &data.data, data.width, data.height, Some(clip),
+        );
+    }
+}
+
+/// Runs `frame` for every frame (moving the text around a bit so nothing can be skipped) and returns how long it all took.
+fn time(mut frame: impl FnMut(f32, &mut Canvas)) -> Duration {
+    let mut canvas = Canvas { width: 640, height: 120, data: vec![0; 640 * 120 * 4] };
+    let start = Instant::now();
+    for i in 0..FRAMES {
+        frame((i % 40) as f32, &mut canvas);
+    }
+    black_box(&canvas.data);
+    start.elapsed()
+}
+
+fn main() {
+    let mut renderer = TextRenderer::<Glyph, Canvas>::load("FreeMono.ttf").unwrap();
+    let white = TextColour::new_rgb(255, 255, 255);
+    let options = DrawOptions::default();
+
+    // warm the glyph cache up first, so both sides are only measuring cache hits
+    let commands = renderer.prepare_commands(TEXT, 14.0, white, &options);
+    let draw = time(|offset, canvas| {
+        renderer.draw_string_with_options(TEXT, offset, offset, 14.0, white, &options, canvas);
+    });
+    let execute = time(|offset, canvas| {
+        renderer.execute_commands(&commands, (offset, offset), canvas).unwrap();
+    });
+    let prepare = time(|_, _| {
+        black_box(renderer.prepare_commands(TEXT, 14.0, white, &options));
+    });
+
+    let per_frame = |total: Duration| total / FRAMES as u32;
+    println!("draw_string_with_options: {:?} per frame", per_frame(draw));
+    println!("prepare_commands:         {:?} per frame", per_frame(prepare));
+    println!("execute_commands:         {:?} per frame", per_frame(execute));
+    println!("execute is {:.1}x faster than drawing", draw.as_secs_f64() / execute.as_secs_f64());
+}
@@ -0,0 +1,212 @@
+//! Drawing text with ANSI escape sequences in it (like captured program output), enabled with the `ansi` feature.
+//! SGR sequences (`\x1b[...m`) change the colours of the text after them. Supported are:
+//! reset (0), bold (1, shown by switching the 8 basic colours to their bright versions) and normal intensity (22),
+//! the basic foreground/background colours (30-37, 40-47, 90-97, 100-107), default colours (39, 49),
+//! the 256 colour palette (38;5;n and 48;5;n) and truecolour (38;2;r;g;b and 48;2;r;g;b).
+//! Every other escape sequence is removed without doing anything.
+
+use crate::colours::TextColour;
+use crate::layout::Run;
+use crate::metrics::DrawnBounds;
+use crate::options::DrawOptions;
+use crate::{GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer};
+
+/// A piece of text that's all in the same colours, returned by `parse_ansi`.
+/// `None` means the default colour that was passed to the draw call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnsiRun {
+    pub text: String,
+    pub fg: Option<TextColour>,
+    pub bg: Option<TextColour>,
+}
+
+/// A colour as set by an SGR sequence. Basic colours are kept as an index so bold can brighten them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnsiColour {
+    Default,
+    Indexed(u8),
+    Rgb(TextColour),
+}
+
+impl AnsiColour {
+    fn resolve(self, bold: bool) -> Option<TextColour> {
+        match self {
+            AnsiColour::Default => None,
+            AnsiColour::Indexed(index) if bold && index < 8 => Some(TextColour::from_ansi_256(index + 8)),
+            AnsiColour::Indexed(index) => Some(TextColour::from_ansi_256(index)),
+            AnsiColour::Rgb(colour) => Some(colour),
+        }
+    }
+}
+
+/// The colours that apply to the text at some point in the string.
+#[derive(Debug, Clone, Copy)]
+struct AnsiState {
+    fg: AnsiColour,
+    bg: AnsiColour,
+    bold: bool,
+}
+
+impl Default for AnsiState {
+    fn default() -> Self {
+        Self {
+            fg: AnsiColour::Default,
+            bg: AnsiColour::Default,
+            bold: false,
+        }
+    }
+}
+
+impl AnsiState {
+    /// Applies the parameters of a single SGR sequence.
+    fn apply(&mut self, params: &[u16]) {
+        // an empty sequence (`\x1b[m`) is the same as a reset
+        if params.is_empty() {
+            *self = Self::default();
+            return;
+        }
+        let mut params = params.iter().copied();
+        while let Some(param) = params.next() {
+            match param {
+                0 => *self = Self::default(),
+                1 => self.bold = true,
+                22 => self.bold = false,
+                30..=37 => self.fg = AnsiColour::Indexed((param - 30) as u8),
+                38 => self.fg = extended_colour(&mut params).unwrap_or(self.fg),
+                39 => self.fg = AnsiColour::Default,
+                40..=47 => self.bg = AnsiColour::Indexed((param - 40) as u8),
+                48 => self.bg = extended_colour(&mut params).unwrap_or(self.bg),
+                49 => self.bg = AnsiColour::Default,
+                90..=97 => self.fg = AnsiColour::Indexed((param - 90 + 8) as u8),
+                100..=107 => self.bg = AnsiColour::Indexed((param - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Internal function to read the rest of a `38;5;n` or `38;2;r;g;b` colour.
+fn extended_colour(params: &mut impl Iterator<Item = u16>) -> Option<AnsiColour> {
+    match params.next()? {
+        5 => Some(AnsiColour::Rgb(TextColour::from_ansi_256(params.next()?.min(255) as u8))),
+        2 => {
+            let mut channel = || params.next().map(|c| c.min(255) as u8);
+            Some(AnsiColour::Rgb(TextColour::new_rgb(channel()?, channel()?, channel()?)))
+        }
+        _ => None,
+    }
+}
+
+/// Splits text with ANSI escape sequences in it up into runs of the same colour, with the escape sequences removed.
+pub fn parse_ansi(text: &str) -> Vec<AnsiRun> {
+    let mut runs: Vec<AnsiRun> = Vec::new();
+    let mut state = AnsiState::default();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    let mut flush = |current: &mut String, state: AnsiState| {
+        if current.is_empty() {
+            return;
+        }
+        let (fg, bg) = (state.fg.resolve(state.bold), state.bg.resolve(false));
+        match runs.last_mut() {
+            Some(last) if last.fg == fg && last.bg == bg => last.text.push_str(current),
+            _ => runs.push(AnsiRun { text: current.clone(), fg, bg }),
+        }
+        current.clear();
+    };
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            current.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameters, then a final byte between @ and ~
+            Some('[') => {
+                let mut sequence = String::new();
+                let mut terminator = None;
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        terminator = Some(c);
+                        break;
+                    }
+                    sequence.push(c);
+                }
+                if terminator == Some('m') {
+                    flush(&mut current, state);
+                    let params = sequence.split([';', ':']).map(|p| p.parse().unwrap_or(0)).collect::<Vec<u16>>();
+                    state.apply(if sequence.is_empty() { &[] } else { &params });
+                }
+            }
+            // OSC: runs until BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // anything else is a two character sequence
+            _ => {}
+        }
+    }
+    flush(&mut current, state);
+    runs
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Draws text containing ANSI escape sequences, see the `ansi` module for which ones are understood.
+    /// `default_fg` and `default_bg` are used until (or after) a sequence changes them,
+    /// and `None` for the background means nothing is drawn behind the text.
+    /// Backgrounds are filled for every character cell (from the top to the bottom of its line) before any text is drawn.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_ansi(
+        &mut self,
+        text: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        default_fg: TextColour,
+        default_bg: Option<TextColour>,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let ansi_runs = parse_ansi(text);
+        let runs = ansi_runs.iter().map(|run| Run::new(&run.text, size)).collect::<Vec<_>>();
+        let options = DrawOptions::default();
+        let laid_out = self.layout_rich(&runs, size, &options);
+        let y = self.layout_y(y);
+
+        // backgrounds first, merging neighbouring cells of the same colour into one rect
+        let mut start = 0;
+        for line in laid_out.metrics.lines.iter() {
+            let mut pen = 0.0;
+            let mut current: Option<(f32, f32, TextColour)> = None;
+            for glyph in laid_out.glyphs[start..start + line.glyph_count].iter() {
+                let advance = self.glyph_advance(glyph);
+                let bg = ansi_runs[glyph.user_data.run].bg.or(default_bg);
+                current = match (current, bg) {
+                    (Some((from, to, colour)), Some(bg)) if colour == bg && to == pen => Some((from, pen + advance, colour)),
+                    (previous, bg) => {
+                        if let Some((from, to, colour)) = previous {
+                            let rect = Rect::new((x + from) as i32, (y + line.y) as i32, (to - from) as usize, line.height as usize);
+                            let filled = self.fill_rect(surface, self.surface_rect(rect), colour, None);
+                            self.mark_dirty(filled);
+                        }
+                        bg.map(|bg| (pen, pen + advance, bg))
+                    }
+                };
+                pen += advance;
+            }
+            if let Some((from, to, colour)) = current {
+                let rect = Rect::new((x + from) as i32, (y + line.y) as i32, (to - from) as usize, line.height as usize);
+                let filled = self.fill_rect(surface, self.surface_rect(rect), colour, None);
+                self.mark_dirty(filled);
+            }
+            start += line.glyph_count;
+        }
+
+        self.draw_glyphs(&laid_out.glyphs, x, y, None, options.blend_mode, options.opacity, GlyphEffects::from_options(&options), surface, |glyph| {
+            ansi_runs[glyph.user_data.run].fg.unwrap_or(default_fg)
+        });
+        laid_out.drawn_bounds(x, self.layout_y(y))
+    }
+}
@@ -0,0 +1,110 @@
+//! Loading fonts without blocking an async executor, enabled with the `async` feature.
+//! Big fonts (like CJK ones) can take a while to read and parse, so that happens in a job that's handed to a spawner
+//! you pass in, and you get back a future that finishes once the job is done. Nothing here depends on a runtime,
+//! the spawner just has to run the job somewhere it's fine to block:
+//! `|job| { tokio::task::spawn_blocking(job); }`, `|job| { std::thread::spawn(job); }` or your engine's task system.
+
+use crate::options::FontOptions;
+use crate::{parse_font, read_font_file, PasteSurface, StoreSurface, TextRenderer, TextRendererError};
+use fontdue::Font;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// Work for a spawner to run, see the module docs.
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A font that's been read and parsed, but isn't in a `TextRenderer` yet.
+struct ParsedFont {
+    font: Font,
+    font_data: Vec<u8>,
+    options: FontOptions,
+}
+
+/// The state shared between a job and the future waiting for it.
+#[derive(Default)]
+struct Shared {
+    result: Option<Result<ParsedFont, TextRendererError>>,
+    /// Set once the job has either finished or been dropped without running.
+    done: bool,
+    waker: Option<Waker>,
+}
+
+/// The job's end. If the spawner drops the job without running it, this still lets the future finish.
+struct Sender(Arc<Mutex<Shared>>);
+
+impl Sender {
+    fn send(self, result: Result<ParsedFont, TextRendererError>) {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).result = Some(result);
+    }
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut shared = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.done = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The future's end.
+struct Receiver(Arc<Mutex<Shared>>);
+
+impl Future for Receiver {
+    type Output = Result<ParsedFont, TextRendererError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if !shared.done {
+            shared.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        // a job that was dropped (or panicked) never loaded anything
+        Poll::Ready(shared.result.take().unwrap_or(Err(TextRendererError::Cancelled)))
+    }
+}
+
+/// Internal function to hand `work` to the spawner and get a future for its result.
+fn spawn(spawner: impl FnOnce(Job), work: impl FnOnce() -> Result<ParsedFont, TextRendererError> + Send + 'static) -> Receiver {
+    let shared = Arc::new(Mutex::new(Shared::default()));
+    let sender = Sender(shared.clone());
+    spawner(Box::new(move || sender.send(work())));
+    Receiver(shared)
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Same as `TextRenderer::load`, but the font is read and parsed in a job run by `spawner` instead of right here.
+    /// Fails in exactly the same ways as `load` does, and also with `TextRendererError::Cancelled`
+    /// if the spawner drops the job without running it.
+    pub fn load_async(font_path: &str, spawner: impl FnOnce(Job)) -> impl Future<Output = Result<Self, TextRendererError>> {
+        let font_path = font_path.to_string();
+        let receiver = spawn(spawner, move || {
+            let font_data = read_font_file(&font_path)?;
+            let options = FontOptions::default();
+            Ok(ParsedFont { font: parse_font(&font_data, &options)?, font_data, options })
+        });
+        async move { receiver.await.map(Self::from_parsed) }
+    }
+
+    /// Parses a font that's already in memory in a job run by `spawner`, for when you've got the bytes some other way
+    /// (like your engine's asset loader). Fails the same way `TextRendererBuilder::from_bytes(font_data).build()` would.
+    pub fn parse_in_background(
+        font_data: Vec<u8>,
+        options: &FontOptions,
+        spawner: impl FnOnce(Job)
+    ) -> impl Future<Output = Result<Self, TextRendererError>> {
+        let options = *options;
+        let receiver = spawn(spawner, move || {
+            Ok(ParsedFont { font: parse_font(&font_data, &options)?, font_data, options })
+        });
+        async move { receiver.await.map(Self::from_parsed) }
+    }
+
+    /// Internal function to finish off a renderer once its font has been parsed.
+    fn from_parsed(parsed: ParsedFont) -> Self {
+        Self::from_parsed_font(parsed.font, parsed.font_data, &parsed.options)
+    }
+}
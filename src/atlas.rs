@@ -0,0 +1,247 @@
+//! Packing glyphs into one big texture, for GPU renderers that want to draw every glyph out of a single atlas
+//! instead of uploading a tiny surface per glyph. A `GlyphAtlas` is just a grid of coverage bytes plus where everything
+//! went in it; `TextRenderer::atlas_glyph` rasterizes glyphs into it and says where to find them (in pixels and UVs).
+//! Glyphs are packed onto shelves (rows as tall as the tallest glyph on them), which wastes a little space but is
+//! quick and works well for text, where glyphs of one size are all about the same height.
+
+use crate::colours::TextColour;
+use crate::metrics::GlyphMetrics;
+use crate::options::CoordinateSystem;
+use crate::{coloured_pixels, GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer};
+use fontdue::layout::GlyphRasterConfig;
+use std::collections::HashMap;
+
+/// How big an atlas is allowed to grow by default (4096x4096), see `GlyphAtlas::set_max_size`.
+pub const DEFAULT_ATLAS_MAX_SIZE: usize = 4096;
+
+/// Where a glyph ended up in a `GlyphAtlas`, returned by `TextRenderer::atlas_glyph`.
+/// To draw it, draw `rect` of the atlas with its top left `left` pixels to the right of the pen and `top` pixels above
+/// the baseline (below it for `CoordinateSystem::PositiveYUp`, where the glyph is stored upside down like every other).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasGlyph {
+    /// Metrics of the glyph, the same as `TextRenderer::glyph_metrics` gives.
+    pub metrics: GlyphMetrics,
+    /// Where the glyph is in the atlas, in pixels. Empty for glyphs with nothing to draw, like spaces.
+    pub rect: Rect,
+    /// `rect` as texture coordinates from 0 to 1: left, top, right, bottom.
+    /// These are only right for the size the atlas was when the glyph was asked for, see `GlyphAtlas::uv_rect`.
+    pub uv: [f32; 4],
+    /// How far to the right of the pen position the left edge of the glyph goes.
+    pub left: f32,
+    /// How far above the baseline the top edge of the glyph goes.
+    pub top: f32,
+}
+
+/// Internal struct, a row of glyphs in the atlas.
+#[derive(Debug, Clone)]
+struct Shelf {
+    y: usize,
+    height: usize,
+    /// How much of the row is used up, from the left.
+    used: usize,
+}
+
+/// A single texture's worth of glyphs, one byte of coverage per pixel. Glyphs are added with `TextRenderer::atlas_glyph`,
+/// and the atlas doubles in size (up to `set_max_size`) when it runs out of room. Glyphs never move once they're added,
+/// but their UVs change when it grows, so check `size` (or ask for the glyphs again, which is cheap) after adding any.
+/// One atlas can be shared by several renderers, as glyphs are keyed by the font they came from.
+#[derive(Debug, Clone)]
+pub struct GlyphAtlas {
+    width: usize,
+    height: usize,
+    max_size: usize,
+    data: Vec<u8>,
+    shelves: Vec<Shelf>,
+    /// (font hash, glyph index, `f32::to_bits` of the size, flipped) to where the glyph went.
+    glyphs: HashMap<(usize, u16, u32, bool), AtlasGlyph>,
+    dirty: Option<Rect>,
+}
+
+impl GlyphAtlas {
+    /// Creates an empty atlas `width` by `height` pixels big (at least 1x1).
+    pub fn new(width: usize, height: usize) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        Self {
+            width,
+            height,
+            max_size: DEFAULT_ATLAS_MAX_SIZE.max(width).max(height),
+            data: vec![0; width * height],
+            shelves: Vec::new(),
+            glyphs: HashMap::new(),
+            dirty: None,
+        }
+    }
+
+    /// Sets how wide and tall the atlas can grow to, which should be no bigger than the biggest texture your GPU can take.
+    /// Defaults to `DEFAULT_ATLAS_MAX_SIZE`. Glyphs that don't fit once the atlas is this big aren't added.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size.max(self.width).max(self.height);
+    }
+
+    /// Returns the (width, height) of the atlas in pixels.
+    pub fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// Returns the coverage of every pixel in the atlas, one byte each, top row first.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns how many glyphs are in the atlas.
+    pub fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.glyphs.is_empty()
+    }
+
+    /// Turns a rect in the atlas into texture coordinates (left, top, right, bottom) for the atlas as it is now.
+    pub fn uv_rect(&self, rect: Rect) -> [f32; 4] {
+        let (width, height) = (self.width as f32, self.height as f32);
+        [
+            rect.x as f32 / width,
+            rect.y as f32 / height,
+            (rect.x as f32 + rect.width as f32) / width,
+            (rect.y as f32 + rect.height as f32) / height,
+        ]
+    }
+
+    /// Returns the part of the atlas that's changed since the last call, so only that has to be uploaded again,
+    /// or `None` if nothing has. The whole atlas counts as changed after it grows.
+    pub fn take_dirty_rect(&mut self) -> Option<Rect> {
+        self.dirty.take()
+    }
+
+    /// Throws away every glyph, keeping the atlas the size it is.
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+        self.shelves.clear();
+        self.glyphs.clear();
+        self.dirty = Some(Rect::new(0, 0, self.width, self.height));
+    }
+
+    /// Internal function to find room for a `width` by `height` glyph (with a pixel of space after it either way so
+    /// filtering doesn't bleed into the neighbours), growing the atlas if there isn't any. Returns the top left.
+    fn allocate(&mut self, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (padded_width, padded_height) = (width + 1, height + 1);
+        loop {
+            // the shortest shelf it fits on, so tall shelves are left for tall glyphs
+            let shelf = self.shelves.iter_mut()
+                .filter(|shelf| shelf.height >= padded_height && shelf.used + padded_width <= self.width)
+                .min_by_key(|shelf| shelf.height);
+            if let Some(shelf) = shelf {
+                shelf.used += padded_width;
+                return Some((shelf.used - padded_width, shelf.y));
+            }
+            let bottom = self.shelves.last().map_or(0, |shelf| shelf.y + shelf.height);
+            if bottom + padded_height <= self.height && padded_width <= self.width {
+                self.shelves.push(Shelf { y: bottom, height: padded_height, used: padded_width });
+                return Some((0, bottom));
+            }
+            if !self.grow() {
+                return None;
+            }
+        }
+    }
+
+    /// Internal function to double the shorter side of the atlas, keeping everything where it is.
+    /// Returns `false` if it's already as big as it's allowed to get.
+    fn grow(&mut self) -> bool {
+        let (width, height) = if self.width <= self.height && self.width < self.max_size {
+            ((self.width * 2).min(self.max_size), self.height)
+        } else if self.height < self.max_size {
+            (self.width, (self.height * 2).min(self.max_size))
+        } else if self.width < self.max_size {
+            ((self.width * 2).min(self.max_size), self.height)
+        } else {
+            return false;
+        };
+        let mut data = vec![0; width * height];
+        for (old, new) in self.data.chunks_exact(self.width).zip(data.chunks_exact_mut(width)) {
+            new[..self.width].copy_from_slice(old);
+        }
+        self.data = data;
+        (self.width, self.height) = (width, height);
+        self.dirty = Some(Rect::new(0, 0, width, height));
+        true
+    }
+
+    /// Internal function to copy a glyph's coverage into the atlas at (x, y).
+    fn write(&mut self, x: usize, y: usize, width: usize, mask: &[u8]) {
+        for (row, line) in mask.chunks_exact(width).enumerate() {
+            let start = (y + row) * self.width + x;
+            self.data[start..start + width].copy_from_slice(line);
+        }
+        let rect = Rect::new(x as i32, y as i32, width, mask.len() / width);
+        self.dirty = Some(self.dirty.map_or(rect, |dirty| dirty.union(&rect)));
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Adds a single character at `size` pixels to `atlas` (if it isn't in there already) and returns where it is.
+    /// The coverage comes from the same cache `GlyphCacheFormat::Alpha` uses, without gamma correction,
+    /// so the shader can tint and correct it however it likes.
+    /// Returns `None` if the font doesn't have a glyph for the character, `size` isn't one that can be drawn,
+    /// or the atlas is full.
+    pub fn atlas_glyph(&mut self, atlas: &mut GlyphAtlas, c: char, size: f32) -> Option<AtlasGlyph> {
+        self.check_size(size).ok()?;
+        let metrics = self.glyph_metrics(c, size)?;
+        let glyph_index = self.font.lookup_glyph_index(c);
+        self.atlas_glyph_indexed(atlas, 0, glyph_index, size, metrics)
+    }
+
+    /// Internal function to add a glyph by its index in one of the renderer's fonts to `atlas`, see `atlas_glyph`.
+    pub(crate) fn atlas_glyph_indexed(
+        &mut self,
+        atlas: &mut GlyphAtlas,
+        font_index: usize,
+        glyph_index: u16,
+        size: f32,
+        metrics: GlyphMetrics
+    ) -> Option<AtlasGlyph> {
+        let font_hash = self.font_at(font_index).file_hash();
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = (font_hash, glyph_index, size.to_bits(), flipped);
+        if let Some(glyph) = atlas.glyphs.get(&key) {
+            return Some(AtlasGlyph { uv: atlas.uv_rect(glyph.rect), ..*glyph });
+        }
+        let (width, height) = (metrics.bitmap_width, metrics.bitmap_height);
+        let rect = if width * height == 0 {
+            Rect::default()
+        } else {
+            let config = GlyphRasterConfig { glyph_index, px: size, font_hash };
+            let gamma = self.gamma.take();
+            let mask = self.get_glyph_mask(font_index, config, width, TextColour::new_rgb(255, 255, 255), GlyphEffects::default(), 0);
+            self.gamma = gamma;
+            let (x, y) = atlas.allocate(width, height)?;
+            atlas.write(x, y, width, &mask);
+            Rect::new(x as i32, y as i32, width, height)
+        };
+        // the bitmap sits on whole pixels, which isn't quite where the bearings are
+        let bitmap = self.font_at(font_index).metrics_indexed(glyph_index, size);
+        let glyph = AtlasGlyph {
+            metrics,
+            rect,
+            uv: atlas.uv_rect(rect),
+            left: bitmap.xmin as f32,
+            top: (bitmap.ymin + bitmap.height as i32) as f32,
+        };
+        atlas.glyphs.insert(key, glyph);
+        Some(glyph)
+    }
+
+    /// Adds every character of `string` at `size` pixels to `atlas`, for filling it up before drawing anything.
+    /// Returns how many characters couldn't be added (the font doesn't have them, or the atlas is full).
+    pub fn atlas_string(&mut self, atlas: &mut GlyphAtlas, string: &str, size: f32) -> usize {
+        string.chars().filter(|c| self.atlas_glyph(atlas, *c, size).is_none()).count()
+    }
+
+    /// Turns `atlas` into a single surface with every glyph in `colour`, with the bytes of each pixel in the renderer's
+    /// pixel order, for backends that would rather have a surface than a grid of coverage.
+    pub fn atlas_surface(&self, atlas: &GlyphAtlas, colour: TextColour) -> G {
+        let data = coloured_pixels(&atlas.data, colour, self.pixel_order, self.premultiplied_alpha);
+        G::from_raw_mask(atlas.width, atlas.height, &data, colour)
+    }
+}
@@ -0,0 +1,262 @@
+//! Software implementations of the `BlendMode`s, for backends that store their pixels in plain RGBA buffers.
+//! All of these take `dst` and `src` starting at the top left pixel of the area being blended,
+//! and a pitch (bytes per row) for each, so you can slice straight into a bigger buffer:
+//! `blend_over(&mut pixels[(y * pitch) + (x * 4)..], pitch, &glyph[..], glyph_pitch, width, height)`.
+//! Pixels that would fall outside of either slice are skipped.
+//! Alpha is expected to be the last byte of every pixel, so these work for `PixelOrder::Rgba` and `PixelOrder::Bgra`.
+
+use crate::colours::{PixelOrder, TextColour};
+use crate::options::BlendMode;
+use crate::Rect;
+
+/// Blends `src` onto `dst` using `mode`.
+pub fn blend(mode: BlendMode, dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    match mode {
+        BlendMode::Replace => copy(dst, dst_pitch, src, src_pitch, width, height),
+        BlendMode::AlphaOver => blend_over(dst, dst_pitch, src, src_pitch, width, height),
+        BlendMode::Additive => blend_additive(dst, dst_pitch, src, src_pitch, width, height),
+        BlendMode::Multiply => blend_multiply(dst, dst_pitch, src, src_pitch, width, height),
+        BlendMode::Screen => blend_screen(dst, dst_pitch, src, src_pitch, width, height),
+    }
+}
+
+/// Overwrites `dst` with `src`, alpha and all.
+pub fn copy(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    for_each_pixel(dst, dst_pitch, src, src_pitch, width, height, |d, s| d.copy_from_slice(s));
+}
+
+/// Composites `src` over `dst` (the porter-duff "over" operator), treating both as straight (not premultiplied) alpha.
+pub fn blend_over(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    for_each_pixel(dst, dst_pitch, src, src_pitch, width, height, over);
+}
+
+/// Composites a whole `src_w` x `src_h` RGBA image over a `dst_w` x `dst_h` RGBA image, with its top left at (`dst_x`, `dst_y`).
+/// This is everything a `PasteSurface::paste` for a plain RGBA buffer needs to do, so you can just call this.
+/// `dst_x`/`dst_y` can be negative, and anything that would land outside of `dst` (or outside of `clip`, if given)
+/// is cut off on all four edges. Fully opaque source pixels are copied straight over.
+/// If either slice is shorter than its size says it is, the missing rows are treated as being outside of it.
+#[allow(clippy::too_many_arguments)]
+pub fn blend_rgba_over(
+    dst: &mut [u8],
+    dst_w: usize,
+    dst_h: usize,
+    dst_x: i32,
+    dst_y: i32,
+    src: &[u8],
+    src_w: usize,
+    src_h: usize,
+    clip: Option<Rect>
+) {
+    blend_rgba(BlendMode::AlphaOver, dst, dst_w, dst_h, dst_x, dst_y, src, src_w, src_h, clip);
+}
+
+/// Same as `blend_rgba_over`, but blends with any `mode`.
+#[allow(clippy::too_many_arguments)]
+pub fn blend_rgba(
+    mode: BlendMode,
+    dst: &mut [u8],
+    dst_w: usize,
+    dst_h: usize,
+    dst_x: i32,
+    dst_y: i32,
+    src: &[u8],
+    src_w: usize,
+    src_h: usize,
+    clip: Option<Rect>
+) {
+    if dst_w == 0 || src_w == 0 {
+        return;
+    }
+    let dst_h = dst_h.min(dst.len() / (dst_w * 4));
+    let src_h = src_h.min(src.len() / (src_w * 4));
+    let target = Rect::new(dst_x, dst_y, src_w, src_h);
+    let visible = target.intersection(&Rect::new(0, 0, dst_w, dst_h))
+        .and_then(|visible| match clip {
+            Some(clip) => visible.intersection(&clip),
+            None => Some(visible),
+        });
+    let visible = match visible {
+        Some(visible) => visible,
+        None => return,
+    };
+    let pixel: fn(&mut [u8], &[u8]) = match mode {
+        BlendMode::Replace => |d, s| d.copy_from_slice(s),
+        BlendMode::AlphaOver => over,
+        BlendMode::Additive => additive,
+        BlendMode::Multiply => multiply,
+        BlendMode::Screen => screen,
+    };
+    let src_x = (visible.x as i64 - dst_x as i64) as usize;
+    let src_y = (visible.y as i64 - dst_y as i64) as usize;
+    for row in 0..visible.height {
+        let src_start = ((src_y + row) * src_w + src_x) * 4;
+        let dst_start = ((visible.y as usize + row) * dst_w + visible.x as usize) * 4;
+        let src_row = src[src_start..src_start + visible.width * 4].chunks_exact(4);
+        let dst_row = dst[dst_start..dst_start + visible.width * 4].chunks_exact_mut(4);
+        for (d, s) in dst_row.zip(src_row) {
+            pixel(d, s);
+        }
+    }
+}
+
+/// Does everything `PasteSurface::paste_blended` has to, for a backend that keeps its pixels in a plain `dst_w` x `dst_h`
+/// RGBA buffer and its glyphs in `src_w` pixel wide RGBA buffers. The arguments in the middle are the ones
+/// `paste_blended` gets, so implementing it is just passing them along:
+/// `paste_rgba(&mut self.pixels, self.width, self.height, dst_x, dst_y, src_x, src_y, width, height, &data.pixels, data.width, mode)`.
+/// Only the `width` x `height` part of the glyph starting at (`src_x`, `src_y`) is blended, and anything past the edges of
+/// either buffer is skipped, so overlapping glyphs blend into each other instead of punching holes in what was there.
+#[allow(clippy::too_many_arguments)]
+pub fn paste_rgba(
+    dst: &mut [u8],
+    dst_w: usize,
+    dst_h: usize,
+    dst_x: usize,
+    dst_y: usize,
+    src_x: usize,
+    src_y: usize,
+    width: usize,
+    height: usize,
+    src: &[u8],
+    src_w: usize,
+    mode: BlendMode
+) {
+    if src_w == 0 || dst_x > i32::MAX as usize || dst_y > i32::MAX as usize {
+        return;
+    }
+    // the whole glyph goes where it would be if it wasn't cut off, and the visible part is the clip
+    let (x, y) = (dst_x as i32, dst_y as i32);
+    let clip = Rect::new(x, y, width, height);
+    blend_rgba(mode, dst, dst_w, dst_h, x - src_x as i32, y - src_y as i32, src, src_w, src.len() / (src_w * 4), Some(clip));
+}
+
+/// Internal function for the "over" operator on a single pixel.
+fn over(d: &mut [u8], s: &[u8]) {
+    let src_a = s[3] as u32;
+    if src_a == 0 {
+        return;
+    }
+    // fast path for the insides of glyphs
+    if src_a == 255 {
+        d.copy_from_slice(s);
+        return;
+    }
+    let dst_a = d[3] as u32 * (255 - src_a) / 255;
+    let out_a = src_a + dst_a;
+    for i in 0..3 {
+        d[i] = ((s[i] as u32 * src_a + d[i] as u32 * dst_a) / out_a) as u8;
+    }
+    d[3] = out_a as u8;
+}
+
+/// Adds `src` (scaled by its alpha) onto `dst`, clamping at 255. Good for glowing text.
+pub fn blend_additive(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    for_each_pixel(dst, dst_pitch, src, src_pitch, width, height, additive);
+}
+
+/// Internal function for additive blending of a single pixel.
+fn additive(d: &mut [u8], s: &[u8]) {
+    let src_a = s[3] as u32;
+    for i in 0..3 {
+        d[i] = (d[i] as u32 + s[i] as u32 * src_a / 255).min(255) as u8;
+    }
+    d[3] = (d[3] as u32 + src_a).min(255) as u8;
+}
+
+/// Multiplies `dst` by `src`, faded by the alpha of `src`. Darkens everything but white.
+pub fn blend_multiply(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    for_each_pixel(dst, dst_pitch, src, src_pitch, width, height, multiply);
+}
+
+/// Internal function for multiply blending of a single pixel.
+fn multiply(d: &mut [u8], s: &[u8]) {
+    mix(d, s, |d, s| d * s / 255);
+}
+
+/// Screens `dst` with `src` (multiplying their inverses), faded by the alpha of `src`. Lightens everything but black.
+pub fn blend_screen(dst: &mut [u8], dst_pitch: usize, src: &[u8], src_pitch: usize, width: usize, height: usize) {
+    for_each_pixel(dst, dst_pitch, src, src_pitch, width, height, screen);
+}
+
+/// Internal function for screen blending of a single pixel.
+fn screen(d: &mut [u8], s: &[u8]) {
+    mix(d, s, |d, s| 255 - (255 - d) * (255 - s) / 255);
+}
+
+/// Internal function to blend a single pixel with a separable blend mode: `f` combines a channel of `dst` with the same
+/// channel of `src`, and the result is faded in by the alpha of `src`. Alpha is combined the same way as "over".
+fn mix(d: &mut [u8], s: &[u8], f: impl Fn(u32, u32) -> u32) {
+    let src_a = s[3] as u32;
+    if src_a == 0 {
+        return;
+    }
+    for i in 0..3 {
+        let (d_c, s_c) = (d[i] as u32, s[i] as u32);
+        let blended = f(d_c, s_c);
+        d[i] = ((d_c * (255 - src_a) + blended * src_a) / 255) as u8;
+    }
+    d[3] = (src_a + d[3] as u32 * (255 - src_a) / 255) as u8;
+}
+
+/// Blends `colour` onto `dst` (with the bytes of each pixel in `pixel_order`) through `coverage`, which has three bytes
+/// per pixel: how much of the red, green and blue subpixels are covered (see `GlyphCacheFormat::Lcd`).
+/// Every channel is moved towards `colour` by its own coverage (times the colour's alpha), which is what makes LCD text
+/// sharper, and alpha ends up as if the most covered subpixel had been composited "over". This is all a
+/// `PasteSurface::paste_lcd` for a plain buffer needs to do, after slicing both to the visible part.
+#[allow(clippy::too_many_arguments)]
+pub fn blend_lcd(
+    dst: &mut [u8],
+    dst_pitch: usize,
+    coverage: &[u8],
+    coverage_pitch: usize,
+    width: usize,
+    height: usize,
+    colour: TextColour,
+    pixel_order: PixelOrder
+) {
+    // where the red, green, blue and alpha bytes are in a pixel
+    let order = pixel_order.arrange(0, 1, 2, 3);
+    let index = |channel: u8| order.iter().position(|byte| *byte == channel).unwrap_or(channel as usize);
+    let (channels, alpha) = ([(index(0), colour.r), (index(1), colour.g), (index(2), colour.b)], index(3));
+    for row in 0..height {
+        let dst_start = row * dst_pitch;
+        let coverage_start = row * coverage_pitch;
+        if dst_start >= dst.len() || coverage_start >= coverage.len() {
+            break;
+        }
+        let dst_row = dst[dst_start..].chunks_exact_mut(4);
+        let coverage_row = coverage[coverage_start..].chunks_exact(3);
+        for (d, c) in dst_row.zip(coverage_row).take(width) {
+            let mut most = 0;
+            for ((i, value), covered) in channels.iter().zip(c) {
+                let amount = *covered as u32 * colour.a as u32 / 255;
+                d[*i] = ((d[*i] as u32 * (255 - amount) + *value as u32 * amount) / 255) as u8;
+                most = most.max(amount);
+            }
+            d[alpha] = (most + d[alpha] as u32 * (255 - most) / 255) as u8;
+        }
+    }
+}
+
+/// Internal function to call `f` with every (dst, src) pair of RGBA pixels that fits in both slices.
+fn for_each_pixel(
+    dst: &mut [u8],
+    dst_pitch: usize,
+    src: &[u8],
+    src_pitch: usize,
+    width: usize,
+    height: usize,
+    mut f: impl FnMut(&mut [u8], &[u8])
+) {
+    for row in 0..height {
+        let dst_start = row * dst_pitch;
+        let src_start = row * src_pitch;
+        if dst_start >= dst.len() || src_start >= src.len() {
+            break;
+        }
+        let dst_row = dst[dst_start..].chunks_exact_mut(4);
+        let src_row = src[src_start..].chunks_exact(4);
+        for (d, s) in dst_row.zip(src_row).take(width) {
+            f(d, s);
+        }
+    }
+}
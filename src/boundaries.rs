@@ -0,0 +1,94 @@
+//! Finding words and lines in text, for double and triple click selection and ctrl+arrow cursor movement.
+//! Words come from Unicode word segmentation (through `unicode-segmentation`), tidied up to behave like a text editor:
+//! runs of punctuation and runs of whitespace are one "word" each, and every CJK ideograph is a word of its own.
+
+use crate::prepared::PreparedText;
+use crate::{PasteSurface, StoreSurface, TextRenderer};
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Internal enum, what a piece of the string is, so neighbouring pieces of the same kind can be joined up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WordKind {
+    /// Letters, numbers and anything joined to them (like the apostrophe in "don't"). Never joined to its neighbours.
+    Word,
+    Whitespace,
+    /// Line breaks are kept on their own, so double clicking the end of a line doesn't pick up the next one.
+    LineBreak,
+    Punctuation,
+}
+
+impl WordKind {
+    /// What kind of piece a segment from `split_word_bound_indices` is.
+    fn of(segment: &str) -> Self {
+        if segment.chars().any(char::is_alphanumeric) {
+            WordKind::Word
+        } else if segment.chars().any(|c| matches!(c, '\n' | '\r' | '\u{b}' | '\u{c}' | '\u{85}' | '\u{2028}' | '\u{2029}')) {
+            WordKind::LineBreak
+        } else if segment.chars().all(char::is_whitespace) {
+            WordKind::Whitespace
+        } else {
+            WordKind::Punctuation
+        }
+    }
+}
+
+/// Internal function to split a string into words (see the module docs), as byte ranges that cover all of it.
+fn words(string: &str) -> Vec<(Range<usize>, WordKind)> {
+    let mut words: Vec<(Range<usize>, WordKind)> = Vec::new();
+    for (start, segment) in string.split_word_bound_indices() {
+        let kind = WordKind::of(segment);
+        let end = start + segment.len();
+        match words.last_mut() {
+            Some((range, last)) if *last == kind && matches!(kind, WordKind::Whitespace | WordKind::Punctuation) => range.end = end,
+            _ => words.push((start..end, kind)),
+        }
+    }
+    words
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// The byte range of the word that `byte_index` is in, for double click selection. Runs of whitespace and runs of
+    /// punctuation count as words too, so this always returns something as long as the string isn't empty
+    /// (an index past the end gets the last word). `byte_index` doesn't have to be on a character boundary.
+    pub fn word_boundaries_at(&self, string: &str, byte_index: usize) -> Range<usize> {
+        let words = words(string);
+        words.iter()
+            .find(|(range, _)| byte_index < range.end)
+            .or(words.last())
+            .map_or(0..0, |(range, _)| range.clone())
+    }
+
+    /// Where the cursor goes for ctrl+right from `byte_index`: the end of the next word or run of punctuation,
+    /// skipping over any whitespace (and line breaks) on the way. Returns the length of the string if there's nothing left.
+    pub fn next_word_boundary(&self, string: &str, byte_index: usize) -> usize {
+        words(string).into_iter()
+            .find(|(range, kind)| range.end > byte_index && !matches!(kind, WordKind::Whitespace | WordKind::LineBreak))
+            .map_or(string.len(), |(range, _)| range.end)
+    }
+
+    /// Where the cursor goes for ctrl+left from `byte_index`: the start of the previous word or run of punctuation,
+    /// skipping over any whitespace (and line breaks) on the way. Returns 0 if there's nothing before it.
+    pub fn prev_word_boundary(&self, string: &str, byte_index: usize) -> usize {
+        words(string).into_iter()
+            .rev()
+            .find(|(range, kind)| range.start < byte_index && !matches!(kind, WordKind::Whitespace | WordKind::LineBreak))
+            .map_or(0, |(range, _)| range.start)
+    }
+
+    /// The byte range of the line that `byte_index` ended up on when `prepared` was laid out, for triple click selection.
+    /// These are the real wrapped lines, so they include the whitespace or newline each line was broken at
+    /// (see `LineInfo::byte_range`). An index past the end of the shown lines gets an empty range where they end.
+    pub fn line_boundaries_at(&self, prepared: &PreparedText, byte_index: usize) -> Range<usize> {
+        let lines = &prepared.metrics().lines;
+        match lines.iter().find(|line| line.byte_range.contains(&byte_index)) {
+            Some(line) => line.byte_range.clone(),
+            // the end of the text is still on the last line, like a cursor sitting after the last character
+            None => match lines.last() {
+                Some(line) if byte_index == line.byte_range.end => line.byte_range.clone(),
+                Some(line) => line.byte_range.end..line.byte_range.end,
+                None => 0..0,
+            },
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use crate::colours::PixelOrder;
+use crate::options::{CachePolicy, CoordinateSystem, FontOptions, GlyphCacheFormat, SubpixelOrder};
+#[cfg(feature = "ttf-parser")]
+use crate::variations::{instance_font, named_instance_coordinates};
+use crate::{read_font_file, PasteSurface, StoreSurface, TextRenderer, TextRendererError, DEFAULT_MAX_SIZE};
+
+/// Where the builder gets the font from.
+#[derive(Debug, Clone)]
+enum FontSource {
+    Path(String),
+    Bytes(Vec<u8>),
+}
+
+/// The recommended way to create a `TextRenderer` if you need anything other than the defaults.
+/// `TextRenderer::load(path)` is the same as `TextRendererBuilder::new(path).build()`.
+/// Chain the setters together: `TextRendererBuilder::new("FreeMono.ttf").scale(16.0).gamma(Some(1.8)).build()`
+#[derive(Debug, Clone)]
+pub struct TextRendererBuilder {
+    source: FontSource,
+    font_options: FontOptions,
+    gamma: Option<f32>,
+    pixel_order: PixelOrder,
+    premultiplied_alpha: bool,
+    subpixel_positions: u8,
+    subpixel_order: SubpixelOrder,
+    coordinate_system: CoordinateSystem,
+    glyph_cache_format: GlyphCacheFormat,
+    cache_policy: CachePolicy,
+    max_size: f32,
+    /// See `variation`.
+    #[cfg(feature = "ttf-parser")]
+    variations: Vec<(String, f32)>,
+    /// See `named_instance`.
+    #[cfg(feature = "ttf-parser")]
+    named_instance: Option<String>,
+}
+
+impl TextRendererBuilder {
+    /// Starts building a renderer for the font file at `font_path`.
+    pub fn new(font_path: &str) -> Self {
+        Self::with_source(FontSource::Path(font_path.to_string()))
+    }
+
+    /// Starts building a renderer for a font that's already in memory (e.g. from `include_bytes!`).
+    pub fn from_bytes(font_data: Vec<u8>) -> Self {
+        Self::with_source(FontSource::Bytes(font_data))
+    }
+
+    fn with_source(source: FontSource) -> Self {
+        Self {
+            source,
+            font_options: FontOptions::default(),
+            gamma: None,
+            pixel_order: PixelOrder::default(),
+            premultiplied_alpha: false,
+            subpixel_positions: 1,
+            subpixel_order: SubpixelOrder::default(),
+            coordinate_system: CoordinateSystem::default(),
+            glyph_cache_format: GlyphCacheFormat::default(),
+            cache_policy: CachePolicy::default(),
+            max_size: DEFAULT_MAX_SIZE,
+            #[cfg(feature = "ttf-parser")]
+            variations: Vec::new(),
+            #[cfg(feature = "ttf-parser")]
+            named_instance: None,
+        }
+    }
+
+    /// Replaces all of the font options at once.
+    pub fn font_options(mut self, font_options: FontOptions) -> Self {
+        self.font_options = font_options;
+        self
+    }
+
+    /// See `FontOptions::scale`.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.font_options.scale = scale;
+        self
+    }
+
+    /// See `FontOptions::collection_index`.
+    pub fn collection_index(mut self, collection_index: u32) -> Self {
+        self.font_options.collection_index = collection_index;
+        self
+    }
+
+    /// Same as calling `TextRenderer::set_gamma` after building.
+    pub fn gamma(mut self, gamma: Option<f32>) -> Self {
+        self.gamma = gamma;
+        self
+    }
+
+    /// Sets the order the bytes of every pixel are in when glyph data is passed to `StoreSurface::from_raw_mask`.
+    /// Defaults to `PixelOrder::Rgba`. This is fixed for the lifetime of the renderer (and its clones).
+    pub fn pixel_order(mut self, pixel_order: PixelOrder) -> Self {
+        self.pixel_order = pixel_order;
+        self
+    }
+
+    /// Makes glyph data passed to `StoreSurface::from_raw_mask` premultiplied, so every colour channel is already scaled
+    /// by the pixel's alpha, for backends and compositors that expect that. Defaults to `false` (straight alpha).
+    /// Masks handed to `PasteSurface::paste_mask` (with `GlyphCacheFormat::Alpha`) are still plain coverage,
+    /// so premultiply those yourself when you tint them. The functions in `sext::blit` expect straight alpha.
+    /// This is fixed for the lifetime of the renderer (and its clones).
+    pub fn premultiplied_alpha(mut self, premultiplied_alpha: bool) -> Self {
+        self.premultiplied_alpha = premultiplied_alpha;
+        self
+    }
+
+    /// Sets how many horizontal positions within a pixel glyphs can be drawn at, so slowly moving text slides smoothly
+    /// instead of jumping a whole pixel at a time. `4` draws glyphs at the nearest quarter of a pixel, and every one of those
+    /// positions is cached separately (so up to 4 times as many glyphs get rasterized). Defaults to `1`, which snaps glyphs
+    /// to whole pixels, and `0` is treated as `1`. Glyphs drawn at a fraction of a pixel are one pixel wider than usual.
+    /// Only `draw_glyphs` based drawing uses this: monospaced grids, `glyph_bitmap` and `execute_commands` still snap.
+    /// This is fixed for the lifetime of the renderer (and its clones).
+    pub fn subpixel_positions(mut self, subpixel_positions: u8) -> Self {
+        self.subpixel_positions = subpixel_positions.max(1);
+        self
+    }
+
+    /// Sets which way the y axis of the surfaces you draw onto points.
+    /// Defaults to `CoordinateSystem::PositiveYDown`. This is fixed for the lifetime of the renderer (and its clones).
+    pub fn coordinate_system(mut self, coordinate_system: CoordinateSystem) -> Self {
+        self.coordinate_system = coordinate_system;
+        self
+    }
+
+    /// Sets how glyphs are stored in the glyph cache. Defaults to `GlyphCacheFormat::Rgba`.
+    /// This is fixed for the lifetime of the renderer (and its clones).
+    pub fn glyph_cache_format(mut self, glyph_cache_format: GlyphCacheFormat) -> Self {
+        self.glyph_cache_format = glyph_cache_format;
+        self
+    }
+
+    /// Sets which order the subpixels of the screen are in, for `GlyphCacheFormat::Lcd`. Defaults to `SubpixelOrder::Rgb`.
+    /// This is fixed for the lifetime of the renderer (and its clones).
+    pub fn subpixel_order(mut self, subpixel_order: SubpixelOrder) -> Self {
+        self.subpixel_order = subpixel_order;
+        self
+    }
+
+    /// Sets whether the glyph cache keeps the raw bytes of every glyph around. Defaults to `CachePolicy::KeepRawBytes`.
+    /// This is fixed for the lifetime of the renderer (and its clones).
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// Sets the biggest text size (in pixels) that will be drawn or measured, see `TextRenderer::check_size`.
+    /// Defaults to `DEFAULT_MAX_SIZE`, which is plenty for anything short of banners. Keep in mind that a single
+    /// glyph takes up roughly `size * size * 4` bytes once it's rasterized.
+    pub fn max_size(mut self, max_size: f32) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Sets an axis of a variable font, like `.variation("wght", 700.0)` for bold. Can be called once for every axis,
+    /// and goes on top of `named_instance` if that's set too. The font is turned into a static one with these
+    /// axis values when it's loaded (see the `variations` module), and building fails with
+    /// `TextRendererError::UnknownVariation` if it isn't variable or doesn't have the axis. Needs the `ttf-parser` feature.
+    #[cfg(feature = "ttf-parser")]
+    pub fn variation(mut self, tag: &str, value: f32) -> Self {
+        self.variations.push((tag.to_string(), value));
+        self
+    }
+
+    /// Uses the axis values of one of a variable font's named instances, like `"Light"` or `"Bold"`
+    /// (see `TextRenderer::named_instances`). Building fails with `TextRendererError::UnknownVariation` if the font
+    /// doesn't have one called `name`. Needs the `ttf-parser` feature.
+    #[cfg(feature = "ttf-parser")]
+    pub fn named_instance(mut self, name: &str) -> Self {
+        self.named_instance = Some(name.to_string());
+        self
+    }
+
+    /// Loads the font and creates the `TextRenderer`.
+    /// Will return `TextRendererError::IoError` if the font could not be read, and `TextRendererError::FontParseError`
+    /// (or `TextRendererError::InvalidCollectionIndex`) if it could not be loaded.
+    pub fn build<G, A>(self) -> Result<TextRenderer<G, A>, TextRendererError> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+        #[allow(unused_mut)] // only changed with some features enabled
+        let (mut font_data, mut font_options) = match self.source {
+            FontSource::Path(path) => (read_font_file(&path)?, self.font_options),
+            FontSource::Bytes(bytes) => (bytes, self.font_options),
+        };
+        #[cfg(feature = "ttf-parser")]
+        if self.named_instance.is_some() || !self.variations.is_empty() {
+            let mut variations = match &self.named_instance {
+                Some(name) => named_instance_coordinates(&font_data, font_options.collection_index, name)
+                    .ok_or_else(|| TextRendererError::UnknownVariation(name.clone()))?,
+                None => Vec::new(),
+            };
+            variations.extend(self.variations);
+            let variations = variations.iter().map(|(tag, value)| (tag.as_str(), *value)).collect::<Vec<_>>();
+            font_data = instance_font(&font_data, font_options.collection_index, &variations)?;
+            font_options.collection_index = 0;
+        }
+        let mut renderer = TextRenderer::from_font_data(font_data, &font_options)?;
+        renderer.set_gamma(self.gamma);
+        renderer.pixel_order = self.pixel_order;
+        renderer.premultiplied_alpha = self.premultiplied_alpha;
+        renderer.subpixel_positions = self.subpixel_positions;
+        renderer.subpixel_order = self.subpixel_order;
+        renderer.coordinate_system = self.coordinate_system;
+        renderer.glyph_cache_format = self.glyph_cache_format;
+        renderer.cache_policy = self.cache_policy;
+        renderer.max_size = self.max_size;
+        Ok(renderer)
+    }
+}
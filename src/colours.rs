@@ -6,6 +6,35 @@ pub struct TextColour {
     pub a: u8,
 }
 
+/// Internal function to make an opaque colour out of a 0xrrggbb number, usable in consts.
+const fn rgb(hex: u32) -> TextColour {
+    TextColour { r: (hex >> 16) as u8, g: (hex >> 8) as u8, b: hex as u8, a: 255 }
+}
+
+// the 16 basic terminal colours, with the same values xterm uses
+pub const ANSI_BLACK: TextColour = rgb(0x000000);
+pub const ANSI_RED: TextColour = rgb(0xcd0000);
+pub const ANSI_GREEN: TextColour = rgb(0x00cd00);
+pub const ANSI_YELLOW: TextColour = rgb(0xcdcd00);
+pub const ANSI_BLUE: TextColour = rgb(0x0000ee);
+pub const ANSI_MAGENTA: TextColour = rgb(0xcd00cd);
+pub const ANSI_CYAN: TextColour = rgb(0x00cdcd);
+pub const ANSI_WHITE: TextColour = rgb(0xe5e5e5);
+pub const ANSI_BRIGHT_BLACK: TextColour = rgb(0x7f7f7f);
+pub const ANSI_BRIGHT_RED: TextColour = rgb(0xff0000);
+pub const ANSI_BRIGHT_GREEN: TextColour = rgb(0x00ff00);
+pub const ANSI_BRIGHT_YELLOW: TextColour = rgb(0xffff00);
+pub const ANSI_BRIGHT_BLUE: TextColour = rgb(0x5c5cff);
+pub const ANSI_BRIGHT_MAGENTA: TextColour = rgb(0xff00ff);
+pub const ANSI_BRIGHT_CYAN: TextColour = rgb(0x00ffff);
+pub const ANSI_BRIGHT_WHITE: TextColour = rgb(0xffffff);
+
+const ANSI_BASIC: [TextColour; 16] = [
+    ANSI_BLACK, ANSI_RED, ANSI_GREEN, ANSI_YELLOW, ANSI_BLUE, ANSI_MAGENTA, ANSI_CYAN, ANSI_WHITE,
+    ANSI_BRIGHT_BLACK, ANSI_BRIGHT_RED, ANSI_BRIGHT_GREEN, ANSI_BRIGHT_YELLOW,
+    ANSI_BRIGHT_BLUE, ANSI_BRIGHT_MAGENTA, ANSI_BRIGHT_CYAN, ANSI_BRIGHT_WHITE,
+];
+
 impl TextColour {
     pub fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
         Self { r, g, b, a }
@@ -34,4 +63,162 @@ impl TextColour {
         let a = u8::from_str_radix(&hex[6..8], 16).unwrap();
         Self { r, g, b, a }
     }
-}
\ No newline at end of file
+
+    /// Returns the same colour with its alpha multiplied by `opacity` (from 0.0 to 1.0).
+    pub fn with_opacity(self, opacity: f32) -> Self {
+        Self { a: (self.a as f32 * opacity.clamp(0.0, 1.0)).round() as u8, ..self }
+    }
+
+    /// Returns the same colour with a different alpha.
+    pub fn with_alpha(self, a: u8) -> Self {
+        Self { a, ..self }
+    }
+
+    /// Returns the colour with its HSL lightness raised by `amount` (from 0.0 to 1.0, so 0.1 is 10 percentage points).
+    /// Lightness stops at white instead of wrapping around, and the hue stays the same.
+    pub fn lighten(self, amount: f32) -> Self {
+        let (h, s, l) = self.to_hsl();
+        Self::from_hsl(h, s, (l + amount).clamp(0.0, 1.0), self.a)
+    }
+
+    /// Same as `lighten`, but lowers the lightness instead. Stops at black.
+    pub fn darken(self, amount: f32) -> Self {
+        self.lighten(-amount)
+    }
+
+    /// Returns the colour with its HSL saturation raised by `amount` (from 0.0 to 1.0), keeping the hue and lightness the same.
+    pub fn saturate(self, amount: f32) -> Self {
+        let (h, s, l) = self.to_hsl();
+        Self::from_hsl(h, (s + amount).clamp(0.0, 1.0), l, self.a)
+    }
+
+    /// Same as `saturate`, but lowers the saturation instead. `desaturate(1.0)` gives a gray of the same lightness.
+    pub fn desaturate(self, amount: f32) -> Self {
+        self.saturate(-amount)
+    }
+
+    /// Returns the opposite colour on every channel, with the same alpha.
+    pub fn invert(self) -> Self {
+        Self { r: 255 - self.r, g: 255 - self.g, b: 255 - self.b, a: self.a }
+    }
+
+    /// Returns the WCAG contrast ratio between this colour and `other`, from 1.0 (the same) to 21.0 (black on white).
+    /// Alpha is ignored. WCAG asks for at least 4.5 for normal text and 3.0 for large text.
+    pub fn contrast_ratio(&self, other: &TextColour) -> f32 {
+        let (a, b) = (self.relative_luminance(), other.relative_luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Returns whichever of opaque black or white is easier to read on top of `background`.
+    pub fn best_on(background: TextColour) -> Self {
+        let (black, white) = (ANSI_BLACK, ANSI_BRIGHT_WHITE);
+        if background.contrast_ratio(&white) > background.contrast_ratio(&black) { white } else { black }
+    }
+
+    /// Internal function to get the WCAG relative luminance of the colour, from 0.0 (black) to 1.0 (white).
+    fn relative_luminance(&self) -> f32 {
+        let linear = |channel: u8| {
+            let channel = channel as f32 / 255.0;
+            if channel <= 0.04045 { channel / 12.92 } else { ((channel + 0.055) / 1.055).powf(2.4) }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// Internal function to convert the colour to (hue in degrees, saturation, lightness), ignoring alpha.
+    fn to_hsl(self) -> (f32, f32, f32) {
+        let (r, g, b) = (self.r as f32 / 255.0, self.g as f32 / 255.0, self.b as f32 / 255.0);
+        let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+        let l = (max + min) / 2.0;
+        let delta = max - min;
+        if delta == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = delta / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            (b - r) / delta + 2.0
+        } else {
+            (r - g) / delta + 4.0
+        };
+        (h * 60.0, s, l)
+    }
+
+    /// Internal function to make a colour out of the (hue, saturation, lightness) `to_hsl` gives.
+    fn from_hsl(h: f32, s: f32, l: f32, a: u8) -> Self {
+        let chroma = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h / 60.0;
+        let x = chroma * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = l - chroma / 2.0;
+        let channel = |c: f32| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Self { r: channel(r), g: channel(g), b: channel(b), a }
+    }
+
+    /// Returns colour `index` of the xterm 256 colour palette: the 16 basic colours, then a 6x6x6 colour cube,
+    /// then a 24 step grayscale ramp.
+    pub fn from_ansi_256(index: u8) -> Self {
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        match index {
+            0..=15 => ANSI_BASIC[index as usize],
+            16..=231 => {
+                let index = index - 16;
+                Self::new_rgb(LEVELS[(index / 36) as usize], LEVELS[(index / 6 % 6) as usize], LEVELS[(index % 6) as usize])
+            }
+            _ => {
+                let level = 8 + (index - 232) * 10;
+                Self::new_rgb(level, level, level)
+            }
+        }
+    }
+
+    /// Returns the index of the closest colour in the xterm 256 colour palette (ignoring alpha).
+    /// If several are just as close, the lowest index wins, so exact matches of the basic colours give back 0-15.
+    pub fn nearest_ansi_256(&self) -> u8 {
+        let distance = |other: TextColour| {
+            let (dr, dg, db) = (self.r as i32 - other.r as i32, self.g as i32 - other.g as i32, self.b as i32 - other.b as i32);
+            dr * dr + dg * dg + db * db
+        };
+        (0..=255u8).min_by_key(|index| distance(Self::from_ansi_256(*index))).unwrap_or(0)
+    }
+}
+
+/// The order the channels of every pixel are written in when glyph data is handed to `StoreSurface::from_raw_mask`.
+/// Set it with `TextRendererBuilder::pixel_order` to whatever your backend uses, so you don't have to swizzle anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PixelOrder {
+    #[default]
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+impl PixelOrder {
+    /// Returns the 4 bytes of a pixel in this order.
+    pub fn arrange(&self, r: u8, g: u8, b: u8, a: u8) -> [u8; 4] {
+        match self {
+            PixelOrder::Rgba => [r, g, b, a],
+            PixelOrder::Bgra => [b, g, r, a],
+            PixelOrder::Argb => [a, r, g, b],
+            PixelOrder::Abgr => [a, b, g, r],
+        }
+    }
+}
+
+/// Internal function to get the 4 bytes of a pixel in `pixel_order`, with the colour scaled by the alpha if `premultiplied`.
+pub(crate) fn pixel_bytes(pixel_order: PixelOrder, premultiplied: bool, r: u8, g: u8, b: u8, a: u8) -> [u8; 4] {
+    if premultiplied {
+        let scale = |channel: u8| ((channel as u16 * a as u16 + 127) / 255) as u8;
+        pixel_order.arrange(scale(r), scale(g), scale(b), a)
+    } else {
+        pixel_order.arrange(r, g, b, a)
+    }
+}
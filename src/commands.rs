@@ -0,0 +1,113 @@
+//! Splitting drawing into two halves, for text that gets drawn every frame but doesn't change.
+//! `TextRenderer::prepare_commands` does the slow half once (laying out the string and making sure every glyph is cached),
+//! and `TextRenderer::execute_commands` does the rest as often as you like: looking every glyph up by its cache key and pasting it.
+
+use crate::colours::TextColour;
+use crate::custom_glyphs::CUSTOM_FONT;
+use crate::metrics::DrawnBounds;
+use crate::options::{BlendMode, DrawOptions, Selection};
+use crate::{paste_visible, CacheKey, GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer, TextRendererError};
+use std::sync::PoisonError;
+
+/// A string that's been laid out and had all of its glyphs cached, returned by `TextRenderer::prepare_commands`.
+/// Positions are relative to the offset given to `TextRenderer::execute_commands`, so the same commands can be drawn anywhere.
+#[derive(Debug, Clone)]
+pub struct DrawCommands {
+    pub(crate) commands: Vec<DrawCommand>,
+    pub(crate) clip: Option<Rect>,
+    pub(crate) blend_mode: BlendMode,
+    /// Where the text ends up when it's drawn at an offset of (0, 0).
+    pub(crate) bounds: DrawnBounds,
+}
+
+impl DrawCommands {
+    /// How many glyphs get pasted. Whitespace doesn't paste anything so it isn't counted.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Whether there's nothing to paste at all.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
+
+/// Internal struct, one glyph to paste: where to find it in the glyph cache and where it goes.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DrawCommand {
+    key: CacheKey,
+    colour: TextColour,
+    glyph_index: u16,
+    /// Top left of the glyph in layout coordinates (see `TextRenderer::layout_y`), before the offset is added.
+    x: f32,
+    y: f32,
+    width: usize,
+    height: usize,
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Lays out a string the same way `draw_string_with_options` would and makes sure every glyph of it is in the glyph cache,
+    /// so it can be drawn over and over with `execute_commands` without doing either of those again.
+    /// Selections and underlines from the options aren't drawn (apart from the selection's foreground colour), and neither are
+    /// dirty rects recorded when the commands are executed. The commands always paste cached RGBA glyphs, even with
+    /// `GlyphCacheFormat::Alpha`, so `options.opacity` is baked into the colour they're cached in.
+    pub fn prepare_commands(&mut self, string: &str, size: f32, colour: TextColour, options: &DrawOptions) -> DrawCommands {
+        let text = self.layout_text(string, size, options);
+        let (dx, dy) = text.origin_offset(options);
+        let mut commands = Vec::new();
+        for glyph in text.glyphs.iter() {
+            if options.opacity <= 0.0 || glyph.width == 0 || glyph.height == 0 || glyph.font_index == CUSTOM_FONT
+                || self.check_size(glyph.key.px).is_err() {
+                continue;
+            }
+            let colour = match &options.selection {
+                Some(Selection { range, foreground: Some(foreground), .. }) if range.contains(&glyph.byte_offset) => *foreground,
+                _ => colour,
+            }.with_opacity(options.opacity);
+            let effects = GlyphEffects::from_options(options);
+            let padding = effects.padding();
+            self.get_glyph_surface(glyph.font_index, glyph.key, glyph.width, glyph.height, colour, effects, 0);
+            commands.push(DrawCommand {
+                key: self.surface_cache_key(glyph.font_index, glyph.key.px, effects),
+                colour,
+                glyph_index: glyph.key.glyph_index,
+                x: dx + glyph.x - padding as f32,
+                y: dy + glyph.y - padding as f32,
+                width: glyph.width + padding * 2,
+                height: glyph.height + padding * 2,
+            });
+        }
+        DrawCommands {
+            commands,
+            clip: options.clip,
+            blend_mode: options.blend_mode,
+            bounds: text.drawn_bounds(dx, self.layout_y(dy)),
+        }
+    }
+
+    /// Pastes prepared commands with the top left of the text at `offset` (the same as the `x` and `y` given to a draw call),
+    /// and returns where the text ended up. This only looks glyphs up in the cache and pastes them, so it doesn't need `&mut self`.
+    /// If a glyph the commands need isn't in the cache anymore (because it was cleared with `clear_glyph_cache`, or `set_gamma`
+    /// changed which glyphs get used), it's skipped and `TextRendererError::StaleCommands` is returned once everything else
+    /// has been pasted. Prepare the commands again to get them back in working order.
+    pub fn execute_commands(&self, commands: &DrawCommands, offset: (f32, f32), surface: &mut A) -> Result<DrawnBounds, TextRendererError> {
+        let (x, y) = (offset.0, self.layout_y(offset.1));
+        let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+        let mut stale = false;
+        for command in commands.commands.iter() {
+            let dst = self.surface_rect(Rect::new((x + command.x).floor() as i32, (y + command.y).floor() as i32, command.width, command.height));
+            let Some(visible) = self.visible_rect(surface, dst, commands.clip) else { continue };
+            let cached = glyph_caches.get(&command.key)
+                .and_then(|glyph_cache| glyph_cache.surface_map.get(&command.colour))
+                .and_then(|colour_map| colour_map.get(&command.glyph_index));
+            match cached {
+                Some(cached) => paste_visible(surface, dst, visible, &cached.surface, commands.blend_mode),
+                None => stale = true,
+            }
+        }
+        if stale {
+            return Err(TextRendererError::StaleCommands);
+        }
+        Ok(DrawnBounds { x: commands.bounds.x + offset.0, y: commands.bounds.y + offset.1, ..commands.bounds })
+    }
+}
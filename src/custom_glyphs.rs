@@ -0,0 +1,151 @@
+//! Glyphs that don't come from a font, like custom emotes and icons, see `TextRenderer::set_glyph_provider`.
+//! They're laid out like any other character (using the advance they were given) and drawn as-is, without being tinted
+//! by the colour of the text.
+
+use crate::colours::pixel_bytes;
+use crate::layout::Glyph;
+use crate::options::{BlendMode, CoordinateSystem};
+use crate::{paste_visible, PasteSurface, Rect, StoreSurface, TextColour, TextRenderer};
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// What custom glyphs are stored under in the `font_index` of a laid out glyph, so nothing mistakes them for a real font's.
+pub(crate) const CUSTOM_FONT: usize = usize::MAX;
+
+/// The function set with `TextRenderer::set_glyph_provider`.
+pub type GlyphProviderFn = dyn Fn(char, f32) -> Option<CustomGlyph> + Send + Sync;
+
+/// An image to draw in place of a character, returned by the function given to `TextRenderer::set_glyph_provider`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomGlyph {
+    pub width: usize,
+    pub height: usize,
+    /// RGBA bytes, 4 per pixel, top row first. They're put in the renderer's pixel order before being handed to
+    /// `StoreSurface::from_raw_mask`, so this is always RGBA.
+    pub data: Vec<u8>,
+    /// How far the pen moves after the glyph.
+    pub advance: f32,
+    /// How far above the baseline the top of the image goes. `CustomGlyph::new` sets this to `height`,
+    /// so the image sits on the baseline like a letter would.
+    pub top: f32,
+    /// If `true`, this is used even when the font does have a glyph for the character.
+    /// Otherwise it's only used for characters the font doesn't have.
+    pub replaces_font: bool,
+}
+
+impl CustomGlyph {
+    /// An image sitting on the baseline, with an advance as wide as the image.
+    /// Only used for characters that the font doesn't have.
+    pub fn new(width: usize, height: usize, data: Vec<u8>) -> Self {
+        Self { width, height, data, advance: width as f32, top: height as f32, replaces_font: false }
+    }
+}
+
+/// Internal struct, a custom glyph after it's been turned into something that can be pasted.
+pub(crate) struct ProvidedGlyph<G> {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) advance: f32,
+    pub(crate) top: f32,
+    pub(crate) replaces_font: bool,
+    /// The bytes `surface` was made from (in the renderer's pixel order and upside down for `CoordinateSystem::PositiveYUp`),
+    /// for drawing it with an opacity.
+    pub(crate) data: Vec<u8>,
+    pub(crate) surface: G,
+}
+
+/// Internal type, every answer the glyph provider has given, keyed by character, `f32::to_bits` of the size and whether
+/// the glyph is stored upside down. `None` means the provider didn't have anything for that character.
+/// Shared between clones of a renderer like the glyph cache is.
+pub(crate) type CustomGlyphCache<G> = Arc<RwLock<HashMap<(char, u32, bool), Option<Arc<ProvidedGlyph<G>>>>>>;
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Sets a function that can draw characters the font can't, like custom emotes in the private use area.
+    /// It's given the character and the size of the text, and returns an image to draw in its place, or `None` to leave
+    /// the character to the font. Whatever it returns is cached (along with every `None`), so it's only asked about
+    /// each character once per size and can take its time. Setting a new provider clears that cache.
+    /// Custom glyphs aren't used for `Direction::Vertical` text, `draw_string_monospaced` or `draw_char`,
+    /// and they take up space but aren't drawn by `draw_string_on_path`, `prepare_commands` or the outline functions.
+    pub fn set_glyph_provider(&mut self, provider: impl Fn(char, f32) -> Option<CustomGlyph> + Send + Sync + 'static) {
+        self.glyph_provider = Some(Arc::new(provider));
+        self.custom_glyphs.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Removes the function set with `set_glyph_provider`.
+    pub fn clear_glyph_provider(&mut self) {
+        self.glyph_provider = None;
+        self.custom_glyphs.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Internal function to get the custom glyph that replaces `c` at `size` in one of the fonts, if there is one,
+    /// asking the glyph provider if it hasn't been asked about it yet.
+    pub(crate) fn provided_glyph(&self, c: char, size: f32, font_index: usize) -> Option<Arc<ProvidedGlyph<G>>> {
+        let provider = self.glyph_provider.as_ref()?;
+        if c.is_control() {
+            return None;
+        }
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = (c, size.to_bits(), flipped);
+        let cached = self.custom_glyphs.read().unwrap_or_else(PoisonError::into_inner).get(&key).cloned();
+        let provided = match cached {
+            Some(provided) => provided,
+            None => {
+                let provided = provider(c, size).filter(|custom| custom.data.len() >= custom.width * custom.height * 4).map(|custom| {
+                    let mut rows = custom.data.chunks_exact(custom.width.max(1) * 4).take(custom.height).collect::<Vec<_>>();
+                    if flipped {
+                        rows.reverse();
+                    }
+                    let data = rows.into_iter().flat_map(|row| row.chunks_exact(4))
+                        .flat_map(|pixel| pixel_bytes(self.pixel_order, self.premultiplied_alpha, pixel[0], pixel[1], pixel[2], pixel[3]))
+                        .collect::<Vec<u8>>();
+                    let surface = G::from_raw_mask(custom.width, custom.height, &data, TextColour::new(255, 255, 255, 255));
+                    Arc::new(ProvidedGlyph {
+                        width: custom.width,
+                        height: custom.height,
+                        advance: custom.advance,
+                        top: custom.top,
+                        replaces_font: custom.replaces_font,
+                        data,
+                        surface,
+                    })
+                });
+                self.custom_glyphs.write().unwrap_or_else(PoisonError::into_inner).insert(key, provided.clone());
+                provided
+            }
+        };
+        provided.filter(|provided| provided.replaces_font || self.font_at(font_index).lookup_glyph_index(c) == 0)
+    }
+
+    /// Internal function to get the custom glyph that a laid out glyph was replaced with, if it was.
+    pub(crate) fn custom_glyph_at(&self, glyph: &Glyph) -> Option<Arc<ProvidedGlyph<G>>> {
+        if glyph.font_index != CUSTOM_FONT {
+            return None;
+        }
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let custom_glyphs = self.custom_glyphs.read().unwrap_or_else(PoisonError::into_inner);
+        custom_glyphs.get(&(glyph.parent, glyph.key.px.to_bits(), flipped)).cloned().flatten()
+    }
+
+    /// Internal function to paste the `visible` part of a custom glyph that goes at `dst`, see `draw_glyphs`.
+    /// Anything see-through gets a faded copy made for it, as the colour of the text isn't in the custom glyph's cache key.
+    pub(crate) fn draw_custom_glyph(&mut self, glyph: &Glyph, dst: Rect, visible: Rect, blend_mode: BlendMode, opacity: f32, surface: &mut A) {
+        let Some(provided) = self.custom_glyph_at(glyph) else { return };
+        if opacity < 1.0 {
+            let alpha = self.pixel_order.arrange(0, 0, 0, 255).iter().position(|byte| *byte == 255).unwrap_or(3);
+            let mut data = provided.data.clone();
+            for pixel in data.chunks_exact_mut(4) {
+                // premultiplied colours have to fade along with the alpha
+                for (i, channel) in pixel.iter_mut().enumerate() {
+                    if i == alpha || self.premultiplied_alpha {
+                        *channel = (*channel as f32 * opacity).round() as u8;
+                    }
+                }
+            }
+            let faded = G::from_raw_mask(provided.width, provided.height, &data, TextColour::new(255, 255, 255, 255));
+            paste_visible(surface, dst, visible, &faded, blend_mode);
+        } else {
+            paste_visible(surface, dst, visible, &provided.surface, blend_mode);
+        }
+        self.mark_dirty(Some(visible));
+    }
+}
@@ -0,0 +1,139 @@
+//! Underlines (see `DrawOptions::underlines`) and whitespace markers (see `DrawOptions::show_whitespace`).
+//! Every underline style is a small tile (one dot, one dash, one period of the wave) that gets pasted over and over
+//! across the underlined part of each line, so long underlines don't need a surface as wide as the text.
+
+use crate::colours::{pixel_bytes, TextColour};
+use crate::layout::{Glyph, LaidOutText};
+use crate::options::{BlendMode, CoordinateSystem, DrawOptions, Underline, UnderlineStyle};
+use crate::{paste_visible, GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer};
+use std::f32::consts::TAU;
+
+/// One repeat of an underline's pattern, as coverage from 0.0 to 1.0 for every pixel, top row first.
+struct Tile {
+    width: usize,
+    height: usize,
+    coverage: Vec<f32>,
+}
+
+impl Tile {
+    /// Makes a tile by asking `coverage` about every (x, y) pixel in it.
+    fn new(width: usize, height: usize, coverage: impl Fn(usize, usize) -> f32) -> Self {
+        let coverage = (0..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| coverage(x, y)).collect();
+        Tile { width, height, coverage }
+    }
+}
+
+/// Internal function to build the tile for `style` at `size` pixels, with lines `thickness` pixels thick.
+fn underline_tile(style: UnderlineStyle, size: f32, thickness: usize) -> Tile {
+    let solid = |on: bool| if on { 1.0 } else { 0.0 };
+    match style {
+        UnderlineStyle::Solid => Tile::new(thickness * 8, thickness, |_, _| 1.0),
+        UnderlineStyle::Dotted => Tile::new(thickness * 2, thickness, |x, _| solid(x < thickness)),
+        UnderlineStyle::Dashed => Tile::new(thickness * 6, thickness, |x, _| solid(x < thickness * 4)),
+        UnderlineStyle::Double => Tile::new(thickness * 8, thickness * 3, |_, y| solid(y < thickness || y >= thickness * 2)),
+        UnderlineStyle::Wavy => {
+            let amplitude = (size / 14.0).max(1.0);
+            let period = (amplitude * 4.0).round().max(4.0);
+            let half = thickness as f32 / 2.0;
+            let height = (amplitude * 2.0 + thickness as f32).ceil() as usize;
+            Tile::new(period as usize, height, |x, y| {
+                let phase = TAU * (x as f32 + 0.5) / period;
+                let centre = amplitude * (1.0 - phase.sin()) + half;
+                // distance to the curve rather than straight up and down, so the steep parts aren't thinner
+                let slope = amplitude * TAU / period * phase.cos();
+                let distance = (y as f32 + 0.5 - centre).abs() / (1.0 + slope * slope).sqrt();
+                (half + 0.5 - distance).clamp(0.0, 1.0)
+            })
+        }
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Internal function to draw underlines for laid out text with its top left at (`x`, `y`) (in layout coordinates,
+    /// see `layout_y`). Each underline is only as long as the glyphs in its range on each line.
+    /// `clip` and `opacity` from the options apply to them the same as they do to the glyphs.
+    pub(crate) fn draw_underlines(&mut self, text: &LaidOutText, underlines: &[Underline], x: f32, y: f32, options: &DrawOptions, surface: &mut A) {
+        if options.opacity <= 0.0 {
+            return;
+        }
+        for underline in underlines {
+            for (left, right, size, line) in self.range_extents(text, &underline.range) {
+                let metrics = options.decoration_metrics.unwrap_or_else(|| self.decoration_metrics(size));
+                let (thickness, offset) = (metrics.underline_thickness.round().max(1.0) as usize, -metrics.underline_offset.round());
+                let tile = underline_tile(underline.style, size, thickness);
+                let bitmap = self.tile_surface(&tile, underline.colour.with_opacity(options.opacity));
+                let (left, right) = ((x + left).floor() as i32, (x + right).floor() as i32);
+                let top = (y + line.baseline + offset).floor() as i32;
+                // the last tile gets cut off at the end of the range, on top of any clip from the options
+                let extent = self.surface_rect(Rect::new(left, top, (right - left).max(0) as usize, tile.height));
+                let extent = match options.clip {
+                    Some(clip) => extent.intersection(&clip),
+                    None => Some(extent),
+                };
+                let Some(extent) = extent else { continue };
+                for tile_x in (left..right).step_by(tile.width.max(1)) {
+                    let dst = self.surface_rect(Rect::new(tile_x, top, tile.width, tile.height));
+                    if let Some(visible) = self.visible_rect(surface, dst, Some(extent)) {
+                        paste_visible(surface, dst, visible, &bitmap, BlendMode::AlphaOver);
+                        self.mark_dirty(Some(visible));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Internal function to draw the markers of `DrawOptions::show_whitespace` (if it's set) over laid out text
+    /// with its top left at (`x`, `y`) (in layout coordinates, see `layout_y`). `colour_fn` is the colour of the text,
+    /// for markers that don't have their own.
+    pub(crate) fn draw_whitespace_markers(
+        &mut self,
+        text: &LaidOutText,
+        x: f32,
+        y: f32,
+        options: &DrawOptions,
+        surface: &mut A,
+        mut colour_fn: impl FnMut(&Glyph) -> TextColour
+    ) {
+        let Some(show) = options.show_whitespace else { return };
+        if text.vertical {
+            return;
+        }
+        let (mut markers, mut colours) = (Vec::new(), Vec::new());
+        let mut start = 0;
+        for line in text.metrics.lines.iter() {
+            let end = (start + line.glyph_count).min(text.glyphs.len());
+            for glyph in text.glyphs[start..end].iter() {
+                let (marker, fallback) = match glyph.parent {
+                    '\n' => (show.newline, '$'),
+                    '\t' => (show.tab, '>'),
+                    c if c.is_whitespace() => (show.space, '.'),
+                    _ => continue,
+                };
+                let font = self.font_at(glyph.font_index);
+                let marker = if font.lookup_glyph_index(marker) != 0 { marker } else { fallback };
+                let size = glyph.key.px;
+                // centered over the whitespace, so the dot of a space sits between the words either side of it
+                let (advance, marker_advance) = (self.glyph_advance(glyph), font.metrics(marker, size).advance_width.ceil());
+                let pen = self.pen_x(glyph) + if advance > 0.0 { ((advance - marker_advance) / 2.0).floor() } else { 0.0 };
+                let mut placed = self.glyph_on_baseline(glyph.font_index, marker, size, pen, line.baseline);
+                placed.user_data.run = colours.len();
+                markers.push(placed);
+                colours.push(show.colour.unwrap_or_else(|| colour_fn(glyph).with_opacity(0.4)));
+            }
+            start = end;
+        }
+        self.draw_glyphs(&markers, x, y, options.clip, options.blend_mode, options.opacity, GlyphEffects::default(), surface, |marker| colours[marker.user_data.run]);
+    }
+
+    /// Internal function to turn a tile into a surface in the given colour, upside down for `CoordinateSystem::PositiveYUp`.
+    fn tile_surface(&self, tile: &Tile, colour: TextColour) -> G {
+        let mut rows = tile.coverage.chunks_exact(tile.width.max(1)).collect::<Vec<_>>();
+        if self.coordinate_system == CoordinateSystem::PositiveYUp {
+            rows.reverse();
+        }
+        let data = rows.into_iter().flatten().flat_map(|coverage| {
+            pixel_bytes(self.pixel_order, self.premultiplied_alpha, colour.r, colour.g, colour.b, (coverage * colour.a as f32).round() as u8)
+        }).collect::<Vec<_>>();
+        G::from_raw_mask(tile.width, tile.height, &data, colour)
+    }
+}
@@ -0,0 +1,100 @@
+//! embedded-graphics displays as a backend, enabled with the `embedded-graphics` feature.
+//! `DrawTargetSurface` wraps any `DrawTarget` (an SSD1306, an ST7789, a simulator...) and glyphs are kept as plain
+//! `RgbaGlyph`s, so `TextRenderer<RgbaGlyph, DrawTargetSurface<Display>>` draws TrueType text on the display.
+//! Displays can't be read back, so glyphs are blended over a fixed background colour and then converted into the
+//! display's colour type (monochrome displays turn on every pixel that comes out at least half bright).
+//! Glyph pixels are expected in the default `PixelOrder::Rgba`.
+
+use crate::blit;
+use crate::colours::TextColour;
+use crate::options::BlendMode;
+use crate::{PasteSurface, StoreSurface};
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::Point;
+use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+use embedded_graphics_core::Pixel;
+use log::debug;
+
+/// A glyph as plain RGBA pixels, for backends that don't have a surface type of their own to keep glyphs in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RgbaGlyph {
+    pub width: usize,
+    pub height: usize,
+    /// 4 bytes per pixel, in the renderer's pixel order.
+    pub data: Vec<u8>,
+}
+
+impl StoreSurface for RgbaGlyph {
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        RgbaGlyph { width, height, data: data.to_vec() }
+    }
+}
+
+/// Draws glyphs onto an embedded-graphics `DrawTarget`.
+pub struct DrawTargetSurface<D> {
+    target: D,
+    /// What the glyphs are blended over, which should be whatever colour the text is being drawn on.
+    pub background: Rgb888,
+}
+
+impl<D: DrawTarget> DrawTargetSurface<D> where D::Color: From<Rgb888> {
+    /// Wraps `target`, blending glyphs over `background` (use `Rgb888::BLACK` for a display that's off by default).
+    pub fn new(target: D, background: Rgb888) -> Self {
+        Self { target, background }
+    }
+
+    pub fn target(&self) -> &D {
+        &self.target
+    }
+
+    pub fn target_mut(&mut self) -> &mut D {
+        &mut self.target
+    }
+
+    /// Gives the display back, to flush it or whatever else the driver needs.
+    pub fn into_inner(self) -> D {
+        self.target
+    }
+}
+
+impl<D: DrawTarget> PasteSurface<RgbaGlyph> for DrawTargetSurface<D> where D::Color: From<Rgb888> {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &RgbaGlyph) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &RgbaGlyph) {
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn width(&self) -> Option<usize> {
+        let bounds = self.target.bounding_box();
+        Some((bounds.top_left.x + bounds.size.width as i32).max(0) as usize)
+    }
+
+    fn height(&self) -> Option<usize> {
+        let bounds = self.target.bounding_box();
+        Some((bounds.top_left.y + bounds.size.height as i32).max(0) as usize)
+    }
+
+    /// Blends the glyph over the background with `sext::blit` (so every mode works like it does everywhere else)
+    /// and draws the result. Pixels the glyph doesn't touch at all are left alone, unless the mode is `Replace`.
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &RgbaGlyph, mode: BlendMode) {
+        let background = [self.background.r(), self.background.g(), self.background.b(), 255];
+        let mut blended = background.repeat(width * height);
+        blit::paste_rgba(&mut blended, width, height, 0, 0, src_x, src_y, width, height, &data.data, data.width, mode);
+        let inked = |x: usize, y: usize| {
+            let alpha = data.data.get(((src_y + y) * data.width + src_x + x) * 4 + 3);
+            mode == BlendMode::Replace || (src_x + x < data.width && alpha.is_some_and(|alpha| *alpha > 0))
+        };
+        let pixels = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|&(x, y)| inked(x, y))
+            .map(|(x, y)| {
+                let pixel = &blended[(y * width + x) * 4..];
+                let colour = D::Color::from(Rgb888::new(pixel[0], pixel[1], pixel[2]));
+                Pixel(Point::new((dst_x + x) as i32, (dst_y + y) as i32), colour)
+            });
+        if self.target.draw_iter(pixels).is_err() {
+            debug!("couldn't draw glyph on the display");
+        }
+    }
+}
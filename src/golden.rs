@@ -0,0 +1,210 @@
+//! Golden image tests: a handful of scenarios get drawn onto a reference surface and compared against the images
+//! checked in to `goldens/`, so changes to how text comes out show up as failing tests instead of as a PPM someone
+//! has to remember to look at.
+//! Run the tests with `SEXT_UPDATE_GOLDENS=1` set to (re)write the goldens from whatever gets drawn now,
+//! then look over the new images before committing them. When a comparison fails, what got drawn is written
+//! next to the golden as `<name>.actual.ppm`.
+
+use crate::colours::TextColour;
+use crate::options::{BlendMode, DrawOptions, FillMode};
+use crate::spans::TextSpan;
+use crate::{PasteSurface, Rect, StoreSurface, TextRenderer};
+use std::path::PathBuf;
+
+/// Every channel of every pixel can be this far off from the golden before it counts as different,
+/// so tiny rounding differences between platforms don't fail the tests.
+const TOLERANCE: u8 = 2;
+
+/// Everything gets drawn over this, so the goldens can be plain RGB without losing how the edges of glyphs blended.
+const BACKGROUND: [u8; 4] = [24, 24, 32, 255];
+
+/// Internal struct, a plain RGBA buffer with correct blending for every `BlendMode`, used both for the surface
+/// the scenarios draw to and for the glyphs in the cache.
+#[derive(Debug, Clone)]
+struct ReferenceSurface {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl ReferenceSurface {
+    /// A surface filled with `BACKGROUND`.
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, height, data: BACKGROUND.repeat(width * height) }
+    }
+
+    /// The surface as a binary PPM. The background is opaque, so dropping the alpha doesn't lose anything.
+    fn to_ppm(&self) -> Vec<u8> {
+        let mut ppm = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        ppm.extend(self.data.chunks_exact(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]));
+        ppm
+    }
+}
+
+impl PasteSurface<Self> for ReferenceSurface {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Self) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, x: usize, y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Self) {
+        self.paste_blended(x, y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_blended(&mut self, x: usize, y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Self, mode: BlendMode) {
+        if mode == BlendMode::AlphaOver {
+            let clip = Rect::new(x as i32, y as i32, width, height);
+            crate::blit::blend_rgba_over(
+                &mut self.data, self.width, self.height, x as i32 - src_x as i32, y as i32 - src_y as i32,
+                &data.data, data.width, data.height, Some(clip),
+            );
+            return;
+        }
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let (pitch, data_pitch) = (self.width * 4, data.width * 4);
+        crate::blit::blend(
+            mode,
+            &mut self.data[(y * pitch) + (x * 4)..],
+            pitch,
+            data.data.get((src_y * data_pitch) + (src_x * 4)..).unwrap_or(&[]),
+            data_pitch,
+            width.min(self.width - x),
+            height.min(self.height - y),
+        );
+    }
+}
+
+impl StoreSurface for ReferenceSurface {
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        Self { width, height, data: data.to_vec() }
+    }
+}
+
+/// Internal function to read the width, height and RGB bytes out of a binary PPM written by `ReferenceSurface::to_ppm`.
+fn read_ppm(ppm: &[u8]) -> Option<(usize, usize, &[u8])> {
+    // the header is 4 whitespace separated fields (magic, width, height, max value) and then a single whitespace byte
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for (i, byte) in ppm.iter().enumerate() {
+        if !byte.is_ascii_whitespace() {
+            continue;
+        }
+        if i > start {
+            fields.push(std::str::from_utf8(&ppm[start..i]).ok()?);
+        }
+        start = i + 1;
+        if fields.len() == 4 {
+            break;
+        }
+    }
+    if fields.len() != 4 || fields[0] != "P6" || fields[3] != "255" {
+        return None;
+    }
+    let (width, height) = (fields[1].parse().ok()?, fields[2].parse().ok()?);
+    let pixels = ppm.get(start..start + width * height * 3)?;
+    Some((width, height, pixels))
+}
+
+/// Internal function to get where the golden called `name` lives.
+fn golden_path(name: &str, suffix: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("goldens").join(format!("{}{}.ppm", name, suffix))
+}
+
+/// Compares what got drawn to the golden called `name`, panicking if they're different sizes or if any channel of any
+/// pixel is more than `TOLERANCE` off. With `SEXT_UPDATE_GOLDENS` set, the golden gets overwritten instead.
+fn check_golden(name: &str, surface: &ReferenceSurface) {
+    let actual = surface.to_ppm();
+    let path = golden_path(name, "");
+    if std::env::var_os("SEXT_UPDATE_GOLDENS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let golden = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("couldn't read {} ({}), run with SEXT_UPDATE_GOLDENS=1 to make it", path.display(), e));
+    let (width, height, expected) = read_ppm(&golden).unwrap_or_else(|| panic!("{} isn't a binary PPM", path.display()));
+    let (_, _, drawn) = read_ppm(&actual).unwrap();
+    let different = if (width, height) != (surface.width, surface.height) {
+        Err(format!("golden is {}x{} but {}x{} was drawn", width, height, surface.width, surface.height))
+    } else {
+        let pixels = expected.chunks_exact(3).zip(drawn.chunks_exact(3))
+            .filter(|(expected, drawn)| expected.iter().zip(drawn.iter()).any(|(e, d)| e.abs_diff(*d) > TOLERANCE))
+            .count();
+        if pixels > 0 { Err(format!("{} pixels are different", pixels)) } else { Ok(()) }
+    };
+    if let Err(e) = different {
+        let actual_path = golden_path(name, ".actual");
+        std::fs::write(&actual_path, &actual).unwrap();
+        panic!("{} doesn't match its golden: {} (what was drawn is in {})", name, e, actual_path.display());
+    }
+}
+
+/// Internal function to get a renderer that draws to `ReferenceSurface`s.
+fn renderer() -> TextRenderer<ReferenceSurface, ReferenceSurface> {
+    TextRenderer::load("FreeMono.ttf").unwrap()
+}
+
+const WHITE: TextColour = TextColour { r: 255, g: 255, b: 255, a: 255 };
+
+#[test]
+fn golden_basic_string() {
+    let mut surface = ReferenceSurface::new(256, 40);
+    renderer().draw_string("hElLo w0r1d! hai!!", 4.0, 4.0, 24.0, WHITE, &mut surface);
+    check_golden("basic_string", &surface);
+}
+
+#[test]
+fn golden_monospaced() {
+    let mut surface = ReferenceSurface::new(256, 40);
+    renderer().draw_string_monospaced("il1| WM@# e\u{301}", 4.0, 4.0, 24.0, WHITE, &mut surface);
+    check_golden("monospaced", &surface);
+}
+
+#[test]
+fn golden_multi_colour() {
+    let mut surface = ReferenceSurface::new(256, 40);
+    let spans = [
+        TextSpan::new("red ").with_colour(TextColour::new_rgb(255, 64, 64)),
+        TextSpan::new("see-through ").with_colour(TextColour::new(64, 255, 64, 128)),
+        TextSpan::new("blue").with_colour(TextColour::new_rgb(64, 128, 255)),
+    ];
+    renderer().draw_spans(&spans, 4.0, 4.0, 20.0, WHITE, &DrawOptions::default(), &mut surface);
+    check_golden("multi_colour", &surface);
+}
+
+#[test]
+fn golden_clipped_edges() {
+    let mut surface = ReferenceSurface::new(96, 96);
+    let mut renderer = renderer();
+    // one string hanging off of each edge
+    renderer.draw_string("left", -20.0, 36.0, 20.0, WHITE, &mut surface);
+    renderer.draw_string("right", 60.0, 36.0, 20.0, WHITE, &mut surface);
+    renderer.draw_string("top", 30.0, -10.0, 20.0, WHITE, &mut surface);
+    renderer.draw_string("bottom", 20.0, 84.0, 20.0, WHITE, &mut surface);
+    check_golden("clipped_edges", &surface);
+}
+
+#[test]
+fn golden_empty_string() {
+    let mut surface = ReferenceSurface::new(32, 32);
+    renderer().draw_string("", 4.0, 4.0, 24.0, WHITE, &mut surface);
+    check_golden("empty_string", &surface);
+}
+
+#[test]
+fn golden_huge_size() {
+    let mut surface = ReferenceSurface::new(192, 256);
+    renderer().draw_string("g", -20.0, -60.0, 320.0, WHITE, &mut surface);
+    check_golden("huge_size", &surface);
+}
+
+#[test]
+fn golden_hollow() {
+    // FreeMono's strokes are too thin at 48px to have much inside to hollow out
+    let mut renderer: TextRenderer<ReferenceSurface, ReferenceSurface> = TextRenderer::load("DejaVuSansMono-Bold.ttf").unwrap();
+    let mut surface = ReferenceSurface::new(256, 64);
+    let options = DrawOptions { fill_mode: FillMode::OutlineRelative { thickness: 0.04 }, ..Default::default() };
+    renderer.draw_string_with_options("Hollow", 4.0, 4.0, 48.0, WHITE, &options, &mut surface);
+    check_golden("hollow", &surface);
+}
@@ -0,0 +1,189 @@
+//! A ready-made wgpu backend, enabled with the `wgpu` feature.
+//! Rather than pasting glyphs onto a surface, text is drawn on the GPU straight out of a `GlyphAtlas` kept in a texture:
+//! `WgpuAtlas` keeps the texture up to date, `TextRenderer::glyph_instances` lays text out into one `GlyphInstance`
+//! per glyph, and `TEXT_SHADER` draws them. The shader's bind group 0 has the `TextUniforms` at binding 0,
+//! `WgpuAtlas::view` at binding 1 and a sampler at binding 2 (a linear one is fine, the glyphs are kept apart).
+//! Every instance is a triangle strip of 4 vertices, so a whole string is one `draw(0..4, 0..instances.len())`,
+//! with `GlyphInstance::layout` as the only vertex buffer and the usual alpha blending.
+
+use crate::atlas::GlyphAtlas;
+use crate::colours::TextColour;
+use crate::custom_glyphs::CUSTOM_FONT;
+use crate::options::{CoordinateSystem, DrawOptions};
+use crate::{PasteSurface, Rect, StoreSurface, TextRenderer};
+use bytemuck::{Pod, Zeroable};
+use log::debug;
+
+/// WGSL for drawing `GlyphInstance`s, with `vs_main` and `fs_main` as the entry points. See the module docs for the bindings.
+pub const TEXT_SHADER: &str = include_str!("text.wgsl");
+
+/// A single glyph to draw, as instance data for `TEXT_SHADER`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct GlyphInstance {
+    /// Where the corner of the glyph that's first in the atlas goes on the screen, in pixels.
+    /// That's the top left, or the bottom left for `CoordinateSystem::PositiveYUp`.
+    pub position: [f32; 2],
+    /// How big the glyph is, in pixels.
+    pub size: [f32; 2],
+    /// Where the glyph is in the atlas, see `AtlasGlyph::uv`.
+    pub uv: [f32; 4],
+    /// The colour of the glyph, from 0 to 1 and not premultiplied.
+    pub colour: [f32; 4],
+}
+
+impl GlyphInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Float32x4];
+
+    /// The layout of the instance buffer for a render pipeline using `TEXT_SHADER`.
+    pub fn layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<GlyphInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// The uniform buffer for `TEXT_SHADER`, see `TextRenderer::text_uniforms`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct TextUniforms {
+    /// How big the surface being drawn to is, in pixels.
+    pub screen_size: [f32; 2],
+    /// 1 if y goes up the screen (`CoordinateSystem::PositiveYUp`), 0 if it goes down.
+    pub y_up: u32,
+    pub _padding: u32,
+}
+
+/// A `GlyphAtlas` along with a texture holding a copy of it (in `wgpu::TextureFormat::R8Unorm`).
+/// Add glyphs to `atlas_mut` (or with `TextRenderer::glyph_instances`), then call `upload` before drawing.
+pub struct WgpuAtlas {
+    atlas: GlyphAtlas,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+impl WgpuAtlas {
+    /// Creates an empty atlas (and texture) `width` by `height` pixels big, see `GlyphAtlas::new`.
+    pub fn new(device: &wgpu::Device, width: usize, height: usize) -> Self {
+        let atlas = GlyphAtlas::new(width, height);
+        let (texture, view) = Self::create_texture(device, &atlas);
+        Self { atlas, texture, view }
+    }
+
+    /// Internal function to make a texture the size of `atlas`.
+    fn create_texture(device: &wgpu::Device, atlas: &GlyphAtlas) -> (wgpu::Texture, wgpu::TextureView) {
+        let (width, height) = atlas.size();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("sext glyph atlas"),
+            size: wgpu::Extent3d { width: width as u32, height: height as u32, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.atlas
+    }
+
+    pub fn atlas_mut(&mut self) -> &mut GlyphAtlas {
+        &mut self.atlas
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Copies whatever's changed in the atlas since the last upload into the texture.
+    /// If the atlas has grown, the texture is made again at the new size and this returns `true`,
+    /// so any bind groups with the old `view` in them have to be made again too.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let (width, height) = self.atlas.size();
+        let grown = self.texture.width() as usize != width || self.texture.height() as usize != height;
+        if grown {
+            (self.texture, self.view) = Self::create_texture(device, &self.atlas);
+        }
+        let dirty = match (self.atlas.take_dirty_rect(), grown) {
+            (_, true) => Rect::new(0, 0, width, height),
+            (Some(dirty), false) => dirty,
+            (None, false) => return false,
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: dirty.x as u32, y: dirty.y as u32, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.atlas.data()[dirty.y as usize * width + dirty.x as usize..],
+            wgpu::TexelCopyBufferLayout { offset: 0, bytes_per_row: Some(width as u32), rows_per_image: None },
+            wgpu::Extent3d { width: dirty.width as u32, height: dirty.height as u32, depth_or_array_layers: 1 },
+        );
+        grown
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Lays out a string the same way `draw_string_with_options` would and returns an instance for every glyph in it,
+    /// adding any that aren't in `atlas` yet. Positions are in the same coordinates as every other draw call.
+    /// Only the layout options (and `options.opacity`) are used, anything that pastes onto a surface
+    /// (selections, underlines, clip rects, blend modes) is left for you to do on the GPU.
+    /// Glyphs that don't fit in the atlas and custom glyphs (see `set_glyph_provider`) are left out.
+    #[allow(clippy::too_many_arguments)]
+    pub fn glyph_instances(
+        &mut self,
+        atlas: &mut GlyphAtlas,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions
+    ) -> Vec<GlyphInstance> {
+        let text = self.layout_text(string, size, options);
+        let (dx, dy) = text.origin_offset(options);
+        let (x, y) = (x + dx, self.layout_y(y) + dy);
+        let colour = [colour.r, colour.g, colour.b, colour.a].map(|channel| channel as f32 / 255.0);
+        let colour = [colour[0], colour[1], colour[2], colour[3] * options.opacity.clamp(0.0, 1.0)];
+        let mut placed = Vec::with_capacity(text.glyphs.len());
+        for glyph in &text.glyphs {
+            if glyph.width == 0 || glyph.height == 0 || glyph.font_index == CUSTOM_FONT || self.check_size(glyph.key.px).is_err() {
+                continue;
+            }
+            let metrics = self.glyph_metrics_indexed(glyph.font_index, glyph.key.glyph_index, glyph.key.px);
+            let Some(in_atlas) = self.atlas_glyph_indexed(atlas, glyph.font_index, glyph.key.glyph_index, glyph.key.px, metrics) else {
+                debug!("glyph {} doesn't fit in the atlas, leaving it out", glyph.key.glyph_index);
+                continue;
+            };
+            let dst = self.surface_rect(Rect::new((x + glyph.x).floor() as i32, (y + glyph.y).floor() as i32, glyph.width, glyph.height));
+            placed.push((dst, in_atlas.rect));
+        }
+        // the atlas might have grown part way through, so the UVs are only worked out once everything is in
+        placed.into_iter().map(|(dst, rect)| GlyphInstance {
+            position: [dst.x as f32, dst.y as f32],
+            size: [dst.width as f32, dst.height as f32],
+            uv: atlas.uv_rect(rect),
+            colour,
+        }).collect()
+    }
+
+    /// Returns the uniforms for `TEXT_SHADER` when drawing to a `width` by `height` surface.
+    pub fn text_uniforms(&self, width: u32, height: u32) -> TextUniforms {
+        TextUniforms {
+            screen_size: [width as f32, height as f32],
+            y_up: (self.coordinate_system == CoordinateSystem::PositiveYUp) as u32,
+            _padding: 0,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Hyphenating words when wrapping, enabled with the `hyphenation` feature (see `DrawOptions::hyphenation`).
+//! Words are split with the TeX hyphenation patterns from the `hyphenation` crate. Only the english patterns are
+//! embedded by default, turn on that crate's `embed_all` feature to be able to use any `Language`.
+
+use hyphenation::{Hyphenator, Load, Standard};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+pub use hyphenation::Language;
+
+/// Internal type, every dictionary that's been loaded, or `None` for languages that aren't embedded.
+/// Loading one takes a while, so they're kept around for the life of the program and shared by every renderer.
+type Dictionaries = Mutex<HashMap<Language, Option<Arc<Standard>>>>;
+
+/// Internal function to get the dictionary for `language`, loading it the first time it's asked for.
+fn dictionary(language: Language) -> Option<Arc<Standard>> {
+    static DICTIONARIES: OnceLock<Dictionaries> = OnceLock::new();
+    let mut dictionaries = DICTIONARIES.get_or_init(Default::default).lock().unwrap_or_else(PoisonError::into_inner);
+    dictionaries.entry(language).or_insert_with(|| {
+        Standard::from_embedded(language)
+            .map_err(|e| warn!("couldn't load hyphenation patterns for {:?}: {}", language, e))
+            .ok()
+            .map(Arc::new)
+    }).clone()
+}
+
+/// Internal function to find everywhere `text` can be hyphenated in `language`, as the byte offsets of the characters
+/// that would start the next line. Words are runs of letters, so anything with digits or punctuation in the middle
+/// is hyphenated as separate words. Nothing is returned if the language's patterns aren't embedded.
+pub(crate) fn hyphenation_points(text: &str, language: Language) -> Vec<usize> {
+    let Some(dictionary) = dictionary(language) else { return Vec::new() };
+    let mut points = Vec::new();
+    let mut word_start = None;
+    for (offset, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_alphabetic(), word_start) {
+            (true, None) => word_start = Some(offset),
+            (false, Some(start)) => {
+                points.extend(dictionary.hyphenate(&text[start..offset]).breaks.iter().map(|point| start + point));
+                word_start = None;
+            }
+            _ => {}
+        }
+    }
+    points
+}
@@ -0,0 +1,932 @@
+//! Internal layout code shared by all of the draw and measure calls.
+
+use crate::custom_glyphs::CUSTOM_FONT;
+use crate::metrics::{DrawnBounds, LineInfo, TextMetrics};
+use crate::options::{Align, BreakOpportunity, Direction, DrawOptions, Origin, TextTransform, VerticalAlign, WrapStyle};
+use crate::{PasteSurface, Rect, StoreSurface, TextRenderer};
+use std::ops::Range;
+use std::sync::PoisonError;
+use fontdue::layout::WrapStyle as FontdueWrapStyle;
+use fontdue::layout::{CharacterData, CoordinateSystem, GlyphPosition, GlyphRasterConfig, Layout, LayoutSettings, TextStyle};
+use fontdue::Font;
+
+/// U+00AD, which marks somewhere a word can be hyphenated.
+const SOFT_HYPHEN: char = '\u{ad}';
+
+/// How much of `max_width` a line has to fill when it's broken between words for the word that didn't fit
+/// to be left alone rather than hyphenated, see `DrawOptions::hyphenation`.
+const HYPHENATION_FILL: f32 = 0.75;
+
+/// A laid out glyph.
+pub(crate) type Glyph = GlyphPosition<GlyphData>;
+
+/// What gets carried around with every laid out glyph, as its user data.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct GlyphData {
+    /// The index of the `Run` the glyph came from.
+    pub run: usize,
+    /// An advance the glyph gets instead of its own: for digits laid out with `DrawOptions::tabular_numbers`, the advance
+    /// every digit gets (see `tabular_advance`), which the glyph is centred in, and for tabs, how far it is to the next tab stop.
+    pub tabular_advance: Option<f32>,
+    /// Extra advance after the glyph from `DrawOptions::letter_spacing` and `DrawOptions::word_spacing`.
+    pub spacing: f32,
+}
+
+/// A piece of text to lay out, all in one size. Several of these can be laid out together in one go,
+/// so rich text still wraps and sits on a shared baseline like a plain string would.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Run<'a> {
+    pub text: &'a str,
+    pub size: f32,
+    /// How far to move this run's baseline up (or down if negative), in pixels.
+    pub baseline_shift: f32,
+    /// Which of the renderer's fonts to use, see `TextRenderer::font_at`.
+    pub font_index: usize,
+}
+
+impl<'a> Run<'a> {
+    pub fn new(text: &'a str, size: f32) -> Self {
+        Self { text, size, baseline_shift: 0.0, font_index: 0 }
+    }
+}
+
+/// Where a line ended up, before it gets measured. Glyphs are indices into the laid out glyphs.
+struct LineBox {
+    glyphs: Range<usize>,
+    baseline: f32,
+    ascent: f32,
+    height: f32,
+}
+
+/// A string after layout, with `max_lines`/`max_height` already applied.
+/// Glyph positions are relative to the top left of the text.
+pub(crate) struct LaidOutText {
+    /// The glyphs that should actually be drawn, including an ellipsis if one was added.
+    /// Byte offsets are relative to the start of the first run.
+    pub glyphs: Vec<Glyph>,
+    /// Measurements of the lines that are shown.
+    pub metrics: TextMetrics,
+    /// Where the baseline of the first line is, even if no lines are shown.
+    pub first_baseline: f32,
+    /// How many glyphs from the source string are shown (not counting an ellipsis).
+    pub glyphs_shown: usize,
+    /// How many bytes from the start of the source string are shown.
+    pub bytes_shown: usize,
+    /// Whether this was laid out with `Direction::Vertical`.
+    pub vertical: bool,
+    /// The indices of hyphens added by `DrawOptions::hyphenation`, which don't stand for any of the string.
+    pub inserted_glyphs: Vec<usize>,
+}
+
+impl LaidOutText {
+    /// How far to move the text so that it sits correctly relative to the requested `Origin` and `Anchor`.
+    pub fn origin_offset(&self, options: &DrawOptions) -> (f32, f32) {
+        let (anchor_x, anchor_y) = options.anchor.fractions();
+        let (x, y) = (-self.metrics.width * anchor_x, -self.metrics.height * anchor_y);
+        match options.origin {
+            Origin::Baseline if !self.vertical => (x, y - self.first_baseline),
+            _ => (x, y),
+        }
+    }
+
+    /// The area covered by the text's line boxes and every glyph's bitmap, relative to the top left of the text.
+    /// Glyphs with negative bearings (or that stick out of their line) can make this bigger than the measured size.
+    pub fn ink_bounds(&self) -> Rect {
+        let (mut left, mut top) = (0, 0);
+        let (mut right, mut bottom) = (self.metrics.width.ceil() as i32, self.metrics.height.ceil() as i32);
+        for glyph in self.glyphs.iter().filter(|glyph| glyph.width > 0 && glyph.height > 0) {
+            left = left.min(glyph.x.floor() as i32);
+            top = top.min(glyph.y.floor() as i32);
+            right = right.max(glyph.x.floor() as i32 + glyph.width as i32);
+            bottom = bottom.max(glyph.y.floor() as i32 + glyph.height as i32);
+        }
+        // a negative measured size (from whatever layout option) would wrap around to something enormous
+        Rect::new(left, top, (right - left).max(0) as usize, (bottom - top).max(0) as usize)
+    }
+
+    /// Where this text ends up when drawn with its top left at (`x`, `y`).
+    pub fn drawn_bounds(&self, x: f32, y: f32) -> DrawnBounds {
+        DrawnBounds {
+            x,
+            y,
+            width: self.metrics.width,
+            height: self.metrics.height,
+            truncated: self.metrics.truncated,
+            glyphs_drawn: self.glyphs_shown,
+            bytes_drawn: self.bytes_shown,
+        }
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Internal function to lay out a string with fontdue.
+    /// Everything is laid out at (0, 0), so glyph positions are relative to the top left of the text
+    /// and callers need to add their own x/y on top. See `layout_runs` for what gets returned.
+    pub(crate) fn layout_string(&self, string: &str, size: f32, options: &DrawOptions) -> (Vec<Glyph>, Layout<GlyphData>) {
+        self.layout_runs(&[Run::new(string, size)], options)
+    }
+
+    /// Internal function to lay out several runs of text with fontdue, one after the other.
+    /// Runs get split up wherever a fallback font takes over (see `set_fallback_fonts`).
+    /// Returns the glyphs, with byte offsets relative to the start of the first run, and fontdue's layout for its lines.
+    pub(crate) fn layout_runs(&self, runs: &[Run], options: &DrawOptions) -> (Vec<Glyph>, Layout<GlyphData>) {
+        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            max_width: options.max_width,
+            wrap_style: match options.wrap_style {
+                WrapStyle::Word => FontdueWrapStyle::Word,
+                WrapStyle::Letter => FontdueWrapStyle::Letter,
+            },
+            wrap_hard_breaks: options.wrap_hard_breaks,
+            ..LayoutSettings::default()
+        });
+        let fonts = (0..self.font_count()).map(|font_index| &**self.font_at(font_index)).collect::<Vec<&Font>>();
+        // every piece of text appended is (run index, where it starts relative to the first run), which the glyphs
+        // point at until they're all laid out
+        let mut pieces = Vec::new();
+        let run_starts = run_starts(runs);
+        // runs at sizes that can't be drawn are left out, see `check_size`
+        for (i, run) in runs.iter().enumerate().filter(|(_, run)| self.check_size(run.size).is_ok()) {
+            let font_index = if run.font_index < fonts.len() { run.font_index } else { 0 };
+            for (offset, text, font_index) in self.font_pieces(run.text, font_index) {
+                let data = GlyphData { run: pieces.len(), tabular_advance: None, spacing: 0.0 };
+                pieces.push((i, run_starts[i] + offset));
+                layout.append(&fonts, &TextStyle::with_user_data(text, run.size, font_index, data));
+            }
+        }
+        let mut glyphs = layout.glyphs().clone();
+        for glyph in glyphs.iter_mut() {
+            let (run, start) = pieces[glyph.user_data.run];
+            glyph.user_data.run = run;
+            glyph.byte_offset += start;
+        }
+        (glyphs, layout)
+    }
+
+    /// Internal function to split a run's text up wherever the font its characters are drawn with changes
+    /// (see `font_for_char`), as (byte offset, text, font index). Whitespace and control characters stay with
+    /// whatever font came before them.
+    fn font_pieces<'t>(&self, text: &'t str, font_index: usize) -> Vec<(usize, &'t str, usize)> {
+        if self.fallback_fonts.is_empty() {
+            return vec![(0, text, font_index)];
+        }
+        let mut pieces: Vec<(usize, &'t str, usize)> = Vec::new();
+        let mut start = 0;
+        let mut current = font_index;
+        for (offset, c) in text.char_indices() {
+            let font = if c.is_whitespace() || c.is_control() { current } else { self.font_for_char(font_index, c) };
+            if font != current && offset > start {
+                pieces.push((start, &text[start..offset], current));
+                start = offset;
+            }
+            current = font;
+        }
+        pieces.push((start, &text[start..], current));
+        pieces
+    }
+
+    /// Internal function to lay out a string and then cut it down to `max_lines`/`max_height`,
+    /// adding an ellipsis to the last shown line if asked to.
+    pub(crate) fn layout_text(&self, string: &str, size: f32, options: &DrawOptions) -> LaidOutText {
+        self.layout_rich(&[Run::new(string, size)], size, options)
+    }
+
+    /// Same as `layout_text`, but for several runs of text laid out together.
+    /// `size` is the size of the surrounding text, which is used for things like the ellipsis and vertical columns.
+    /// If `size` or the size of any of the runs isn't one that can be drawn (see `check_size`), nothing is laid out at all.
+    pub(crate) fn layout_rich(&self, runs: &[Run], size: f32, options: &DrawOptions) -> LaidOutText {
+        if options.text_transform != TextTransform::None || hyphenates(options) {
+            return self.layout_transformed(runs, size, options);
+        }
+        self.layout_plain(runs, size, options, &[])
+    }
+
+    /// Internal function that does the work of `layout_rich` once text transforms and hyphenation have been applied.
+    /// `added_hyphens` are the byte offsets of soft hyphens that were put in the text by hyphenation, sorted.
+    fn layout_plain(&self, runs: &[Run], size: f32, options: &DrawOptions, added_hyphens: &[usize]) -> LaidOutText {
+        let vertical = options.direction == Direction::Vertical;
+        if self.check_size(size).is_err() || runs.iter().any(|run| self.check_size(run.size).is_err()) {
+            return LaidOutText {
+                glyphs: Vec::new(),
+                metrics: TextMetrics::default(),
+                first_baseline: 0.0,
+                glyphs_shown: 0,
+                bytes_shown: 0,
+                vertical,
+                inserted_glyphs: Vec::new(),
+            };
+        }
+        let (mut glyphs, mut metrics) = if vertical {
+            self.layout_vertical(runs, size, options)
+        } else if options.line_breaks.is_some() || options.tabular_numbers || options.letter_spacing != 0.0 || options.word_spacing != 0.0
+            || options.tab_stops.is_some()
+            || runs.iter().any(|run| run.text.contains(SOFT_HYPHEN))
+            || self.has_custom_glyphs(runs) {
+            let (mut glyphs, mut metrics) = self.layout_custom_breaks(runs, options, added_hyphens);
+            self.apply_baseline_shifts(&mut glyphs, &mut metrics, runs);
+            (glyphs, metrics)
+        } else {
+            let (mut glyphs, layout) = self.layout_runs(runs, options);
+            let lines = layout.lines().map_or(&[][..], |lines| lines.as_slice()).iter().map(|line| LineBox {
+                glyphs: line.glyph_start..line.glyph_end + 1,
+                baseline: line.baseline_y,
+                ascent: line.max_ascent,
+                height: line.max_new_line_size,
+            }).collect::<Vec<_>>();
+            let mut metrics = self.measure_lines(&glyphs, &lines, layout.height());
+            self.apply_baseline_shifts(&mut glyphs, &mut metrics, runs);
+            (glyphs, metrics)
+        };
+        if (options.line_spacing.is_some() || options.line_height_scale != 1.0) && !vertical {
+            apply_line_spacing(&mut glyphs, &mut metrics, options);
+        }
+        let first_baseline = metrics.lines.first().map_or(0.0, |line| line.baseline);
+
+        // work out how many lines fit (for vertical text max_height is what wraps the columns instead)
+        let shown_lines = metrics.lines.iter().take_while(|line| {
+            vertical || options.max_height.is_none_or(|max_height| line.y + line.height <= max_height)
+        }).count().min(options.max_lines.unwrap_or(usize::MAX));
+        if shown_lines < metrics.lines.len() {
+            metrics.truncated = true;
+            metrics.lines.truncate(shown_lines);
+            glyphs.truncate(metrics.lines.iter().map(|line| line.glyph_count).sum());
+            if vertical {
+                // columns go right to left, so everything that's left has to move over to the left edge
+                let shift = metrics.width - metrics.lines.iter().map(|line| line.height).sum::<f32>();
+                glyphs.iter_mut().for_each(|glyph| glyph.x -= shift);
+                metrics.width -= shift;
+                metrics.height = metrics.lines.iter().map(|line| line.width).fold(0.0, f32::max);
+            } else {
+                if options.ellipsis && shown_lines > 0 {
+                    self.add_ellipsis(&mut glyphs, &mut metrics, size, options);
+                }
+                metrics.width = metrics.lines.iter().map(|line| line.width).fold(0.0, f32::max);
+                metrics.height = metrics.lines.last().map_or(0.0, |line| line.y + line.height);
+            }
+        }
+
+        if options.align != Align::Left && !vertical {
+            apply_align(&mut glyphs, &mut metrics, options.align, options.max_width);
+        }
+        if let Some(max_height) = options.max_height.filter(|_| options.vertical_align != VerticalAlign::Top && !vertical) {
+            apply_vertical_align(&mut glyphs, &mut metrics, options.vertical_align, max_height);
+        }
+        let glyphs_shown = metrics.lines.iter().map(|line| line.glyph_count).sum();
+        let bytes_shown = metrics.lines.last().map_or(0, |line| line.byte_range.end);
+        LaidOutText { glyphs, metrics, first_baseline, glyphs_shown, bytes_shown, vertical, inserted_glyphs: Vec::new() }
+    }
+
+    /// Internal function to lay out runs with `DrawOptions::text_transform` applied, and with soft hyphens added wherever
+    /// `DrawOptions::hyphenation` says words can be split. The transformed text gets laid out like any other,
+    /// then every glyph's byte offset, run and character are pointed back at the text it was made from.
+    fn layout_transformed(&self, runs: &[Run], size: f32, options: &DrawOptions) -> LaidOutText {
+        // the transformed text, split up wherever the run or whether it's small caps changes
+        let mut pieces: Vec<(String, usize, bool)> = Vec::new();
+        // for every byte of the transformed text, the byte of the source text it came from
+        let mut source_bytes = Vec::new();
+        // where the added soft hyphens are in the transformed text
+        let mut added_hyphens = Vec::new();
+        let run_starts = run_starts(runs);
+        for (run_index, run) in runs.iter().enumerate() {
+            let hyphenation_points = hyphenation_points(run.text, options);
+            for (offset, c) in run.text.char_indices() {
+                let small = options.text_transform == TextTransform::SmallCaps && c.is_lowercase();
+                let mut transformed: String = match options.text_transform {
+                    TextTransform::Uppercase => c.to_uppercase().collect(),
+                    TextTransform::Lowercase => c.to_lowercase().collect(),
+                    _ if small => c.to_uppercase().collect(),
+                    _ => c.to_string(),
+                };
+                // the soft hyphen goes before the character that would start the next line, and takes up none of the source
+                if hyphenation_points.binary_search(&offset).is_ok() {
+                    added_hyphens.push(source_bytes.len());
+                    transformed.insert(0, SOFT_HYPHEN);
+                }
+                source_bytes.extend(std::iter::repeat_n(run_starts[run_index] + offset, transformed.len()));
+                match pieces.last_mut() {
+                    Some((text, index, was_small)) if *index == run_index && *was_small == small => text.push_str(&transformed),
+                    _ => pieces.push((transformed, run_index, small)),
+                }
+            }
+        }
+        let transformed_runs = pieces.iter().map(|(text, run_index, small)| {
+            let run = runs[*run_index];
+            Run { text, size: if *small { run.size * options.small_caps_scale } else { run.size }, ..run }
+        }).collect::<Vec<_>>();
+        let mut text = self.layout_plain(&transformed_runs, size, options, &added_hyphens);
+
+        let source_len = runs.iter().map(|run| run.text.len()).sum();
+        let source = |byte: usize| source_bytes.get(byte).copied().unwrap_or(source_len);
+        for (i, glyph) in text.glyphs.iter_mut().enumerate() {
+            // a line broken at an added soft hyphen ends in a hyphen that isn't in the source at all
+            let inserted = i < text.glyphs_shown && glyph.parent == SOFT_HYPHEN && added_hyphens.binary_search(&glyph.byte_offset).is_ok();
+            glyph.byte_offset = source(glyph.byte_offset);
+            glyph.user_data.run = pieces.get(glyph.user_data.run).map_or(0, |(_, run_index, _)| *run_index);
+            if inserted {
+                glyph.parent = '-';
+                text.inserted_glyphs.push(i);
+            // an ellipsis wasn't made from anything, and custom glyphs are looked up by the character they were drawn as
+            } else if i < text.glyphs_shown && glyph.font_index != CUSTOM_FONT {
+                let run = runs[glyph.user_data.run];
+                glyph.parent = run.text[glyph.byte_offset - run_starts[glyph.user_data.run]..].chars().next().unwrap_or(glyph.parent);
+            }
+        }
+        for line in text.metrics.lines.iter_mut() {
+            line.byte_range = source(line.byte_range.start)..source(line.byte_range.end);
+        }
+        text.bytes_shown = source(text.bytes_shown);
+        text
+    }
+
+    /// Internal function to lay out runs with our own line breaking instead of fontdue's,
+    /// for `DrawOptions::line_breaks` and soft hyphens which fontdue's wrap styles can't do, and for custom glyphs
+    /// (see `set_glyph_provider`), `DrawOptions::tabular_numbers`, `DrawOptions::letter_spacing`, `DrawOptions::word_spacing`
+    /// and `DrawOptions::tab_stops` which need advances fontdue doesn't know about.
+    /// fontdue lays everything out on one long line, which then gets split up into lines here and moved into place.
+    /// Soft hyphens at `added_hyphens` came from hyphenation, and are only broken at if breaking between words
+    /// would leave the line emptier than `HYPHENATION_FILL`.
+    fn layout_custom_breaks(&self, runs: &[Run], options: &DrawOptions, added_hyphens: &[usize]) -> (Vec<Glyph>, TextMetrics) {
+        let unwrapped = DrawOptions { max_width: None, wrap_hard_breaks: false, ..options.clone() };
+        let (mut glyphs, layout) = self.layout_runs(runs, &unwrapped);
+        let unwrapped_baseline = layout.lines().and_then(|lines| lines.first()).map_or(0.0, |line| line.baseline_y);
+
+        // how far each glyph sits from fontdue's pen, and where the pen is before each glyph once soft hyphens take up no space
+        let mut offsets = glyphs.iter().scan(0.0, |pen, glyph| {
+            let offset = glyph.x - *pen;
+            *pen += self.glyph_advance(glyph);
+            Some(offset)
+        }).collect::<Vec<_>>();
+        for (glyph, offset) in glyphs.iter_mut().zip(offsets.iter_mut()) {
+            let run = &runs[glyph.user_data.run];
+            if let Some(provided) = self.provided_glyph(glyph.parent, run.size, run.font_index) {
+                glyph.font_index = CUSTOM_FONT;
+                glyph.width = provided.width;
+                glyph.height = provided.height;
+            } else if options.tabular_numbers && glyph.parent.is_ascii_digit() {
+                let advance = self.tabular_advance(glyph.font_index, glyph.key.px);
+                *offset += self.tabular_shift(glyph, advance);
+                glyph.user_data.tabular_advance = Some(advance);
+            }
+            if !glyph.char_data.is_control() {
+                glyph.user_data.spacing = options.letter_spacing + if is_word_space(glyph.parent) { options.word_spacing } else { 0.0 };
+            }
+        }
+        let mut pens = vec![0.0];
+        // where the pen was at the start of the current line, before wrapping, which tab stops are measured from
+        let mut line_pen = 0.0;
+        for glyph in glyphs.iter_mut() {
+            let pen = pens[pens.len() - 1];
+            if let Some(tab_stops) = options.tab_stops.as_ref().filter(|_| glyph.parent == '\t') {
+                let space = self.font_at(glyph.font_index).metrics(' ', glyph.key.px).advance_width.ceil();
+                glyph.user_data.tabular_advance = Some(tab_stops.next_stop(pen - line_pen, space) - (pen - line_pen));
+            }
+            let advance = if glyph.parent == SOFT_HYPHEN { 0.0 } else { self.glyph_advance(glyph) };
+            pens.push(pen + advance);
+            if glyph.parent == '\n' && options.wrap_hard_breaks {
+                line_pen = pen + advance;
+            }
+        }
+
+        // work out where the lines get broken, as indices of the first glyph of every line after the first
+        let max_width = options.max_width.unwrap_or(f32::INFINITY);
+        let hyphen = |glyph: &Glyph| self.font_at(glyph.font_index).lookup_glyph_index('-');
+        let hyphen_advance = |glyph: &Glyph| self.font_at(glyph.font_index).metrics_indexed(hyphen(glyph), glyph.key.px).advance_width.ceil();
+        let mut breaks = Vec::new();
+        let (mut start, mut candidate, mut hyphen_candidate, mut i) = (0, None, None, 0);
+        while i < glyphs.len() {
+            let opportunity = if i > start { break_opportunity(glyphs[i - 1].parent, glyphs[i].parent, options) } else { None };
+            match opportunity {
+                Some(BreakOpportunity::Mandatory) => {
+                    breaks.push(i);
+                    start = i;
+                    candidate = None;
+                    hyphen_candidate = None;
+                }
+                // a soft hyphen is only somewhere to break if the hyphen it turns into fits too
+                Some(BreakOpportunity::Allowed) if glyphs[i - 1].parent != SOFT_HYPHEN
+                    || pens[i] - pens[start] + hyphen_advance(&glyphs[i - 1]) <= max_width => {
+                    if glyphs[i - 1].parent == SOFT_HYPHEN && added_hyphens.binary_search(&glyphs[i - 1].byte_offset).is_ok() {
+                        hyphen_candidate = Some(i);
+                    } else {
+                        candidate = Some(i);
+                    }
+                }
+                _ => {}
+            }
+            let glyph = &glyphs[i];
+            let inked = !glyph.char_data.is_whitespace() && !glyph.char_data.is_control();
+            if inked && i > start && pens[i + 1] - pens[start] > max_width {
+                // a word only gets hyphenated if breaking before it would leave the line too empty
+                let between_words = candidate.filter(|&at| pens[at] - pens[start] >= max_width * HYPHENATION_FILL);
+                let hyphenated = hyphen_candidate.filter(|&at| candidate.is_none_or(|candidate| at > candidate));
+                // with nowhere better to break, the glyph that doesn't fit starts the next line
+                let at = between_words.or(hyphenated).or(candidate).or((opportunity != Some(BreakOpportunity::Forbidden)).then_some(i));
+                if let Some(at) = at {
+                    breaks.push(at);
+                    start = at;
+                    candidate = None;
+                    hyphen_candidate = None;
+                    i = at;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+
+        // then move every line into place
+        let mut laid_out = Vec::with_capacity(glyphs.len());
+        let mut lines = Vec::new();
+        let mut height = 0.0;
+        let line_ends = breaks.iter().copied().chain(std::iter::once(glyphs.len()));
+        for (line_start, line_end) in std::iter::once(0).chain(breaks.iter().copied()).zip(line_ends) {
+            let line_glyphs = &glyphs[line_start..line_end];
+            let (ascent, new_line_size) = line_glyphs.iter().fold((0.0f32, 0.0f32), |(ascent, new_line_size), glyph| {
+                let run = &runs[glyph.user_data.run];
+                let metrics = self.font_line_metrics(run.font_index, run.size);
+                (ascent.max(metrics.ascent.ceil()), new_line_size.max(metrics.line_height.ceil()))
+            });
+            let baseline = height + ascent;
+            let glyph_start = laid_out.len();
+            for (i, glyph) in line_glyphs.iter().enumerate().map(|(i, glyph)| (line_start + i, glyph)) {
+                let mut glyph = *glyph;
+                if glyph.parent == SOFT_HYPHEN {
+                    if i + 1 != line_end || line_end == glyphs.len() {
+                        continue;
+                    }
+                    // the line was broken here, so it gets a real hyphen (still pointing at the soft hyphen's bytes)
+                    let hyphen = hyphen(&glyph);
+                    let metrics = self.font_at(glyph.font_index).metrics_indexed(hyphen, glyph.key.px);
+                    glyph.key.glyph_index = hyphen;
+                    glyph.char_data = CharacterData::classify('-', hyphen);
+                    glyph.x = pens[i] - pens[line_start] + metrics.bounds.xmin.floor();
+                    glyph.y = (-metrics.bounds.height - metrics.bounds.ymin).floor() + baseline;
+                    glyph.width = metrics.width;
+                    glyph.height = metrics.height;
+                } else if let Some(provided) = self.custom_glyph_at(&glyph) {
+                    glyph.x = pens[i] - pens[line_start];
+                    glyph.y = (baseline - provided.top).floor();
+                } else {
+                    glyph.x = pens[i] - pens[line_start] + offsets[i];
+                    glyph.y += baseline - unwrapped_baseline;
+                }
+                laid_out.push(glyph);
+            }
+            // a line that was nothing but soft hyphens doesn't need to exist
+            if laid_out.len() == glyph_start {
+                continue;
+            }
+            lines.push(LineBox { glyphs: glyph_start..laid_out.len(), baseline, ascent, height: new_line_size });
+            height += new_line_size;
+        }
+        let metrics = self.measure_lines(&laid_out, &lines, height);
+        (laid_out, metrics)
+    }
+
+    /// Internal function to lay out a string in top to bottom columns, stacked right to left.
+    /// fontdue's layout can't do this, so glyphs are placed by hand.
+    /// Baseline shifts aren't applied to vertical text.
+    fn layout_vertical(&self, runs: &[Run], size: f32, options: &DrawOptions) -> (Vec<Glyph>, TextMetrics) {
+        let horizontal = self.line_metrics(size);
+        let column_width = match self.font.vertical_line_metrics(size) {
+            Some(vertical) => vertical.new_line_size.ceil(),
+            None => horizontal.line_height.ceil(),
+        };
+        let has_vertical_advances = self.font.vertical_line_metrics(size).is_some();
+        let baseline = horizontal.ascent.ceil();
+        let max_height = options.max_height.unwrap_or(f32::MAX);
+
+        // first split everything up into columns of (glyph, advance)
+        let mut columns: Vec<Vec<(Glyph, f32)>> = vec![Vec::new()];
+        let mut pen = 0.0;
+        let chars = runs.iter().zip(run_starts(runs)).enumerate().flat_map(|(i, (run, run_start))| {
+            run.text.char_indices().map(move |(byte_offset, c)| (i, byte_offset + run_start, c))
+        });
+        for (run, byte_offset, c) in chars {
+            let (size, font_index) = (runs[run].size, self.font_for_char(runs[run].font_index, c));
+            let font = self.font_at(font_index);
+            let glyph_index = font.lookup_glyph_index(c);
+            let char_data = CharacterData::classify(c, glyph_index);
+            let metrics = if char_data.is_control() { Default::default() } else { font.metrics_indexed(glyph_index, size) };
+            let advance = if char_data.is_control() {
+                0.0
+            } else if has_vertical_advances && metrics.advance_height > 0.0 {
+                metrics.advance_height.ceil()
+            } else {
+                self.font_line_metrics(font_index, size).line_height.ceil()
+            };
+            let column = columns.last_mut().unwrap();
+            if !column.is_empty() && pen + advance > max_height && !char_data.is_whitespace() {
+                columns.push(Vec::new());
+                pen = 0.0;
+            }
+            let glyph = GlyphPosition {
+                key: GlyphRasterConfig { glyph_index, px: size, font_hash: font.file_hash() },
+                font_index: if font_index < self.font_count() { font_index } else { 0 },
+                parent: c,
+                // centred across the column, and sitting where it would in its em box vertically
+                x: ((column_width - metrics.width as f32) / 2.0).floor(),
+                y: pen + (-metrics.bounds.height - metrics.bounds.ymin).floor() + baseline,
+                width: metrics.width,
+                height: metrics.height,
+                byte_offset,
+                char_data,
+                user_data: GlyphData { run, tabular_advance: None, spacing: 0.0 },
+            };
+            columns.last_mut().unwrap().push((glyph, advance));
+            pen += advance;
+            if c == '\n' && options.wrap_hard_breaks {
+                columns.push(Vec::new());
+                pen = 0.0;
+            }
+        }
+        if columns.last().is_some_and(|column| column.is_empty()) {
+            columns.pop();
+        }
+
+        let mut glyphs = Vec::new();
+        let mut metrics = TextMetrics::default();
+        let count = columns.len();
+        for (i, column) in columns.into_iter().enumerate() {
+            let x = (count - 1 - i) as f32 * column_width;
+            let inked = column.iter()
+                .rposition(|(g, _)| !g.char_data.is_whitespace() && !g.char_data.is_control())
+                .map_or(0, |i| i + 1);
+            let length = column[..inked].iter().map(|(_, advance)| advance).sum::<f32>();
+            let (first, last) = (column[0].0, column[column.len() - 1].0);
+            metrics.lines.push(LineInfo {
+                byte_range: first.byte_offset..last.byte_offset + last.parent.len_utf8(),
+                width: length,
+                height: column_width,
+                x: 0.0,
+                y: i as f32 * column_width,
+                baseline: i as f32 * column_width + column_width / 2.0,
+                glyph_count: column.len(),
+            });
+            metrics.height = metrics.height.max(length);
+            glyphs.extend(column.into_iter().map(|(mut glyph, _)| {
+                glyph.x += x;
+                glyph
+            }));
+        }
+        metrics.width = count as f32 * column_width;
+        (glyphs, metrics)
+    }
+
+    /// Internal function to replace the end of the last line in `glyphs` with an ellipsis.
+    /// Glyphs are taken off the end of the line until the ellipsis fits within `max_width`.
+    fn add_ellipsis(&self, glyphs: &mut Vec<Glyph>, metrics: &mut TextMetrics, size: f32, options: &DrawOptions) {
+        let ellipsis = if self.font.lookup_glyph_index('…') != 0 { "…" } else { "..." };
+        let (ellipsis_glyphs, ellipsis_layout) = self.layout_string(ellipsis, size, &DrawOptions::default());
+        let ellipsis_width = ellipsis_glyphs.iter().map(|g| self.glyph_advance(g)).sum::<f32>();
+        let ellipsis_baseline = ellipsis_layout.lines().map_or(0.0, |lines| lines[0].baseline_y);
+
+        let line = metrics.lines.last_mut().unwrap();
+        let line_start = glyphs.len() - line.glyph_count;
+        // the pen after every glyph of the line, so taking glyphs off doesn't mean measuring the whole line again
+        let pens = glyphs[line_start..].iter().scan(0.0, |pen, glyph| {
+            *pen += self.glyph_advance(glyph);
+            Some(*pen)
+        }).collect::<Vec<_>>();
+        // same as `line_width`, trailing whitespace doesn't count
+        let width_of = |kept: usize| glyphs[line_start..kept].iter()
+            .rposition(|g| !g.char_data.is_whitespace() && !g.char_data.is_control())
+            .map_or(0.0, |i| pens[i]);
+        let mut kept = glyphs.len();
+        let mut width = width_of(kept);
+        if let Some(max_width) = options.max_width {
+            while kept > line_start && width + ellipsis_width > max_width {
+                kept -= 1;
+                width = width_of(kept);
+            }
+        }
+        glyphs.truncate(kept);
+        line.glyph_count = kept - line_start;
+        line.byte_range.end = match glyphs[line_start..].last() {
+            Some(last) => last.byte_offset + last.parent.len_utf8(),
+            None => line.byte_range.start,
+        };
+        line.width = width + ellipsis_width;
+
+        let byte_offset = line.byte_range.end;
+        let y = line.baseline - ellipsis_baseline;
+        glyphs.extend(ellipsis_glyphs.into_iter().map(|mut glyph| {
+            glyph.x += width;
+            glyph.y += y;
+            glyph.byte_offset = byte_offset;
+            glyph
+        }));
+    }
+
+    /// Internal function to measure already laid out glyphs, given the lines they were split into.
+    fn measure_lines(&self, glyphs: &[Glyph], lines: &[LineBox], height: f32) -> TextMetrics {
+        let mut metrics = TextMetrics::default();
+        for line in lines.iter() {
+            // fontdue gives lines with no glyphs in them (when wrapping at a tiny width) an end before their start,
+            // so those get no glyphs instead of panicking
+            let line_glyphs = glyphs.get(line.glyphs.clone()).unwrap_or(&[]);
+            let width = self.line_width(line_glyphs);
+            let byte_range = match (line_glyphs.first(), line_glyphs.last()) {
+                (Some(first), Some(last)) => first.byte_offset..last.byte_offset + last.parent.len_utf8(),
+                _ => {
+                    let end = metrics.lines.last().map_or(0, |previous: &LineInfo| previous.byte_range.end);
+                    end..end
+                }
+            };
+            let top = line.baseline - line.ascent;
+            metrics.lines.push(LineInfo {
+                byte_range,
+                width,
+                height: line.height,
+                x: 0.0,
+                y: top,
+                baseline: line.baseline,
+                glyph_count: line_glyphs.len(),
+            });
+            metrics.width = metrics.width.max(width);
+        }
+        if !metrics.lines.is_empty() {
+            metrics.height = height;
+        }
+        metrics
+    }
+
+    /// Internal function to move the glyphs of runs with a baseline shift up or down.
+    /// If that makes them poke out of the top or bottom of their line, the line gets taller to fit them
+    /// (and every line after it moves down).
+    fn apply_baseline_shifts(&self, glyphs: &mut [Glyph], metrics: &mut TextMetrics, runs: &[Run]) {
+        if runs.iter().all(|run| run.baseline_shift == 0.0) {
+            return;
+        }
+        let mut moved = 0.0;
+        let mut start = 0;
+        for line in metrics.lines.iter_mut() {
+            let line_glyphs = &mut glyphs[start..start + line.glyph_count];
+            start += line.glyph_count;
+            let (mut extra_top, mut extra_bottom) = (0.0f32, 0.0f32);
+            for glyph in line_glyphs.iter() {
+                let run = &runs[glyph.user_data.run];
+                if run.baseline_shift == 0.0 {
+                    continue;
+                }
+                let metrics = self.font_line_metrics(run.font_index, run.size);
+                let top = line.baseline - run.baseline_shift - metrics.ascent.ceil();
+                let bottom = line.baseline - run.baseline_shift - metrics.descent.ceil();
+                extra_top = extra_top.max(line.y - top);
+                extra_bottom = extra_bottom.max(bottom - (line.y + line.height));
+            }
+            line.y += moved;
+            line.baseline += moved + extra_top;
+            line.height += extra_top + extra_bottom;
+            for glyph in line_glyphs.iter_mut() {
+                glyph.y += moved + extra_top - runs[glyph.user_data.run].baseline_shift;
+            }
+            moved += extra_top + extra_bottom;
+        }
+        metrics.height += moved;
+    }
+
+    /// Internal function to get the width of a line of glyphs. Trailing whitespace doesn't count towards the width.
+    fn line_width(&self, line_glyphs: &[Glyph]) -> f32 {
+        let inked = line_glyphs.iter()
+            .rposition(|g| !g.char_data.is_whitespace() && !g.char_data.is_control())
+            .map_or(0, |i| i + 1);
+        line_glyphs[..inked].iter().map(|g| self.glyph_advance(g)).sum()
+    }
+
+    /// Internal function to get where the pen was when fontdue placed a glyph, relative to the left of the text.
+    /// fontdue puts glyphs at `floor(pen + xmin)` and the pen is always on a whole pixel, so this is exact.
+    pub(crate) fn pen_x(&self, glyph: &Glyph) -> f32 {
+        if glyph.char_data.is_control() || glyph.font_index == CUSTOM_FONT {
+            return glyph.x;
+        }
+        let shift = glyph.user_data.tabular_advance.map_or(0.0, |advance| self.tabular_shift(glyph, advance));
+        glyph.x - shift - self.font_at(glyph.font_index).metrics_indexed(glyph.key.glyph_index, glyph.key.px).bounds.xmin.floor()
+    }
+
+    /// Internal function to get the area covered by the glyphs that start in `range`, with one rect for every line they're on.
+    /// Rects go from the pen position before the first glyph to after the advance of the last one,
+    /// are the full height of the line, and are relative to the top left of the text.
+    /// Doesn't work for vertical text, so that always gets no rects.
+    pub(crate) fn range_rects(&self, text: &LaidOutText, range: &Range<usize>) -> Vec<Rect> {
+        self.range_extents(text, range).into_iter().map(|(left, right, _, line)| {
+            Rect::new(left as i32, line.y as i32, (right - left) as usize, line.height.ceil() as usize)
+        }).collect()
+    }
+
+    /// Internal function to find the (left, right) pixel extent of the glyphs in a byte range on every line it's on,
+    /// from the pen position before the first glyph to after the advance of the last one,
+    /// along with the biggest pixel size of those glyphs.
+    pub(crate) fn range_extents<'t>(&self, text: &'t LaidOutText, range: &Range<usize>) -> Vec<(f32, f32, f32, &'t LineInfo)> {
+        let mut extents = Vec::new();
+        if text.vertical || range.is_empty() {
+            return extents;
+        }
+        let mut start = 0;
+        for line in text.metrics.lines.iter() {
+            let glyphs = &text.glyphs[start..(start + line.glyph_count).min(text.glyphs.len())];
+            start += line.glyph_count;
+            let extent = glyphs.iter().filter(|glyph| range.contains(&glyph.byte_offset)).fold(None, |extent, glyph| {
+                let left = self.pen_x(glyph);
+                let right = left + self.glyph_advance(glyph);
+                match extent {
+                    None => Some((left, right, glyph.key.px)),
+                    Some((min, max, size)) => Some((f32::min(min, left), f32::max(max, right), f32::max(size, glyph.key.px))),
+                }
+            });
+            if let Some((left, right, size)) = extent {
+                extents.push((left, right, size, line));
+            }
+        }
+        extents
+    }
+
+    /// Internal function to check whether the glyph provider (see `set_glyph_provider`) replaces any character of the runs.
+    fn has_custom_glyphs(&self, runs: &[Run]) -> bool {
+        self.glyph_provider.is_some() && runs.iter().any(|run| run.text.chars().any(|c| self.provided_glyph(c, run.size, run.font_index).is_some()))
+    }
+
+    /// Internal function to get how far fontdue's layout moves the pen after a glyph
+    /// (or for custom glyphs, how far the glyph provider said it should).
+    pub(crate) fn glyph_advance(&self, glyph: &Glyph) -> f32 {
+        let advance = if let Some(advance) = glyph.user_data.tabular_advance {
+            advance
+        } else if glyph.char_data.is_control() {
+            return 0.0;
+        } else if let Some(provided) = self.custom_glyph_at(glyph) {
+            provided.advance.ceil()
+        } else {
+            self.font_at(glyph.font_index).metrics_indexed(glyph.key.glyph_index, glyph.key.px).advance_width.ceil()
+        };
+        advance + glyph.user_data.spacing
+    }
+
+    /// Internal function to get the advance every digit gets with `DrawOptions::tabular_numbers`: the advance of the widest
+    /// of 0 to 9 in the font at `size`. Worked out once for every font and size, then cached.
+    pub(crate) fn tabular_advance(&self, font_index: usize, size: f32) -> f32 {
+        let key = (font_index, size.to_bits());
+        if let Some(advance) = self.tabular_advances.read().unwrap_or_else(PoisonError::into_inner).get(&key) {
+            return *advance;
+        }
+        let font = self.font_at(font_index);
+        let advance = ('0'..='9').map(|c| font.metrics(c, size).advance_width.ceil()).fold(0.0, f32::max);
+        self.tabular_advances.write().unwrap_or_else(PoisonError::into_inner).insert(key, advance);
+        advance
+    }
+
+    /// Internal function to get how far a digit moves right to be centred in its tabular `advance`.
+    fn tabular_shift(&self, glyph: &Glyph, advance: f32) -> f32 {
+        let natural = self.font_at(glyph.font_index).metrics_indexed(glyph.key.glyph_index, glyph.key.px).advance_width.ceil();
+        ((advance - natural) / 2.0).floor()
+    }
+}
+
+/// Internal function to decide whether a line can be broken between two characters.
+/// `options.line_breaks` gets the first say, and if it doesn't have one the normal rules for the wrap style are used:
+/// newlines (if `wrap_hard_breaks` is on) and soft hyphens always count, then `WrapStyle::Letter` can break anywhere,
+/// and `WrapStyle::Word` can break after whitespace and after a hyphen that's followed by a letter.
+/// `None` means there's no opportunity, but the line can still be broken here if a word doesn't fit at all.
+fn break_opportunity(prev: char, next: char, options: &DrawOptions) -> Option<BreakOpportunity> {
+    if let Some(opportunity) = options.line_breaks.and_then(|line_breaks| line_breaks(prev, next)) {
+        return Some(opportunity);
+    }
+    match (prev, next) {
+        ('\n', _) if options.wrap_hard_breaks => Some(BreakOpportunity::Mandatory),
+        (SOFT_HYPHEN, _) => Some(BreakOpportunity::Allowed),
+        _ if options.wrap_style == WrapStyle::Letter => Some(BreakOpportunity::Allowed),
+        (prev, next) if prev.is_whitespace() && !next.is_whitespace() => Some(BreakOpportunity::Allowed),
+        ('-', next) if next.is_alphabetic() => Some(BreakOpportunity::Allowed),
+        _ => None,
+    }
+}
+
+/// Internal function to restack laid out lines so every line is `DrawOptions::line_spacing` pixels tall, or
+/// `DrawOptions::line_height_scale` times as tall as it was. Glyphs keep where they were relative to their line's baseline.
+fn apply_line_spacing(glyphs: &mut [Glyph], metrics: &mut TextMetrics, options: &DrawOptions) {
+    let mut start = 0;
+    let mut y = 0.0;
+    for line in metrics.lines.iter_mut() {
+        let moved = y - line.y;
+        glyphs[start..start + line.glyph_count].iter_mut().for_each(|glyph| glyph.y += moved);
+        start += line.glyph_count;
+        line.y += moved;
+        line.baseline += moved;
+        let height = match options.line_spacing {
+            Some(spacing) if !spacing.is_nan() => spacing,
+            _ if options.line_height_scale.is_nan() => line.height,
+            _ => (line.height * options.line_height_scale).round(),
+        };
+        // lines can be squashed right down, but not turned inside out
+        line.height = height.max(0.0);
+        y += line.height;
+    }
+    metrics.height = y;
+}
+
+/// Internal function to move every line over to where `align` puts it, see `DrawOptions::align`.
+/// Lines are aligned within `max_width` if there is one (which the text then takes up all of), or within the widest line.
+fn apply_align(glyphs: &mut [Glyph], metrics: &mut TextMetrics, align: Align, max_width: Option<f32>) {
+    let width = max_width.unwrap_or(metrics.width);
+    let mut start = 0;
+    let count = metrics.lines.len();
+    for (i, line) in metrics.lines.iter_mut().enumerate() {
+        let line_glyphs = &mut glyphs[start..start + line.glyph_count];
+        start += line.glyph_count;
+        let moved = match align {
+            Align::Left => 0.0,
+            Align::Center => ((width - line.width) / 2.0).floor(),
+            Align::Right => (width - line.width).floor(),
+            // the last line of every paragraph stays how it is
+            Align::Justify => {
+                let ends_paragraph = i + 1 == count || line_glyphs.last().is_some_and(|glyph| glyph.parent == '\n');
+                let extra = (width - line.width).floor();
+                if !ends_paragraph && justify_line(line_glyphs, extra) {
+                    line.width += extra;
+                }
+                0.0
+            }
+        };
+        line_glyphs.iter_mut().for_each(|glyph| glyph.x += moved);
+        line.x = moved;
+    }
+    if !metrics.lines.is_empty() {
+        metrics.width = width;
+    }
+}
+
+/// Internal function to spread `extra` pixels out over the spaces between the words of a line, for `Align::Justify`.
+/// Spaces at the end of the line don't get any. Returns `false` (and leaves the line alone) if there aren't any spaces to widen.
+fn justify_line(line_glyphs: &mut [Glyph], extra: f32) -> bool {
+    let inked = line_glyphs.iter()
+        .rposition(|glyph| !glyph.char_data.is_whitespace() && !glyph.char_data.is_control())
+        .map_or(0, |i| i + 1);
+    let gaps = line_glyphs[..inked].iter().filter(|glyph| glyph.char_data.is_whitespace() && !glyph.char_data.is_control()).count();
+    if gaps == 0 || extra <= 0.0 {
+        return false;
+    }
+    // every gap gets a whole number of pixels, with whatever's left over going to the first ones
+    let (each, mut left_over) = ((extra / gaps as f32).floor(), extra % gaps as f32);
+    let mut moved = 0.0;
+    for (i, glyph) in line_glyphs.iter_mut().enumerate() {
+        glyph.x += moved;
+        if i < inked && glyph.char_data.is_whitespace() && !glyph.char_data.is_control() {
+            moved += each + if left_over >= 1.0 { 1.0 } else { 0.0 };
+            left_over -= 1.0;
+        }
+    }
+    true
+}
+
+/// Internal function to move every line down to where `vertical_align` puts the text within `max_height`,
+/// see `DrawOptions::vertical_align`.
+fn apply_vertical_align(glyphs: &mut [Glyph], metrics: &mut TextMetrics, vertical_align: VerticalAlign, max_height: f32) {
+    let moved = match vertical_align {
+        VerticalAlign::Top => 0.0,
+        VerticalAlign::Middle => ((max_height - metrics.height) / 2.0).floor(),
+        VerticalAlign::Bottom => (max_height - metrics.height).floor(),
+    };
+    glyphs.iter_mut().for_each(|glyph| glyph.y += moved);
+    for line in metrics.lines.iter_mut() {
+        line.y += moved;
+        line.baseline += moved;
+    }
+    metrics.height = max_height;
+}
+
+/// Internal function to check whether `DrawOptions::word_spacing` gets added to a character's advance.
+fn is_word_space(c: char) -> bool {
+    c == ' ' || c == '\u{a0}'
+}
+
+/// Internal function to get the byte offset each run starts at, if all of the runs were joined together.
+fn run_starts(runs: &[Run]) -> Vec<usize> {
+    runs.iter().scan(0, |start, run| {
+        let run_start = *start;
+        *start += run.text.len();
+        Some(run_start)
+    }).collect()
+}
+
+/// Internal function to check whether words get hyphenated with these options, see `DrawOptions::hyphenation`.
+fn hyphenates(options: &DrawOptions) -> bool {
+    #[cfg(feature = "hyphenation")]
+    let language = options.hyphenation;
+    #[cfg(not(feature = "hyphenation"))]
+    let language: Option<()> = None;
+    language.is_some() && options.max_width.is_some() && options.direction != Direction::Vertical
+}
+
+/// Internal function to find where the words of `text` can be hyphenated with these options, as sorted byte offsets
+/// of the characters that would start the next line.
+#[cfg(feature = "hyphenation")]
+fn hyphenation_points(text: &str, options: &DrawOptions) -> Vec<usize> {
+    match options.hyphenation {
+        Some(language) if hyphenates(options) => crate::hyphenate::hyphenation_points(text, language),
+        _ => Vec::new(),
+    }
+}
+
+/// Without the `hyphenation` feature nothing ever gets hyphenated.
+#[cfg(not(feature = "hyphenation"))]
+fn hyphenation_points(_text: &str, _options: &DrawOptions) -> Vec<usize> {
+    Vec::new()
+}
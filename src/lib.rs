@@ -1,40 +1,249 @@
+#[cfg(feature = "ansi")]
+pub mod ansi;
+#[cfg(feature = "async")]
+pub mod asynchronous;
+pub mod atlas;
+pub mod blit;
+mod boundaries;
+pub mod builder;
 pub mod colours;
+pub mod commands;
+pub mod custom_glyphs;
+mod decorations;
+#[cfg(feature = "embedded-graphics")]
+pub mod embedded;
+#[cfg(test)]
+mod golden;
+#[cfg(feature = "wgpu")]
+pub mod gpu;
+#[cfg(feature = "hyphenation")]
+pub mod hyphenate;
+mod layout;
+#[cfg(feature = "markup")]
+pub mod markup;
+pub mod metrics;
+#[cfg(feature = "ttf-parser")]
+mod msdf;
+pub mod options;
+#[cfg(feature = "ttf-parser")]
+pub mod outline;
+pub mod path;
+#[cfg(feature = "tiny-skia")]
+pub mod pixmap;
+pub mod prepared;
+#[cfg(feature = "image")]
+pub mod rgba_image;
+#[cfg(feature = "sdl2")]
+pub mod sdl;
+pub mod sdf;
+#[cfg(feature = "shaping")]
+pub mod shaping;
+pub mod spans;
+pub mod string_cache;
+#[cfg(feature = "ttf-parser")]
+pub mod svg;
+#[cfg(feature = "system-fonts")]
+pub mod system_fonts;
+#[cfg(feature = "ttf-parser")]
+pub mod variations;
+pub mod warm;
 
-use crate::colours::TextColour;
-use fontdue::layout::GlyphPosition;
-use fontdue::layout::{CoordinateSystem, Layout, LayoutSettings, TextStyle};
+use crate::colours::{pixel_bytes, PixelOrder, TextColour};
+use crate::layout::{Glyph, GlyphData, LaidOutText};
+use crate::metrics::{CharDrawResult, DecorationMetrics, DrawnBounds, DrawnLine, FittedSize, GlyphMetrics, LineInfo, LineMetrics, RenderMetrics, TextMetrics, TextOverflow};
+use crate::builder::TextRendererBuilder;
+use crate::options::{Align, Anchor, BlendMode, CachePolicy, CoordinateSystem, DrawOptions, FillMode, FontOptions, GlyphCacheFormat, GlyphDrawParams, LineExtent, Origin, Selection, SubpixelOrder, WrapOptions, WrapStyle};
+use crate::prepared::{PreparedText, TextLayoutBuilder};
+use crate::spans::{BaselineShift, TextSpan};
+use crate::custom_glyphs::{CustomGlyphCache, GlyphProviderFn, CUSTOM_FONT};
+use crate::path::RotatedMask;
+use crate::sdf::GlyphSdf;
+use crate::string_cache::StringCache;
+use crate::layout::Run;
+use fontdue::layout::{CharacterData, GlyphPosition, GlyphRasterConfig};
+use fontdue::layout::{CoordinateSystem as FontdueCoordinates, Layout};
 use fontdue::Font;
 use fontdue::FontSettings;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Instant;
+use unicode_width::UnicodeWidthChar;
 use log::debug;
 
-/// The main text renderer struct, which holds a single font and its cache.
-/// Try not to clone this as it may end up containing a large amount of data.
-/// Instead, you might want to wrap this in an `Arc` or some other pointer type.
+/// The main text renderer struct, which holds a font (plus any extra ones added with `add_font`) and its cache.
+/// Cloning this is cheap, and all of the clones share the same glyph cache, so handing a clone to everything
+/// that needs to draw text is fine (glyphs drawn by one clone are already cached for all of the others).
 /// Type parameter `G` refers to the type that will store glyph data.
 /// Type parameter `A` refers to the type that glyph data will be drawn onto.
 #[derive(Clone)]
 pub struct TextRenderer<G, A> {
     pub font: Arc<Font>,
     pub layout: Arc<Layout>,
-    glyph_caches: HashMap<u16, GlyphCache<G>>,
+    /// The raw bytes of the font file, kept around for things that need to read font tables fontdue doesn't expose.
+    #[allow(dead_code)] // only used with some features enabled
+    font_data: Arc<Vec<u8>>,
+    /// Which font in `font_data` is being used, if it's a collection.
+    #[allow(dead_code)] // only used with some features enabled
+    collection_index: u32,
+    /// Fonts added with `add_font`, font index 1 onwards.
+    extra_fonts: Vec<ExtraFont>,
+    /// See `set_fallback_fonts`.
+    fallback_fonts: Vec<usize>,
+    /// Shared between clones of the renderer, so a glyph only ever gets rasterized once.
+    glyph_caches: Arc<RwLock<HashMap<CacheKey, GlyphCache<G>>>>,
+    /// Not shared between clones, each one counts its own draws.
+    metrics: RenderMetrics,
+    /// See `set_gamma`.
+    gamma: Option<f32>,
+    /// See `TextRendererBuilder::pixel_order`.
+    pixel_order: PixelOrder,
+    /// See `TextRendererBuilder::premultiplied_alpha`.
+    premultiplied_alpha: bool,
+    /// See `TextRendererBuilder::subpixel_positions`.
+    subpixel_positions: u8,
+    /// See `TextRendererBuilder::subpixel_order`.
+    subpixel_order: SubpixelOrder,
+    /// See `TextRendererBuilder::coordinate_system`.
+    coordinate_system: CoordinateSystem,
+    /// See `TextRendererBuilder::glyph_cache_format`.
+    glyph_cache_format: GlyphCacheFormat,
+    /// See `TextRendererBuilder::cache_policy`.
+    cache_policy: CachePolicy,
+    /// See `TextRendererBuilder::max_size`.
+    max_size: f32,
+    /// See `draw_string_cached`.
+    string_cache: StringCache<A>,
+    /// Everything pasted since the last `take_dirty_rects`, if `track_dirty_rects` is on.
+    dirty_rects: Option<Vec<Rect>>,
+    /// See `set_clip`.
+    clip: Option<Rect>,
+    /// See `set_glyph_provider`.
+    glyph_provider: Option<Arc<GlyphProviderFn>>,
+    /// Everything `glyph_provider` has been asked about, shared between clones like `glyph_caches`.
+    custom_glyphs: CustomGlyphCache<G>,
+    /// See `tabular_advance`, by font index and `f32::to_bits` of the size. Shared between clones like `glyph_caches`.
+    tabular_advances: Arc<RwLock<HashMap<(usize, u32), f32>>>,
     phantom: std::marker::PhantomData<A>,
 }
 
-/// Internal struct, contains a `HashMap` of `TextColour` to a `HashMap` of `char` to (raw glyph data, `DrawableSurface`).
-/// This is because, historically as SDL2 surfaces were used, it was important to keep the raw glyph data alive so that
-/// less memory copying was required for SDL2 surfaces. It is thus recommended that you do not copy the raw glyph data,
-/// and instead attempt to borrow it within your `DrawableSurface` implementation. (which we didn't do in our test implementation cause we were lazy)
+/// Internal struct, a font added with `TextRenderer::add_font` and the raw data it was loaded from.
+#[derive(Clone)]
+struct ExtraFont {
+    font: Arc<Font>,
+    #[allow(dead_code)] // only used with some features enabled
+    data: Arc<Vec<u8>>,
+    #[allow(dead_code)] // only used with some features enabled
+    collection_index: u32,
+}
+
+/// Internal struct, what the glyph caches are split up by.
+/// Anything that changes how a glyph gets rasterized has to be in here so we don't hand out stale bitmaps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    /// Which font the glyph comes from, as glyph indices mean something different in every font.
+    font: usize,
+    /// `f32::to_bits` of the pixel size, so fractional sizes don't share glyphs.
+    size: u32,
+    /// `f32::to_bits` of the gamma, if there is one. Always `None` for `GlyphCacheFormat::Alpha`,
+    /// as gamma depends on the colour so it's applied when the glyph is drawn.
+    gamma: Option<u32>,
+    /// Whether the bitmap is stored bottom row first, for `CoordinateSystem::PositiveYUp`.
+    flipped: bool,
+    /// `f32::to_bits` of how many pixels thick the outline is for `FillMode::Outline`, so hollow glyphs
+    /// don't get mixed up with filled ones. `None` for filled glyphs.
+    outline: Option<u32>,
+    /// `f32::to_bits` of the radius of `DrawOptions::blur`, as blurred glyphs are bigger than sharp ones too.
+    blur: Option<u32>,
+    /// How many `TextRendererBuilder::subpixel_positions` the glyph is shifted to the right, 0 for whole pixels.
+    subpixel: u8,
+}
+
+/// Internal struct, everything from the options that changes what a glyph's bitmap looks like (rather than just
+/// where it goes or what colour it is), so it can be handed to everything between drawing and rasterizing in one go.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct GlyphEffects {
+    fill_mode: FillMode,
+    blur: Option<f32>,
+}
+
+impl GlyphEffects {
+    /// The effects the options ask for.
+    pub(crate) fn from_options(options: &DrawOptions) -> Self {
+        Self { fill_mode: options.fill_mode, blur: options.blur }
+    }
+
+    /// How many pixels thick the outline of a hollow glyph at `size` pixels is, see `FillMode::outline_thickness`.
+    fn outline(&self, size: f32) -> Option<f32> {
+        self.fill_mode.outline_thickness(size)
+    }
+
+    /// The blur radius, if there's one that would actually blur anything.
+    fn blur(&self) -> Option<f32> {
+        self.blur.filter(|radius| *radius > 0.0 && radius.is_finite())
+    }
+
+    /// How many pixels bigger the bitmap of a glyph is on every side than the glyph itself, so a blur isn't cut off.
+    pub(crate) fn padding(&self) -> usize {
+        self.blur().map_or(0, |radius| radius.ceil() as usize)
+    }
+
+    /// The `CacheKey::outline` and `CacheKey::blur` of a glyph at `size` pixels.
+    fn cache_bits(&self, size: f32) -> (Option<u32>, Option<u32>) {
+        (self.outline(size).map(f32::to_bits), self.blur().map(f32::to_bits))
+    }
+}
+
+/// Internal struct, contains a `HashMap` of `TextColour` to a `HashMap` of glyph index to `CachedGlyph`.
+/// Glyphs are keyed by their index in the font rather than by `char`, as one character can map to
+/// several different glyphs once shaping is involved.
 #[derive(Clone)]
 #[allow(dead_code)] // listen i'll use it at some point okay!
 struct GlyphCache<T> {
     pub size: f32,
-    pub surface_map: HashMap<TextColour, HashMap<char, (Vec<u8>, T)>>,
+    pub surface_map: HashMap<TextColour, HashMap<u16, CachedGlyph<T>>>,
+    /// Glyph index to coverage, one byte per pixel, for `GlyphCacheFormat::Alpha`.
+    pub mask_map: HashMap<u16, Arc<[u8]>>,
+    /// Glyph index to red, green and blue coverage, three bytes per pixel, for `GlyphCacheFormat::Lcd`.
+    pub lcd_map: HashMap<u16, Arc<[u8]>>,
+    /// (glyph index, spread, channels) to distance field, for `TextRenderer::glyph_sdf` (1 channel)
+    /// and `TextRenderer::glyph_msdf` (3 channels).
+    pub sdf_map: HashMap<(u16, usize, usize), GlyphSdf>,
+    /// (glyph index, rotation) to coverage, for `TextRenderer::draw_string_on_path`.
+    pub rotated_map: HashMap<(u16, u16), RotatedMask>,
+}
+
+impl<T> GlyphCache<T> {
+    /// Internal function to make an empty cache for glyphs `size` pixels big.
+    fn new(size: f32) -> Self {
+        GlyphCache {
+            size,
+            surface_map: HashMap::new(),
+            mask_map: HashMap::new(),
+            lcd_map: HashMap::new(),
+            sdf_map: HashMap::new(),
+            rotated_map: HashMap::new(),
+        }
+    }
+}
+
+/// Internal struct, a glyph in the glyph cache.
+/// Historically as SDL2 surfaces were used, it was important to keep the raw glyph data alive so that
+/// less memory copying was required for SDL2 surfaces, which is what `CachePolicy::KeepRawBytes` is for.
+/// It is thus recommended that you do not copy the raw glyph data, and instead attempt to borrow it within your
+/// `DrawableSurface` implementation. (which we didn't do in our test implementation cause we were lazy)
+#[derive(Clone)]
+struct CachedGlyph<T> {
+    /// The RGBA bytes `surface` was made from, unless the renderer uses `CachePolicy::SurfaceOnly`.
+    raw: Option<Vec<u8>>,
+    surface: T,
+    /// Width * height of the glyph, for estimating how big `surface` is.
+    pixels: usize,
 }
 
 /// A "surface" that you can draw pixels to.
 /// Historically, this was an SDL2 surface, but it has been abstracted out to allow for other backends.
+/// SDL2 surfaces still work out of the box with the `sdl2` feature, see `sext::sdl`.
 /// The generic `D` is the type of the object that contains the glyph data, usually something that implements StoreSurface.
 pub trait PasteSurface<D> {
     /// This function will be called to "paste" a glyph upon the surface.
@@ -44,6 +253,112 @@ pub trait PasteSurface<D> {
     /// `data` is *usually* in reference to another `DrawableSurface` that contains the glyph data.
     /// however, in some cases, it may reference something else which is why we have the `D` generic.
     fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &D);
+
+    /// Same as `paste`, but only pastes part of the glyph. This is called instead of `paste` when a glyph
+    /// is partially cut off by a clip rect.
+    /// `dst_x` and `dst_y` are where the visible part of the glyph goes on this surface,
+    /// `src_x` and `src_y` are where the visible part starts inside of `data`,
+    /// and `width`/`height` are the size of the visible part.
+    /// The default implementation just calls `paste` when the visible part starts at the top left of the glyph
+    /// (so only the right/bottom got cut off), and otherwise skips the glyph as `paste` can't offset into `data`.
+    /// If your backend can copy from an offset, you should override this so glyphs cut off at the top/left show up.
+    #[allow(clippy::too_many_arguments)]
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &D) {
+        if src_x == 0 && src_y == 0 {
+            self.paste(dst_x, dst_y, width, height, data);
+        } else {
+            debug!("skipping glyph clipped at ({}, {}), backend doesn't implement paste_clipped", src_x, src_y);
+        }
+    }
+
+    /// How many pixels wide the surface is. If this returns a width, glyphs (and backgrounds and the like) that go past
+    /// the right edge are cut off before being pasted, so `paste` never gets anything outside of the surface.
+    /// The default implementation returns `None`, which means the renderer doesn't know and `paste` has to check for itself.
+    fn width(&self) -> Option<usize> {
+        None
+    }
+
+    /// How many pixels tall the surface is, see `width`.
+    fn height(&self) -> Option<usize> {
+        None
+    }
+
+    /// Creates a new, fully transparent surface, for `TextRenderer::render_to_surface`.
+    /// The default implementation makes one out of an empty RGBA mask with `StoreSurface::from_raw_mask`,
+    /// so it's only there if your surface can store glyphs too. Override it if there's a faster way to make a blank one.
+    fn new_empty(width: usize, height: usize) -> Self where Self: Sized + StoreSurface {
+        Self::from_raw_mask(width, height, &vec![0; width * height * 4], TextColour::new(0, 0, 0, 0))
+    }
+
+    /// This is what actually gets called for every glyph, with the `BlendMode` from the draw options.
+    /// Arguments are the same as `paste_clipped`, and `src_x`/`src_y` are 0 when the glyph isn't cut off.
+    /// Text is expected to be composited with `BlendMode::AlphaOver` by default, so transparent pixels
+    /// around a glyph don't punch holes in whatever was drawn before it.
+    /// The default implementation ignores `mode` and calls `paste` (or `paste_clipped` if the top/left of the glyph
+    /// got cut off), leaving the blending up to those. If your backend has plain RGBA buffers,
+    /// `sext::blit::paste_rgba` takes these same arguments and does all of the modes (and the clipping) for you.
+    #[allow(clippy::too_many_arguments)]
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &D, mode: BlendMode) {
+        let _ = mode;
+        if src_x == 0 && src_y == 0 {
+            self.paste(dst_x, dst_y, width, height, data);
+        } else {
+            self.paste_clipped(dst_x, dst_y, src_x, src_y, width, height, data);
+        }
+    }
+
+    /// Called instead of `paste_blended` for every glyph when the renderer uses `GlyphCacheFormat::Alpha`.
+    /// `mask` is the whole glyph as one byte of coverage per pixel, `mask_width` pixels wide,
+    /// and it should be drawn in `colour` (with the colour's alpha multiplied into the coverage).
+    /// The other arguments are the same as `paste_blended`.
+    /// The default implementation turns the mask into a coloured `StoreSurface` (with the bytes of each pixel in `pixel_order`)
+    /// and passes that to `paste_blended`, so the output is exactly the same as with `GlyphCacheFormat::Rgba`.
+    /// If your backend can tint a mask while drawing it, override this to skip building the surface every time.
+    #[allow(clippy::too_many_arguments)]
+    fn paste_mask(
+        &mut self,
+        dst_x: usize,
+        dst_y: usize,
+        src_x: usize,
+        src_y: usize,
+        width: usize,
+        height: usize,
+        mask: &[u8],
+        mask_width: usize,
+        colour: TextColour,
+        pixel_order: PixelOrder,
+        mode: BlendMode
+    ) where D: StoreSurface {
+        let data = coloured_pixels(mask, colour, pixel_order, false);
+        let bitmap = D::from_raw_mask(mask_width, mask.len() / mask_width.max(1), &data, colour);
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, &bitmap, mode);
+    }
+
+    /// Called instead of `paste_mask` for every glyph when the renderer uses `GlyphCacheFormat::Lcd`.
+    /// `coverage` has three bytes per pixel instead of one: how much of the red, green and blue subpixels the glyph covers,
+    /// always in that order (they've already been swapped around for `SubpixelOrder::Bgr`). Every channel of the surface
+    /// should be blended towards `colour` by its own coverage (times the colour's alpha). The other arguments are the same
+    /// as `paste_mask`. The default implementation averages the three into one byte of coverage and calls `paste_mask`,
+    /// which works everywhere but looks like plain greyscale antialiasing. `sext::blit::blend_lcd` does it properly for
+    /// plain RGBA buffers.
+    #[allow(clippy::too_many_arguments)]
+    fn paste_lcd(
+        &mut self,
+        dst_x: usize,
+        dst_y: usize,
+        src_x: usize,
+        src_y: usize,
+        width: usize,
+        height: usize,
+        coverage: &[u8],
+        mask_width: usize,
+        colour: TextColour,
+        pixel_order: PixelOrder,
+        mode: BlendMode
+    ) where D: StoreSurface {
+        let mask = coverage.chunks_exact(3).map(|rgb| ((rgb[0] as u16 + rgb[1] as u16 + rgb[2] as u16) / 3) as u8).collect::<Vec<_>>();
+        self.paste_mask(dst_x, dst_y, src_x, src_y, width, height, &mask, mask_width, colour, pixel_order, mode);
+    }
 }
 
 /// A "surface" for storing glyph data.
@@ -51,55 +366,444 @@ pub trait PasteSurface<D> {
 pub trait StoreSurface {
     /// This function takes in raw RGBA bytes and creates a `StoreSurface` from them.
     /// The `width` and `height` are the dimensions of the surface.
-    /// The `data` parameter is a slice of bytes that contains the RGBA data.
+    /// The `data` parameter is a slice of bytes that contains the RGBA data, 4 bytes per pixel.
+    /// The bytes of each pixel are in the order set with `TextRendererBuilder::pixel_order` (RGBA unless you changed it).
     /// The `colour` parameter is a `TextColour` that will be used to colour the surface.
     /// There is little reason to actually care about the `colour` parameter, as it is only used for caching.
     /// Check the tests section of this library for an example of how to use this function.
     fn from_raw_mask(width: usize, height: usize, data: &[u8], colour: TextColour) -> Self;
 }
 
-/// Enum for the different (1) possible errors that you could get while constructing a TextRenderer.
-#[derive(Debug, Clone, Copy)]
+/// A rectangle on a surface, in pixels. `x` and `y` are the top left corner and may be negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Returns the overlapping part of two rects, or `None` if they don't overlap at all.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let left = self.x.max(other.x);
+        let top = self.y.max(other.y);
+        let right = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+        if right <= left as i64 || bottom <= top as i64 {
+            return None;
+        }
+        Some(Rect::new(left, top, (right - left as i64) as usize, (bottom - top as i64) as usize))
+    }
+
+    /// Returns the smallest rect that covers both rects.
+    pub fn union(&self, other: &Rect) -> Rect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x as i64 + self.width as i64).max(other.x as i64 + other.width as i64);
+        let bottom = (self.y as i64 + self.height as i64).max(other.y as i64 + other.height as i64);
+        Rect::new(left, top, (right - left as i64) as usize, (bottom - top as i64) as usize)
+    }
+}
+
+/// The biggest text size (in pixels) that gets drawn or measured unless you change it with `TextRendererBuilder::max_size`.
+pub const DEFAULT_MAX_SIZE: f32 = 4096.0;
+
+/// The usual gamma for sRGB displays, a good value to pass to `TextRenderer::set_gamma` if you don't want to tune it.
+pub const DEFAULT_GAMMA: f32 = 2.2;
+
+/// Enum for the different possible errors that you could get while constructing or using a TextRenderer.
+#[derive(Debug)]
 pub enum TextRendererError {
+    /// A font that was asked for by name isn't there, like a family that isn't installed (see `TextRenderer::from_family`).
     FontNotFound,
+    /// The font file couldn't be read.
+    IoError(std::io::Error),
+    /// The font's data couldn't be parsed, with the reason fontdue (or ttf-parser) gave.
+    FontParseError(String),
+    /// A `FontOptions::collection_index` past the last font in the file. Files that aren't collections have one font.
+    InvalidCollectionIndex { index: u32, fonts: u32 },
+    /// A variable font axis or named instance the font doesn't have (see `TextRendererBuilder::variation`),
+    /// with the axis tag or instance name that was asked for. Fonts that aren't variable don't have any.
+    UnknownVariation(String),
+    /// The job loading a font was dropped without being run, see `TextRenderer::load_async`.
+    Cancelled,
+    /// A text size that's NaN, infinite, zero or less, or bigger than the renderer's max size (see `TextRenderer::check_size`).
+    InvalidSize(f32),
+    /// Draw commands needed a glyph that isn't in the glyph cache anymore, see `TextRenderer::execute_commands`.
+    StaleCommands,
 }
 
-/// Internal function to convert the fontdue grayscale bitmaps to our superior RGBA bitmaps
-fn cache_glyph<T>(font: Arc<Font>, glyph: GlyphPosition, colour: TextColour, make_t: impl FnOnce(&[u8]) -> T) -> (Vec<u8>, T) {
-    debug!("caching glyph: {:?}", glyph);
-    let (_metrics, mut bitmap) = font.rasterize_config(glyph.key);
-    let mut coloured_pixels = Vec::new();
-    for pixel in bitmap.iter_mut() {
-        coloured_pixels.push(colour.r); // u8
-        coloured_pixels.push(colour.g); // u8
-        coloured_pixels.push(colour.b); // u8
-        coloured_pixels.push(*pixel); // u8
+impl std::fmt::Display for TextRendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextRendererError::FontNotFound => write!(f, "font not found"),
+            TextRendererError::IoError(e) => write!(f, "couldn't read font file: {}", e),
+            TextRendererError::FontParseError(reason) => write!(f, "couldn't parse font: {}", reason),
+            TextRendererError::InvalidCollectionIndex { index, fonts } => {
+                write!(f, "font collection index {} is out of range, the file only has {} font(s)", index, fonts)
+            }
+            TextRendererError::UnknownVariation(variation) => write!(f, "the font doesn't have a variation called {:?}", variation),
+            TextRendererError::Cancelled => write!(f, "the job loading the font was dropped without being run"),
+            TextRendererError::InvalidSize(size) => write!(f, "{} isn't a text size that can be drawn", size),
+            TextRendererError::StaleCommands => write!(f, "draw commands need glyphs that aren't in the glyph cache anymore"),
+        }
+    }
+}
+
+impl std::error::Error for TextRendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TextRendererError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Internal function to read a font file, so every way of loading one fails the same way.
+pub(crate) fn read_font_file(font_path: &str) -> Result<Vec<u8>, TextRendererError> {
+    std::fs::read(font_path).map_err(TextRendererError::IoError)
+}
+
+/// Internal function to count the faces in a font file: the count in the header of a font collection (.ttc), otherwise 1.
+fn face_count(font_data: &[u8]) -> u32 {
+    match font_data.get(..12) {
+        Some([b't', b't', b'c', b'f', _, _, _, _, count @ ..]) => u32::from_be_bytes([count[0], count[1], count[2], count[3]]),
+        _ => 1,
+    }
+}
+
+/// Internal function to parse a font with fontdue, so every way of loading one fails the same way.
+/// fontdue quietly uses the only face of a file that isn't a collection whatever the collection index is,
+/// so that's checked here instead.
+pub(crate) fn parse_font(font_data: &[u8], options: &FontOptions) -> Result<Font, TextRendererError> {
+    let fonts = face_count(font_data);
+    if options.collection_index >= fonts {
+        return Err(TextRendererError::InvalidCollectionIndex { index: options.collection_index, fonts });
+    }
+    let settings = FontSettings {
+        collection_index: options.collection_index,
+        scale: options.scale,
+    };
+    Font::from_bytes(font_data, settings).map_err(|reason| TextRendererError::FontParseError(reason.to_string()))
+}
+
+/// Internal function to paste the `visible` part of a glyph that would be at `dst` onto the surface.
+fn paste_visible<G, A: PasteSurface<G>>(surface: &mut A, dst: Rect, visible: Rect, bitmap: &G, mode: BlendMode) {
+    surface.paste_blended(
+        visible.x as usize,
+        visible.y as usize,
+        (visible.x - dst.x) as usize,
+        (visible.y - dst.y) as usize,
+        visible.width,
+        visible.height,
+        bitmap,
+        mode,
+    );
+}
+
+/// Internal function to convert the fontdue grayscale bitmaps (from `rasterize_mask`) to our superior RGBA bitmaps
+fn cache_glyph<T>(
+    mut bitmap: Vec<u8>,
+    colour: TextColour,
+    gamma: Option<f32>,
+    pixel_order: PixelOrder,
+    premultiplied: bool,
+    make_t: impl FnOnce(&[u8]) -> T
+) -> (Vec<u8>, T) {
+    if let Some(gamma) = gamma {
+        adjust_coverage(&mut bitmap, colour, gamma);
     }
+    let coloured_pixels = coloured_pixels(&bitmap, colour, pixel_order, premultiplied);
     // create T from bitmap
     let t = make_t(&coloured_pixels);
     (coloured_pixels, t)
 }
 
+/// Internal function to rasterize a glyph into a coverage mask, upside down if `flipped`, and with any `effects` applied:
+/// hollowed out for `FillMode::Outline`, then blurred for `DrawOptions::blur` (which makes it `effects.padding()` pixels
+/// bigger on every side).
+fn rasterize_mask(font: &Font, glyph: GlyphRasterConfig, flipped: bool, effects: GlyphEffects) -> Vec<u8> {
+    debug!("caching glyph: {:?}", glyph);
+    let (metrics, mut bitmap) = font.rasterize_config(glyph);
+    if flipped && metrics.width > 0 {
+        bitmap = bitmap.chunks_exact(metrics.width).rev().flatten().copied().collect();
+    }
+    if let Some(thickness) = effects.outline(glyph.px) {
+        bitmap = hollow_mask(&bitmap, metrics.width, thickness);
+    }
+    if let Some(radius) = effects.blur() {
+        bitmap = blur_mask(&bitmap, metrics.width, metrics.height, radius);
+    }
+    bitmap
+}
+
+/// Internal function to rasterize a glyph at three times the horizontal resolution, into three bytes of coverage per pixel
+/// in red, green, blue order for a screen with its subpixels in `order`. Upside down if `flipped`, like `rasterize_mask`.
+fn rasterize_lcd(font: &Font, glyph: GlyphRasterConfig, flipped: bool, order: SubpixelOrder) -> Vec<u8> {
+    debug!("caching lcd glyph: {:?}", glyph);
+    let (metrics, mut bitmap) = font.rasterize_config_subpixel(glyph);
+    if flipped && metrics.width > 0 {
+        bitmap = bitmap.chunks_exact(metrics.width * 3).rev().flatten().copied().collect();
+    }
+    // fontdue goes left to right, which is blue first on a bgr screen
+    if order == SubpixelOrder::Bgr {
+        bitmap.chunks_exact_mut(3).for_each(|subpixels| subpixels.swap(0, 2));
+    }
+    bitmap
+}
+
+/// Internal function to blur a coverage mask `width` by `height` pixels with a gaussian, one direction at a time.
+/// The gaussian is cut off `radius` pixels out (three standard deviations, where there's barely anything left of it),
+/// and the returned mask is that many pixels (rounded up) bigger on every side so none of the blur gets cut off.
+fn blur_mask(mask: &[u8], width: usize, height: usize, radius: f32) -> Vec<u8> {
+    let padding = radius.ceil() as usize;
+    let (padded_width, padded_height) = (width + padding * 2, height + padding * 2);
+    if width == 0 || height == 0 {
+        return vec![0; padded_width * padded_height];
+    }
+    let sigma = radius / 3.0;
+    let kernel = (0..padding * 2 + 1).map(|i| {
+        let distance = i as f32 - padding as f32;
+        (-distance * distance / (2.0 * sigma * sigma)).exp()
+    }).collect::<Vec<_>>();
+    let total = kernel.iter().sum::<f32>();
+    let kernel = kernel.into_iter().map(|weight| weight / total).collect::<Vec<_>>();
+
+    let mut padded = vec![0.0f32; padded_width * padded_height];
+    for (y, row) in mask.chunks_exact(width).enumerate() {
+        let start = (y + padding) * padded_width + padding;
+        padded[start..start + width].iter_mut().zip(row).for_each(|(padded, coverage)| *padded = *coverage as f32);
+    }
+    // `step` is 1 to blur across and `padded_width` to blur down, everything past the edges counts as uncovered
+    let blur = |source: &[f32], step: usize, length: usize| -> Vec<f32> {
+        (0..source.len()).map(|i| {
+            let position = if step == 1 { i % padded_width } else { i / padded_width };
+            kernel.iter().enumerate().map(|(k, weight)| {
+                let offset = position as isize + k as isize - padding as isize;
+                if offset < 0 || offset >= length as isize {
+                    return 0.0;
+                }
+                weight * source[(i as isize + (k as isize - padding as isize) * step as isize) as usize]
+            }).sum()
+        }).collect()
+    };
+    let across = blur(&padded, 1, padded_width);
+    blur(&across, padded_width, padded_height).into_iter().map(|coverage| coverage.round().clamp(0.0, 255.0) as u8).collect()
+}
+
+/// Internal function to hollow out a coverage mask `width` pixels wide, leaving a ring `thickness` pixels thick
+/// around the inside of its edges. It's the mask minus an erosion of it (with a round brush, so corners stay round),
+/// and a fractional thickness blends between the erosions either side of it.
+fn hollow_mask(mask: &[u8], width: usize, thickness: f32) -> Vec<u8> {
+    if mask.is_empty() || width == 0 {
+        return mask.to_vec();
+    }
+    let height = mask.len() / width;
+    // anything thicker than the glyph erodes all of it anyway
+    let thickness = thickness.clamp(1.0, width.max(height) as f32);
+    let (inner, fraction) = (thickness.floor(), thickness.fract());
+    let eroded = erode_mask(mask, width, height, inner as usize);
+    let eroded_more = (fraction > 0.0).then(|| erode_mask(mask, width, height, inner as usize + 1));
+    mask.iter().enumerate().map(|(i, coverage)| {
+        let eroded = match &eroded_more {
+            Some(eroded_more) => eroded[i] as f32 * (1.0 - fraction) + eroded_more[i] as f32 * fraction,
+            None => eroded[i] as f32,
+        };
+        (*coverage as f32 - eroded).round().max(0.0) as u8
+    }).collect()
+}
+
+/// Internal function to erode a coverage mask: every pixel becomes the least covered pixel within `radius` of it,
+/// counting everything outside of the mask as uncovered.
+fn erode_mask(mask: &[u8], width: usize, height: usize, radius: usize) -> Vec<u8> {
+    let radius = radius as isize;
+    let brush = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| dx * dx + dy * dy <= radius * radius)
+        .collect::<Vec<_>>();
+    (0..mask.len()).map(|i| {
+        let (x, y) = ((i % width) as isize, (i / width) as isize);
+        let mut least = u8::MAX;
+        for (dx, dy) in brush.iter() {
+            let (x, y) = (x + dx, y + dy);
+            let coverage = if x < 0 || y < 0 || x >= width as isize || y >= height as isize { 0 } else { mask[y as usize * width + x as usize] };
+            least = least.min(coverage);
+            if least == 0 {
+                break;
+            }
+        }
+        least
+    }).collect()
+}
+
+/// Internal function to turn a coverage mask into RGBA pixels (in `pixel_order`, and `premultiplied` or not) of the given colour.
+fn coloured_pixels(mask: &[u8], colour: TextColour, pixel_order: PixelOrder, premultiplied: bool) -> Vec<u8> {
+    let mut coloured_pixels = Vec::with_capacity(mask.len() * 4);
+    for pixel in mask.iter() {
+        // the colour's own alpha makes the whole glyph see-through
+        let alpha = (*pixel as u16 * colour.a as u16 / 255) as u8;
+        coloured_pixels.extend(pixel_bytes(pixel_order, premultiplied, colour.r, colour.g, colour.b, alpha)); // u8 x4
+    }
+    coloured_pixels
+}
+
+/// Internal function to make up for coverage being blended in sRGB space instead of linear light.
+/// Blending in sRGB makes dark text come out too heavy and light text too thin, so coverage gets raised to a power
+/// between `gamma` (for black text, thinning it out) and `1 / gamma` (for white text, thickening it).
+fn adjust_coverage(bitmap: &mut [u8], colour: TextColour, gamma: f32) {
+    let luminance = (0.2126 * colour.r as f32 + 0.7152 * colour.g as f32 + 0.0722 * colour.b as f32) / 255.0;
+    let exponent = gamma.powf(1.0 - 2.0 * luminance);
+    for pixel in bitmap.iter_mut() {
+        *pixel = ((*pixel as f32 / 255.0).powf(exponent) * 255.0).round() as u8;
+    }
+}
+
 impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
     /// Loads a font from a specified path and creates a `TextRenderer` instance.
-    /// Will return `TextRendererError::FontNotFound` if the font could not be found.
-    /// Will also return a `TextRendererError::FontNotFound` if the font could not be loaded, because i haven't added other errors yet.
+    /// Will return `TextRendererError::IoError` if the font could not be read, and `TextRendererError::FontParseError`
+    /// if it could not be loaded.
+    /// If you want to change any of the font settings, use `TextRendererBuilder` instead.
     pub fn load(font_path: &str) -> Result<Self, TextRendererError> {
-        let font_data = std::fs::read(font_path).map_err(|_| TextRendererError::FontNotFound)?;
-        let font = Font::from_bytes(font_data, FontSettings::default())
-            .map_err(|_| TextRendererError::FontNotFound)?;
-        let layout = Layout::new(CoordinateSystem::PositiveYDown);
-        Ok(TextRenderer {
+        TextRendererBuilder::new(font_path).build()
+    }
+
+    /// Same as `load`, but with font settings, like which face to use out of a font collection (.ttc).
+    /// Same as `TextRendererBuilder::new(font_path).font_options(*options).build()`.
+    pub fn load_with_options(font_path: &str, options: &FontOptions) -> Result<Self, TextRendererError> {
+        TextRendererBuilder::new(font_path).font_options(*options).build()
+    }
+
+    /// Same as `load`, but for a font that's already in memory (e.g. from `include_bytes!` or the network),
+    /// so the filesystem is never touched. The bytes are copied, so they don't have to outlive the renderer.
+    /// Same as `TextRendererBuilder::from_bytes(font_data.to_vec()).build()`.
+    pub fn from_bytes(font_data: &[u8]) -> Result<Self, TextRendererError> {
+        TextRendererBuilder::from_bytes(font_data.to_vec()).build()
+    }
+
+    /// Creates a `TextRenderer` around a font you've already loaded with fontdue, with the default settings.
+    /// fontdue doesn't hand back the bytes a font was loaded from, so anything that needs them gets nothing:
+    /// `shape_string` returns no glyphs and `glyph_outline` returns `None`. Use `from_bytes` if you need those.
+    pub fn from_font(font: Font) -> Self {
+        Self::from_parsed_font(font, Vec::new(), &FontOptions::default())
+    }
+
+    /// Internal function to create a `TextRenderer` from the raw bytes of a font, used by `TextRendererBuilder`.
+    pub(crate) fn from_font_data(font_data: Vec<u8>, options: &FontOptions) -> Result<Self, TextRendererError> {
+        let font = parse_font(&font_data, options)?;
+        Ok(Self::from_parsed_font(font, font_data, options))
+    }
+
+    /// Internal function to create a `TextRenderer` around a font that's already been parsed from `font_data`.
+    pub(crate) fn from_parsed_font(font: Font, font_data: Vec<u8>, options: &FontOptions) -> Self {
+        let layout = Layout::new(FontdueCoordinates::PositiveYDown);
+        TextRenderer {
             font: Arc::new(font),
             layout: Arc::new(layout),
-            glyph_caches: HashMap::new(),
+            font_data: Arc::new(font_data),
+            collection_index: options.collection_index,
+            extra_fonts: Vec::new(),
+            fallback_fonts: Vec::new(),
+            glyph_caches: Arc::new(RwLock::new(HashMap::new())),
+            metrics: RenderMetrics::default(),
+            gamma: None,
+            pixel_order: PixelOrder::default(),
+            premultiplied_alpha: false,
+            subpixel_positions: 1,
+            subpixel_order: SubpixelOrder::default(),
+            coordinate_system: CoordinateSystem::default(),
+            glyph_cache_format: GlyphCacheFormat::default(),
+            cache_policy: CachePolicy::default(),
+            max_size: DEFAULT_MAX_SIZE,
+            dirty_rects: None,
+            clip: None,
+            glyph_provider: None,
+            custom_glyphs: Arc::new(RwLock::new(HashMap::new())),
+            tabular_advances: Arc::new(RwLock::new(HashMap::new())),
+            string_cache: StringCache::default(),
             phantom: Default::default()
-        })
+        }
+    }
+
+    /// Loads another font from a file so spans can use it (see `TextSpan::font`), and returns its font index.
+    /// The renderer's own font is always index 0, so the first font added is 1, the next is 2 and so on.
+    /// Fails the same way `load` does.
+    /// Clones made before this is called don't get the new font.
+    pub fn add_font(&mut self, font_path: &str, options: &FontOptions) -> Result<usize, TextRendererError> {
+        self.add_font_from_bytes(read_font_file(font_path)?, options)
+    }
+
+    /// Same as `add_font`, but for a font that's already in memory.
+    pub fn add_font_from_bytes(&mut self, font_data: Vec<u8>, options: &FontOptions) -> Result<usize, TextRendererError> {
+        let font = parse_font(&font_data, options)?;
+        self.extra_fonts.push(ExtraFont {
+            font: Arc::new(font),
+            data: Arc::new(font_data),
+            collection_index: options.collection_index,
+        });
+        Ok(self.extra_fonts.len())
+    }
+
+    /// Returns how many fonts there are, counting the renderer's own font.
+    pub fn font_count(&self) -> usize {
+        self.extra_fonts.len() + 1
+    }
+
+    /// Sets which fonts (by font index, see `add_font`) get tried, in order, for characters missing from the font
+    /// they'd normally be drawn with. The first one that has the character is used, and if none of them do
+    /// the normal font draws it anyway (usually as a box). Font indices that don't exist are skipped.
+    /// Whitespace stays in the font of the text around it, so spaces don't change width next to a fallback character.
+    /// Strings cached by `draw_string_cached` are thrown away, as they might look different now.
+    /// Fallback fonts aren't used by `draw_char` or `shape_string`.
+    pub fn set_fallback_fonts(&mut self, font_indices: &[usize]) {
+        self.fallback_fonts = font_indices.to_vec();
+        self.clear_string_cache();
+    }
+
+    /// The fonts set with `set_fallback_fonts`, in the order they're tried.
+    pub fn fallback_fonts(&self) -> &[usize] {
+        &self.fallback_fonts
+    }
+
+    /// Internal function to get the index of the font that `c` gets drawn with in text that's meant to use `font_index`,
+    /// going through the fallback fonts if that font doesn't have it (see `set_fallback_fonts`).
+    pub(crate) fn font_for_char(&self, font_index: usize, c: char) -> usize {
+        if self.fallback_fonts.is_empty() || c.is_control() || self.font_at(font_index).lookup_glyph_index(c) != 0 {
+            return font_index;
+        }
+        self.fallback_fonts.iter().copied()
+            .find(|&fallback| fallback < self.font_count() && self.font_at(fallback).lookup_glyph_index(c) != 0)
+            .unwrap_or(font_index)
+    }
+
+    /// Internal function to get a font by its index. Indices that don't exist get the renderer's own font,
+    /// so a span with a bad font index still shows up.
+    pub(crate) fn font_at(&self, font_index: usize) -> &Arc<Font> {
+        match font_index.checked_sub(1).and_then(|i| self.extra_fonts.get(i)) {
+            Some(extra) => &extra.font,
+            None => &self.font,
+        }
+    }
+
+    /// Internal function to get the raw data and collection index of a font by its index, same as `font_at`.
+    #[allow(dead_code)] // only used with some features enabled
+    pub(crate) fn font_data_at(&self, font_index: usize) -> (&[u8], u32) {
+        match font_index.checked_sub(1).and_then(|i| self.extra_fonts.get(i)) {
+            Some(extra) => (&extra.data, extra.collection_index),
+            None => (&self.font_data, self.collection_index),
+        }
     }
 
     /// Same as `draw_string`, but forces each character to be rendered at the same width.
     /// This can cause some minor visual artifacts, but is useful for some cases where i'm lazy.
     /// Notable warning: this will currently cause each character to have a kerning of 0.
+    /// Cells are `size / 2` pixels wide, and characters take up as many of them as a terminal would give them
+    /// (going by their East Asian Width): CJK and other fullwidth characters take two cells and are centered across both,
+    /// combining marks don't take one at all and go on top of the character before them, and control characters are skipped.
     pub fn draw_string_monospaced(
         &mut self,
         string: &str,
@@ -109,29 +813,71 @@ impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clon
         colour: TextColour,
         surface: &mut A
     ) {
-        let mut layout_settings = LayoutSettings::default();
-        layout_settings.x = x;
-        layout_settings.y = y;
-        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
-        layout.reset(&layout_settings);
-        layout.append(&[self.font.clone()], &TextStyle::new(string, size, 0));
-        let glyphs = layout.glyphs();
-        for (glyph, i) in glyphs.iter().zip(0..) {
-            let bitmap = self.get_glyph_surface(*glyph, glyph.width, glyph.height, colour);
+        self.draw_string_monospaced_aligned(string, x, y, size, colour, Align::Left, surface);
+    }
+
+    /// Same as `draw_string_monospaced`, but `x` can be the right edge or the centre of the text instead of the left.
+    /// The text is as wide as all of the cells its characters take up, so right aligned numbers that change from "999"
+    /// to "1000" keep their last digit in exactly the same place.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_monospaced_aligned(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        align: Align,
+        surface: &mut A
+    ) {
+        let (glyphs, _) = self.layout_string(string, size, &DrawOptions::default());
+        let y = self.layout_y(y);
+        let cell = size / 2.0;
+        let columns = string.chars().filter_map(|c| c.width()).sum::<usize>();
+        let x = match align {
+            Align::Left | Align::Justify => x,
+            Align::Center => x - cell * columns as f32 / 2.0,
+            Align::Right => x - cell * columns as f32,
+        };
+        // the column the last character that took up any cells started at, and how many it took
+        let (mut column, mut span) = (0, 0);
+        for glyph in glyphs.iter() {
+            let (left, width) = match glyph.parent.width() {
+                None => continue,
+                // combining marks hang off of the pen position after the character they combine with
+                Some(0) => ((x + cell * (column + span) as f32 + glyph.x - self.pen_x(glyph)).floor(), glyph.width),
+                Some(cells) => {
+                    column += span;
+                    span = cells;
+                    let left = x + cell * column as f32;
+                    match cells {
+                        1 => (left.floor(), cell as usize),
+                        _ => {
+                            let cells_width = cell * cells as f32;
+                            ((left + ((cells_width - glyph.width as f32) / 2.0).max(0.0)).floor(), glyph.width.min(cells_width as usize))
+                        }
+                    }
+                }
+            };
+            let dst = self.surface_rect(Rect::new(left as i32, (y + glyph.y).floor() as i32, width, glyph.height));
+            let visible = match self.visible_rect(surface, dst, None) {
+                Some(visible) => visible,
+                None => continue,
+            };
+            let bitmap = self.get_glyph_surface(glyph.font_index, glyph.key, glyph.width, glyph.height, colour, GlyphEffects::default(), 0);
             // draw to surface
-            surface.paste(
-                (x + (size / 2.0) * i as f32) as usize,
-                (y + glyph.y) as usize,
-                (size / 2.0) as usize,
-                glyph.height as usize,
-                &bitmap,
-            );
+            paste_visible(surface, dst, visible, &bitmap, BlendMode::default());
+            self.mark_dirty(Some(visible));
         }
     }
 
     /// Draws a string using the default settings and fontdue's layout engine.
     /// In the future, this will probably have added systems for typesetting, but for now you'll have
     /// to live without being able to set the kerning of your text.
+    /// `x` and `y` can be negative, glyphs that end up partly off the top or left of the surface are cut off
+    /// (see `PasteSurface::paste_clipped`) and ones that are completely off of it aren't pasted at all.
+    /// The same goes for the right and bottom of the surface, if it says how big it is (see `PasteSurface::width`).
+    /// Nothing is drawn if `size` isn't one that can be (see `check_size`).
     pub fn draw_string(
         &mut self,
         string: &str,
@@ -141,144 +887,4167 @@ impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clon
         colour: TextColour,
         surface: &mut A
     ) {
-        let mut layout_settings = LayoutSettings::default();
-        layout_settings.x = x;
-        layout_settings.y = y;
-        let mut layout = Layout::new(CoordinateSystem::PositiveYDown);
-        layout.reset(&layout_settings);
-        layout.append(&[self.font.clone()], &TextStyle::new(string, size, 0));
-        let glyphs = layout.glyphs();
-        for glyph in glyphs.iter() {
-            let bitmap = self.get_glyph_surface(*glyph, glyph.width, glyph.height, colour);
-            // draw to surface
-            surface.paste(
-                (x + glyph.x) as usize,
-                (y + glyph.y) as usize,
-                glyph.width as usize,
-                glyph.height as usize,
-                &bitmap,
-            );
-        }
+        self.draw_string_with_options(string, x, y, size, colour, &DrawOptions::default(), surface);
     }
 
-    /// Internal function to get the glyph drawable from either the cache or the font
-    fn get_glyph_surface(
+    /// Same as `draw_string`, but `y` is where the baseline of the first line goes instead of the top of the text
+    /// (see `Origin::Baseline`), so text of different sizes drawn at the same `y` lines up.
+    pub fn draw_string_baseline(
         &mut self,
-        glpyh: GlyphPosition,
-        width: usize,
-        height: usize,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
         colour: TextColour,
-    ) -> G {
-        let size = height as u16;
-        // check if glyph cache exists
-        // if not create it
-        self.glyph_caches.entry(size).or_insert(GlyphCache {
-            size: size as f32,
-            surface_map: HashMap::new(),
-        });
-        // get glyph cache
-        // check if colour exists
-        // if not create it
-        let glyph_cache = self.glyph_caches.get_mut(&size).unwrap();
-        glyph_cache.surface_map.entry(colour).or_insert_with(|| HashMap::new());
-        // get colour map
-        // check if glyph exists
-        // if not create it
-        let colour_map = glyph_cache.surface_map.get_mut(&colour).unwrap();
-        if let std::collections::hash_map::Entry::Vacant(e) = colour_map.entry(glpyh.parent) {
-            e.insert(cache_glyph(self.font.clone(), glpyh, colour, |data| G::from_raw_mask(width, height, data, colour)));
-        }
-        // get glyph surface
-        let glyph_surface = colour_map.get(&glpyh.parent).unwrap();
-        // return glyph surface
-        glyph_surface.1.clone()
+        surface: &mut A
+    ) {
+        self.draw_string_with_options(string, x, y, size, colour, &DrawOptions { origin: Origin::Baseline, ..Default::default() }, surface);
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::io::Write;
-    use super::*;
 
-    #[derive(Debug, Clone)]
-    struct TestSurface {
-        width: usize,
-        height: usize,
-        data: Vec<u8>,
+    /// Same as `draw_string`, but takes a `DrawOptions` to change how the string is positioned and drawn.
+    /// Check the docs on `DrawOptions` for what you can change.
+    /// Returns where the text ended up, and whether any of it got cut off by `max_lines`/`max_height`.
+    /// If `size` isn't one that can be drawn this quietly draws nothing, use `try_draw_string_with_options` to find out about it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_with_options(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let text = self.layout_text(string, size, options);
+        self.draw_laid_out(&text, x, y, options, surface, |_| colour)
     }
 
-    impl PasteSurface<Self> for TestSurface {
-        fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Self) {
-            println!("paste: x: {}, y: {}, width: {}, height: {}, data: {:?}", x, y, width, height, data);
-            // data contains an rgba bitmap
-            let data_pitch = data.width as i32 * 4;
-            let pitch = self.width as i32 * 4;
-            let mut data_index = 0i32;
-            let mut index = (y as i32 * pitch) + (x as i32 * 4);
-            // WIDTH AND DATA WIDTH ARE DIFFERENT
-            for _ in 0..height {
-                for _ in 0..width {
-                    // if we're out of bounds on either surface, skip
-                    if index < 0 || index >= (self.width * self.height * 4) as i32 || data_index < 0 || data_index >= (data.width * data.height * 4) as i32 {
-                        data_index += 4;
-                        index += 4;
-                        continue;
-                    }
-                    self.data[index as usize] = data.data[data_index as usize];
-                    self.data[index as usize + 1] = data.data[data_index as usize + 1];
-                    self.data[index as usize + 2] = data.data[data_index as usize + 2];
-                    self.data[index as usize + 3] = data.data[data_index as usize + 3];
-                    data_index += 4;
-                    index += 4;
-                }
-                index += pitch - (width as i32 * 4);
-                data_index += data_pitch - (width as i32 * 4);
+    /// Same as `draw_string_with_options`, but calls `hook` for every line before anything is drawn on it (even the selection),
+    /// so it can fill backgrounds or draw markers underneath the text, like alternating row colours or a diff gutter.
+    /// Empty lines get called with too. `extent` is how wide the rects the hook gets are.
+    /// The hook isn't called for `Direction::Vertical` text.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_with_line_hook(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        extent: LineExtent,
+        surface: &mut A,
+        mut hook: impl FnMut(DrawnLine, &mut A)
+    ) -> DrawnBounds {
+        let text = self.layout_text(string, size, options);
+        if !text.vertical {
+            let (dx, dy) = text.origin_offset(options);
+            let (left, top) = (x + dx, self.layout_y(y) + dy);
+            let full_width = options.max_width.unwrap_or(text.metrics.width);
+            // every line goes down to the top of the next one, rounded the same way, so there's never a gap between them
+            let tops = text.metrics.lines.iter().map(|line| (top + line.y).round() as i32).collect::<Vec<_>>();
+            for (index, line) in text.metrics.lines.iter().enumerate() {
+                let bottom = tops.get(index + 1).copied().unwrap_or((top + line.y + line.height).round() as i32);
+                let width = match extent {
+                    LineExtent::MaxWidth => full_width,
+                    LineExtent::Line => line.width,
+                };
+                let line_left = match extent {
+                    LineExtent::MaxWidth => left,
+                    LineExtent::Line => left + line.x,
+                };
+                let rect = Rect::new(line_left.floor() as i32, tops[index], width.ceil() as usize, (bottom - tops[index]).max(0) as usize);
+                hook(DrawnLine {
+                    index,
+                    byte_range: line.byte_range.clone(),
+                    rect: self.surface_rect(rect),
+                    baseline: self.layout_y(top + line.baseline),
+                }, surface);
             }
         }
+        self.draw_laid_out(&text, x, y, options, surface, |_| colour)
     }
 
-    impl StoreSurface for TestSurface {
-        // data is rgba
-        fn from_raw_mask(width: usize, height: usize, data: &[u8], colour: TextColour) -> Self {
-            println!("from_raw_mask");
-            println!("width: {}", width);
-            println!("height: {}", height);
-            println!("data: {:?}", data);
-            TestSurface {
-                width,
-                height,
-                data: data.to_vec(),
-            }
-        }
+    /// Same as `draw_string_with_options`, but fails with `TextRendererError::InvalidSize` instead of quietly
+    /// drawing nothing if `size` isn't one that can be drawn (see `check_size`).
+    #[allow(clippy::too_many_arguments)]
+    pub fn try_draw_string_with_options(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> Result<DrawnBounds, TextRendererError> {
+        self.check_size(size)?;
+        Ok(self.draw_string_with_options(string, x, y, size, colour, options, surface))
     }
 
-    #[test]
-    fn test_text_renderer() {
-        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
-        let mut surface = TestSurface {
-            width: 256,
-            height: 256,
-            data: vec![0; 256 * 256 * 4],
+    /// Draws a string into a brand new surface that's exactly big enough to hold it (made with `PasteSurface::new_empty`),
+    /// and returns the surface along with where in it the text ended up.
+    /// The surface covers the measured size of the text and every glyph's bitmap, so glyphs with negative bearings
+    /// aren't cut off; the returned bounds' `x`/`y` say how far the top left of the text is from the top left of the surface.
+    /// `origin`, `anchor` and `clip` from the options aren't used, and neither is the clip rect from `set_clip`.
+    pub fn render_to_surface(&mut self, string: &str, size: f32, colour: TextColour, options: &DrawOptions) -> (A, DrawnBounds) where A: StoreSurface {
+        let options = DrawOptions { origin: Origin::TopLeft, anchor: Anchor::TopLeft, clip: None, ..options.clone() };
+        let text = self.layout_text(string, size, &options);
+        self.render_laid_out(&text, colour, &options)
+    }
+
+    /// Internal function to draw laid out text into a new surface that fits its ink bounds, see `render_to_surface`.
+    pub(crate) fn render_laid_out(&mut self, text: &LaidOutText, colour: TextColour, options: &DrawOptions) -> (A, DrawnBounds) where A: StoreSurface {
+        let ink = text.ink_bounds();
+        let mut surface = A::new_empty(ink.width, ink.height);
+        // the top of the text has to end up `ink.y` below the top of the surface, whichever way up it is
+        let y = match self.coordinate_system {
+            CoordinateSystem::PositiveYDown => -ink.y,
+            CoordinateSystem::PositiveYUp => ink.height as i32 + ink.y,
         };
-        renderer.draw_string_monospaced("hElLo w0r1d! hai!!", 0.0, 0.0, 24.0, TextColour::new_rgb(255, 255, 255), &mut surface);
-        renderer.draw_string("hElLo w0r1d!", 0.0, 24.0, 24.0, TextColour::new_rgb(255, 255, 255), &mut surface);
-        // convert from rgba to rgb
-        let mut rgb_data = Vec::new();
-        for i in 0..(surface.width * surface.height) {
-            // if transparent, put black; otherwise, put the pixel
-            if surface.data[i * 4 + 3] == 0 {
-                rgb_data.push(0);
-                rgb_data.push(0);
-                rgb_data.push(0);
+        // the renderer's clip is for the caller's surface, so it mustn't cut bits off of this one,
+        // and nothing drawn here is on the caller's surface so it isn't dirty either
+        let clip = self.clip.take();
+        let dirty_rects = self.dirty_rects.take();
+        let bounds = self.draw_laid_out(text, -ink.x as f32, y as f32, options, &mut surface, |_| colour);
+        self.clip = clip;
+        self.dirty_rects = dirty_rects;
+        (surface, bounds)
+    }
+
+    /// Same as `draw_string`, but asks `colour_fn` for the colour of every glyph, so you can colour
+    /// each character differently (e.g. for syntax highlighting) without breaking the string up into
+    /// multiple draw calls. The closure gets the byte index of the character in `string` and the character itself.
+    /// The whole string is still laid out in one go, so glyphs end up exactly where `draw_string` would put them.
+    pub fn draw_string_with(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        surface: &mut A,
+        mut colour_fn: impl FnMut(usize, char) -> TextColour
+    ) {
+        let (glyphs, _) = self.layout_string(string, size, &DrawOptions::default());
+        let y = self.layout_y(y);
+        self.draw_glyphs(&glyphs, x, y, None, BlendMode::default(), 1.0, GlyphEffects::default(), surface, |glyph| colour_fn(glyph.byte_offset, glyph.parent));
+    }
+
+    /// Draws a string with `fx` getting to move every glyph and fade it in or out, for animated text
+    /// (bouncing, shaking, typewriter reveals and the like). `fx` gets the index of the glyph, the character it was made from
+    /// and where it would normally be drawn, and returns where and how to actually draw it.
+    /// The string is laid out normally first, so kerning stays the same however the glyphs get moved,
+    /// and the returned bounds are where the text would be without any effects.
+    /// For a wave, add something like `(time + i as f32 * 0.5).sin() * 4.0` to `y`; for a typewriter,
+    /// set `alpha` to 0.0 for every glyph past the ones that have been "typed" so far.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_fx(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        surface: &mut A,
+        mut fx: impl FnMut(usize, char, GlyphDrawParams) -> GlyphDrawParams
+    ) -> DrawnBounds {
+        let text = self.layout_text(string, size, &DrawOptions::default());
+        let y = self.layout_y(y);
+        for (index, glyph) in text.glyphs.iter().enumerate() {
+            let params = fx(index, glyph.parent, GlyphDrawParams { x: x + glyph.x, y: self.layout_y(y + glyph.y), alpha: 1.0 });
+            let moved = Glyph { x: params.x, y: self.layout_y(params.y), ..*glyph };
+            self.draw_glyphs(std::slice::from_ref(&moved), 0.0, 0.0, None, BlendMode::default(), params.alpha, GlyphEffects::default(), surface, |_| colour);
+        }
+        text.drawn_bounds(x, self.layout_y(y))
+    }
+
+    /// Draws a single character with its pen position at `x` and its baseline at `y` (not the top of the line like
+    /// `draw_string` does, so characters of different sizes placed at the same `y` sit on the same line),
+    /// for when you're laying out text yourself. Returns how far to advance to the next character and what got drawn.
+    /// Whitespace doesn't draw anything but still has its advance. This goes through the same glyph cache as
+    /// `draw_string`, so mixing the two never rasterizes a glyph twice.
+    /// No kerning is applied, since there's no character before it to kern against.
+    /// Nothing is drawn if `size` isn't one that can be (see `check_size`).
+    pub fn draw_char(&mut self, c: char, x: f32, y: f32, size: f32, colour: TextColour, surface: &mut A) -> CharDrawResult {
+        let glyph_index = self.font.lookup_glyph_index(c);
+        let found = self.has_glyph(c);
+        let char_data = CharacterData::classify(c, glyph_index);
+        if self.check_size(size).is_err() || char_data.is_control() {
+            return CharDrawResult { found, ..Default::default() };
+        }
+        let advance = self.font.metrics_indexed(glyph_index, size).advance_width.ceil();
+        if char_data.is_whitespace() {
+            return CharDrawResult { advance, ink: None, found };
+        }
+        let glyph = self.glyph_on_baseline(0, c, size, 0.0, 0.0);
+        let y = self.layout_y(y);
+        let dst = self.surface_rect(Rect::new((x + glyph.x).floor() as i32, (y + glyph.y).floor() as i32, glyph.width, glyph.height));
+        self.draw_glyphs(std::slice::from_ref(&glyph), x, y, None, BlendMode::default(), 1.0, GlyphEffects::default(), surface, |_| colour);
+        CharDrawResult { advance, ink: self.visible_rect(surface, dst, None), found }
+    }
+
+    /// Internal function to place a single character of one of the fonts with its pen position at `x` and its baseline at `y`
+    /// (in layout coordinates, see `layout_y`), the same way fontdue would place it in a laid out string.
+    pub(crate) fn glyph_on_baseline(&self, font_index: usize, c: char, size: f32, x: f32, y: f32) -> Glyph {
+        let font = self.font_at(font_index);
+        let glyph_index = font.lookup_glyph_index(c);
+        let metrics = font.metrics_indexed(glyph_index, size);
+        GlyphPosition {
+            key: GlyphRasterConfig { glyph_index, px: size, font_hash: font.file_hash() },
+            font_index,
+            parent: c,
+            x: (x + metrics.xmin as f32).floor(),
+            y: y + (-metrics.bounds.height - metrics.bounds.ymin).floor(),
+            width: metrics.width,
+            height: metrics.height,
+            byte_offset: 0,
+            char_data: CharacterData::classify(c, glyph_index),
+            user_data: GlyphData::default(),
+        }
+    }
+
+    /// Returns whether the renderer's own font has a glyph for `c` (rather than drawing its "missing glyph" box).
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font.lookup_glyph_index(c) != 0
+    }
+
+    /// Draws several differently styled spans of text as if they were one string, so they wrap together
+    /// and sit on the same baseline. `size` and `colour` are used for any span that doesn't set its own.
+    /// Superscript and subscript spans make their line taller if they'd stick out of it.
+    /// Returns where the text ended up, same as `draw_string_with_options`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_spans(
+        &mut self,
+        spans: &[TextSpan],
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let runs = spans.iter().map(|span| self.span_run(span, size, options)).collect::<Vec<_>>();
+        let text = self.layout_rich(&runs, size, options);
+        self.draw_laid_out(&text, x, y, options, surface, |glyph| {
+            spans.get(glyph.user_data.run).and_then(|span| span.colour).unwrap_or(colour)
+        })
+    }
+
+    /// Internal function to work out the size and baseline shift of a span.
+    pub(crate) fn span_run<'a>(&self, span: &'a TextSpan, size: f32, options: &DrawOptions) -> Run<'a> {
+        let size = span.size.unwrap_or(size);
+        let parent = self.line_metrics(size);
+        let (size, baseline_shift) = match span.baseline_shift {
+            BaselineShift::None => (size, 0.0),
+            BaselineShift::Superscript => (size * options.script_scale, (parent.ascent * 0.4).round()),
+            BaselineShift::Subscript => (size * options.script_scale, (parent.descent * 0.6).round()),
+            BaselineShift::Exact(shift) => (size, shift),
+        };
+        Run { text: &span.text, size, baseline_shift, font_index: span.font }
+    }
+
+    /// Lays out a string once so it can be drawn over and over again with `draw_prepared` or `draw_prefix`
+    /// without doing any layout work. The positions are worked out the same way `draw_string_with_options` does it.
+    pub fn prepare_text(
+        &self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions
+    ) -> PreparedText {
+        let text = self.layout_text(string, size, options);
+        self.prepare_laid_out(&text, x, y, colour, vec![colour], options)
+    }
+
+    /// Starts building up a `PreparedText` out of differently styled pieces, see `TextLayoutBuilder`.
+    /// `size` and `colour` are used for the pieces that don't set their own, and the finished text
+    /// is positioned at (`x`, `y`) the same way `prepare_text` would.
+    pub fn begin_layout(&self, x: f32, y: f32, size: f32, colour: TextColour, options: &DrawOptions) -> TextLayoutBuilder<'_, G, A> {
+        TextLayoutBuilder::new(self, x, y, size, colour, options.clone())
+    }
+
+    /// Internal function to turn laid out text into a `PreparedText` with its top left at (`x`, `y`)
+    /// (as given to a draw call, so before the origin is applied). `run_colours` has the colour of every run.
+    pub(crate) fn prepare_laid_out(
+        &self,
+        text: &LaidOutText,
+        x: f32,
+        y: f32,
+        colour: TextColour,
+        run_colours: Vec<TextColour>,
+        options: &DrawOptions
+    ) -> PreparedText {
+        let (dx, dy) = text.origin_offset(options);
+        let (x, y) = (x + dx, self.layout_y(y) + dy);
+        let pens = text.glyphs.iter().map(|glyph| {
+            let left = x + self.pen_x(glyph);
+            (left, left + self.glyph_advance(glyph))
+        }).collect();
+        let glyphs = text.glyphs.iter().map(|glyph| {
+            let mut glyph = *glyph;
+            glyph.x += x;
+            glyph.y += y;
+            glyph
+        }).collect();
+        PreparedText {
+            glyphs,
+            colour,
+            run_colours,
+            clip: options.clip,
+            blend_mode: options.blend_mode,
+            opacity: options.opacity,
+            metrics: text.metrics.clone(),
+            top: y,
+            pens,
+            source_glyphs: text.glyphs_shown,
+            effects: GlyphEffects::from_options(options),
+            inserted_glyphs: text.inserted_glyphs.clone(),
+            flipped: self.coordinate_system == CoordinateSystem::PositiveYUp,
+        }
+    }
+
+    /// Draws all of the glyphs of a `PreparedText`.
+    pub fn draw_prepared(&mut self, prepared: &PreparedText, surface: &mut A) {
+        self.draw_prefix(prepared, prepared.glyph_count(), surface);
+    }
+
+    /// Draws only the first `glyph_count` glyphs of a `PreparedText`, in the same place they would be if
+    /// the whole thing was drawn. If `glyph_count` is bigger than the number of glyphs, everything is drawn.
+    pub fn draw_prefix(&mut self, prepared: &PreparedText, glyph_count: usize, surface: &mut A) {
+        let glyphs = &prepared.glyphs[..glyph_count.min(prepared.glyphs.len())];
+        self.draw_glyphs(glyphs, 0.0, 0.0, prepared.clip, prepared.blend_mode, prepared.opacity, prepared.effects, surface, |glyph| {
+            prepared.run_colours.get(glyph.user_data.run).copied().unwrap_or(prepared.colour)
+        });
+    }
+
+    /// Internal function to draw some laid out text at (`x`, `y`) (as given to a draw call, so before the origin is applied),
+    /// including everything from the options that happens around the glyphs themselves, like the selection.
+    fn draw_laid_out(
+        &mut self,
+        text: &LaidOutText,
+        x: f32,
+        y: f32,
+        options: &DrawOptions,
+        surface: &mut A,
+        mut colour_fn: impl FnMut(&Glyph) -> TextColour
+    ) -> DrawnBounds {
+        let (dx, dy) = text.origin_offset(options);
+        let (x, y) = (x + dx, self.layout_y(y) + dy);
+        if let Some(selection) = &options.selection {
+            for rect in self.range_rects(text, &selection.range) {
+                let rect = self.surface_rect(Rect::new(rect.x + x.floor() as i32, rect.y + y.floor() as i32, rect.width, rect.height));
+                let filled = self.fill_rect(surface, rect, selection.background.with_opacity(options.opacity), options.clip);
+                self.mark_dirty(filled);
+            }
+        }
+        self.draw_underlines(text, &options.underlines, x, y, options, surface);
+        let mut colour_fn = |glyph: &Glyph| match &options.selection {
+            Some(Selection { range, foreground: Some(foreground), .. }) if range.contains(&glyph.byte_offset) => *foreground,
+            _ => colour_fn(glyph),
+        };
+        self.draw_glyphs(&text.glyphs, x, y, options.clip, options.blend_mode, options.opacity, GlyphEffects::from_options(options), surface, &mut colour_fn);
+        self.draw_whitespace_markers(text, x, y, options, surface, colour_fn);
+        text.drawn_bounds(x, self.layout_y(y))
+    }
+
+    /// Internal function to turn a `y` given to a draw call into the y-down coordinates text is laid out in,
+    /// or the other way around (for `CoordinateSystem::PositiveYUp` that's just flipping the sign both ways).
+    pub(crate) fn layout_y(&self, y: f32) -> f32 {
+        match self.coordinate_system {
+            CoordinateSystem::PositiveYDown => y,
+            CoordinateSystem::PositiveYUp => -y,
+        }
+    }
+
+    /// Internal function to get the `y` to give a draw call for text to start at the top of a rect on the surface.
+    /// For `CoordinateSystem::PositiveYUp` a rect's `y` is its bottom edge, so that's the other end of it.
+    fn rect_top(&self, rect: Rect) -> f32 {
+        match self.coordinate_system {
+            CoordinateSystem::PositiveYDown => rect.y as f32,
+            CoordinateSystem::PositiveYUp => rect.y as f32 + rect.height as f32,
+        }
+    }
+
+    /// Internal function to turn a rect in layout coordinates (see `layout_y`) into one on the surface.
+    pub(crate) fn surface_rect(&self, rect: Rect) -> Rect {
+        match self.coordinate_system {
+            CoordinateSystem::PositiveYDown => rect,
+            CoordinateSystem::PositiveYUp => Rect::new(rect.x, -rect.y - rect.height as i32, rect.width, rect.height),
+        }
+    }
+
+    /// Internal function to paste laid out glyphs onto the surface, offset by `x` and `y` (in layout coordinates, see `layout_y`).
+    /// Glyphs outside of `clip` are skipped, and glyphs partially inside of it only have their visible part pasted.
+    /// See `DrawOptions::opacity` for `opacity`, and `GlyphEffects` for `effects`.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_glyphs(
+        &mut self,
+        glyphs: &[Glyph],
+        x: f32,
+        y: f32,
+        clip: Option<Rect>,
+        blend_mode: BlendMode,
+        opacity: f32,
+        effects: GlyphEffects,
+        surface: &mut A,
+        mut colour_fn: impl FnMut(&Glyph) -> TextColour
+    ) {
+        if opacity <= 0.0 {
+            return;
+        }
+        for glyph in glyphs.iter() {
+            // layout already leaves these out, but glyphs can come from elsewhere (like shaping)
+            if self.check_size(glyph.key.px).is_err() {
+                continue;
+            }
+            // glyphs that are nothing (like spaces) stay that way, rather than becoming a square of blurred nothing
+            let inked = glyph.width > 0 && glyph.height > 0 && glyph.font_index != CUSTOM_FONT;
+            let lcd = self.glyph_cache_format == GlyphCacheFormat::Lcd && effects == GlyphEffects::default();
+            let padding = if inked { effects.padding() } else { 0 };
+            let (left, subpixel) = match inked && !lcd {
+                true => self.subpixel_position(x + glyph.x),
+                false => ((x + glyph.x).floor() as i32, 0),
+            };
+            let dst = self.surface_rect(Rect::new(
+                left - padding as i32,
+                (y + glyph.y).floor() as i32 - padding as i32,
+                glyph.width + padding * 2 + (subpixel > 0) as usize,
+                glyph.height + padding * 2,
+            ));
+            let visible = match self.visible_rect(surface, dst, clip) {
+                Some(visible) => visible,
+                None => continue,
+            };
+            if glyph.font_index == CUSTOM_FONT {
+                self.draw_custom_glyph(glyph, dst, visible, blend_mode, opacity, surface);
+                continue;
+            }
+            let colour = colour_fn(glyph);
+            if lcd {
+                let colour = if opacity < 1.0 { colour.with_opacity(opacity) } else { colour };
+                let coverage = self.get_glyph_lcd(glyph.font_index, glyph.key, colour);
+                surface.paste_lcd(
+                    visible.x as usize,
+                    visible.y as usize,
+                    (visible.x - dst.x) as usize,
+                    (visible.y - dst.y) as usize,
+                    visible.width,
+                    visible.height,
+                    &coverage,
+                    dst.width,
+                    colour,
+                    self.pixel_order,
+                    blend_mode,
+                );
+                self.mark_dirty(Some(visible));
+                continue;
+            }
+            // see-through glyphs are coloured as they're pasted, so the glyph cache only has the colours text is really in
+            if self.glyph_cache_format != GlyphCacheFormat::Rgba || opacity < 1.0 {
+                let colour = if opacity < 1.0 { colour.with_opacity(opacity) } else { colour };
+                let mask = self.get_glyph_mask(glyph.font_index, glyph.key, glyph.width, colour, effects, subpixel);
+                self.paste_coverage(surface, dst, visible, &mask, dst.width, colour, blend_mode);
+                self.mark_dirty(Some(visible));
+                continue;
+            }
+            let bitmap = self.get_glyph_surface(glyph.font_index, glyph.key, glyph.width, glyph.height, colour, effects, subpixel);
+            // draw to surface
+            paste_visible(surface, dst, visible, &bitmap, blend_mode);
+            self.mark_dirty(Some(visible));
+        }
+    }
+
+    /// Measures a string without drawing it, using the exact same layout (and line breaks) that `draw_string` would.
+    /// Gives the width and height of the whole string, and the width of every line (see `TextMetrics::lines`),
+    /// so you can center it or lay out UI around it before drawing anything.
+    /// Sizes that can't be drawn (see `check_size`) measure as empty.
+    pub fn measure_string(&self, string: &str, size: f32) -> TextMetrics {
+        self.measure_string_with_options(string, size, &DrawOptions::default())
+    }
+
+    /// Same as `measure_string`, but with the same layout `draw_string_with_options` would use with these options.
+    /// Lines cut off by `max_lines`/`max_height` aren't included.
+    /// If `size` isn't one that can be drawn this quietly measures as empty, use `try_measure_string_with_options` to find out about it.
+    pub fn measure_string_with_options(&self, string: &str, size: f32, options: &DrawOptions) -> TextMetrics {
+        self.layout_text(string, size, options).metrics
+    }
+
+    /// Same as `measure_string_with_options`, but fails with `TextRendererError::InvalidSize` instead of quietly
+    /// returning empty metrics if `size` isn't one that can be drawn (see `check_size`).
+    pub fn try_measure_string_with_options(&self, string: &str, size: f32, options: &DrawOptions) -> Result<TextMetrics, TextRendererError> {
+        self.check_size(size)?;
+        Ok(self.measure_string_with_options(string, size, options))
+    }
+
+    /// Checks whether text can be drawn at `size` pixels: it has to be a normal positive number
+    /// no bigger than the max size set with `TextRendererBuilder::max_size` (`DEFAULT_MAX_SIZE` unless you changed it).
+    /// Every draw and measure call quietly skips text with a size that fails this (drawing nothing, and measuring as empty)
+    /// rather than trying to rasterize gigantic or nonsensical glyphs, so use this (or the `try_` versions of the draw calls)
+    /// if you need to know about it.
+    pub fn check_size(&self, size: f32) -> Result<(), TextRendererError> {
+        if size.is_finite() && size > 0.0 && size <= self.max_size {
+            Ok(())
+        } else {
+            Err(TextRendererError::InvalidSize(size))
+        }
+    }
+
+    /// Returns the biggest size text can be drawn at, see `TextRendererBuilder::max_size`.
+    pub fn max_size(&self) -> f32 {
+        self.max_size
+    }
+
+    /// Wraps a string to `max_width` pixels and returns where every line starts and ends, without drawing anything.
+    /// The line breaks are exactly the ones `draw_string_with_options` makes with the same width and wrap settings.
+    /// Whitespace (and the newline, if there is one) that a line was broken at belongs to the end of that line's
+    /// `byte_range`, but isn't counted in its `width`. Together the byte ranges cover the whole string with no gaps.
+    pub fn wrap_string(&self, string: &str, size: f32, max_width: f32, options: &WrapOptions) -> Vec<LineInfo> {
+        let options = DrawOptions {
+            max_width: Some(max_width),
+            wrap_style: options.style,
+            wrap_hard_breaks: options.hard_breaks,
+            line_breaks: options.line_breaks,
+            #[cfg(feature = "hyphenation")]
+            hyphenation: options.hyphenation,
+            ..Default::default()
+        };
+        self.layout_text(string, size, &options).metrics.lines
+    }
+
+    /// Finds the biggest size in `range` that the string fits into a `max_width` x `max_height` box at,
+    /// to within `tolerance` pixels (binary searching with `measure_string_with_options`, so nothing gets rasterized).
+    /// If `options.max_width` is set the text is wrapped at `max_width` instead (whatever the value was),
+    /// so it fits if all of the lines fit in `max_height`. Getting cut off by `max_lines` counts as not fitting.
+    #[allow(clippy::too_many_arguments)]
+    pub fn fit_size(
+        &self,
+        string: &str,
+        max_width: f32,
+        max_height: f32,
+        range: RangeInclusive<f32>,
+        tolerance: f32,
+        options: &DrawOptions
+    ) -> FittedSize {
+        let options = DrawOptions {
+            max_width: options.max_width.map(|_| max_width),
+            max_height: None,
+            ..options.clone()
+        };
+        let fits = |size: f32| {
+            let metrics = self.measure_string_with_options(string, size, &options);
+            self.check_size(size).is_ok() && !metrics.truncated && metrics.width <= max_width && metrics.height <= max_height
+        };
+        let (mut low, mut high) = (*range.start(), range.end().min(self.max_size));
+        if fits(high) {
+            return FittedSize { size: high, fits: true };
+        }
+        if !fits(low) {
+            return FittedSize { size: low, fits: false };
+        }
+        // low always fits and high never does, and a tiny (or zero) tolerance still stops eventually
+        for _ in 0..64 {
+            if high - low <= tolerance {
+                break;
+            }
+            let middle = (low + high) / 2.0;
+            if fits(middle) {
+                low = middle;
             } else {
-                rgb_data.push(surface.data[i * 4]);
-                rgb_data.push(surface.data[i * 4 + 1]);
-                rgb_data.push(surface.data[i * 4 + 2]);
+                high = middle;
             }
         }
-        // output to bitmap
-        let mut file = std::fs::File::create("test.ppm").unwrap();
-        let _ = file.write(format!("P6\n{} {}\n255\n", surface.width, surface.height).as_bytes()).unwrap();
-        let _ = file.write(&rgb_data).unwrap();
+        FittedSize { size: low, fits: true }
+    }
+
+    /// Draws a string at the biggest size in `range` (to within half a pixel) that fits in a `max_width` x `max_height` box
+    /// with its top left at (`x`, `y`). See `fit_size` for how fitting works.
+    /// If it doesn't fit at any size, it's drawn at the smallest one anyway, so use `max_lines` or `clip` if that's a problem.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_fitted(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        max_width: f32,
+        max_height: f32,
+        range: RangeInclusive<f32>,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> (FittedSize, DrawnBounds) {
+        let fitted = self.fit_size(string, max_width, max_height, range, 0.5, options);
+        let options = DrawOptions { max_width: options.max_width.map(|_| max_width), ..options.clone() };
+        let bounds = self.draw_string_with_options(string, x, y, fitted.size, colour, &options, surface);
+        (fitted, bounds)
+    }
+
+    /// Draws a string on a single line, cut off with "…" (or "..." if the font doesn't have it) if it would be wider than `max_width`.
+    /// The string is cut between any two characters (not just between words), and newlines don't start a new line.
+    /// `options.max_width`, `max_lines`, `wrap_style`, `wrap_hard_breaks` and `ellipsis` are replaced to do this.
+    /// Returns where the text ended up, with `truncated` set (and `bytes_drawn` saying how much of it was kept) if it got cut off.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_truncated(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        max_width: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let options = DrawOptions {
+            max_width: Some(max_width),
+            max_lines: Some(1),
+            wrap_style: WrapStyle::Letter,
+            wrap_hard_breaks: false,
+            ellipsis: true,
+            ..options.clone()
+        };
+        self.draw_string_with_options(string, x, y, size, colour, &options, surface)
+    }
+
+    /// Draws a paragraph inside of `rect`, wrapping lines that would be wider than it (see `DrawOptions::wrap_style`)
+    /// and leaving out lines that would go past its bottom. The text starts at the rect's top left, whatever
+    /// `options.origin` and `options.anchor` are, and `options.max_width`/`options.max_height` are replaced by the rect's size.
+    /// Set `options.align` and `options.vertical_align` to put the text somewhere else in the rect, like its middle.
+    /// Returns where the text ended up, with `truncated` set if some lines didn't fit.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_paragraph(
+        &mut self,
+        string: &str,
+        rect: Rect,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let options = DrawOptions {
+            origin: Origin::TopLeft,
+            anchor: Anchor::TopLeft,
+            max_width: Some(rect.width as f32),
+            max_height: Some(rect.height as f32),
+            ..options.clone()
+        };
+        self.draw_string_with_options(string, rect.x as f32, self.rect_top(rect), size, colour, &options, surface)
+    }
+
+    /// Draws text inside of `rect` like `draw_paragraph`, but instead of leaving out lines that don't fit, everything
+    /// is drawn and anything outside of the rect is clipped off (on top of `options.clip`, if that's set).
+    /// Returns how far the text went past the rect, and where in the string it started getting clipped, so you can
+    /// tell when your widget needs to grow or scroll.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_text_in_rect(
+        &mut self,
+        string: &str,
+        rect: Rect,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> TextOverflow {
+        let (x, y) = (rect.x as f32, self.rect_top(rect));
+        let clip = match options.clip {
+            Some(other) => other.intersection(&rect).unwrap_or(Rect::new(rect.x, rect.y, 0, 0)),
+            None => rect,
+        };
+        let options = DrawOptions {
+            origin: Origin::TopLeft,
+            anchor: Anchor::TopLeft,
+            clip: Some(clip),
+            max_width: Some(rect.width as f32),
+            ..options.clone()
+        };
+        let text = self.layout_text(string, size, &options);
+        let (width, height) = (rect.width as f32, rect.height as f32);
+        let clipped_from = text.metrics.lines.iter()
+            .find(|line| line.x + line.width > width || line.y + line.height > height)
+            .map(|line| line.byte_range.start);
+        TextOverflow {
+            bounds: self.draw_laid_out(&text, x, y, &options, surface, |_| colour),
+            overflow_x: (text.metrics.width - width).max(0.0),
+            overflow_y: (text.metrics.height - height).max(0.0),
+            clipped_from,
+        }
+    }
+
+    /// Returns the vertical metrics of the font at the given pixel size.
+    /// If the font doesn't have any horizontal line metrics (which would be weird), everything is 0.
+    pub fn line_metrics(&self, size: f32) -> LineMetrics {
+        self.font_line_metrics(0, size)
+    }
+
+    /// How far above the baseline the font reaches at the given pixel size, same as `line_metrics(size).ascent`.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.line_metrics(size).ascent
+    }
+
+    /// How far below the baseline the font reaches at the given pixel size, same as `line_metrics(size).descent`.
+    /// This is usually negative.
+    pub fn descent(&self, size: f32) -> f32 {
+        self.line_metrics(size).descent
+    }
+
+    /// The gap the font wants between lines at the given pixel size, same as `line_metrics(size).line_gap`.
+    pub fn line_gap(&self, size: f32) -> f32 {
+        self.line_metrics(size).line_gap
+    }
+
+    /// The distance the font recommends between two baselines at the given pixel size, same as `line_metrics(size).line_height`.
+    /// Handy for stacking the output of several renderers on top of each other.
+    pub fn line_height(&self, size: f32) -> f32 {
+        self.line_metrics(size).line_height
+    }
+
+    /// Internal function to get the vertical metrics of any of the fonts, see `line_metrics`.
+    pub(crate) fn font_line_metrics(&self, font_index: usize, size: f32) -> LineMetrics {
+        match self.font_at(font_index).horizontal_line_metrics(size) {
+            Some(metrics) => LineMetrics {
+                ascent: metrics.ascent,
+                descent: metrics.descent,
+                line_gap: metrics.line_gap,
+                line_height: metrics.new_line_size,
+            },
+            None => LineMetrics::default(),
+        }
+    }
+
+    /// Returns where underlines and strikeouts go for text at the given pixel size, scaled from the positions and thicknesses
+    /// the font's designer put in its `post` and `OS/2` tables. These are only read with the `ttf-parser` feature,
+    /// and anything the font doesn't have (or everything, without the feature) is worked out from the size and ascent instead.
+    pub fn decoration_metrics(&self, size: f32) -> DecorationMetrics {
+        let thickness = size / 16.0;
+        #[allow(unused_mut)]
+        let mut metrics = DecorationMetrics {
+            underline_offset: -size / 12.0,
+            underline_thickness: thickness,
+            strikeout_offset: self.line_metrics(size).ascent * 0.3 + thickness / 2.0,
+            strikeout_thickness: thickness,
+        };
+        #[cfg(feature = "ttf-parser")]
+        if let Ok(face) = ttf_parser::Face::parse(&self.font_data, self.collection_index) {
+            let scale = size / face.units_per_em() as f32;
+            if let Some(underline) = face.underline_metrics().filter(|underline| underline.thickness > 0) {
+                metrics.underline_offset = underline.position as f32 * scale;
+                metrics.underline_thickness = underline.thickness as f32 * scale;
+            }
+            if let Some(strikeout) = face.strikeout_metrics().filter(|strikeout| strikeout.thickness > 0) {
+                metrics.strikeout_offset = strikeout.position as f32 * scale;
+                metrics.strikeout_thickness = strikeout.thickness as f32 * scale;
+            }
+        }
+        metrics
+    }
+
+    /// Returns how far the pen moves after drawing the given character at the given pixel size.
+    /// This doesn't include any kerning, as that depends on the characters around it.
+    pub fn char_advance(&self, c: char, size: f32) -> f32 {
+        self.font.metrics(c, size).advance_width
+    }
+
+    /// Returns the metrics (advance, bearings and inked bounds) of a single character at the given pixel size,
+    /// without rasterizing it. Returns `None` if the font doesn't have a glyph for the character.
+    /// Whitespace has a real advance but an empty inked box.
+    pub fn glyph_metrics(&self, c: char, size: f32) -> Option<GlyphMetrics> {
+        let glyph_index = self.font.lookup_glyph_index(c);
+        if glyph_index == 0 {
+            return None;
+        }
+        let metrics = self.glyph_metrics_indexed(0, glyph_index, size);
+        if c.is_whitespace() {
+            return Some(GlyphMetrics { advance: metrics.advance, ..Default::default() });
+        }
+        Some(metrics)
+    }
+
+    /// Internal function to get the metrics of a glyph by its index in one of the renderer's fonts, see `glyph_metrics`.
+    pub(crate) fn glyph_metrics_indexed(&self, font_index: usize, glyph_index: u16, size: f32) -> GlyphMetrics {
+        let metrics = self.font_at(font_index).metrics_indexed(glyph_index, size);
+        GlyphMetrics {
+            advance: metrics.advance_width,
+            left_bearing: metrics.bounds.xmin,
+            top_bearing: metrics.bounds.ymin + metrics.bounds.height,
+            width: metrics.bounds.width,
+            height: metrics.bounds.height,
+            bitmap_width: metrics.width,
+            bitmap_height: metrics.height,
+        }
+    }
+
+    /// Same as `glyph_metrics`, but also hands back the glyph's bitmap (through the glyph cache) so you can
+    /// paste it yourself wherever you like. The bitmap is `bitmap_width` by `bitmap_height` pixels, and its top left
+    /// goes `left_bearing` to the right of the pen and `top_bearing` above the baseline.
+    /// Returns `None` if `size` isn't one that can be drawn (see `check_size`).
+    pub fn glyph_bitmap(&mut self, c: char, size: f32, colour: TextColour) -> Option<(GlyphMetrics, G)> {
+        self.check_size(size).ok()?;
+        let metrics = self.glyph_metrics(c, size)?;
+        let config = GlyphRasterConfig {
+            glyph_index: self.font.lookup_glyph_index(c),
+            px: size,
+            font_hash: self.font.file_hash(),
+        };
+        let bitmap = self.get_glyph_surface(0, config, metrics.bitmap_width, metrics.bitmap_height, colour, GlyphEffects::default(), 0);
+        Some((metrics, bitmap))
+    }
+
+    /// Returns a snapshot of the glyph cache counters collected since the renderer was created
+    /// (or since the last call to `reset_metrics`).
+    pub fn metrics(&self) -> RenderMetrics {
+        self.metrics
+    }
+
+    /// Roughly how many bytes the glyph cache (shared by every clone of this renderer) takes up:
+    /// the raw bytes kept for every glyph (see `CachePolicy`), plus 4 bytes per pixel for every `StoreSurface`
+    /// 1 byte per pixel for every `GlyphCacheFormat::Alpha` mask and rotated glyph (see `draw_string_on_path`),
+    /// 3 bytes per pixel for every `GlyphCacheFormat::Lcd` glyph, and 1 byte per pixel per channel for every signed distance field
+    /// (see `glyph_sdf` and `glyph_msdf`).
+    /// Custom glyphs (see `set_glyph_provider`) count as their RGBA bytes plus their surface.
+    pub fn glyph_cache_size(&self) -> usize {
+        let custom_glyphs = self.custom_glyphs.read().unwrap_or_else(PoisonError::into_inner);
+        let custom = custom_glyphs.values().flatten().map(|provided| provided.data.len() * 2).sum::<usize>();
+        let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+        custom + glyph_caches.values().map(|glyph_cache| {
+            let surfaces = glyph_cache.surface_map.values().flat_map(|colour_map| colour_map.values())
+                .map(|cached| cached.raw.as_ref().map_or(0, Vec::len) + cached.pixels * 4)
+                .sum::<usize>();
+            surfaces + glyph_cache.mask_map.values().map(|mask| mask.len()).sum::<usize>()
+                + glyph_cache.lcd_map.values().map(|coverage| coverage.len()).sum::<usize>()
+                + glyph_cache.sdf_map.values().map(|sdf| sdf.data.len()).sum::<usize>()
+                + glyph_cache.rotated_map.values().map(|rotated| rotated.mask.len()).sum::<usize>()
+        }).sum::<usize>()
+    }
+
+    /// Throws away every cached glyph (including custom ones, see `set_glyph_provider`), for this renderer and every clone of it,
+    /// to get the memory back.
+    /// Glyphs are rasterized again the next time they're drawn, but `DrawCommands` have to be prepared again
+    /// (see `execute_commands`).
+    pub fn clear_glyph_cache(&mut self) {
+        self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner).clear();
+        self.custom_glyphs.write().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+
+    /// Resets all the counters returned by `metrics` back to zero.
+    pub fn reset_metrics(&mut self) {
+        self.metrics = RenderMetrics::default();
+    }
+
+    /// Turns on gamma correction of glyph coverage (or turns it off again with `None`).
+    /// Without it, dark text on light backgrounds looks a bit too heavy and light text on dark backgrounds a bit too thin,
+    /// which is most noticeable at small sizes. Something around `1.8` to `2.2` (`DEFAULT_GAMMA`) works well for most fonts.
+    /// Glyphs are cached separately for every setting, so switching back and forth doesn't mix them up.
+    pub fn set_gamma(&mut self, gamma: Option<f32>) {
+        self.gamma = gamma;
+    }
+
+    /// Returns the order glyph data is handed to `StoreSurface::from_raw_mask` in.
+    pub fn pixel_order(&self) -> PixelOrder {
+        self.pixel_order
+    }
+
+    /// Returns how many horizontal positions within a pixel glyphs are drawn at, see `TextRendererBuilder::subpixel_positions`.
+    pub fn subpixel_positions(&self) -> u8 {
+        self.subpixel_positions
+    }
+
+    /// Returns which order the subpixels of the screen are in, see `TextRendererBuilder::subpixel_order`.
+    pub fn subpixel_order(&self) -> SubpixelOrder {
+        self.subpixel_order
+    }
+
+    /// Returns whether glyph data is handed to `StoreSurface::from_raw_mask` premultiplied,
+    /// see `TextRendererBuilder::premultiplied_alpha`.
+    pub fn premultiplied_alpha(&self) -> bool {
+        self.premultiplied_alpha
+    }
+
+    /// Returns which way the y axis points, see `TextRendererBuilder::coordinate_system`.
+    pub fn coordinate_system(&self) -> CoordinateSystem {
+        self.coordinate_system
+    }
+
+    /// Turns recording of everything that gets pasted onto surfaces on or off, for partial screen updates.
+    /// While it's on, every glyph, background, selection and underline that's actually pasted
+    /// (anything left of or above the surface or outside of the clip rect is cut off first) is remembered until
+    /// `take_dirty_rects` is called. Glyphs that weren't pasted at all (whitespace, or completely clipped) aren't included.
+    /// Turning it off throws away anything that was recorded.
+    pub fn track_dirty_rects(&mut self, track: bool) {
+        self.dirty_rects = if track { Some(Vec::new()) } else { None };
+    }
+
+    /// Returns every rect that was pasted onto a surface since tracking was turned on or this was last called,
+    /// see `track_dirty_rects`. They can overlap, so fold them together with `Rect::union` if you only want one.
+    /// Always empty if tracking is off.
+    pub fn take_dirty_rects(&mut self) -> Vec<Rect> {
+        self.dirty_rects.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Sets a clip rect (on the surface) that everything the renderer draws is cut off at, like a scissor rect.
+    /// It applies to every draw call until it's changed or set back to `None`, on top of any `DrawOptions::clip`
+    /// (so only what's inside of both gets drawn). Handy for drawing a whole scrolling panel's worth of text without
+    /// having to pass the panel's rect to every call. Glyphs that are partly inside of it are cropped (see `PasteSurface::paste_clipped`).
+    pub fn set_clip(&mut self, clip: Option<Rect>) {
+        self.clip = clip;
+    }
+
+    /// Returns the clip rect set with `set_clip`.
+    pub fn clip(&self) -> Option<Rect> {
+        self.clip
+    }
+
+    /// Internal function to work out which part of a glyph pasted at `dst` can actually be drawn.
+    /// Anything left of or above (0, 0) can never be drawn as the surface coordinates are unsigned, anything past
+    /// the right or bottom of the surface (if it knows its size, see `PasteSurface::width`) can't be either,
+    /// and anything outside of `clip` or the renderer's clip (see `set_clip`) is cut off too.
+    /// Returns `None` if none of the glyph is visible.
+    pub(crate) fn visible_rect<S, P: PasteSurface<S>>(&self, surface: &P, dst: Rect, clip: Option<Rect>) -> Option<Rect> {
+        let (width, height) = (surface.width().unwrap_or(i32::MAX as usize), surface.height().unwrap_or(i32::MAX as usize));
+        let surface = Rect::new(0, 0, width.min(i32::MAX as usize), height.min(i32::MAX as usize));
+        let mut visible = dst.intersection(&surface)?;
+        for clip in [clip, self.clip].into_iter().flatten() {
+            visible = visible.intersection(&clip)?;
+        }
+        Some(visible)
+    }
+
+    /// Internal function to fill a rect of the surface with a solid colour (for backgrounds and the like).
+    /// Builds a `StoreSurface` of the right size every time, so don't use it for anything that happens a lot.
+    /// Returns the part of the surface that was filled, if any.
+    pub(crate) fn fill_rect(&self, surface: &mut A, rect: Rect, colour: TextColour, clip: Option<Rect>) -> Option<Rect> {
+        let visible = self.visible_rect(surface, rect, clip)?;
+        let data = pixel_bytes(self.pixel_order, self.premultiplied_alpha, colour.r, colour.g, colour.b, colour.a).repeat(rect.width * rect.height);
+        let bitmap = G::from_raw_mask(rect.width, rect.height, &data, colour);
+        paste_visible(surface, rect, visible, &bitmap, BlendMode::AlphaOver);
+        Some(visible)
+    }
+
+    /// Internal function to split the left edge of a glyph into the whole pixel it starts on and which of the
+    /// `subpixel_positions` within that pixel it's closest to. Rounding up to the next whole pixel gives position 0 of it.
+    /// With just the one position, glyphs always go on the pixel they start in.
+    pub(crate) fn subpixel_position(&self, left: f32) -> (i32, u8) {
+        if self.subpixel_positions <= 1 {
+            return (left.floor() as i32, 0);
+        }
+        let positions = self.subpixel_positions as f32;
+        let step = ((left - left.floor()) * positions).round();
+        if step >= positions {
+            (left.floor() as i32 + 1, 0)
+        } else {
+            (left.floor() as i32, step as u8)
+        }
+    }
+
+    /// Internal function to shift a coverage mask `width` pixels wide to the right by `subpixel` of the `subpixel_positions`
+    /// in a pixel, blending every pixel with its neighbour on the left. The shifted mask is one pixel wider, unless
+    /// `subpixel` is 0 and it's handed back as is.
+    fn shift_mask(&self, mask: Vec<u8>, width: usize, subpixel: u8) -> Vec<u8> {
+        if subpixel == 0 || width == 0 {
+            return mask;
+        }
+        let shift = subpixel as f32 / self.subpixel_positions as f32;
+        mask.chunks_exact(width).flat_map(|row| {
+            (0..=width).map(move |x| {
+                let here = if x < width { row[x] as f32 } else { 0.0 };
+                let left = if x > 0 { row[x - 1] as f32 } else { 0.0 };
+                (here * (1.0 - shift) + left * shift).round() as u8
+            })
+        }).collect()
+    }
+
+    /// Internal function to paste the `visible` part of a coverage mask `mask_width` pixels wide that goes at `dst`, in `colour`.
+    /// This goes through `PasteSurface::paste_mask`, unless the glyph cache is RGBA and pixels should be premultiplied,
+    /// as the default `paste_mask` only makes straight alpha. Then the mask is coloured here instead.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn paste_coverage(&self, surface: &mut A, dst: Rect, visible: Rect, mask: &[u8], mask_width: usize, colour: TextColour, mode: BlendMode) {
+        if self.premultiplied_alpha && self.glyph_cache_format == GlyphCacheFormat::Rgba {
+            let data = coloured_pixels(mask, colour, self.pixel_order, true);
+            let bitmap = G::from_raw_mask(mask_width, mask.len() / mask_width.max(1), &data, colour);
+            paste_visible(surface, dst, visible, &bitmap, mode);
+            return;
+        }
+        surface.paste_mask(
+            visible.x as usize,
+            visible.y as usize,
+            (visible.x - dst.x) as usize,
+            (visible.y - dst.y) as usize,
+            visible.width,
+            visible.height,
+            mask,
+            mask_width,
+            colour,
+            self.pixel_order,
+            mode,
+        );
+    }
+
+    /// Internal function to remember that part of a surface was pasted onto, if `track_dirty_rects` is on.
+    pub(crate) fn mark_dirty(&mut self, rect: Option<Rect>) {
+        if let (Some(dirty_rects), Some(rect)) = (&mut self.dirty_rects, rect) {
+            dirty_rects.push(rect);
+        }
+    }
+
+    /// Returns the gamma set with `set_gamma`.
+    pub fn gamma(&self) -> Option<f32> {
+        self.gamma
+    }
+
+    /// Internal function to get the key that `get_glyph_surface` caches glyphs of a font at a size (with some effects) under.
+    pub(crate) fn surface_cache_key(&self, font_index: usize, size: f32, effects: GlyphEffects) -> CacheKey {
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let (outline, blur) = effects.cache_bits(size);
+        CacheKey { font: font_index, size: size.to_bits(), gamma: self.gamma.map(f32::to_bits), flipped, outline, blur, subpixel: 0 }
     }
-}
\ No newline at end of file
+
+    /// Internal function to get the glyph drawable from either the cache or the font, with `effects` applied.
+    /// `width` and `height` are the size of the glyph without effects, the drawable is `effects.padding()` pixels bigger
+    /// than that on every side, and one pixel wider if it's shifted over by `subpixel` (see `subpixel_position`).
+    #[allow(clippy::too_many_arguments)]
+    fn get_glyph_surface(
+        &mut self,
+        font_index: usize,
+        glpyh: GlyphRasterConfig,
+        width: usize,
+        height: usize,
+        colour: TextColour,
+        effects: GlyphEffects,
+        subpixel: u8,
+    ) -> G {
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = CacheKey { subpixel, ..self.surface_cache_key(font_index, glpyh.px, effects) };
+        let (width, height) = (width + effects.padding() * 2, height + effects.padding() * 2);
+        // most glyphs are already cached, so try that with just a read lock first
+        {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            let cached = glyph_caches.get(&key)
+                .and_then(|glyph_cache| glyph_cache.surface_map.get(&colour))
+                .and_then(|colour_map| colour_map.get(&glpyh.glyph_index));
+            if let Some(cached) = cached {
+                self.metrics.cache_hits += 1;
+                return cached.surface.clone();
+            }
+        }
+        // a poisoned lock just means another thread panicked while drawing, the cache itself is still fine
+        let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+        // check if glyph cache exists
+        // if not create it
+        glyph_caches.entry(key).or_insert_with(|| GlyphCache::new(glpyh.px));
+        // get glyph cache
+        // check if colour exists
+        // if not create it
+        let glyph_cache = glyph_caches.get_mut(&key).unwrap();
+        glyph_cache.surface_map.entry(colour).or_default();
+        // get colour map
+        // check if glyph exists
+        // if not create it
+        let colour_map = glyph_cache.surface_map.get_mut(&colour).unwrap();
+        if let std::collections::hash_map::Entry::Vacant(e) = colour_map.entry(glpyh.glyph_index) {
+            self.metrics.cache_misses += 1;
+            let start = Instant::now();
+            let mask = self.shift_mask(rasterize_mask(self.font_at(font_index), glpyh, flipped, effects), width, subpixel);
+            let width = width + (subpixel > 0) as usize;
+            let (raw, surface) = cache_glyph(mask, colour, self.gamma, self.pixel_order, self.premultiplied_alpha, |data| G::from_raw_mask(width, height, data, colour));
+            e.insert(CachedGlyph {
+                raw: (self.cache_policy == CachePolicy::KeepRawBytes).then_some(raw),
+                surface,
+                pixels: width * height,
+            });
+            self.metrics.rasterizations += 1;
+            self.metrics.rasterization_time += start.elapsed();
+        } else {
+            // another clone got to it between the read and write locks
+            self.metrics.cache_hits += 1;
+        }
+        // get glyph surface
+        let glyph_surface = colour_map.get(&glpyh.glyph_index).unwrap();
+        // return glyph surface
+        glyph_surface.surface.clone()
+    }
+
+    /// Internal function to get the coverage mask of a glyph from either the cache or the font, for `GlyphCacheFormat::Alpha`.
+    /// Without gamma this is the cached mask itself, so drawing it again doesn't copy anything. Gamma (if there is any)
+    /// depends on the colour, so it's applied to a copy instead.
+    /// `effects` and `subpixel` are applied like they are for `get_glyph_surface`, `width` is the width of the glyph without them.
+    #[allow(clippy::too_many_arguments)]
+    fn get_glyph_mask(
+        &mut self,
+        font_index: usize,
+        glyph: GlyphRasterConfig,
+        width: usize,
+        colour: TextColour,
+        effects: GlyphEffects,
+        subpixel: u8,
+    ) -> Arc<[u8]> {
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let (outline, blur) = effects.cache_bits(glyph.px);
+        let key = CacheKey { font: font_index, size: glyph.px.to_bits(), gamma: None, flipped, outline, blur, subpixel };
+        let cached = {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            glyph_caches.get(&key).and_then(|glyph_cache| glyph_cache.mask_map.get(&glyph.glyph_index)).cloned()
+        };
+        let mask = match cached {
+            Some(mask) => {
+                self.metrics.cache_hits += 1;
+                mask
+            }
+            None => {
+                let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+                let glyph_cache = glyph_caches.entry(key).or_insert_with(|| GlyphCache::new(glyph.px));
+                match glyph_cache.mask_map.entry(glyph.glyph_index) {
+                    std::collections::hash_map::Entry::Vacant(e) => {
+                        self.metrics.cache_misses += 1;
+                        let start = Instant::now();
+                        let mask = rasterize_mask(self.font_at(font_index), glyph, flipped, effects);
+                        let mask = e.insert(self.shift_mask(mask, width + effects.padding() * 2, subpixel).into()).clone();
+                        self.metrics.rasterizations += 1;
+                        self.metrics.rasterization_time += start.elapsed();
+                        mask
+                    }
+                    // another clone got to it between the read and write locks
+                    std::collections::hash_map::Entry::Occupied(e) => {
+                        self.metrics.cache_hits += 1;
+                        e.get().clone()
+                    }
+                }
+            }
+        };
+        match self.gamma {
+            Some(gamma) => {
+                let mut adjusted = mask.to_vec();
+                adjust_coverage(&mut adjusted, colour, gamma);
+                adjusted.into()
+            }
+            None => mask,
+        }
+    }
+
+    /// Internal function to get the red, green and blue coverage of a glyph from either the cache or the font,
+    /// for `GlyphCacheFormat::Lcd`. Like `get_glyph_mask`, it's only copied if gamma has to be applied to it.
+    fn get_glyph_lcd(&mut self, font_index: usize, glyph: GlyphRasterConfig, colour: TextColour) -> Arc<[u8]> {
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = CacheKey { font: font_index, size: glyph.px.to_bits(), gamma: None, flipped, outline: None, blur: None, subpixel: 0 };
+        let cached = {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            glyph_caches.get(&key).and_then(|glyph_cache| glyph_cache.lcd_map.get(&glyph.glyph_index)).cloned()
+        };
+        let coverage = match cached {
+            Some(coverage) => {
+                self.metrics.cache_hits += 1;
+                coverage
+            }
+            None => {
+                self.metrics.cache_misses += 1;
+                let start = Instant::now();
+                let coverage = rasterize_lcd(self.font_at(font_index), glyph, flipped, self.subpixel_order);
+                self.metrics.rasterizations += 1;
+                self.metrics.rasterization_time += start.elapsed();
+                let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+                let glyph_cache = glyph_caches.entry(key).or_insert_with(|| GlyphCache::new(glyph.px));
+                glyph_cache.lcd_map.entry(glyph.glyph_index).or_insert(coverage.into()).clone()
+            }
+        };
+        match self.gamma {
+            Some(gamma) => {
+                let mut adjusted = coverage.to_vec();
+                adjust_coverage(&mut adjusted, colour, gamma);
+                adjusted.into()
+            }
+            None => coverage,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+    use crate::options::{Align, Anchor, BlendMode, CachePolicy, BreakOpportunity, FillMode, GlyphCacheFormat, GlyphDrawParams, LineExtent, Underline, UnderlineStyle, FontOptions, Direction, Origin, PathOverflow, PathPlacement, PathTextOptions, ShowWhitespace, TabStops, TextTransform, VerticalAlign, WrapOptions, WrapStyle};
+    use crate::path::Path;
+    use crate::spans::TextSpan;
+
+    #[derive(Debug, Clone)]
+    struct TestSurface {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    }
+
+    impl TestSurface {
+        fn new(width: usize, height: usize) -> Self {
+            TestSurface {
+                width,
+                height,
+                data: vec![0; width * height * 4],
+            }
+        }
+
+        /// Returns the (top, bottom) rows that have any non-transparent pixels between columns `x0` and `x1`.
+        fn inked_rows(&self, x0: usize, x1: usize) -> Option<(usize, usize)> {
+            let mut rows = (0..self.height)
+                .filter(|y| (x0..x1).any(|x| self.data[(y * self.width + x) * 4 + 3] != 0));
+            let top = rows.next()?;
+            Some((top, rows.next_back().unwrap_or(top)))
+        }
+    }
+
+    impl PasteSurface<Self> for TestSurface {
+        fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Self) {
+            self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+        }
+
+        fn width(&self) -> Option<usize> {
+            Some(self.width)
+        }
+
+        fn height(&self) -> Option<usize> {
+            Some(self.height)
+        }
+
+        fn paste_clipped(&mut self, x: usize, y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Self) {
+            self.paste_blended(x, y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+        }
+
+        fn paste_blended(&mut self, x: usize, y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Self, mode: BlendMode) {
+            println!("paste: x: {}, y: {}, src_x: {}, src_y: {}, width: {}, height: {}, mode: {:?}, data: {:?}", x, y, src_x, src_y, width, height, mode, data);
+            // data contains an rgba bitmap
+            crate::blit::paste_rgba(&mut self.data, self.width, self.height, x, y, src_x, src_y, width, height, &data.data, data.width, mode);
+        }
+
+        fn paste_lcd(
+            &mut self,
+            x: usize,
+            y: usize,
+            src_x: usize,
+            src_y: usize,
+            width: usize,
+            height: usize,
+            coverage: &[u8],
+            mask_width: usize,
+            colour: TextColour,
+            pixel_order: PixelOrder,
+            _mode: BlendMode
+        ) {
+            let dst = &mut self.data[(y * self.width + x) * 4..];
+            let coverage = &coverage[(src_y * mask_width + src_x) * 3..];
+            crate::blit::blend_lcd(dst, self.width * 4, coverage, mask_width * 3, width, height, colour, pixel_order);
+        }
+    }
+
+    impl StoreSurface for TestSurface {
+        // data is rgba
+        fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+            println!("from_raw_mask");
+            println!("width: {}", width);
+            println!("height: {}", height);
+            println!("data: {:?}", data);
+            TestSurface {
+                width,
+                height,
+                data: data.to_vec(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_renderer() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface {
+            width: 256,
+            height: 256,
+            data: vec![0; 256 * 256 * 4],
+        };
+        renderer.draw_string_monospaced("hElLo w0r1d! hai!!", 0.0, 0.0, 24.0, TextColour::new_rgb(255, 255, 255), &mut surface);
+        renderer.draw_string("hElLo w0r1d!", 0.0, 24.0, 24.0, TextColour::new_rgb(255, 255, 255), &mut surface);
+        // convert from rgba to rgb
+        let mut rgb_data = Vec::new();
+        for i in 0..(surface.width * surface.height) {
+            // if transparent, put black; otherwise, put the pixel
+            if surface.data[i * 4 + 3] == 0 {
+                rgb_data.push(0);
+                rgb_data.push(0);
+                rgb_data.push(0);
+            } else {
+                rgb_data.push(surface.data[i * 4]);
+                rgb_data.push(surface.data[i * 4 + 1]);
+                rgb_data.push(surface.data[i * 4 + 2]);
+            }
+        }
+        // output to bitmap
+        let mut file = std::fs::File::create("test.ppm").unwrap();
+        let _ = file.write(format!("P6\n{} {}\n255\n", surface.width, surface.height).as_bytes()).unwrap();
+        let _ = file.write(&rgb_data).unwrap();
+    }
+
+    #[test]
+    fn test_metrics_cache_hits() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface {
+            width: 256,
+            height: 64,
+            data: vec![0; 256 * 64 * 4],
+        };
+        let colour = TextColour::new_rgb(255, 255, 255);
+        renderer.draw_string("hai world", 0.0, 0.0, 24.0, colour, &mut surface);
+        let first = renderer.metrics();
+        assert!(first.rasterizations > 0);
+        assert_eq!(first.cache_misses, first.rasterizations);
+
+        renderer.reset_metrics();
+        assert_eq!(renderer.metrics(), RenderMetrics::default());
+        renderer.draw_string("hai world", 0.0, 0.0, 24.0, colour, &mut surface);
+        let second = renderer.metrics();
+        assert_eq!(second.rasterizations, 0);
+        assert_eq!(second.cache_misses, 0);
+        // the space doesn't draw anything, so it never gets looked up
+        assert_eq!(second.cache_hits, "haiworld".chars().count() as u64);
+    }
+
+    #[test]
+    fn test_baseline_origin_shares_baseline() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface::new(256, 128);
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let options = DrawOptions { origin: Origin::Baseline, ..Default::default() };
+        renderer.draw_string_with_options("H", 0.0, 100.0, 16.0, colour, &options, &mut surface);
+        renderer.draw_string_with_options("H", 100.0, 100.0, 64.0, colour, &options, &mut surface);
+        let (small_top, small_bottom) = surface.inked_rows(0, 100).unwrap();
+        let (big_top, big_bottom) = surface.inked_rows(100, 256).unwrap();
+        // the H should sit right on top of the baseline for both sizes
+        assert!((small_bottom as i32 - big_bottom as i32).abs() <= 1);
+        assert!((99..=100).contains(&big_bottom));
+        assert!(big_top < small_top);
+    }
+
+    #[test]
+    fn test_font_metrics() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let line = renderer.line_metrics(64.0);
+        assert!(line.ascent > 0.0);
+        assert!(line.descent < 0.0);
+        assert!((line.line_height - (line.ascent - line.descent + line.line_gap)).abs() < 0.01);
+        assert_eq!((renderer.ascent(64.0), renderer.descent(64.0)), (line.ascent, line.descent));
+        assert_eq!((renderer.line_gap(64.0), renderer.line_height(64.0)), (line.line_gap, line.line_height));
+
+        // caps + descenders should take up roughly the whole ascent to descent range
+        let mut surface = TestSurface::new(512, 128);
+        let options = DrawOptions { origin: Origin::Baseline, ..Default::default() };
+        renderer.draw_string_with_options("HÅgjpqy", 0.0, 100.0, 64.0, TextColour::new_rgb(255, 255, 255), &options, &mut surface);
+        let (top, bottom) = surface.inked_rows(0, 512).unwrap();
+        let inked = (bottom - top + 1) as f32;
+        let expected = line.ascent - line.descent;
+        assert!(inked <= expected + 1.0, "inked {} > expected {}", inked, expected);
+        assert!(inked >= expected * 0.7, "inked {} < expected {}", inked, expected);
+
+        // FreeMono is monospaced, so every advance is the same
+        assert_eq!(renderer.char_advance('i', 24.0), renderer.char_advance('W', 24.0));
+        let space = renderer.glyph_metrics(' ', 24.0).unwrap();
+        assert_eq!((space.width, space.bitmap_width), (0.0, 0));
+        assert!(space.advance > 0.0);
+        let h = renderer.glyph_metrics('H', 24.0).unwrap();
+        assert!(h.width > 0.0 && h.height > 0.0);
+        assert!(h.top_bearing > 0.0 && h.top_bearing <= line.ascent);
+        assert!(renderer.glyph_metrics('\u{e000}', 24.0).is_none());
+
+        // the bitmap is the same size as the metrics say, and comes from the cache the second time
+        let (metrics, bitmap) = renderer.glyph_bitmap('H', 24.0, TextColour::new_rgb(255, 255, 255)).unwrap();
+        assert_eq!(metrics, h);
+        assert_eq!((bitmap.width, bitmap.height), (h.bitmap_width, h.bitmap_height));
+        renderer.reset_metrics();
+        renderer.glyph_bitmap('H', 24.0, TextColour::new_rgb(255, 255, 255)).unwrap();
+        assert_eq!(renderer.metrics().cache_hits, 1);
+    }
+
+    #[test]
+    fn test_per_character_colours() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let red = TextColour::new_rgb(255, 0, 0);
+        let blue = TextColour::new_rgb(0, 0, 255);
+        let mut plain = TestSurface::new(256, 32);
+        let mut coloured = TestSurface::new(256, 32);
+        renderer.draw_string("let x = 1;", 0.0, 0.0, 24.0, red, &mut plain);
+        renderer.draw_string_with("let x = 1;", 0.0, 0.0, 24.0, &mut coloured, |i, _| if i < 3 { blue } else { red });
+
+        // same glyph placement as a plain draw
+        let alphas = |surface: &TestSurface| surface.data.chunks(4).map(|p| p[3]).collect::<Vec<_>>();
+        assert_eq!(alphas(&plain), alphas(&coloured));
+        // "let" is blue, everything after it is red
+        let colour_at = |x0: usize, x1: usize| {
+            coloured.data.chunks(4).enumerate()
+                .filter(|(i, p)| (x0..x1).contains(&(i % 256)) && p[3] != 0)
+                .map(|(_, p)| (p[0], p[2]))
+                .collect::<Vec<_>>()
+        };
+        let advance = renderer.char_advance('l', 24.0) as usize;
+        assert!(colour_at(0, advance * 3).iter().all(|&c| c == (0, 255)));
+        assert!(colour_at(advance * 4, 256).iter().all(|&c| c == (255, 0)));
+    }
+
+    #[test]
+    fn test_prepared_text() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        // replace, so drawing the same glyphs over and over gives the same result as drawing them once
+        let options = DrawOptions { blend_mode: BlendMode::Replace, ..Default::default() };
+        let prepared = renderer.prepare_text("hello!", 10.0, 5.0, 24.0, colour, &options);
+        assert_eq!(prepared.glyph_count(), 6);
+        assert_eq!(prepared.glyph_char(1), Some('e'));
+        assert!(prepared.glyph_bounds(6).is_none());
+        let first = prepared.glyph_bounds(0).unwrap();
+        let second = prepared.glyph_bounds(1).unwrap();
+        assert!(first.x >= 10 && second.x > first.x);
+
+        // drawing everything matches a plain draw
+        let mut plain = TestSurface::new(256, 64);
+        let mut drawn = TestSurface::new(256, 64);
+        renderer.draw_string_with_options("hello!", 10.0, 5.0, 24.0, colour, &options, &mut plain);
+        renderer.draw_prepared(&prepared, &mut drawn);
+        assert_eq!(plain.data, drawn.data);
+
+        // revealing glyphs one at a time never moves the ones already drawn, and doesn't rasterize anything new
+        renderer.reset_metrics();
+        let mut revealed = TestSurface::new(256, 64);
+        for count in 0..=prepared.glyph_count() + 1 {
+            renderer.draw_prefix(&prepared, count, &mut revealed);
+        }
+        assert_eq!(plain.data, revealed.data);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+
+        let mut partial = TestSurface::new(256, 64);
+        renderer.draw_prefix(&prepared, 3, &mut partial);
+        let third = prepared.glyph_bounds(2).unwrap();
+        assert!(partial.inked_rows(0, third.x as usize + third.width).is_some());
+        assert!(partial.inked_rows(prepared.glyph_bounds(3).unwrap().x as usize + 1, 256).is_none());
+    }
+
+    #[test]
+    fn test_clip_rect() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let paragraph = "scrolling\ntext\ninside\na panel\nthat is\nway too\nsmall";
+        let clip = Rect::new(0, 20, 256, 100);
+        let options = DrawOptions { clip: Some(clip), ..Default::default() };
+        let mut unclipped = TestSurface::new(256, 256);
+        renderer.draw_string(paragraph, 0.0, 7.0, 24.0, colour, &mut unclipped);
+        let mut clipped = TestSurface::new(256, 256);
+        renderer.draw_string_with_options(paragraph, 0.0, 7.0, 24.0, colour, &options, &mut clipped);
+
+        // nothing outside of the clip rect, everything inside matches the unclipped draw
+        for y in 0..256 {
+            for x in 0..256 {
+                let i = (y * 256 + x) * 4;
+                if (20..120).contains(&y) {
+                    assert_eq!(clipped.data[i..i + 4], unclipped.data[i..i + 4], "pixel ({}, {})", x, y);
+                } else {
+                    assert_eq!(clipped.data[i + 3], 0, "pixel ({}, {})", x, y);
+                }
+            }
+        }
+        // and there are partial glyphs at both edges
+        let (top, bottom) = clipped.inked_rows(0, 256).unwrap();
+        assert_eq!(top, 20);
+        assert_eq!(bottom, 119);
+    }
+
+    #[test]
+    fn test_negative_and_offscreen_coordinates() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let mut reference = TestSurface::new(256, 256);
+        renderer.draw_string("Hello", 20.0, 20.0, 48.0, colour, &mut reference);
+        let mut surface = TestSurface::new(256, 256);
+        renderer.draw_string("Hello", -10.0, -10.0, 48.0, colour, &mut surface);
+
+        // should be the reference moved up and left by 30 pixels, with the cut off bits missing
+        for y in 0..200 {
+            for x in 0..200 {
+                let i = (y * 256 + x) * 4;
+                let j = ((y + 30) * 256 + x + 30) * 4;
+                assert_eq!(surface.data[i..i + 4], reference.data[j..j + 4], "pixel ({}, {})", x, y);
+            }
+        }
+        assert_eq!(surface.inked_rows(0, 256).unwrap().0, 0);
+
+        // entirely off the top/left draws nothing
+        let mut surface = TestSurface::new(256, 256);
+        renderer.draw_string("Hello", -500.0, -500.0, 48.0, colour, &mut surface);
+        renderer.draw_string("Hello", 0.0, -100.0, 48.0, colour, &mut surface);
+        renderer.draw_string_monospaced("Hello", -500.0, 0.0, 48.0, colour, &mut surface);
+        assert!(surface.inked_rows(0, 256).is_none());
+
+        // off the bottom/right shouldn't panic either
+        renderer.draw_string("Hello", 240.0, 240.0, 48.0, colour, &mut surface);
+        renderer.draw_string("Hello", 5000.0, 5000.0, 48.0, colour, &mut surface);
+    }
+
+    #[test]
+    fn test_wrap_styles() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let advance = renderer.char_advance('a', 24.0).ceil();
+        let word = DrawOptions { max_width: Some(advance * 8.0), ..Default::default() };
+        let letter = DrawOptions { wrap_style: WrapStyle::Letter, ..word.clone() };
+
+        // word wrapping keeps words together
+        let metrics = renderer.measure_string_with_options("hai world foo", 24.0, &word);
+        let ranges = metrics.lines.iter().map(|l| l.byte_range.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..4, 4..10, 10..13]);
+        assert_eq!(metrics.lines[0].width, advance * 3.0);
+        assert_eq!(metrics.width, advance * 5.0);
+        assert_eq!(metrics.height, metrics.lines.iter().map(|l| l.height).sum::<f32>());
+
+        // letter wrapping fills each line up
+        let metrics = renderer.measure_string_with_options("hai world foo", 24.0, &letter);
+        let ranges = metrics.lines.iter().map(|l| l.byte_range.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..8, 8..13]);
+
+        // a word longer than the whole line falls back to being broken between letters
+        let metrics = renderer.measure_string_with_options("a 0123456789abcdef", 24.0, &word);
+        let ranges = metrics.lines.iter().map(|l| l.byte_range.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..2, 2..10, 10..18]);
+        assert!(metrics.lines.iter().all(|l| l.width <= advance * 8.0));
+
+        // hard breaks can be turned off
+        let metrics = renderer.measure_string_with_options("a\nb", 24.0, &DrawOptions::default());
+        assert_eq!(metrics.lines.len(), 2);
+        let no_hard = DrawOptions { wrap_hard_breaks: false, ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options("a\nb", 24.0, &no_hard).lines.len(), 1);
+
+        // and drawing uses the same lines
+        let metrics = renderer.measure_string_with_options("hai world foo", 24.0, &word);
+        let mut renderer = renderer;
+        let mut surface = TestSurface::new(256, 128);
+        renderer.draw_string_with_options("hai world foo", 0.0, 0.0, 24.0, TextColour::new_rgb(255, 255, 255), &word, &mut surface);
+        let (_, bottom) = surface.inked_rows(0, 256).unwrap();
+        let last = &metrics.lines[2];
+        assert!(bottom as f32 > last.y && (bottom as f32) < last.y + last.height);
+        assert!(surface.inked_rows(metrics.width as usize + 1, 256).is_none());
+    }
+
+    #[test]
+    fn test_max_lines_and_height() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let text = "the quick brown fox jumps over the lazy dog";
+        let advance = renderer.char_advance('a', 24.0).ceil();
+        let wrapped = DrawOptions { max_width: Some(advance * 10.0), ..Default::default() };
+        let full = renderer.measure_string_with_options(text, 24.0, &wrapped);
+        assert!(full.lines.len() > 3 && !full.truncated);
+
+        // cut down to two lines, with an ellipsis
+        let options = DrawOptions { max_lines: Some(2), ellipsis: true, ..wrapped.clone() };
+        let mut surface = TestSurface::new(256, 128);
+        let bounds = renderer.draw_string_with_options(text, 0.0, 0.0, 24.0, colour, &options, &mut surface);
+        assert!(bounds.truncated);
+        assert!(bounds.bytes_drawn <= full.lines[1].byte_range.end);
+        assert_eq!(bounds.glyphs_drawn, text[..bounds.bytes_drawn].chars().count());
+        assert_eq!(bounds.height, full.lines[0].height * 2.0);
+        assert!(bounds.width <= advance * 10.0);
+        let (_, bottom) = surface.inked_rows(0, 256).unwrap();
+        assert!((bottom as f32) < bounds.height);
+
+        // the same through max_height, without the ellipsis
+        let options = DrawOptions { max_height: Some(full.lines[0].height * 2.5), ..wrapped.clone() };
+        let measured = renderer.measure_string_with_options(text, 24.0, &options);
+        assert!(measured.truncated);
+        assert_eq!(measured.lines, full.lines[..2].to_vec());
+
+        // a limit smaller than a single line draws nothing at all
+        let options = DrawOptions { max_height: Some(5.0), ..wrapped };
+        let mut surface = TestSurface::new(256, 128);
+        let bounds = renderer.draw_string_with_options(text, 0.0, 0.0, 24.0, colour, &options, &mut surface);
+        assert!(bounds.truncated);
+        assert_eq!(bounds.glyphs_drawn, 0);
+        assert_eq!(bounds.bytes_drawn, 0);
+        assert!(surface.inked_rows(0, 256).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "shaping")]
+    fn test_shaping_latin_matches_layout() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let shaped = renderer.shape_string("héllo", 24.0);
+        assert_eq!(shaped.len(), 5);
+        assert_eq!(shaped.iter().map(|g| g.cluster).collect::<Vec<_>>(), vec![0, 1, 3, 4, 5]);
+        for (glyph, c) in shaped.iter().zip("héllo".chars()) {
+            assert_eq!(glyph.glyph_id, renderer.font.lookup_glyph_index(c));
+        }
+
+        let mut plain = TestSurface::new(256, 64);
+        let mut shaped = TestSurface::new(256, 64);
+        renderer.draw_string("hello", 0.0, 0.0, 24.0, colour, &mut plain);
+        let bounds = renderer.draw_string_shaped("hello", 0.0, 0.0, 24.0, colour, &DrawOptions::default(), &mut shaped);
+        assert_eq!(bounds.glyphs_drawn, 5);
+        assert_eq!(plain.inked_rows(0, 256), shaped.inked_rows(0, 256));
+        // fontdue rounds advances up, so allow a pixel or two of drift by the end of the string
+        let inked_columns = |surface: &TestSurface| (0..256).filter(|&x| surface.inked_rows(x, x + 1).is_some()).collect::<Vec<_>>();
+        let (plain, shaped) = (inked_columns(&plain), inked_columns(&shaped));
+        assert_eq!(plain[0], shaped[0]);
+        assert!((*plain.last().unwrap() as i32 - *shaped.last().unwrap() as i32).abs() <= 2);
+    }
+
+    #[test]
+    fn test_vertical_layout() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let line_height = renderer.line_metrics(24.0).line_height.ceil();
+        let options = DrawOptions { direction: Direction::Vertical, max_height: Some(line_height * 3.0), ..Default::default() };
+
+        let metrics = renderer.measure_string_with_options("abcdefg", 24.0, &options);
+        let ranges = metrics.lines.iter().map(|l| l.byte_range.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..3, 3..6, 6..7]);
+        assert_eq!(metrics.width, line_height * 3.0);
+        assert_eq!(metrics.height, line_height * 3.0);
+
+        // first column is on the right, and each character sits below the last
+        let prepared = renderer.prepare_text("abcdefg", 0.0, 0.0, 24.0, colour, &options);
+        let a = prepared.glyph_bounds(0).unwrap();
+        let b = prepared.glyph_bounds(1).unwrap();
+        let d = prepared.glyph_bounds(3).unwrap();
+        let g = prepared.glyph_bounds(6).unwrap();
+        assert!(a.x > d.x && d.x > g.x);
+        assert!(b.y > a.y && (b.y - a.y) as f32 <= line_height + 1.0);
+        assert!(g.x >= 0 && (a.x as f32) < metrics.width);
+
+        // newlines start a new column, and max_lines limits the columns
+        let options = DrawOptions { direction: Direction::Vertical, max_lines: Some(1), ..Default::default() };
+        let mut surface = TestSurface::new(64, 128);
+        let bounds = renderer.draw_string_with_options("ab\ncd", 0.0, 0.0, 24.0, colour, &options, &mut surface);
+        assert!(bounds.truncated);
+        assert_eq!(bounds.bytes_drawn, 3);
+        assert_eq!(bounds.width, line_height);
+        let (_, bottom) = surface.inked_rows(0, 64).unwrap();
+        assert!(bottom as f32 > line_height && (bottom as f32) < line_height * 2.0);
+    }
+
+    #[test]
+    fn test_superscript_spans() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let plain = renderer.measure_string_with_options("x", 32.0, &DrawOptions::default());
+        let advance = renderer.char_advance('x', 32.0).ceil() as usize;
+
+        let spans = [TextSpan::new("x"), TextSpan::superscript("x")];
+        let mut surface = TestSurface::new(128, 128);
+        let bounds = renderer.draw_spans(&spans, 0.0, 0.0, 32.0, colour, &DrawOptions::default(), &mut surface);
+        assert_eq!(bounds.glyphs_drawn, 2);
+        // still one line, but it had to get taller to fit the raised span
+        assert!(bounds.height > plain.height);
+        let (base_top, base_bottom) = surface.inked_rows(0, advance).unwrap();
+        let (sup_top, sup_bottom) = surface.inked_rows(advance, 128).unwrap();
+        assert!(sup_top < base_top);
+        assert!(sup_bottom < base_bottom);
+        assert!(sup_bottom - sup_top < base_bottom - base_top);
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let red = TextColour::new_rgb(255, 0, 0);
+        let background = |colour: TextColour| {
+            let mut surface = TestSurface::new(64, 32);
+            surface.data.chunks_exact_mut(4).for_each(|pixel| pixel.copy_from_slice(&[colour.r, colour.g, colour.b, colour.a]));
+            surface
+        };
+        // somewhere inside of the glyph's bitmap that doesn't have any ink
+        let bounds = renderer.prepare_text("o", 0.0, 0.0, 24.0, white, &DrawOptions::default()).glyph_bounds(0).unwrap();
+        let centre = ((bounds.y as usize + bounds.height / 2) * 64 + bounds.x as usize + bounds.width / 2) * 4;
+
+        let mut over = background(red);
+        renderer.draw_string("o", 0.0, 0.0, 24.0, white, &mut over);
+        assert_eq!(over.data[centre..centre + 4], [255, 0, 0, 255]);
+        assert!(over.data.chunks_exact(4).any(|pixel| pixel[1] > 128 && pixel[3] == 255));
+
+        let mut replaced = background(red);
+        let options = DrawOptions { blend_mode: BlendMode::Replace, ..Default::default() };
+        renderer.draw_string_with_options("o", 0.0, 0.0, 24.0, white, &options, &mut replaced);
+        assert_eq!(replaced.data[centre + 3], 0);
+
+        let mut added = background(TextColour::new_rgb(0, 0, 128));
+        let options = DrawOptions { blend_mode: BlendMode::Additive, ..Default::default() };
+        renderer.draw_string_with_options("o", 0.0, 0.0, 24.0, red, &options, &mut added);
+        assert!(added.data.chunks_exact(4).any(|pixel| pixel[0] > 128 && pixel[2] == 128));
+
+        // multiplying by red takes the green out of yellow, and only where there's ink
+        let mut multiplied = background(TextColour::new_rgb(255, 255, 0));
+        let options = DrawOptions { blend_mode: BlendMode::Multiply, ..Default::default() };
+        renderer.draw_string_with_options("o", 0.0, 0.0, 24.0, red, &options, &mut multiplied);
+        assert_eq!(multiplied.data[centre..centre + 4], [255, 255, 0, 255]);
+        assert!(multiplied.data.chunks_exact(4).any(|pixel| pixel[1] < 128));
+        assert!(multiplied.data.chunks_exact(4).all(|pixel| pixel[0] == 255 && pixel[2] == 0));
+
+        // screening with red adds red to blue without touching the blue
+        let mut screened = background(TextColour::new_rgb(0, 0, 255));
+        let options = DrawOptions { blend_mode: BlendMode::Screen, ..Default::default() };
+        renderer.draw_string_with_options("o", 0.0, 0.0, 24.0, red, &options, &mut screened);
+        assert_eq!(screened.data[centre..centre + 4], [0, 0, 255, 255]);
+        assert!(screened.data.chunks_exact(4).any(|pixel| pixel[0] > 128));
+        assert!(screened.data.chunks_exact(4).all(|pixel| pixel[1] == 0 && pixel[2] == 255));
+    }
+
+    #[test]
+    fn test_blend_over_half_alpha() {
+        let mut dst = [0, 0, 255, 255, 0, 0, 0, 0];
+        crate::blit::blend_over(&mut dst, 8, &[255, 0, 0, 128, 255, 0, 0, 128], 8, 2, 1);
+        assert_eq!(dst, [128, 0, 127, 255, 255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn test_gamma() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let ink = |renderer: &mut TextRenderer<TestSurface, TestSurface>, colour: TextColour| {
+            let mut surface = TestSurface::new(32, 32);
+            renderer.draw_string("e", 0.0, 0.0, 12.0, colour, &mut surface);
+            surface.data.chunks_exact(4).map(|pixel| pixel[3] as u32).sum::<u32>()
+        };
+        let white = TextColour::new_rgb(255, 255, 255);
+        let black = TextColour::new_rgb(0, 0, 0);
+        let (plain_white, plain_black) = (ink(&mut renderer, white), ink(&mut renderer, black));
+        assert_eq!(plain_white, plain_black);
+
+        renderer.set_gamma(Some(2.0));
+        let (gamma_white, gamma_black) = (ink(&mut renderer, white), ink(&mut renderer, black));
+        assert!(gamma_white > plain_white);
+        assert!(gamma_black < plain_black);
+        // and a stronger correction goes further
+        renderer.set_gamma(Some(DEFAULT_GAMMA));
+        assert!(ink(&mut renderer, white) > gamma_white);
+        assert!(ink(&mut renderer, black) < gamma_black);
+
+        // turning it back off again doesn't reuse the adjusted glyphs
+        renderer.set_gamma(None);
+        renderer.reset_metrics();
+        assert_eq!(ink(&mut renderer, white), plain_white);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+    }
+
+    #[test]
+    fn test_builder() {
+        let font_data = std::fs::read("FreeMono.ttf").unwrap();
+        let mut loaded = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut built: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::from_bytes(font_data)
+            .gamma(Some(2.0))
+            .build()
+            .unwrap();
+        assert_eq!(built.gamma(), Some(2.0));
+        built.set_gamma(None);
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let mut a = TestSurface::new(128, 32);
+        let mut b = TestSurface::new(128, 32);
+        loaded.draw_string("hai", 0.0, 0.0, 16.0, colour, &mut a);
+        built.draw_string("hai", 0.0, 0.0, 16.0, colour, &mut b);
+        assert_eq!(a.data, b.data);
+
+        let missing = TextRendererBuilder::new("missing.ttf").build::<TestSurface, TestSurface>();
+        assert!(matches!(missing, Err(TextRendererError::IoError(ref e)) if e.kind() == std::io::ErrorKind::NotFound));
+        // the io error is the source, so it shows up in error chains
+        let missing = missing.err().unwrap();
+        assert!(std::error::Error::source(&missing).is_some());
+        assert!(missing.to_string().starts_with("couldn't read font file: "));
+
+        // the collection index goes all the way through to the font, and FreeMono isn't a collection so only 0 works
+        let first = FontOptions { collection_index: 0, ..Default::default() };
+        assert!(TextRenderer::<TestSurface, TestSurface>::load_with_options("FreeMono.ttf", &first).is_ok());
+        let second = FontOptions { collection_index: 1, ..Default::default() };
+        let result = TextRenderer::<TestSurface, TestSurface>::load_with_options("FreeMono.ttf", &second);
+        assert!(matches!(result, Err(TextRendererError::InvalidCollectionIndex { index: 1, fonts: 1 })));
+    }
+
+    #[test]
+    fn test_from_bytes_and_font() {
+        let font_data = include_bytes!("../FreeMono.ttf");
+        let mut loaded = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut from_bytes = TextRenderer::from_bytes(font_data).unwrap();
+        let mut from_font = TextRenderer::from_font(Font::from_bytes(&font_data[..], FontSettings::default()).unwrap());
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let (mut a, mut b, mut c) = (TestSurface::new(128, 32), TestSurface::new(128, 32), TestSurface::new(128, 32));
+        loaded.draw_string("hai", 0.0, 0.0, 16.0, colour, &mut a);
+        from_bytes.draw_string("hai", 0.0, 0.0, 16.0, colour, &mut b);
+        from_font.draw_string("hai", 0.0, 0.0, 16.0, colour, &mut c);
+        assert_eq!(a.data, b.data);
+        assert_eq!(a.data, c.data);
+
+        let result = TextRenderer::<TestSurface, TestSurface>::from_bytes(b"not a font");
+        assert!(matches!(result, Err(TextRendererError::FontParseError(_))));
+    }
+
+    #[test]
+    fn test_fractional_sizes() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let small = renderer.prepare_text("O", 0.0, 0.0, 16.0, colour, &DrawOptions::default());
+        let large = renderer.prepare_text("O", 0.0, 0.0, 16.5, colour, &DrawOptions::default());
+        let (small_bounds, large_bounds) = (small.glyph_bounds(0).unwrap(), large.glyph_bounds(0).unwrap());
+        assert_ne!((small_bounds.width, small_bounds.height), (large_bounds.width, large_bounds.height));
+
+        // each size gets its own cached bitmap, and drawing one doesn't change what the other looks like
+        let draw = |renderer: &mut TextRenderer<TestSurface, TestSurface>, prepared: &PreparedText| {
+            let mut surface = TestSurface::new(32, 32);
+            renderer.draw_prepared(prepared, &mut surface);
+            surface.data
+        };
+        let small_first = draw(&mut renderer, &small);
+        let large_first = draw(&mut renderer, &large);
+        assert_eq!(renderer.metrics().rasterizations, 2);
+        assert_ne!(small_first, large_first);
+        assert_eq!(draw(&mut renderer, &small), small_first);
+        assert_eq!(draw(&mut renderer, &large), large_first);
+        assert_eq!(renderer.metrics().rasterizations, 2);
+    }
+
+    #[test]
+    fn test_wrap_string() {
+        let renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        let text = "one two three\nfour five";
+        let advance = renderer.char_advance('a', 24.0).ceil();
+        let lines = renderer.wrap_string(text, 24.0, advance * 9.0, &WrapOptions::default());
+        let ranges = lines.iter().map(|line| &text[line.byte_range.clone()]).collect::<Vec<_>>();
+        assert_eq!(ranges, ["one two ", "three\n", "four five"]);
+        // the space the first line was broken at isn't part of its width
+        assert_eq!(lines[0].width, advance * 7.0);
+        assert_eq!(lines[0].glyph_count, 8);
+        assert_eq!(lines[2].y, lines[0].height * 2.0);
+
+        // same breaks as drawing
+        let options = DrawOptions { max_width: Some(advance * 9.0), ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options(text, 24.0, &options).lines, lines);
+
+        let options = WrapOptions { style: WrapStyle::Letter, hard_breaks: false, ..Default::default() };
+        let lines = renderer.wrap_string(text, 24.0, advance * 9.0, &options);
+        assert_eq!(text[lines[0].byte_range.clone()].chars().count(), 9);
+    }
+
+    #[test]
+    #[cfg(feature = "markup")]
+    fn test_markup() {
+        use crate::markup::{parse_markup, MarkupError};
+        use crate::spans::BaselineShift;
+        let red = TextColour::from_hex("#ff0000");
+        let spans = parse_markup("pick up the {color=#ff0000}red {size=30}key{/size}{/color} and press {b}E{/b} {{x}}{sup}2{/sup}", true).unwrap();
+        let texts = spans.iter().map(|span| span.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, ["pick up the ", "red ", "key", " and press ", "E", " {x}", "2"]);
+        assert_eq!(spans[1].colour, Some(red));
+        assert_eq!((spans[2].colour, spans[2].size), (Some(red), Some(30.0)));
+        assert_eq!((spans[3].colour, spans[3].size), (None, None));
+        assert_eq!(spans[6].baseline_shift, BaselineShift::Superscript);
+
+        // unclosed tags run to the end unless we're being strict
+        let spans = parse_markup("{colour=#ff0000}oops", false).unwrap();
+        assert_eq!(spans, [TextSpan::new("oops").with_colour(red)]);
+        assert_eq!(parse_markup("{colour=#ff0000}oops", true), Err(MarkupError::UnclosedTag { offset: 0, tag: "colour".to_string() }));
+
+        // anything else that's wrong gives back the raw text
+        for broken in ["{b}wrong order{i}{/b}{/i}", "{colour=red}x{/colour}", "{nope}", "a } b", "a { b"] {
+            assert_eq!(parse_markup(broken, false).unwrap(), [TextSpan::new(broken)]);
+            assert!(parse_markup(broken, true).is_err());
+        }
+
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface::new(256, 32);
+        let bounds = renderer.draw_markup("a{colour=#ff0000}b{/colour}", 0.0, 0.0, 24.0, TextColour::new_rgb(0, 0, 255), &mut surface);
+        assert_eq!(bounds.glyphs_drawn, 2);
+        assert!(surface.data.chunks_exact(4).any(|pixel| pixel[0] > 0 && pixel[2] == 0));
+        assert!(surface.data.chunks_exact(4).any(|pixel| pixel[0] == 0 && pixel[2] > 0));
+    }
+
+    #[test]
+    #[cfg(feature = "ansi")]
+    fn test_ansi() {
+        use crate::ansi::{parse_ansi, AnsiRun};
+        // what `ls --color` spits out
+        let listing = "\x1b[0m\x1b[01;34mdir\x1b[0m  notes.txt  \x1b[01;32mrun.sh\x1b[0m\n\x1b[38;5;196mred\x1b[38;2;1;2;3m!\x1b[48;5;232m?\x1b[m\x1b[2K\x1b]0;title\x07.";
+        let runs = parse_ansi(listing);
+        let run = |text: &str, fg: Option<TextColour>, bg: Option<TextColour>| AnsiRun { text: text.to_string(), fg, bg };
+        assert_eq!(runs, [
+            run("dir", Some(TextColour::new_rgb(0x5c, 0x5c, 0xff)), None),
+            run("  notes.txt  ", None, None),
+            run("run.sh", Some(TextColour::new_rgb(0, 0xff, 0)), None),
+            run("\n", None, None),
+            run("red", Some(TextColour::new_rgb(255, 0, 0)), None),
+            run("!", Some(TextColour::new_rgb(1, 2, 3)), None),
+            run("?", Some(TextColour::new_rgb(1, 2, 3)), Some(TextColour::new_rgb(8, 8, 8))),
+            run(".", None, None),
+        ]);
+
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface::new(128, 32);
+        let white = TextColour::new_rgb(255, 255, 255);
+        let bounds = renderer.draw_ansi("a\x1b[31;44mb\x1b[0mc", 0.0, 0.0, 24.0, white, None, &mut surface);
+        assert_eq!(bounds.glyphs_drawn, 3);
+        let advance = renderer.char_advance('a', 24.0).ceil() as usize;
+        // only the middle cell has a (blue) background
+        let pixel = |x: usize, y: usize| &surface.data[(y * 128 + x) * 4..(y * 128 + x) * 4 + 4];
+        assert_eq!(pixel(advance, 0), [0, 0, 0xee, 255]);
+        assert_eq!(pixel(advance * 2 - 1, 0), [0, 0, 0xee, 255]);
+        assert_eq!(pixel(advance - 1, 0)[3], 0);
+        assert_eq!(pixel(advance * 2, 0)[3], 0);
+        // with the red text on top of it
+        assert!((advance..advance * 2).any(|x| (0..32).any(|y| pixel(x, y)[0] > 0)));
+    }
+
+    #[test]
+    fn test_ansi_palette() {
+        use crate::colours::*;
+        let table = [
+            (0, 0x000000), (1, 0xcd0000), (4, 0x0000ee), (7, 0xe5e5e5), (8, 0x7f7f7f), (12, 0x5c5cff), (15, 0xffffff),
+            (16, 0x000000), (17, 0x00005f), (21, 0x0000ff), (46, 0x00ff00), (124, 0xaf0000), (196, 0xff0000),
+            (208, 0xff8700), (231, 0xffffff), (232, 0x080808), (244, 0x808080), (255, 0xeeeeee),
+        ];
+        for (index, rgb) in table {
+            let expected = TextColour::new_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+            assert_eq!(TextColour::from_ansi_256(index), expected, "colour {}", index);
+        }
+        assert_eq!(TextColour::from_ansi_256(9), ANSI_BRIGHT_RED);
+        assert_eq!(TextColour::from_ansi_256(3), ANSI_YELLOW);
+
+        assert_eq!(ANSI_BRIGHT_RED.nearest_ansi_256(), 9);
+        assert_eq!(TextColour::new_rgb(0xff, 0x87, 0x00).nearest_ansi_256(), 208);
+        assert_eq!(TextColour::new_rgb(0x81, 0x81, 0x81).nearest_ansi_256(), 244);
+        for index in 16..=255 {
+            let colour = TextColour::from_ansi_256(index);
+            assert_eq!(TextColour::from_ansi_256(colour.nearest_ansi_256()), colour);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "ttf-parser")]
+    fn test_glyph_outlines() {
+        use crate::outline::PathCommand;
+        let renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        let i = renderer.glyph_outline('I', 48.0).unwrap();
+        assert!(matches!(i.commands.first(), Some(PathCommand::MoveTo(..))));
+        assert_eq!(i.commands.last(), Some(&PathCommand::Close));
+        // the outline covers the same area as the rasterized glyph
+        let metrics = renderer.glyph_metrics('I', 48.0).unwrap();
+        let (min_x, min_y, max_x, max_y) = i.bounds().unwrap();
+        assert!((min_x - metrics.left_bearing).abs() < 1.0);
+        assert!((max_x - min_x - metrics.width).abs() < 1.0);
+        assert!((-min_y - metrics.top_bearing).abs() < 1.0);
+        assert!((max_y - min_y - metrics.height).abs() < 1.0);
+
+        assert_eq!(renderer.glyph_outline(' ', 48.0).unwrap().commands, []);
+        assert!(renderer.glyph_outline('\u{e000}', 48.0).is_none());
+        // composite glyphs come out with all of their pieces
+        let a = renderer.glyph_outline('a', 48.0).unwrap();
+        let a_ring = renderer.glyph_outline('å', 48.0).unwrap();
+        assert!(a_ring.bounds().unwrap().1 < a.bounds().unwrap().1);
+
+        // outlines in a string line up with where the bitmaps get drawn
+        let outlines = renderer.string_outlines("hi I", 10.0, 20.0, 48.0, &DrawOptions::default());
+        assert_eq!(outlines.iter().map(|glyph| glyph.c).collect::<String>(), "hiI");
+        let prepared = renderer.prepare_text("hi I", 10.0, 20.0, 48.0, TextColour::new_rgb(0, 0, 0), &DrawOptions::default());
+        let bitmap = prepared.glyph_bounds(3).unwrap();
+        let (min_x, min_y, _, _) = outlines[2].path.bounds().unwrap();
+        assert!((min_x - bitmap.x as f32).abs() < 1.0 && (min_y - bitmap.y as f32).abs() < 1.0);
+    }
+
+    #[test]
+    #[cfg(feature = "ttf-parser")]
+    fn test_variable_fonts() {
+        // VariableTest.ttf has one axis (wght from 100 to 900) and an "I" that gets wider and bolder with it
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let variable = renderer.add_font("VariableTest.ttf", &FontOptions::default()).unwrap();
+        let axes = renderer.variation_axes(variable);
+        assert_eq!(axes.len(), 1);
+        assert_eq!((axes[0].tag.as_str(), axes[0].name.as_deref(), axes[0].min, axes[0].default, axes[0].max), ("wght", Some("Weight"), 100.0, 400.0, 900.0));
+        let names = renderer.named_instances(variable).into_iter().map(|instance| (instance.name, instance.coordinates)).collect::<Vec<_>>();
+        assert_eq!(names, [("Light".to_string(), vec![("wght".to_string(), 300.0)]), ("Bold".to_string(), vec![("wght".to_string(), 700.0)])]);
+        assert!(renderer.variation_axes(0).is_empty());
+
+        // every instance is a font of its own, so they're measured and cached separately
+        let heavy = renderer.add_font_variation(variable, &[("wght", 900.0)], &FontOptions::default()).unwrap();
+        let light = renderer.add_named_instance(variable, "Light", &FontOptions::default()).unwrap();
+        let width = |renderer: &TextRenderer<TestSurface, TestSurface>, font_index: usize| {
+            renderer.layout_rich(&[Run { font_index, ..Run::new("I", 100.0) }], 100.0, &DrawOptions::default()).metrics.width
+        };
+        assert_eq!((width(&renderer, variable), width(&renderer, heavy), width(&renderer, light)), (40.0, 60.0, 35.0));
+        let white = TextColour::new_rgb(255, 255, 255);
+        let mut surface = TestSurface::new(256, 128);
+        let spans = [TextSpan::new("I").with_font(variable), TextSpan::new("I").with_font(heavy)];
+        renderer.draw_spans(&spans, 0.0, 0.0, 100.0, white, &DrawOptions::default(), &mut surface);
+        assert_eq!(renderer.metrics().rasterizations, 2);
+        let inked = (0..surface.width).filter(|x| (0..surface.height).any(|y| surface.data[(y * surface.width + x) * 4 + 3] != 0)).collect::<Vec<_>>();
+        assert_eq!(inked, (10..30).chain(50..90).collect::<Vec<_>>());
+
+        // the builder can make the renderer's own font an instance too
+        let bold: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("VariableTest.ttf").named_instance("Bold").build().unwrap();
+        assert_eq!(bold.measure_string_with_options("I", 100.0, &DrawOptions::default()).width, 52.0);
+        let result = TextRendererBuilder::new("VariableTest.ttf").variation("wdth", 50.0).build::<TestSurface, TestSurface>();
+        assert!(matches!(result, Err(TextRendererError::UnknownVariation(tag)) if tag == "wdth"));
+        let result = TextRendererBuilder::new("FreeMono.ttf").variation("wght", 700.0).build::<TestSurface, TestSurface>();
+        assert!(matches!(result, Err(TextRendererError::UnknownVariation(_))));
+        assert!(matches!(renderer.add_named_instance(variable, "Black", &FontOptions::default()), Err(TextRendererError::UnknownVariation(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "ttf-parser")]
+    fn test_svg_export() {
+        let renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        let options = DrawOptions { max_width: Some(100.0), ..Default::default() };
+        let measured = renderer.measure_string_with_options("hai world", 24.0, &options);
+        assert_eq!(measured.lines.len(), 2);
+        let svg = renderer.render_to_svg("hai world", 24.0, TextColour::new(255, 0, 0, 128), &options);
+        let (width, height) = (measured.width.ceil(), measured.height.ceil());
+        assert!(svg.starts_with(&format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#)));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<path").count(), 8);
+        assert_eq!(svg.matches(r##"fill="#ff0000" fill-opacity="0.5""##).count(), 8);
+
+        let spans = [TextSpan::new("a"), TextSpan::new("b").with_colour(TextColour::new_rgb(0, 0, 255))];
+        let svg = renderer.render_spans_to_svg(&spans, 24.0, TextColour::new_rgb(0, 0, 0), &DrawOptions::default());
+        let fills = svg.lines().filter(|line| line.starts_with("<path")).filter_map(|line| line.split('"').nth(1)).collect::<Vec<_>>();
+        assert_eq!(fills, ["#000000", "#0000ff"]);
+    }
+
+    #[test]
+    fn test_clones_share_cache() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let mut clone = renderer.clone();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let mut surface = TestSurface::new(256, 32);
+        clone.draw_string("hai world", 0.0, 0.0, 24.0, colour, &mut surface);
+        assert_eq!(clone.metrics().rasterizations, 8);
+        renderer.draw_string("hai world", 0.0, 0.0, 24.0, colour, &mut surface);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+        assert_eq!(renderer.metrics().cache_hits, 8);
+    }
+
+    #[test]
+    fn test_pixel_order() {
+        let colour = TextColour::new_rgb(10, 20, 30);
+        let orders = [
+            (PixelOrder::Rgba, [10, 20, 30], 3),
+            (PixelOrder::Bgra, [30, 20, 10], 3),
+            (PixelOrder::Argb, [10, 20, 30], 0),
+            (PixelOrder::Abgr, [30, 20, 10], 0),
+        ];
+        for (order, rgb, alpha) in orders {
+            let mut renderer: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf").pixel_order(order).build().unwrap();
+            assert_eq!(renderer.pixel_order(), order);
+            let (_, bitmap) = renderer.glyph_bitmap('H', 24.0, colour).unwrap();
+            let inked = bitmap.data.chunks_exact(4).find(|pixel| pixel[alpha] == 255).unwrap();
+            let colour_bytes = if alpha == 0 { &inked[1..] } else { &inked[..3] };
+            assert_eq!(colour_bytes, rgb, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_subpixel_positions() {
+        let white = TextColour::new_rgb(255, 255, 255);
+        // where the ink is horizontally, weighted by coverage
+        let centre = |renderer: &mut TextRenderer<TestSurface, TestSurface>, x: f32| {
+            let mut surface = TestSurface::new(32, 32);
+            renderer.draw_string("l", x, 0.0, 16.0, white, &mut surface);
+            let pixels = surface.data.chunks_exact(4).enumerate().map(|(i, pixel)| ((i % 32) as f32, pixel[3] as f32));
+            let (moment, total) = pixels.fold((0.0, 0.0), |(moment, total), (x, alpha)| (moment + x * alpha, total + alpha));
+            moment / total
+        };
+
+        // without it, half a pixel to the right is exactly the same as not moving at all
+        let mut snapped = TextRenderer::load("FreeMono.ttf").unwrap();
+        assert_eq!(snapped.subpixel_positions(), 1);
+        assert_eq!(centre(&mut snapped, 0.0), centre(&mut snapped, 0.4));
+
+        let mut smooth: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf").subpixel_positions(4).build().unwrap();
+        assert_eq!(smooth.subpixel_positions(), 4);
+        let start = centre(&mut smooth, 0.0);
+        let (quarter, half) = (centre(&mut smooth, 0.25), centre(&mut smooth, 0.5));
+        assert!((quarter - start - 0.25).abs() < 0.05, "{} {}", start, quarter);
+        assert!((half - start - 0.5).abs() < 0.05, "{} {}", start, half);
+        // every quarter is cached on its own, and close enough positions share one
+        smooth.reset_metrics();
+        centre(&mut smooth, 0.26);
+        centre(&mut smooth, 1.0);
+        assert_eq!(smooth.metrics().rasterizations, 0);
+        centre(&mut smooth, 0.75);
+        assert_eq!(smooth.metrics().rasterizations, 1);
+    }
+
+    #[test]
+    fn test_lcd() {
+        let white = TextColour::new_rgb(255, 255, 255);
+        let draw = |renderer: &mut TextRenderer<TestSurface, TestSurface>| {
+            let mut surface = TestSurface::new(32, 32);
+            renderer.draw_string("l", 3.0, 0.0, 20.0, white, &mut surface);
+            surface.data
+        };
+        let lcd = |order: SubpixelOrder| -> TextRenderer<TestSurface, TestSurface> {
+            TextRendererBuilder::new("FreeMono.ttf").glyph_cache_format(GlyphCacheFormat::Lcd).subpixel_order(order).build().unwrap()
+        };
+        let mut rgb = lcd(SubpixelOrder::Rgb);
+        assert_eq!(rgb.subpixel_order(), SubpixelOrder::Rgb);
+        let (rgb_pixels, bgr_pixels) = (draw(&mut rgb), draw(&mut lcd(SubpixelOrder::Bgr)));
+        // the edges of the glyph get coloured fringes, which greyscale glyphs never have
+        assert!(rgb_pixels.chunks_exact(4).any(|pixel| pixel[0] != pixel[2]));
+        assert!(draw(&mut TextRenderer::load("FreeMono.ttf").unwrap()).chunks_exact(4).all(|pixel| pixel[0] == pixel[2]));
+        // and the other subpixel order mirrors them
+        for (rgb, bgr) in rgb_pixels.chunks_exact(4).zip(bgr_pixels.chunks_exact(4)) {
+            assert_eq!([rgb[0], rgb[1], rgb[2], rgb[3]], [bgr[2], bgr[1], bgr[0], bgr[3]]);
+        }
+        // every glyph is only rasterized once whatever colour it's drawn in
+        rgb.reset_metrics();
+        let mut surface = TestSurface::new(32, 32);
+        rgb.draw_string("l", 3.0, 0.0, 20.0, TextColour::new_rgb(255, 0, 0), &mut surface);
+        assert_eq!(rgb.metrics().rasterizations, 0);
+        assert!(surface.data.chunks_exact(4).any(|pixel| pixel[0] > 0 && pixel[1] == 0 && pixel[2] == 0));
+        // or copied, without any gamma to apply
+        let glyph = GlyphRasterConfig { glyph_index: rgb.font.lookup_glyph_index('l'), px: 20.0, font_hash: rgb.font.file_hash() };
+        assert!(Arc::ptr_eq(&rgb.get_glyph_lcd(0, glyph, white), &rgb.get_glyph_lcd(0, glyph, TextColour::new_rgb(255, 0, 0))));
+        assert_eq!(rgb.metrics().rasterizations, 0);
+    }
+
+    #[test]
+    fn test_glyph_sdf() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let sdf = renderer.glyph_sdf('O', 96.0, 4).unwrap();
+        let (metrics, bitmap) = renderer.glyph_bitmap('O', 96.0, TextColour::new_rgb(255, 255, 255)).unwrap();
+        assert_eq!(sdf.metrics, metrics);
+        assert_eq!((sdf.width, sdf.height), (metrics.bitmap_width + 8, metrics.bitmap_height + 8));
+        assert_eq!(sdf.left, metrics.left_bearing.floor() - 4.0);
+        assert_eq!((sdf.channels, sdf.data.len()), (1, sdf.width * sdf.height));
+        // thresholding at the edge gives the glyph back, and it fades out towards the edges of the field
+        for y in 0..metrics.bitmap_height {
+            for x in 0..metrics.bitmap_width {
+                let covered = bitmap.data[(y * metrics.bitmap_width + x) * 4 + 3] >= 128;
+                assert_eq!(sdf.data[(y + 4) * sdf.width + x + 4] >= 128, covered, "pixel ({}, {})", x, y);
+            }
+        }
+        assert_eq!(sdf.data[0], 0);
+        assert!(sdf.data.iter().any(|distance| *distance > 128 + 32));
+        assert!(sdf.data.iter().any(|distance| *distance > 0 && *distance < 128 - 32));
+
+        // cached alongside the normal bitmaps, separately for every spread
+        renderer.reset_metrics();
+        assert_eq!(renderer.glyph_sdf('O', 96.0, 4).unwrap(), sdf);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+        assert_eq!(renderer.glyph_sdf('O', 96.0, 0).unwrap().spread, 1);
+        assert_eq!(renderer.metrics().rasterizations, 1);
+        assert!(renderer.glyph_sdf('\u{10ffff}', 96.0, 4).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "ttf-parser")]
+    fn test_glyph_msdf() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let sdf = renderer.glyph_sdf('H', 96.0, 4).unwrap();
+        let msdf = renderer.glyph_msdf('H', 96.0, 4).unwrap();
+        assert_eq!((msdf.width, msdf.height, msdf.left, msdf.top), (sdf.width, sdf.height, sdf.left, sdf.top));
+        assert_eq!((msdf.channels, msdf.data.len()), (3, sdf.width * sdf.height * 3));
+        // the median of the channels gives the same shape as the single channel field, give or take the odd edge pixel
+        let median = |pixel: &[u8]| pixel[0].max(pixel[1]).min(pixel[0].min(pixel[1]).max(pixel[2]));
+        let different = msdf.data.chunks_exact(3).zip(&sdf.data)
+            .filter(|(pixel, distance)| (median(pixel) >= 128) != (**distance >= 128))
+            .count();
+        assert!(different < sdf.data.iter().filter(|distance| **distance >= 128).count() / 20, "{} pixels differ", different);
+        // but the channels disagree around the corners, which is what keeps them sharp
+        assert!(msdf.data.chunks_exact(3).any(|pixel| pixel.iter().max().unwrap() - pixel.iter().min().unwrap() > 64));
+
+        renderer.reset_metrics();
+        assert_eq!(renderer.glyph_msdf('H', 96.0, 4).unwrap(), msdf);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+    }
+
+    #[test]
+    fn test_glyph_atlas() {
+        use crate::atlas::GlyphAtlas;
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut atlas = GlyphAtlas::new(32, 32);
+        let a = renderer.atlas_glyph(&mut atlas, 'A', 24.0).unwrap();
+        assert_eq!((a.rect.width, a.rect.height), (a.metrics.bitmap_width, a.metrics.bitmap_height));
+        assert_eq!(a.uv, atlas.uv_rect(a.rect));
+        assert!(atlas.take_dirty_rect().is_some_and(|dirty| dirty.intersection(&a.rect) == Some(a.rect)));
+        assert!(atlas.take_dirty_rect().is_none());
+        // the coverage in the atlas is the glyph's
+        let (_, bitmap) = renderer.glyph_bitmap('A', 24.0, TextColour::new_rgb(255, 255, 255)).unwrap();
+        for y in 0..a.rect.height {
+            for x in 0..a.rect.width {
+                let atlas_pixel = atlas.data()[(a.rect.y as usize + y) * atlas.size().0 + a.rect.x as usize + x];
+                assert_eq!(atlas_pixel, bitmap.data[(y * a.rect.width + x) * 4 + 3]);
+            }
+        }
+
+        // asking again doesn't add it twice, and the atlas grows to fit more without moving anything
+        assert_eq!(renderer.atlas_glyph(&mut atlas, 'A', 24.0).unwrap(), a);
+        assert_eq!(renderer.atlas_string(&mut atlas, "the quick brown fox jumps over the lazy dog", 24.0), 0);
+        assert!(atlas.size().0 > 32 || atlas.size().1 > 32);
+        assert_eq!(renderer.atlas_glyph(&mut atlas, 'A', 24.0).unwrap().rect, a.rect);
+        let space = renderer.atlas_glyph(&mut atlas, ' ', 24.0).unwrap();
+        assert_eq!((space.rect.width, space.rect.height), (0, 0));
+        assert_eq!(atlas.len(), 28);
+        // nothing overlaps
+        let rects: Vec<Rect> = "abcdefghijklmnopqrstuvwxyzA".chars().map(|c| renderer.atlas_glyph(&mut atlas, c, 24.0).unwrap().rect).collect();
+        for (i, first) in rects.iter().enumerate() {
+            assert!(rects[i + 1..].iter().all(|second| first.intersection(second).is_none()));
+        }
+
+        // full is full
+        let mut tiny = GlyphAtlas::new(8, 8);
+        tiny.set_max_size(16);
+        assert!(renderer.atlas_glyph(&mut tiny, 'W', 48.0).is_none());
+        assert!(tiny.is_empty());
+        let surface = renderer.atlas_surface(&atlas, TextColour::new_rgb(255, 0, 0));
+        assert_eq!((surface.width, surface.height), atlas.size());
+    }
+
+    #[test]
+    #[cfg(feature = "wgpu")]
+    fn test_glyph_instances() {
+        use crate::atlas::GlyphAtlas;
+        use crate::gpu::{GlyphInstance, TEXT_SHADER};
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut atlas = GlyphAtlas::new(64, 64);
+        let white = TextColour::new_rgb(255, 255, 255);
+        let instances = renderer.glyph_instances(&mut atlas, "same, every frame", 10.5, 20.0, 24.0, white, &DrawOptions::default());
+        assert_eq!(instances.len(), "same,everyframe".len());
+        assert_eq!(instances[0].colour, [1.0; 4]);
+        // drawing the atlas where the instances say gives exactly what drawing the string does
+        let mut surface = TestSurface::new(300, 60);
+        renderer.draw_string("same, every frame", 10.5, 20.0, 24.0, white, &mut surface);
+        let (atlas_width, atlas_height) = atlas.size();
+        for instance in &instances {
+            assert!(instance.uv.iter().all(|uv| (0.0..=1.0).contains(uv)));
+            let (left, top) = ((instance.uv[0] * atlas_width as f32) as usize, (instance.uv[1] * atlas_height as f32) as usize);
+            for y in 0..instance.size[1] as usize {
+                for x in 0..instance.size[0] as usize {
+                    let drawn = surface.data[((instance.position[1] as usize + y) * 300 + instance.position[0] as usize + x) * 4 + 3];
+                    assert_eq!(atlas.data()[(top + y) * atlas_width + left + x], drawn);
+                }
+            }
+        }
+        assert_eq!(GlyphInstance::layout().array_stride, 48);
+        let module = wgpu::naga::front::wgsl::parse_str(TEXT_SHADER).unwrap();
+        wgpu::naga::valid::Validator::new(Default::default(), Default::default()).validate(&module).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "image")]
+    fn test_rgba_image() {
+        use image::RgbaImage;
+        let colour = TextColour::new_rgb(255, 128, 0);
+        let mut renderer = TextRenderer::<RgbaImage, RgbaImage>::load("FreeMono.ttf").unwrap();
+        let mut test_renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        // the same pixels as any other RGBA surface, cut off at the edges and all
+        let mut image = RgbaImage::new(100, 40);
+        let mut surface = TestSurface::new(100, 40);
+        renderer.draw_string("images!", -4.0, 10.0, 24.0, colour, &mut image);
+        test_renderer.draw_string("images!", -4.0, 10.0, 24.0, colour, &mut surface);
+        assert_eq!(image.as_raw(), &surface.data);
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 128, 0, 255]));
+
+        let (rendered, _) = renderer.render_to_surface("hi", 24.0, colour, &DrawOptions::default());
+        let mut png = std::io::Cursor::new(Vec::new());
+        rendered.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let decoded = image::load_from_memory(png.get_ref()).unwrap().to_rgba8();
+        assert_eq!(decoded, rendered);
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-graphics")]
+    fn test_draw_target_surface() {
+        use crate::embedded::{DrawTargetSurface, RgbaGlyph};
+        use embedded_graphics_core::pixelcolor::{BinaryColor, Rgb888, RgbColor};
+        use embedded_graphics_core::prelude::*;
+
+        struct Display<C> {
+            pixels: Vec<C>,
+        }
+
+        impl<C: PixelColor> OriginDimensions for Display<C> {
+            fn size(&self) -> Size {
+                Size::new(64, 24)
+            }
+        }
+
+        impl<C: PixelColor> DrawTarget for Display<C> {
+            type Color = C;
+            type Error = std::convert::Infallible;
+
+            fn draw_iter<I: IntoIterator<Item = Pixel<C>>>(&mut self, pixels: I) -> Result<(), Self::Error> {
+                for Pixel(point, colour) in pixels {
+                    self.pixels[point.y as usize * 64 + point.x as usize] = colour;
+                }
+                Ok(())
+            }
+        }
+
+        // a colour display ends up with the same pixels as any other surface with the same background
+        let mut renderer = TextRenderer::<RgbaGlyph, DrawTargetSurface<Display<Rgb888>>>::load("FreeMono.ttf").unwrap();
+        let mut display = DrawTargetSurface::new(Display { pixels: vec![Rgb888::BLACK; 64 * 24] }, Rgb888::BLACK);
+        renderer.draw_string("sext", 2.0, 2.0, 18.0, TextColour::new_rgb(255, 200, 0), &mut display);
+        let mut surface = TestSurface { width: 64, height: 24, data: [0, 0, 0, 255].repeat(64 * 24) };
+        let mut test_renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        test_renderer.draw_string("sext", 2.0, 2.0, 18.0, TextColour::new_rgb(255, 200, 0), &mut surface);
+        let expected: Vec<Rgb888> = surface.data.chunks_exact(4).map(|pixel| Rgb888::new(pixel[0], pixel[1], pixel[2])).collect();
+        assert_eq!(display.target().pixels, expected);
+
+        // and a monochrome one has every pixel that's at least half bright turned on
+        let mut renderer = TextRenderer::<RgbaGlyph, DrawTargetSurface<Display<BinaryColor>>>::load("FreeMono.ttf").unwrap();
+        let mut display = DrawTargetSurface::new(Display { pixels: vec![BinaryColor::Off; 64 * 24] }, Rgb888::BLACK);
+        renderer.draw_string("sext", 2.0, 2.0, 18.0, TextColour::new_rgb(255, 255, 255), &mut display);
+        let mut surface = TestSurface { width: 64, height: 24, data: [0, 0, 0, 255].repeat(64 * 24) };
+        test_renderer.draw_string("sext", 2.0, 2.0, 18.0, TextColour::new_rgb(255, 255, 255), &mut surface);
+        let expected: Vec<BinaryColor> = surface.data.chunks_exact(4).map(|pixel| (pixel[0] >= 128).into()).collect();
+        let lit = expected.iter().filter(|pixel| **pixel == BinaryColor::On).count();
+        assert_eq!(display.into_inner().pixels, expected);
+        assert!(lit > 0);
+    }
+
+    #[test]
+    #[cfg(feature = "tiny-skia")]
+    fn test_pixmap() {
+        use tiny_skia::{Color, Pixmap};
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let mut expected = TestSurface { width: 80, height: 30, data: [0, 0, 0, 255].repeat(80 * 30) };
+        let mut test_renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        test_renderer.draw_string("skia", -3.0, 4.0, 20.0, colour, &mut expected);
+        // tiny-skia does the blending, so give or take its rounding it's the same as everywhere else (cut off glyphs included)
+        for format in [GlyphCacheFormat::Rgba, GlyphCacheFormat::Alpha] {
+            let mut renderer: TextRenderer<Pixmap, Pixmap> = TextRendererBuilder::new("FreeMono.ttf")
+                .premultiplied_alpha(true)
+                .glyph_cache_format(format)
+                .build()
+                .unwrap();
+            let mut pixmap = Pixmap::new(80, 30).unwrap();
+            pixmap.fill(Color::BLACK);
+            renderer.draw_string("skia", -3.0, 4.0, 20.0, colour, &mut pixmap);
+            for (drawn, expected) in pixmap.data().chunks_exact(4).zip(expected.data.chunks_exact(4)) {
+                assert!(drawn.iter().zip(expected).all(|(a, b)| a.abs_diff(*b) <= 1), "{:?} != {:?}", drawn, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_premultiplied_alpha() {
+        let colour = TextColour::new_rgb(200, 100, 50);
+        let mut renderer: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf").premultiplied_alpha(true).build().unwrap();
+        assert!(renderer.premultiplied_alpha());
+        let (_, bitmap) = renderer.glyph_bitmap('H', 24.0, colour).unwrap();
+        // every channel is scaled by the coverage, so edge pixels are darker and transparent ones are all zero
+        for pixel in bitmap.data.chunks_exact(4) {
+            let scale = |channel: u8| ((channel as u16 * pixel[3] as u16 + 127) / 255) as u8;
+            assert_eq!(pixel[..3], [scale(200), scale(100), scale(50)]);
+        }
+        assert!(bitmap.data.chunks_exact(4).any(|pixel| pixel[3] > 0 && pixel[3] < 255));
+        assert!(bitmap.data.chunks_exact(4).any(|pixel| pixel[..] == [200, 100, 50, 255]));
+
+        // straight alpha is still the default
+        let mut renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        assert!(!renderer.premultiplied_alpha());
+        let (_, bitmap) = renderer.glyph_bitmap('H', 24.0, colour).unwrap();
+        assert!(bitmap.data.chunks_exact(4).all(|pixel| pixel[..3] == [200, 100, 50]));
+    }
+
+    #[test]
+    fn test_blend_rgba_over_random_rects() {
+        // tiny xorshift so we don't need a whole rng crate for one test
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut random = |max: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % max as u64) as usize
+        };
+        for _ in 0..2000 {
+            let (dst_w, dst_h, src_w, src_h) = (random(20), random(20), random(12), random(12));
+            let (dst_x, dst_y) = (random(40) as i32 - 15, random(40) as i32 - 15);
+            let clip = match random(2) {
+                0 => None,
+                _ => Some(Rect::new(random(30) as i32 - 5, random(30) as i32 - 5, random(20), random(20))),
+            };
+            let mut dst = (0..dst_w * dst_h * 4).map(|_| random(256) as u8).collect::<Vec<_>>();
+            // lots of fully transparent and fully opaque pixels, like a real glyph
+            let src = (0..src_w * src_h * 4).map(|_| [0, 255, random(256)][random(3)] as u8).collect::<Vec<_>>();
+
+            // the obvious (slow) way of doing it
+            let mut expected = dst.clone();
+            for y in 0..dst_h as i32 {
+                for x in 0..dst_w as i32 {
+                    let inside_src = x >= dst_x && y >= dst_y && x < dst_x + src_w as i32 && y < dst_y + src_h as i32;
+                    let inside_clip = clip.is_none_or(|clip| x >= clip.x && y >= clip.y && x < clip.x + clip.width as i32 && y < clip.y + clip.height as i32);
+                    if inside_src && inside_clip {
+                        let d = ((y * dst_w as i32 + x) * 4) as usize;
+                        let s = (((y - dst_y) * src_w as i32 + (x - dst_x)) * 4) as usize;
+                        crate::blit::blend_over(&mut expected[d..d + 4], 4, &src[s..s + 4], 4, 1, 1);
+                    }
+                }
+            }
+            crate::blit::blend_rgba_over(&mut dst, dst_w, dst_h, dst_x, dst_y, &src, src_w, src_h, clip);
+            assert_eq!(dst, expected);
+        }
+        // slices that are too short for the sizes they're given don't get read or written past the end
+        let mut dst = vec![0; 4 * 4 * 4 - 5];
+        crate::blit::blend_rgba_over(&mut dst, 4, 4, 0, 0, &[255; 4 * 4 * 4 - 1], 4, 4, None);
+        assert!(dst[..4 * 3 * 4].iter().all(|byte| *byte == 255));
+    }
+
+    #[test]
+    fn test_paste_rgba() {
+        // two 3x3 "glyphs" whose boxes overlap by a column, with transparent edges like a real one
+        let mut glyph = vec![0u8; 3 * 3 * 4];
+        glyph[(3 + 1) * 4..][..4].copy_from_slice(&[255, 0, 0, 255]);
+        let mut dst = vec![0u8; 5 * 3 * 4];
+        crate::blit::paste_rgba(&mut dst, 5, 3, 0, 0, 0, 0, 3, 3, &glyph, 3, BlendMode::AlphaOver);
+        crate::blit::paste_rgba(&mut dst, 5, 3, 1, 0, 0, 0, 3, 3, &glyph, 3, BlendMode::AlphaOver);
+        // the second box's transparent edge didn't wipe out the first glyph
+        assert_eq!(dst[(5 + 1) * 4..][..4], [255, 0, 0, 255]);
+        assert_eq!(dst[(5 + 2) * 4..][..4], [255, 0, 0, 255]);
+
+        // only the asked for part of the glyph is pasted, and replace does replace
+        let mut dst = vec![9u8; 5 * 3 * 4];
+        crate::blit::paste_rgba(&mut dst, 5, 3, 3, 1, 1, 1, 1, 1, &glyph, 3, BlendMode::Replace);
+        assert_eq!(dst[(5 + 3) * 4..][..4], [255, 0, 0, 255]);
+        assert_eq!(dst.iter().filter(|byte| **byte == 9).count(), 5 * 3 * 4 - 4);
+
+        // anything past the edge of the destination is cut off
+        let mut dst = vec![0u8; 5 * 3 * 4];
+        crate::blit::paste_rgba(&mut dst, 5, 3, 4, 2, 0, 0, 3, 3, &[255; 3 * 3 * 4], 3, BlendMode::Additive);
+        assert_eq!(dst.iter().filter(|byte| **byte == 255).count(), 4);
+    }
+
+    #[test]
+    fn test_selection() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let blue = TextColour::new_rgb(0, 0, 255);
+        let red = TextColour::new_rgb(255, 0, 0);
+        let advance = renderer.char_advance('a', 24.0).ceil();
+        let text = "one two three";
+        let wrapped = DrawOptions { max_width: Some(advance * 9.0), ..Default::default() };
+        let line_height = renderer.measure_string_with_options(text, 24.0, &wrapped).lines[0].height;
+
+        // "two th" spans both lines, including the space the first line was broken at
+        let selection = Selection { range: 4..10, background: blue, foreground: Some(red) };
+        let options = DrawOptions { selection: Some(selection), ..wrapped.clone() };
+        let mut surface = TestSurface::new(256, 64);
+        renderer.draw_string_with_options(text, 0.0, 0.0, 24.0, white, &options, &mut surface);
+        let pixel = |x: f32, y: f32| surface.data[((y as usize) * 256 + x as usize) * 4..][..4].to_vec();
+        // the top left of the selection on each line is background (no glyph gets that close to the top)
+        assert_eq!(pixel(advance * 4.0, 0.0), [0, 0, 255, 255]);
+        assert_eq!(pixel(advance * 8.0 - 1.0, 0.0), [0, 0, 255, 255]);
+        assert_eq!(pixel(0.0, line_height), [0, 0, 255, 255]);
+        assert_eq!(pixel(advance * 2.0 - 1.0, line_height), [0, 0, 255, 255]);
+        // and it stops where the selection does
+        assert_eq!(pixel(advance * 4.0 - 1.0, 0.0)[3], 0);
+        assert_eq!(pixel(advance * 2.0, line_height)[3], 0);
+        // selected glyphs are red, the rest are still white
+        let pixels = surface.data.chunks_exact(4).collect::<Vec<_>>();
+        assert!(pixels.iter().any(|pixel| pixel[0] > 128 && pixel[1] == 0));
+        assert!(pixels.iter().any(|pixel| pixel[0] > 128 && pixel[1] > 128));
+
+        // an empty selection draws nothing extra
+        let selection = Selection { range: 4..4, background: blue, foreground: None };
+        let options = DrawOptions { selection: Some(selection), ..wrapped.clone() };
+        let mut selected = TestSurface::new(256, 64);
+        let mut plain = TestSurface::new(256, 64);
+        renderer.draw_string_with_options(text, 0.0, 0.0, 24.0, white, &options, &mut selected);
+        renderer.draw_string_with_options(text, 0.0, 0.0, 24.0, white, &wrapped, &mut plain);
+        assert_eq!(selected.data, plain.data);
+    }
+
+    #[test]
+    fn test_render_to_surface() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let colour = TextColour::new_rgb(255, 255, 255);
+        let measured = renderer.measure_string_with_options("hai world", 24.0, &DrawOptions::default());
+        let (surface, bounds): (TestSurface, _) = renderer.render_to_surface("hai world", 24.0, colour, &DrawOptions::default());
+        assert!(surface.width >= measured.width as usize && surface.height >= measured.height as usize);
+        assert_eq!(surface.data.len(), surface.width * surface.height * 4);
+        assert_eq!(bounds.glyphs_drawn, 9);
+        // same pixels as drawing it onto a surface of our own at the same spot
+        let mut drawn = TestSurface::new(surface.width, surface.height);
+        renderer.draw_string("hai world", bounds.x, bounds.y, 24.0, colour, &mut drawn);
+        assert_eq!(surface.data, drawn.data);
+
+        // every bit of ink makes it onto the surface, even for glyphs that reach right up to (or past) the line box
+        let (surface, _): (TestSurface, _) = renderer.render_to_surface("ÅjÇ_|", 48.0, colour, &DrawOptions::default());
+        let ink = surface.data.chunks_exact(4).filter(|pixel| pixel[3] != 0).count();
+        let mut big = TestSurface::new(256, 128);
+        renderer.draw_string("ÅjÇ_|", 32.0, 32.0, 48.0, colour, &mut big);
+        assert_eq!(ink, big.data.chunks_exact(4).filter(|pixel| pixel[3] != 0).count());
+
+        // text that somehow measures less than nothing gets an empty surface rather than a huge one
+        let mut text = renderer.layout_text("\u{200B}", 24.0, &DrawOptions::default());
+        (text.metrics.width, text.metrics.height) = (-15.0, -100.0);
+        assert_eq!((text.ink_bounds().width, text.ink_bounds().height), (0, 0));
+        let (surface, _): (TestSurface, _) = renderer.render_laid_out(&text, colour, &DrawOptions::default());
+        assert!(surface.data.is_empty());
+    }
+
+    #[test]
+    fn test_fit_size() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let name = "xXx_player_xXx";
+        let fitted = renderer.fit_size(name, 180.0, 40.0, 4.0..=100.0, 0.1, &DrawOptions::default());
+        assert!(fitted.fits);
+        let fits = |size: f32| renderer.measure_string_with_options(name, size, &DrawOptions::default());
+        assert!(fits(fitted.size).width <= 180.0 && fits(fitted.size).height <= 40.0);
+        assert!(fits(fitted.size + 0.2).width > 180.0 || fits(fitted.size + 0.2).height > 40.0);
+
+        // everything fits, or nothing does
+        assert_eq!(renderer.fit_size("hi", 180.0, 40.0, 4.0..=12.0, 0.1, &DrawOptions::default()).size, 12.0);
+        let fitted = renderer.fit_size(name, 10.0, 40.0, 4.0..=100.0, 0.1, &DrawOptions::default());
+        assert_eq!((fitted.size, fitted.fits), (4.0, false));
+
+        // with wrapping it can be bigger, as long as the lines fit in the height
+        let wrapped = DrawOptions { max_width: Some(0.0), ..Default::default() };
+        let fitted_wrapped = renderer.fit_size("a b c d e f", 40.0, 200.0, 4.0..=100.0, 0.1, &wrapped);
+        let fitted_single = renderer.fit_size("a b c d e f", 40.0, 200.0, 4.0..=100.0, 0.1, &DrawOptions::default());
+        assert!(fitted_wrapped.size > fitted_single.size);
+
+        let mut surface = TestSurface::new(256, 64);
+        let (fitted, bounds) = renderer.draw_string_fitted(name, 0.0, 0.0, 180.0, 40.0, 4.0..=100.0, TextColour::new_rgb(255, 255, 255), &DrawOptions::default(), &mut surface);
+        assert!(fitted.fits && bounds.width <= 180.0 && bounds.height <= 40.0);
+        let (_, bottom) = surface.inked_rows(0, 256).unwrap();
+        assert!(bottom < 40);
+    }
+
+    #[test]
+    fn test_line_break_callback() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let name = "com.example.service.AccountManager";
+        let char_width = renderer.measure_string_with_options("x", 20.0, &DrawOptions::default()).width;
+        let max_width = char_width * 20.0;
+
+        // with the normal rules the only way to break an identifier is between any two letters
+        let lines = renderer.wrap_string(name, 20.0, max_width, &WrapOptions::default());
+        assert_eq!(&name[lines[0].byte_range.clone()], "com.example.service.");
+
+        fn after_dots(prev: char, _next: char) -> Option<BreakOpportunity> {
+            (prev == '.').then_some(BreakOpportunity::Allowed)
+        }
+        let options = WrapOptions { line_breaks: Some(after_dots), ..Default::default() };
+        let lines = renderer.wrap_string(name, 20.0, char_width * 14.0, &options);
+        let lines = lines.iter().map(|line| &name[line.byte_range.clone()]).collect::<Vec<_>>();
+        assert_eq!(lines, ["com.example.", "service.", "AccountManager"]);
+
+        fn forbid_all(_prev: char, _next: char) -> Option<BreakOpportunity> {
+            Some(BreakOpportunity::Forbidden)
+        }
+        let options = WrapOptions { line_breaks: Some(forbid_all), ..Default::default() };
+        assert_eq!(renderer.wrap_string("no breaks at all", 20.0, char_width * 4.0, &options).len(), 1);
+
+        fn semicolons(prev: char, _next: char) -> Option<BreakOpportunity> {
+            (prev == ';').then_some(BreakOpportunity::Mandatory)
+        }
+        let options = WrapOptions { line_breaks: Some(semicolons), ..Default::default() };
+        let lines = renderer.wrap_string("a;b;c\nd", 20.0, 1000.0, &options);
+        assert_eq!(lines.iter().map(|line| line.byte_range.clone()).collect::<Vec<_>>(), [0..2, 2..4, 4..6, 6..7]);
+        // lines sit in the same places fontdue would put them
+        let fontdue_lines = renderer.wrap_string("a\nb\nc\nd", 20.0, 1000.0, &WrapOptions::default());
+        for (line, fontdue_line) in lines.iter().zip(fontdue_lines.iter()) {
+            assert_eq!((line.y, line.baseline, line.height), (fontdue_line.y, fontdue_line.baseline, fontdue_line.height));
+        }
+    }
+
+    #[test]
+    fn test_soft_hyphens() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let char_width = renderer.measure_string_with_options("x", 20.0, &DrawOptions::default()).width;
+        let word = "hyphen\u{ad}ation";
+
+        // not broken, so it takes up no space and isn't drawn
+        let metrics = renderer.measure_string_with_options(word, 20.0, &DrawOptions::default());
+        assert_eq!(metrics.width, renderer.measure_string_with_options("hyphenation", 20.0, &DrawOptions::default()).width);
+        let mut with = TestSurface::new(256, 32);
+        let mut without = TestSurface::new(256, 32);
+        renderer.draw_string(word, 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &mut with);
+        renderer.draw_string("hyphenation", 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &mut without);
+        assert!(with.data == without.data);
+
+        // broken, so the first line ends in a hyphen
+        let options = DrawOptions { max_width: Some(char_width * 8.0), ..Default::default() };
+        let metrics = renderer.measure_string_with_options(word, 20.0, &options);
+        assert_eq!(metrics.lines.len(), 2);
+        assert_eq!(metrics.lines[0].byte_range, 0.."hyphen\u{ad}".len());
+        assert_eq!(metrics.lines[0].width, char_width * 7.0);
+        let mut broken = TestSurface::new(256, 64);
+        let mut expected = TestSurface::new(256, 64);
+        renderer.draw_string_with_options(word, 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &options, &mut broken);
+        renderer.draw_string("hyphen-\nation", 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &mut expected);
+        assert!(broken.data == expected.data);
+    }
+
+    #[cfg(feature = "system-fonts")]
+    #[test]
+    fn test_find_family() {
+        use crate::system_fonts::{find_face, FontStyle};
+        // the installed fonts are different everywhere, so this looks through just the fonts in the repo
+        let mut database = fontdb::Database::new();
+        database.load_font_data(std::fs::read("FreeMono.ttf").unwrap());
+        database.load_font_data(std::fs::read("DejaVuSansMono-Bold.ttf").unwrap());
+        let (free_mono, _) = find_face(&database, "FreeMono", FontStyle::default()).unwrap();
+        assert_eq!(free_mono, std::fs::read("FreeMono.ttf").unwrap());
+        // only the bold face is there, so that's the closest to regular
+        let (dejavu, index) = find_face(&database, "DejaVu Sans Mono", FontStyle::default()).unwrap();
+        assert_eq!((dejavu, index), (std::fs::read("DejaVuSansMono-Bold.ttf").unwrap(), 0));
+        assert!(find_face(&database, "Not A Real Font", FontStyle::BOLD).is_none());
+    }
+
+    #[cfg(feature = "hyphenation")]
+    #[test]
+    fn test_hyphenation() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let char_width = renderer.measure_string_with_options("x", 20.0, &DrawOptions::default()).width;
+        let options = DrawOptions {
+            max_width: Some(char_width * 10.0),
+            hyphenation: Some(crate::hyphenate::Language::EnglishUS),
+            ..Default::default()
+        };
+
+        // breaking after "a" would leave most of the line empty, so the word gets split (and the hyphen has to fit too)
+        let string = "a hyphenation";
+        let lines = renderer.measure_string_with_options(string, 20.0, &options).lines;
+        assert_eq!(lines.iter().map(|line| &string[line.byte_range.clone()]).collect::<Vec<_>>(), ["a hyphena", "tion"]);
+        assert_eq!(lines[0].width, char_width * 10.0);
+        let mut hyphenated = TestSurface::new(256, 64);
+        let mut expected = TestSurface::new(256, 64);
+        renderer.draw_string_with_options(string, 0.0, 0.0, 20.0, white, &options, &mut hyphenated);
+        renderer.draw_string("a hyphena-\ntion", 0.0, 0.0, 20.0, white, &mut expected);
+        assert!(hyphenated.data == expected.data);
+
+        // the hyphen isn't part of the string
+        let prepared = renderer.prepare_text(string, 0.0, 0.0, 20.0, white, &options);
+        assert_eq!(prepared.glyph_char(9), Some('-'));
+        assert_eq!(prepared.glyph_byte_range(9), Some(9..9));
+        assert_eq!(prepared.glyph_index_for_byte(9), Some(10));
+
+        // a line that's full enough when broken between words stays that way
+        let string = "the quick hyphenation";
+        let lines = renderer.wrap_string(string, 20.0, char_width * 10.0, &WrapOptions {
+            hyphenation: Some(crate::hyphenate::Language::EnglishUS),
+            ..Default::default()
+        });
+        assert_eq!(lines.iter().map(|line| &string[line.byte_range.clone()]).collect::<Vec<_>>(), ["the quick ", "hyphena", "tion"]);
+    }
+
+    #[test]
+    fn test_hollow_text() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let ink = |surface: &TestSurface| surface.data.chunks_exact(4).map(|pixel| pixel[3] as u32).sum::<u32>();
+        let mut solid = TestSurface::new(128, 64);
+        renderer.draw_string("HOLLOW", 0.0, 0.0, 20.0, white, &mut solid);
+
+        // hollow glyphs are cached separately from the filled ones already in there, and have less ink
+        let mut hollow = TestSurface::new(128, 64);
+        let options = DrawOptions { fill_mode: FillMode::Outline { thickness: 1.0 }, ..Default::default() };
+        renderer.draw_string_with_options("HOLLOW", 0.0, 0.0, 20.0, white, &options, &mut hollow);
+        assert!(ink(&hollow) > 0 && ink(&hollow) < ink(&solid));
+
+        // a relative thickness that works out thinner than a pixel still draws a pixel thick outline
+        let mut thin = TestSurface::new(128, 64);
+        let options = DrawOptions { fill_mode: FillMode::OutlineRelative { thickness: 0.001 }, ..Default::default() };
+        renderer.draw_string_with_options("HOLLOW", 0.0, 0.0, 20.0, white, &options, &mut thin);
+        assert!(thin.data == hollow.data);
+        assert_eq!(FillMode::OutlineRelative { thickness: 0.05 }.outline_thickness(40.0), Some(2.0));
+        assert_eq!(FillMode::Solid.outline_thickness(40.0), None);
+    }
+
+    #[test]
+    fn test_blurred_glow() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let (white, accent) = (TextColour::new_rgb(255, 255, 255), TextColour::new_rgb(64, 160, 255));
+        let blurred = DrawOptions { blur: Some(6.0), ..Default::default() };
+        let covered = |surface: &TestSurface| surface.data.chunks_exact(4).enumerate()
+            .filter(|(_, pixel)| pixel[3] > 0)
+            .map(|(i, _)| (i % surface.width, i / surface.width))
+            .collect::<Vec<_>>();
+
+        // a glow: blurred in the accent colour, then sharp on top
+        let mut glow = TestSurface::new(128, 64);
+        renderer.draw_string_with_options("glow", 20.0, 16.0, 20.0, accent, &blurred, &mut glow);
+        renderer.draw_string("glow", 20.0, 16.0, 20.0, white, &mut glow);
+        let mut sharp = TestSurface::new(128, 64);
+        renderer.draw_string("glow", 20.0, 16.0, 20.0, white, &mut sharp);
+        let (glow_pixels, sharp_pixels) = (covered(&glow), covered(&sharp));
+        assert!(sharp_pixels.iter().all(|pixel| glow_pixels.contains(pixel)));
+        assert!(glow_pixels.len() > sharp_pixels.len());
+        // the blurred glyphs were cached on their own, so they didn't get mixed up with the sharp ones
+        assert!(glow.data.chunks_exact(4).any(|pixel| pixel[..3] == [64, 160, 255]));
+        let mut sharp_again = TestSurface::new(128, 64);
+        renderer.draw_string_with_options("glow", 20.0, 16.0, 20.0, white, &blurred, &mut TestSurface::new(128, 64));
+        renderer.draw_string("glow", 20.0, 16.0, 20.0, white, &mut sharp_again);
+        assert!(sharp_again.data == sharp.data);
+
+        // the blur spreads out evenly around the sharp text, without being cut off
+        let mut blur_only = TestSurface::new(128, 64);
+        renderer.draw_string_with_options("glow", 20.0, 16.0, 20.0, white, &blurred, &mut blur_only);
+        let bounds = |pixels: &[(usize, usize)]| (
+            pixels.iter().map(|pixel| pixel.0).min().unwrap(),
+            pixels.iter().map(|pixel| pixel.1).min().unwrap(),
+            pixels.iter().map(|pixel| pixel.0).max().unwrap(),
+            pixels.iter().map(|pixel| pixel.1).max().unwrap(),
+        );
+        let (sharp_bounds, blur_bounds) = (bounds(&sharp_pixels), bounds(&covered(&blur_only)));
+        assert!(blur_bounds.0 < sharp_bounds.0 && blur_bounds.1 < sharp_bounds.1);
+        assert!(blur_bounds.2 > sharp_bounds.2 && blur_bounds.3 > sharp_bounds.3);
+        assert!((sharp_bounds.0 - blur_bounds.0).abs_diff(blur_bounds.2 - sharp_bounds.2) <= 1);
+        assert!((sharp_bounds.1 - blur_bounds.1).abs_diff(blur_bounds.3 - sharp_bounds.3) <= 1);
+    }
+
+    #[test]
+    fn test_tabular_numbers() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let tabular = DrawOptions { tabular_numbers: true, ..Default::default() };
+        let ones = renderer.measure_string_with_options("111", 24.0, &tabular);
+        let nines = renderer.measure_string_with_options("999", 24.0, &tabular);
+        assert_eq!(ones.width, nines.width);
+        // the widest digit's advance is worked out once and shared between clones
+        let advance = renderer.tabular_advance(0, 24.0);
+        assert_eq!(ones.width, advance * 3.0);
+        assert!(renderer.clone().tabular_advances.read().unwrap().contains_key(&(0, 24.0f32.to_bits())));
+
+        // FreeMono's digits are all the same width already, so nothing moves
+        let white = TextColour::new_rgb(255, 255, 255);
+        let (mut plain, mut with_option) = (TestSurface::new(128, 32), TestSurface::new(128, 32));
+        renderer.draw_string("-12.5", 0.0, 0.0, 24.0, white, &mut plain);
+        renderer.draw_string_with_options("-12.5", 0.0, 0.0, 24.0, white, &tabular, &mut with_option);
+        assert!(plain.data == with_option.data);
+        assert_eq!(renderer.measure_string_with_options("a1 b", 24.0, &tabular).width, renderer.measure_string_with_options("a1 b", 24.0, &DrawOptions::default()).width);
+    }
+
+    #[test]
+    fn test_word_and_line_boundaries() {
+        let renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        let string = "Hello, world!! 你好世界  don't...\nfoo_bar 42";
+        let at = |needle: &str| string.find(needle).unwrap();
+        let word = |byte_index: usize| &string[renderer.word_boundaries_at(string, byte_index)];
+        assert_eq!(word(1), "Hello");
+        assert_eq!(word(at(",")), ",");
+        assert_eq!(word(at("!!") + 1), "!!");
+        // every ideograph is a word of its own, even in the middle of a run of them
+        assert_eq!(word(at("好")), "好");
+        assert_eq!(word(at("好") + 1), "好");
+        assert_eq!(word(at("  ")), "  ");
+        assert_eq!(word(at("'")), "don't");
+        assert_eq!(word(at("...") + 2), "...");
+        assert_eq!(word(at("\n")), "\n");
+        assert_eq!(word(at("_")), "foo_bar");
+        assert_eq!(word(string.len()), "42");
+        assert_eq!(renderer.word_boundaries_at("", 0), 0..0);
+
+        // ctrl+arrows skip whitespace and stop at both ends of words and punctuation
+        assert_eq!(renderer.next_word_boundary(string, 0), at(","));
+        assert_eq!(renderer.next_word_boundary(string, at(",")), at(" "));
+        assert_eq!(renderer.next_word_boundary(string, at(" ")), at("!!"));
+        assert_eq!(renderer.next_word_boundary(string, at("...") + 3), at("_") + 4);
+        assert_eq!(renderer.next_word_boundary(string, string.len()), string.len());
+        assert_eq!(renderer.prev_word_boundary(string, at("!!")), at("world"));
+        assert_eq!(renderer.prev_word_boundary(string, at("'")), at("don't"));
+        assert_eq!(renderer.prev_word_boundary(string, at("foo")), at("..."));
+        assert_eq!(renderer.prev_word_boundary(string, 1), 0);
+
+        // lines are the wrapped ones from layout, whitespace it was broken at and all
+        let options = DrawOptions { max_width: Some(renderer.measure_string_with_options("Hello, world", 20.0, &DrawOptions::default()).width), ..Default::default() };
+        let prepared = renderer.prepare_text(string, 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &options);
+        let lines = &prepared.metrics().lines;
+        assert_eq!(&string[renderer.line_boundaries_at(&prepared, 3)], "Hello, ");
+        assert_eq!(&string[renderer.line_boundaries_at(&prepared, at("好"))], &string[lines[1].byte_range.clone()]);
+        assert!(string[renderer.line_boundaries_at(&prepared, at("\n"))].ends_with("don't...\n"));
+        assert_eq!(renderer.line_boundaries_at(&prepared, string.len()), lines[lines.len() - 1].byte_range);
+        assert_eq!(renderer.line_boundaries_at(&prepared, string.len() + 10), string.len()..string.len());
+    }
+
+    #[test]
+    fn test_layout_builder() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let red = TextColour::new_rgb(255, 0, 0);
+        let mut builder = renderer.begin_layout(10.0, 10.0, 20.0, white, &DrawOptions::default());
+        builder.push_styled("bob", 20.0, red).push_str(": hi");
+        builder.push_break();
+        builder.push_str("").push_str("second line");
+        let prepared = builder.finish();
+
+        // the same as drawing the spans directly
+        let spans = [TextSpan::new("bob").with_colour(red), TextSpan::new(": hi\nsecond line")];
+        let mut expected = TestSurface::new(256, 64);
+        let bounds = renderer.draw_spans(&spans, 10.0, 10.0, 20.0, white, &DrawOptions::default(), &mut expected);
+        assert_eq!(prepared.metrics().lines.len(), 2);
+        assert_eq!((prepared.metrics().width, prepared.metrics().height), (bounds.width, bounds.height));
+        let mut surface = TestSurface::new(256, 64);
+        renderer.draw_prepared(&prepared, &mut surface);
+        assert!(surface.data == expected.data);
+
+        // drawing again doesn't lay anything out or rasterize anything, and comes out the same
+        renderer.reset_metrics();
+        let mut again = TestSurface::new(256, 64);
+        renderer.draw_prepared(&prepared, &mut again);
+        assert_eq!(renderer.metrics().rasterizations, 0);
+        assert!(again.data == surface.data);
+
+        // hit testing finds characters by their cells
+        let advance = renderer.char_advance('a', 20.0).ceil();
+        let line = &prepared.metrics().lines[1];
+        assert_eq!(prepared.hit_test(10.0 + advance * 0.5, 10.0 + 1.0), Some(0));
+        assert_eq!(prepared.hit_test(10.0 + advance * 3.5, 10.0 + 1.0), Some(3));
+        assert_eq!(prepared.hit_test(10.0 + advance * 7.5, 10.0 + line.y + 1.0), Some("bob: hi\nsecond ".len()));
+        assert_eq!(prepared.hit_test(5.0, 11.0), None);
+        assert_eq!(prepared.hit_test(11.0, 10.0 + line.y + line.height + 1.0), None);
+    }
+
+    #[test]
+    fn test_multiple_fonts() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        assert!(renderer.add_font("not_a_font.ttf", &FontOptions::default()).is_err());
+        let second = renderer.add_font_from_bytes(std::fs::read("FreeMono.ttf").unwrap(), &FontOptions::default()).unwrap();
+        assert_eq!((second, renderer.font_count()), (1, 2));
+
+        // the same glyph index in two fonts gets cached twice, and both sit on the same line
+        let white = TextColour::new_rgb(255, 255, 255);
+        let spans = [TextSpan::new("A"), TextSpan::new("A").with_font(second)];
+        let mut surface = TestSurface::new(128, 64);
+        let bounds = renderer.draw_spans(&spans, 0.0, 0.0, 24.0, white, &DrawOptions::default(), &mut surface);
+        assert_eq!(renderer.metrics().rasterizations, 2);
+        let (left_top, left_bottom) = surface.inked_rows(0, 14).unwrap();
+        let (right_top, right_bottom) = surface.inked_rows(14, 128).unwrap();
+        assert_eq!((left_top, left_bottom), (right_top, right_bottom));
+        let plain = renderer.measure_string_with_options("AA", 24.0, &DrawOptions::default());
+        assert_eq!((bounds.width, bounds.height), (plain.width, plain.height));
+
+        // a font that doesn't exist falls back to the renderer's own one, which is already cached
+        let spans = [TextSpan::new("A").with_font(7)];
+        renderer.draw_spans(&spans, 0.0, 32.0, 24.0, white, &DrawOptions::default(), &mut surface);
+        assert_eq!(renderer.metrics().rasterizations, 2);
+    }
+
+    #[test]
+    fn test_fallback_fonts() {
+        let mut renderer: TextRenderer<TestSurface, TestSurface> = TextRenderer::load("FreeMono.ttf").unwrap();
+        let dejavu = renderer.add_font("DejaVuSansMono-Bold.ttf", &FontOptions::default()).unwrap();
+        // FreeMono doesn't have U+023A, DejaVu Sans Mono does
+        let fonts = |renderer: &TextRenderer<TestSurface, TestSurface>| {
+            let text = renderer.layout_text("a \u{23a} b", 24.0, &DrawOptions::default());
+            text.glyphs.iter().map(|glyph| (glyph.parent, glyph.font_index, glyph.byte_offset)).collect::<Vec<_>>()
+        };
+        assert!(fonts(&renderer).iter().all(|&(_, font_index, _)| font_index == 0));
+
+        // nonexistent fonts are skipped, and the first one that has the character wins
+        renderer.set_fallback_fonts(&[7, dejavu]);
+        assert_eq!(renderer.fallback_fonts(), &[7, dejavu]);
+        assert_eq!(fonts(&renderer), vec![('a', 0, 0), (' ', 0, 1), ('\u{23a}', dejavu, 2), (' ', dejavu, 4), ('b', 0, 5)]);
+
+        // spans in the fallback font still use it for everything it has
+        renderer.set_fallback_fonts(&[0]);
+        let text = renderer.layout_rich(&[Run { font_index: dejavu, ..Run::new("a\u{23a}", 24.0) }], 24.0, &DrawOptions::default());
+        assert!(text.glyphs.iter().all(|glyph| glyph.font_index == dejavu));
+    }
+
+    #[test]
+    fn test_string_cache() {
+        let mut renderer = TextRenderer::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let options = DrawOptions { origin: Origin::Baseline, ..Default::default() };
+        let mut expected = TestSurface::new(256, 64);
+        let expected_bounds = renderer.draw_string_with_options("Score: 100", 10.0, 40.0, 24.0, white, &options, &mut expected);
+
+        // the first draw renders it, every draw after that is just a paste
+        let mut surface = TestSurface::new(256, 64);
+        let bounds = renderer.draw_string_cached("score", "Score: 100", 10.0, 40.0, 24.0, white, &options, &mut surface);
+        assert_eq!(bounds, expected_bounds);
+        assert!(surface.data == expected.data);
+        renderer.reset_metrics();
+        let mut again = TestSurface::new(256, 64);
+        renderer.draw_string_cached("score", "Score: 100", 10.0, 40.0, 24.0, white, &options, &mut again);
+        assert_eq!(renderer.metrics(), RenderMetrics::default());
+        assert!(again.data == expected.data);
+
+        // changing the string re-renders it, and so does invalidating it
+        renderer.draw_string_cached("score", "Score: 200", 10.0, 40.0, 24.0, white, &options, &mut again);
+        assert!(renderer.metrics().cache_hits > 0);
+        renderer.reset_metrics();
+        assert!(renderer.invalidate_string("score"));
+        assert!(!renderer.invalidate_string("score"));
+        renderer.draw_string_cached("score", "Score: 200", 10.0, 40.0, 24.0, white, &options, &mut again);
+        assert!(renderer.metrics().cache_hits > 0);
+
+        // with only room for one string, drawing a second one evicts the first
+        renderer.set_string_cache_budget(bounds.width as usize * 40 * 4);
+        renderer.draw_string_cached(1u64, "Score: 100", 10.0, 40.0, 24.0, white, &options, &mut again);
+        renderer.draw_string_cached(2u64, "Lives: 3", 10.0, 40.0, 24.0, white, &options, &mut again);
+        renderer.reset_metrics();
+        renderer.draw_string_cached(2u64, "Lives: 3", 10.0, 40.0, 24.0, white, &options, &mut again);
+        assert_eq!(renderer.metrics(), RenderMetrics::default());
+        renderer.draw_string_cached(1u64, "Score: 100", 10.0, 40.0, 24.0, white, &options, &mut again);
+        assert!(renderer.metrics().cache_hits > 0);
+
+        // strings that could never fit are still drawn
+        renderer.set_string_cache_budget(0);
+        let mut uncached = TestSurface::new(256, 64);
+        renderer.draw_string_cached("score", "Score: 100", 10.0, 40.0, 24.0, white, &options, &mut uncached);
+        assert!(uncached.data == expected.data);
+    }
+
+    /// Runs a future to completion on the current thread, so the async tests don't need a runtime.
+    #[cfg(feature = "async")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(std::thread::Thread);
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = std::task::Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut context = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_async_loading() {
+        let spawner = |job: crate::asynchronous::Job| {
+            std::thread::spawn(job);
+        };
+        let mut renderer: TextRenderer<TestSurface, TestSurface> = block_on(TextRenderer::load_async("FreeMono.ttf", spawner)).unwrap();
+        let mut surface = TestSurface::new(64, 32);
+        renderer.draw_string("hi", 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &mut surface);
+        assert!(surface.inked_rows(0, 64).is_some());
+
+        let font_data = std::fs::read("FreeMono.ttf").unwrap();
+        let parsed = block_on(TextRenderer::<TestSurface, TestSurface>::parse_in_background(font_data, &FontOptions::default(), spawner));
+        assert!(parsed.is_ok());
+
+        // same errors as loading synchronously, and a job that never runs doesn't hang forever
+        assert!(block_on(TextRenderer::<TestSurface, TestSurface>::load_async("not_a_font.ttf", spawner)).is_err());
+        let garbage = block_on(TextRenderer::<TestSurface, TestSurface>::parse_in_background(vec![1, 2, 3], &FontOptions::default(), spawner));
+        assert!(garbage.is_err());
+        let dropped = block_on(TextRenderer::<TestSurface, TestSurface>::load_async("FreeMono.ttf", drop));
+        assert!(matches!(dropped, Err(TextRendererError::Cancelled)));
+    }
+
+    #[test]
+    fn test_glyph_byte_mapping() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let text = "héllo wörld ✓ añadir\nnext";
+        let advance = renderer.char_advance('a', 20.0).ceil();
+        let options = DrawOptions { max_width: Some(advance * 8.0), ..Default::default() };
+        let prepared = renderer.prepare_text(text, 0.0, 0.0, 20.0, white, &options);
+        assert!(prepared.metrics().lines.len() > 2);
+
+        // every glyph maps back to exactly the character it was made from, and every byte of that character maps back to it
+        assert_eq!(prepared.glyph_count(), text.chars().count());
+        for (i, (offset, c)) in text.char_indices().enumerate() {
+            assert_eq!(prepared.glyph_byte_range(i), Some(offset..offset + c.len_utf8()));
+            assert_eq!(prepared.byte_for_glyph(i), Some(offset));
+            assert_eq!(prepared.glyph_char(i), Some(c));
+            for byte in offset..offset + c.len_utf8() {
+                assert_eq!(prepared.glyph_index_for_byte(byte), Some(i));
+            }
+        }
+        assert_eq!(prepared.glyph_index_for_byte(text.len()), None);
+        assert_eq!(prepared.glyph_byte_range(prepared.glyph_count()), None);
+
+        // an unbroken soft hyphen doesn't get a glyph, so the glyphs after it are one behind the characters
+        let prepared = renderer.prepare_text("ab\u{ad}cd", 0.0, 0.0, 20.0, white, &DrawOptions::default());
+        assert_eq!(prepared.glyph_index_for_byte(3), None);
+        assert_eq!(prepared.glyph_index_for_byte(4), Some(2));
+        assert_eq!(prepared.glyph_byte_range(2), Some(4..5));
+
+        // ellipsis glyphs don't stand for any of the string
+        let options = DrawOptions { max_lines: Some(1), max_width: Some(advance * 8.0), ellipsis: true, ..Default::default() };
+        let prepared = renderer.prepare_text("one two three four", 0.0, 0.0, 20.0, white, &options);
+        let last = prepared.glyph_count() - 1;
+        let end = prepared.metrics().lines[0].byte_range.end;
+        assert_eq!(prepared.glyph_byte_range(last), Some(end..end));
+        assert!(prepared.glyph_index_for_byte(end).is_none_or(|i| i < last));
+    }
+
+    #[test]
+    fn test_y_up() {
+        let mut down = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut up: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf")
+            .coordinate_system(CoordinateSystem::PositiveYUp)
+            .build()
+            .unwrap();
+        assert_eq!(up.coordinate_system(), CoordinateSystem::PositiveYUp);
+        let white = TextColour::new_rgb(255, 255, 255);
+        let (width, height) = (200, 100);
+        let flip_rows = |surface: &TestSurface| surface.data.chunks_exact(width * 4).rev().flatten().copied().collect::<Vec<_>>();
+
+        // the same text at mirrored positions (and clipped by mirrored rects) comes out as the same image upside down
+        let clip = Rect::new(0, 20, 150, 30);
+        let options = DrawOptions { max_width: Some(150.0), clip: Some(clip), ..Default::default() };
+        let up_clip = Rect::new(0, height as i32 - clip.y - clip.height as i32, clip.width, clip.height);
+        let up_options = DrawOptions { clip: Some(up_clip), ..options.clone() };
+        let (mut a, mut b) = (TestSurface::new(width, height), TestSurface::new(width, height));
+        let bounds_down = down.draw_string_with_options("Ägypten gq\nsecond line", 10.0, 15.0, 20.0, white, &options, &mut a);
+        let bounds_up = up.draw_string_with_options("Ägypten gq\nsecond line", 10.0, height as f32 - 15.0, 20.0, white, &up_options, &mut b);
+        assert!(a.data.iter().any(|byte| *byte != 0));
+        assert_eq!(flip_rows(&b), a.data);
+        assert_eq!(bounds_up.y, height as f32 - 15.0);
+        assert_eq!(bounds_up.width, bounds_down.width);
+
+        // prepared text and the string cache go through the same flipping
+        let (mut a, mut b) = (TestSurface::new(width, height), TestSurface::new(width, height));
+        let options = DrawOptions { origin: Origin::Baseline, ..Default::default() };
+        let prepared_down = down.prepare_text("baseline", 5.0, 40.0, 20.0, white, &options);
+        let prepared_up = up.prepare_text("baseline", 5.0, height as f32 - 40.0, 20.0, white, &options);
+        down.draw_prepared(&prepared_down, &mut a);
+        up.draw_prepared(&prepared_up, &mut b);
+        down.draw_string_cached("cached", "cached", 5.0, 60.0, 20.0, white, &options, &mut a);
+        up.draw_string_cached("cached", "cached", 5.0, height as f32 - 60.0, 20.0, white, &options, &mut b);
+        assert_eq!(flip_rows(&b), a.data);
+        let (rect_down, rect_up) = (prepared_down.glyph_bounds(0).unwrap(), prepared_up.glyph_bounds(0).unwrap());
+        assert_eq!(rect_up.y, height as i32 - rect_down.y - rect_down.height as i32);
+        assert_eq!(prepared_up.hit_test(rect_up.x as f32 + 1.0, rect_up.y as f32 + 1.0), Some(0));
+
+        // rects are on the surface, so they get mirrored like clip rects do and the text still starts at their top
+        let (mut a, mut b) = (TestSurface::new(width, height), TestSurface::new(width, height));
+        let (rect, up_rect) = (Rect::new(10, 5, 120, 40), Rect::new(10, height as i32 - 45, 120, 40));
+        let text = "the quick brown fox jumps over the lazy dog";
+        down.draw_paragraph(text, rect, 16.0, white, &DrawOptions::default(), &mut a);
+        up.draw_paragraph(text, up_rect, 16.0, white, &DrawOptions::default(), &mut b);
+        let rect = Rect::new(10, 55, 120, 30);
+        let up_rect = Rect::new(10, height as i32 - 85, 120, 30);
+        let overflow_down = down.draw_text_in_rect(text, rect, 16.0, white, &DrawOptions::default(), &mut a);
+        let overflow_up = up.draw_text_in_rect(text, up_rect, 16.0, white, &DrawOptions::default(), &mut b);
+        assert_eq!(flip_rows(&b), a.data);
+        assert_eq!(overflow_up.clipped_from, overflow_down.clipped_from);
+        assert!(overflow_up.overflowed());
+    }
+
+    #[test]
+    fn test_anchor() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let text = "score: 1234\nbest: 99999";
+        let measured = renderer.measure_string_with_options(text, 20.0, &DrawOptions::default());
+
+        // the bounds are shifted so the anchor point of the whole block is at (x, y)
+        let mut top_left = TestSurface::new(300, 200);
+        let bounds = renderer.draw_string_with_options(text, 300.0 - measured.width, 200.0 - measured.height, 20.0, white, &DrawOptions::default(), &mut top_left);
+        let mut bottom_right = TestSurface::new(300, 200);
+        let options = DrawOptions { anchor: Anchor::BottomRight, ..Default::default() };
+        let anchored = renderer.draw_string_with_options(text, 300.0, 200.0, 20.0, white, &options, &mut bottom_right);
+        assert_eq!((anchored.x, anchored.y), (bounds.x, bounds.y));
+        assert_eq!(top_left.data, bottom_right.data);
+
+        let options = DrawOptions { anchor: Anchor::Center, ..Default::default() };
+        let centered = renderer.draw_string_with_options(text, 150.0, 100.0, 20.0, white, &options, &mut TestSurface::new(300, 200));
+        assert_eq!(centered.x + centered.width / 2.0, 150.0);
+        assert_eq!(centered.y + centered.height / 2.0, 100.0);
+
+        // prepared text ends up in the same place
+        let prepared = renderer.prepare_text(text, 300.0, 200.0, 20.0, white, &DrawOptions { anchor: Anchor::BottomRight, ..Default::default() });
+        let mut from_prepared = TestSurface::new(300, 200);
+        renderer.draw_prepared(&prepared, &mut from_prepared);
+        assert_eq!(from_prepared.data, top_left.data);
+    }
+
+    #[test]
+    fn test_underlines() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let red = TextColour::new_rgb(255, 0, 0);
+        let advance = renderer.char_advance('a', 32.0).ceil() as usize;
+        let ascent = renderer.line_metrics(32.0).ascent;
+        let mut plain = TestSurface::new(400, 60);
+        renderer.draw_string_with_options("hello world", 0.0, 0.0, 32.0, white, &DrawOptions::default(), &mut plain);
+
+        // returns the (left, right, top, bottom) of the pixels the underline changed, and how many of its columns are empty
+        let mut underlined = |style| {
+            let underline = Underline { range: 6..11, style, colour: red };
+            let options = DrawOptions { underlines: vec![underline], ..Default::default() };
+            let mut surface = TestSurface::new(400, 60);
+            renderer.draw_string_with_options("hello world", 0.0, 0.0, 32.0, white, &options, &mut surface);
+            let changed = (0..60).flat_map(|y| (0..400).map(move |x| (x, y)))
+                .filter(|(x, y)| plain.data[(y * 400 + x) * 4..][..4] != surface.data[(y * 400 + x) * 4..][..4])
+                .collect::<Vec<_>>();
+            let left = changed.iter().map(|(x, _)| *x).min().unwrap();
+            let right = changed.iter().map(|(x, _)| *x).max().unwrap();
+            let top = changed.iter().map(|(_, y)| *y).min().unwrap();
+            let bottom = changed.iter().map(|(_, y)| *y).max().unwrap();
+            let gaps = (left..=right).filter(|x| !changed.iter().any(|(cx, _)| cx == x)).count();
+            (left, right, top, bottom, gaps)
+        };
+
+        // only "world" is underlined, and nothing goes past its last advance
+        let (left, right, top, bottom, gaps) = underlined(UnderlineStyle::Solid);
+        assert_eq!(left, advance * 6);
+        assert!(right < advance * 11);
+        assert!(right >= advance * 11 - 3);
+        assert_eq!(gaps, 0);
+        assert!(top as f32 > ascent);
+        assert_eq!(bottom - top + 1, 2);
+
+        let (_, _, _, _, gaps) = underlined(UnderlineStyle::Dotted);
+        assert!(gaps > 10);
+        let (_, _, _, _, gaps) = underlined(UnderlineStyle::Dashed);
+        assert!(gaps > 0);
+        let (_, _, top, bottom, _) = underlined(UnderlineStyle::Double);
+        assert_eq!(bottom - top + 1, 6);
+        let (left, right, top, bottom, gaps) = underlined(UnderlineStyle::Wavy);
+        assert_eq!((left, gaps), (advance * 6, 0));
+        assert!(right < advance * 11);
+        assert!(bottom - top > 3);
+    }
+
+    #[test]
+    fn test_glyph_fx() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let mut plain = TestSurface::new(200, 60);
+        renderer.draw_string("hello", 10.0, 10.0, 20.0, white, &mut plain);
+
+        // leaving everything alone is the same as a normal draw
+        let mut surface = TestSurface::new(200, 60);
+        let bounds = renderer.draw_string_fx("hello", 10.0, 10.0, 20.0, white, &mut surface, |_, _, params| params);
+        assert_eq!(surface.data, plain.data);
+        assert_eq!((bounds.x, bounds.y), (10.0, 10.0));
+
+        // moving every glyph down is the same as drawing the string lower, and kerning doesn't change
+        let mut moved = TestSurface::new(200, 60);
+        let mut lower = TestSurface::new(200, 60);
+        renderer.draw_string_fx("hello", 10.0, 10.0, 20.0, white, &mut moved, |_, _, params| GlyphDrawParams { y: params.y + 7.0, ..params });
+        renderer.draw_string("hello", 10.0, 17.0, 20.0, white, &mut lower);
+        assert_eq!(moved.data, lower.data);
+
+        // a typewriter that has only got to the third character
+        let mut typed = TestSurface::new(200, 60);
+        let mut prefix = TestSurface::new(200, 60);
+        let mut seen = Vec::new();
+        renderer.draw_string_fx("hello", 10.0, 10.0, 20.0, white, &mut typed, |i, c, params| {
+            seen.push(c);
+            GlyphDrawParams { alpha: if i < 3 { 1.0 } else { 0.0 }, ..params }
+        });
+        renderer.draw_string("hel", 10.0, 10.0, 20.0, white, &mut prefix);
+        assert_eq!(seen, vec!['h', 'e', 'l', 'l', 'o']);
+        assert_eq!(typed.data, prefix.data);
+
+        // half alpha halves the alpha of every pixel
+        let mut faded = TestSurface::new(200, 60);
+        renderer.draw_string_fx("hello", 10.0, 10.0, 20.0, white, &mut faded, |_, _, params| GlyphDrawParams { alpha: 0.5, ..params });
+        for (faded, plain) in faded.data.chunks_exact(4).zip(plain.data.chunks_exact(4)) {
+            assert!((faded[3] as i32 - plain[3] as i32 / 2).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_alpha_cache_format() {
+        let mut rgba = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut alpha: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf")
+            .glyph_cache_format(GlyphCacheFormat::Alpha)
+            .build()
+            .unwrap();
+        rgba.set_gamma(Some(1.8));
+        alpha.set_gamma(Some(1.8));
+        let colours = [TextColour::new_rgb(255, 255, 255), TextColour::new_rgb(200, 30, 30), TextColour::new(0, 0, 0, 128)];
+        let options = DrawOptions { clip: Some(Rect::new(4, 4, 180, 80)), ..Default::default() };
+        let (mut a, mut b) = (TestSurface::new(200, 100), TestSurface::new(200, 100));
+        for (i, colour) in colours.iter().enumerate() {
+            let y = i as f32 * 28.0;
+            rgba.draw_string_with_options("Wavy jigs & quartz", 0.0, y, 22.0, *colour, &options, &mut a);
+            alpha.draw_string_with_options("Wavy jigs & quartz", 0.0, y, 22.0, *colour, &options, &mut b);
+        }
+        assert!(a.data.iter().any(|byte| *byte != 0));
+        assert_eq!(a.data, b.data);
+
+        // every glyph was only rasterized once, whatever colour it was drawn in
+        assert!(alpha.metrics().rasterizations > 0);
+        assert_eq!(rgba.metrics().rasterizations, alpha.metrics().rasterizations * colours.len() as u64);
+
+        // and without gamma, drawing a cached mask again doesn't copy it
+        alpha.set_gamma(None);
+        let glyph = GlyphRasterConfig { glyph_index: alpha.font.lookup_glyph_index('W'), px: 22.0, font_hash: alpha.font.file_hash() };
+        let width = alpha.glyph_metrics('W', 22.0).unwrap().bitmap_width;
+        let first = alpha.get_glyph_mask(0, glyph, width, colours[0], GlyphEffects::default(), 0);
+        let second = alpha.get_glyph_mask(0, glyph, width, colours[1], GlyphEffects::default(), 0);
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn test_dirty_rects() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let (width, height) = (200, 80);
+        let mut surface = TestSurface::new(width, height);
+        // nothing is recorded until tracking is turned on
+        renderer.draw_string("before", 0.0, 0.0, 20.0, white, &mut surface);
+        assert!(renderer.take_dirty_rects().is_empty());
+
+        let mut surface = TestSurface::new(width, height);
+        renderer.track_dirty_rects(true);
+        let clip = Rect::new(0, 0, 150, 80);
+        let options = DrawOptions {
+            clip: Some(clip),
+            selection: Some(Selection { range: 0..3, background: TextColour::new_rgb(0, 0, 255), foreground: None }),
+            underlines: vec![Underline { range: 4..9, style: UnderlineStyle::Wavy, colour: TextColour::new_rgb(255, 0, 0) }],
+            ..Default::default()
+        };
+        // starts off the left edge and runs past the clip on the right
+        renderer.draw_string_with_options("off the edge of it all", -15.0, 20.0, 20.0, white, &options, &mut surface);
+        renderer.draw_string("   ", 0.0, 50.0, 20.0, white, &mut surface);
+        let rects = renderer.take_dirty_rects();
+        assert!(!rects.is_empty());
+        assert!(renderer.take_dirty_rects().is_empty());
+
+        // everything that got drawn is covered, and nothing outside of what got drawn is
+        let inked = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)))
+            .filter(|(x, y)| surface.data[(y * width + x) * 4 + 3] != 0)
+            .collect::<Vec<_>>();
+        let contains = |rect: &Rect, x: usize, y: usize| {
+            (rect.x..rect.x + rect.width as i32).contains(&(x as i32)) && (rect.y..rect.y + rect.height as i32).contains(&(y as i32))
+        };
+        assert!(inked.iter().all(|(x, y)| rects.iter().any(|rect| contains(rect, *x, *y))));
+        for rect in rects.iter() {
+            assert_eq!(rect.intersection(&clip), Some(*rect));
+            assert!(inked.iter().any(|(x, y)| contains(rect, *x, *y)));
+        }
+        let union = rects.iter().skip(1).fold(rects[0], |union, rect| union.union(rect));
+        let left = inked.iter().map(|(x, _)| *x).min().unwrap();
+        let right = inked.iter().map(|(x, _)| *x).max().unwrap();
+        let top = inked.iter().map(|(_, y)| *y).min().unwrap();
+        let bottom = inked.iter().map(|(_, y)| *y).max().unwrap();
+        assert_eq!(union, Rect::new(left as i32, top as i32, right - left + 1, bottom - top + 1));
+
+        // rendering offscreen doesn't touch the caller's surface, so only pasting the cached string counts
+        let (rendered, bounds) = renderer.render_to_surface("hello", 20.0, white, &DrawOptions::default());
+        assert!(renderer.take_dirty_rects().is_empty());
+        let pasted = Rect::new((100.0 - bounds.x).floor() as i32, (50.0 - bounds.y).floor() as i32, rendered.width, rendered.height);
+        let mut surface = TestSurface::new(width, height);
+        for _ in 0..2 {
+            renderer.draw_string_cached("k", "hello", 100.0, 50.0, 20.0, white, &DrawOptions::default(), &mut surface);
+            assert_eq!(renderer.take_dirty_rects(), vec![pasted.intersection(&Rect::new(0, 0, width, height)).unwrap()]);
+        }
+    }
+
+    #[test]
+    fn test_cache_policy() {
+        let mut keep = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut surface_only: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf")
+            .cache_policy(CachePolicy::SurfaceOnly)
+            .build()
+            .unwrap();
+        assert_eq!(keep.glyph_cache_size(), 0);
+        let white = TextColour::new_rgb(255, 255, 255);
+        let (mut a, mut b) = (TestSurface::new(200, 40), TestSurface::new(200, 40));
+        keep.draw_string("cache me", 0.0, 0.0, 20.0, white, &mut a);
+        surface_only.draw_string("cache me", 0.0, 0.0, 20.0, white, &mut b);
+        assert_eq!(a.data, b.data);
+        assert!(surface_only.glyph_cache_size() > 0);
+        assert_eq!(keep.glyph_cache_size(), surface_only.glyph_cache_size() * 2);
+    }
+
+    #[test]
+    fn test_invalid_sizes() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let options = DrawOptions::default();
+        for size in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY, 0.0, -12.0, 2_000_000.0, DEFAULT_MAX_SIZE + 1.0] {
+            assert!(matches!(renderer.check_size(size), Err(TextRendererError::InvalidSize(_))));
+            let mut surface = TestSurface::new(64, 64);
+            let result = renderer.try_draw_string_with_options("big", 0.0, 0.0, size, white, &options, &mut surface);
+            assert!(matches!(result, Err(TextRendererError::InvalidSize(_))));
+            assert!(renderer.try_measure_string_with_options("big", size, &options).is_err());
+
+            // the unchecked calls don't draw anything either, and nothing gets rasterized
+            let bounds = renderer.draw_string_with_options("big", 0.0, 0.0, size, white, &options, &mut surface);
+            renderer.draw_string("big", 0.0, 0.0, size, white, &mut surface);
+            renderer.draw_spans(&[TextSpan::new("a"), TextSpan::new("big").with_size(size)], 0.0, 0.0, 20.0, white, &options, &mut surface);
+            assert_eq!(bounds.glyphs_drawn, 0);
+            assert_eq!(renderer.measure_string_with_options("big", size, &options), TextMetrics::default());
+            assert!(renderer.glyph_bitmap('A', size, white).is_none());
+            assert!(surface.data.iter().all(|byte| *byte == 0));
+            assert_eq!(renderer.metrics().rasterizations, 0);
+        }
+        assert!(renderer.check_size(DEFAULT_MAX_SIZE).is_ok());
+        assert!(renderer.fit_size("big", 1e9, 1e9, 1.0..=1e6, 0.5, &options).size <= DEFAULT_MAX_SIZE);
+
+        // the limit can be raised for people who really do want huge text
+        let banner: TextRenderer<TestSurface, TestSurface> = TextRendererBuilder::new("FreeMono.ttf").max_size(10_000.0).build().unwrap();
+        assert!(banner.check_size(8000.0).is_ok());
+        assert!(banner.check_size(20_000.0).is_err());
+    }
+
+    #[test]
+    fn test_opacity() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let mut plain = TestSurface::new(200, 40);
+        renderer.draw_string_with_options("fade", 0.0, 0.0, 20.0, white, &DrawOptions::default(), &mut plain);
+
+        // fading in over lots of frames only ever caches the glyphs in their real colour
+        for frame in 0..=60 {
+            let options = DrawOptions { opacity: frame as f32 / 60.0, ..DrawOptions::default() };
+            let mut surface = TestSurface::new(200, 40);
+            renderer.draw_string_with_options("fade", 0.0, 0.0, 20.0, white, &options, &mut surface);
+            // and after the first see-through frame, every one just reuses the same shared masks
+            if frame == 2 {
+                renderer.reset_metrics();
+            } else if frame > 2 {
+                assert_eq!(renderer.metrics().rasterizations, 0);
+            }
+            match frame {
+                0 => assert!(surface.data.iter().all(|byte| *byte == 0)),
+                60 => assert_eq!(surface.data, plain.data),
+                _ => assert!(surface.data.chunks_exact(4).zip(plain.data.chunks_exact(4)).all(|(faded, plain)| faded[3] <= plain[3])),
+            }
+        }
+        for glyph_cache in renderer.glyph_caches.read().unwrap().values() {
+            assert!(glyph_cache.surface_map.keys().all(|colour| *colour == white));
+        }
+        let glyph = GlyphRasterConfig { glyph_index: renderer.font.lookup_glyph_index('f'), px: 20.0, font_hash: renderer.font.file_hash() };
+        let width = renderer.glyph_metrics('f', 20.0).unwrap().bitmap_width;
+        let faded = [0.25, 0.75].map(|opacity| renderer.get_glyph_mask(0, glyph, width, white.with_opacity(opacity), GlyphEffects::default(), 0));
+        assert!(Arc::ptr_eq(&faded[0], &faded[1]));
+    }
+
+    #[test]
+    fn test_draw_char() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let mut string = TestSurface::new(200, 40);
+        renderer.draw_string("a b", 10.0, 5.0, 20.0, white, &mut string);
+        let rasterizations = renderer.metrics().rasterizations;
+
+        // drawing it a character at a time on the same baseline comes out the same, without rasterizing anything new
+        let baseline = 5.0 + renderer.line_metrics(20.0).ascent.ceil();
+        let mut chars = TestSurface::new(200, 40);
+        let mut pen = 10.0;
+        for c in "a b".chars() {
+            let result = renderer.draw_char(c, pen, baseline, 20.0, white, &mut chars);
+            assert!(result.found);
+            assert_eq!(result.ink.is_none(), c == ' ');
+            assert!(result.advance > 0.0);
+            pen += result.advance;
+        }
+        assert_eq!(chars.data, string.data);
+        assert_eq!(renderer.metrics().rasterizations, rasterizations);
+        assert_eq!(pen - 10.0, renderer.measure_string_with_options("a b", 20.0, &DrawOptions::default()).width);
+
+        // the ink rect is exactly where the pixels went
+        let mut single = TestSurface::new(200, 40);
+        let ink = renderer.draw_char('W', 50.0, 30.0, 20.0, white, &mut single).ink.unwrap();
+        for (i, pixel) in single.data.chunks_exact(4).enumerate() {
+            let (x, y) = ((i % 200) as i32, (i / 200) as i32);
+            if pixel[3] > 0 {
+                assert!(x >= ink.x && y >= ink.y && x < ink.x + ink.width as i32 && y < ink.y + ink.height as i32);
+            }
+        }
+        assert!(!renderer.draw_char('\u{10ffff}', 0.0, 30.0, 20.0, white, &mut single).found);
+    }
+
+    #[test]
+    fn test_draw_commands() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let options = DrawOptions { origin: Origin::Baseline, ..DrawOptions::default() };
+        let commands = renderer.prepare_commands("same every frame", 16.0, white, &options);
+        assert_eq!(commands.len(), "sameeveryframe".len());
+        let metrics = renderer.metrics();
+
+        // executing is exactly the same as drawing, wherever it ends up, and doesn't touch the cache counters
+        for offset in [(0.0, 20.0), (13.5, 40.25), (150.0, 79.0)] {
+            let (mut drawn, mut executed) = (TestSurface::new(300, 80), TestSurface::new(300, 80));
+            let expected = renderer.draw_string_with_options("same every frame", offset.0, offset.1, 16.0, white, &options, &mut drawn);
+            let metrics = renderer.metrics();
+            assert_eq!(renderer.execute_commands(&commands, offset, &mut executed).unwrap(), expected);
+            assert_eq!(renderer.metrics(), metrics);
+            assert_eq!(executed.data, drawn.data);
+        }
+        assert_eq!(renderer.metrics().rasterizations, metrics.rasterizations);
+
+        // once the glyphs are gone the commands say so instead of quietly drawing nothing, until they're prepared again
+        renderer.clear_glyph_cache();
+        let mut surface = TestSurface::new(300, 80);
+        assert!(matches!(renderer.execute_commands(&commands, (0.0, 20.0), &mut surface), Err(TextRendererError::StaleCommands)));
+        let commands = renderer.prepare_commands("same every frame", 16.0, white, &options);
+        assert!(renderer.execute_commands(&commands, (0.0, 20.0), &mut surface).is_ok());
+    }
+
+    #[test]
+    fn test_show_whitespace() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let red = TextColour::new_rgb(255, 0, 0);
+        let draw = |renderer: &mut TextRenderer<TestSurface, TestSurface>, show_whitespace: Option<ShowWhitespace>| {
+            let options = DrawOptions { show_whitespace, max_width: Some(80.0), ..DrawOptions::default() };
+            let mut surface = TestSurface::new(120, 100);
+            let bounds = renderer.draw_string_with_options("ab cd\nef ghijk", 0.0, 0.0, 20.0, white, &options, &mut surface);
+            (surface, bounds)
+        };
+        let (plain, plain_bounds) = draw(&mut renderer, None);
+
+        // invisible markers leave everything exactly where it was
+        let (hidden, hidden_bounds) = draw(&mut renderer, Some(ShowWhitespace { colour: Some(TextColour::new(0, 0, 0, 0)), ..Default::default() }));
+        assert_eq!(hidden.data, plain.data);
+        assert_eq!(hidden_bounds, plain_bounds);
+
+        // the dot is in the space between "ab" and "cd", the pilcrow is after "cd", and the wrapped line doesn't get one
+        let (marked, _) = draw(&mut renderer, Some(ShowWhitespace { colour: Some(red), ..Default::default() }));
+        let advance = renderer.measure_string_with_options("a", 20.0, &DrawOptions::default()).width;
+        let line_height = renderer.line_metrics(20.0).line_height.ceil();
+        let red_columns = |line: usize| {
+            let rows = (line as f32 * line_height) as usize..((line + 1) as f32 * line_height) as usize;
+            let mut columns = (0..120).filter(|x| rows.clone().any(|y| {
+                let pixel = &marked.data[(y * 120 + x) * 4..][..4];
+                pixel[3] > 0 && pixel[1] == 0
+            })).collect::<Vec<_>>();
+            columns.dedup();
+            columns
+        };
+        let first = red_columns(0);
+        assert!(first.iter().any(|x| (advance * 2.0..advance * 3.0).contains(&(*x as f32))));
+        assert!(first.iter().any(|x| *x as f32 >= advance * 5.0));
+        assert!(!first.iter().any(|x| (advance * 3.0..advance * 5.0).contains(&(*x as f32))));
+        assert!(red_columns(1).iter().all(|x| (*x as f32) < advance * 3.0));
+
+        // a marker the font doesn't have falls back to ascii
+        let (missing, _) = draw(&mut renderer, Some(ShowWhitespace { newline: '\u{10ffff}', colour: Some(red), ..Default::default() }));
+        let (ascii, _) = draw(&mut renderer, Some(ShowWhitespace { newline: '$', colour: Some(red), ..Default::default() }));
+        assert!(!renderer.has_glyph('\u{10ffff}'));
+        assert_eq!(missing.data, ascii.data);
+    }
+
+    #[test]
+    fn test_monospaced_wide_characters() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let cell = 10.0;
+
+        // a terminal puts "a日本語b" in columns 0, 1-2, 3-4, 5-6 and 7
+        let mut line = TestSurface::new(120, 30);
+        renderer.draw_string_monospaced("a日本語b", 0.0, 0.0, 20.0, white, &mut line);
+        let mut expected = TestSurface::new(120, 30);
+        for (c, column) in [("a", 0), ("日", 1), ("本", 3), ("語", 5), ("b", 7)] {
+            renderer.draw_string_monospaced(c, column as f32 * cell, 0.0, 20.0, white, &mut expected);
+        }
+        assert_eq!(line.data, expected.data);
+
+        // wide glyphs stay inside of their two cells
+        let mut wide = TestSurface::new(120, 30);
+        renderer.draw_string_monospaced("日", cell, 0.0, 20.0, white, &mut wide);
+        assert!(wide.data.chunks_exact(4).any(|pixel| pixel[3] > 0));
+        for (i, pixel) in wide.data.chunks_exact(4).enumerate() {
+            if pixel[3] > 0 {
+                assert!((10..30).contains(&(i % 120)));
+            }
+        }
+
+        // a combining mark goes on the character before it instead of taking up a cell of its own
+        let mut combined = TestSurface::new(120, 30);
+        renderer.draw_string_monospaced("e\u{301}x", 0.0, 0.0, 20.0, white, &mut combined);
+        let mut expected = TestSurface::new(120, 30);
+        renderer.draw_string_monospaced("e\u{301}", 0.0, 0.0, 20.0, white, &mut expected);
+        renderer.draw_string_monospaced("x", cell, 0.0, 20.0, white, &mut expected);
+        assert_eq!(combined.data, expected.data);
+        let mut plain = TestSurface::new(120, 30);
+        renderer.draw_string_monospaced("ex", 0.0, 0.0, 20.0, white, &mut plain);
+        assert_ne!(combined.data, plain.data);
+    }
+
+    #[test]
+    fn test_text_on_path() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let options = PathTextOptions::default();
+
+        // along a flat line it's the same as drawing the string normally on that baseline
+        let mut flat = TestSurface::new(200, 80);
+        let mut normal = TestSurface::new(200, 80);
+        renderer.draw_string_on_path("flat", &Path::new(10.0, 50.0).line_to(190.0, 50.0), 20.0, white, &mut flat, &options);
+        renderer.draw_string_with_options("flat", 10.0, 50.0, 20.0, white, &DrawOptions { origin: Origin::Baseline, ..Default::default() }, &mut normal);
+        assert_eq!(flat.data, normal.data);
+
+        // over the top of a circle, everything is outside of it, and drawing it again doesn't rotate anything
+        let arc = Path::arc(100.0, 100.0, 60.0, -std::f32::consts::PI, 0.0);
+        assert!((arc.length() - std::f32::consts::PI * 60.0).abs() < 0.5);
+        let mut badge = TestSurface::new(200, 200);
+        renderer.draw_string_on_path("ROUND BADGE", &arc, 20.0, white, &mut badge, &options).unwrap();
+        let rasterizations = renderer.metrics().rasterizations;
+        renderer.draw_string_on_path("ROUND BADGE", &arc, 20.0, white, &mut badge, &options);
+        assert_eq!(renderer.metrics().rasterizations, rasterizations);
+        // or copy anything either
+        let glyph = GlyphRasterConfig { glyph_index: renderer.font.lookup_glyph_index('R'), px: 20.0, font_hash: renderer.font.file_hash() };
+        let metrics = renderer.glyph_metrics('R', 20.0).unwrap();
+        let rotated = [0, 1].map(|_| renderer.get_rotated_mask(0, glyph, metrics.bitmap_width, metrics.bitmap_height, 45));
+        assert!(Arc::ptr_eq(&rotated[0].mask, &rotated[1].mask));
+        for (i, pixel) in badge.data.chunks_exact(4).enumerate() {
+            let (x, y) = ((i % 200) as f32 + 0.5, (i / 200) as f32 + 0.5);
+            if pixel[3] > 0 {
+                assert!((x - 100.0).hypot(y - 100.0) > 58.0);
+            }
+        }
+        let mut below = TestSurface::new(200, 200);
+        renderer.draw_string_on_path("ROUND BADGE", &arc, 20.0, white, &mut below, &PathTextOptions { placement: PathPlacement::Below, ..options });
+        for (i, pixel) in below.data.chunks_exact(4).enumerate() {
+            let (x, y) = ((i % 200) as f32 + 0.5, (i / 200) as f32 + 0.5);
+            if pixel[3] > 0 {
+                assert!((x - 100.0).hypot(y - 100.0) < 62.0);
+            }
+        }
+
+        // glyphs that don't fit are dropped, unless they're asked to carry on past the end
+        let short = Path::new(0.0, 30.0).line_to(40.0, 30.0);
+        let mut dropped = TestSurface::new(200, 40);
+        let mut extended = TestSurface::new(200, 40);
+        let kept = renderer.draw_string_on_path("too long", &short, 20.0, white, &mut dropped, &options).unwrap();
+        let all = renderer.draw_string_on_path("too long", &short, 20.0, white, &mut extended, &PathTextOptions { overflow: PathOverflow::Extend, ..options }).unwrap();
+        assert!(kept.x + kept.width as i32 <= 46);
+        assert!(all.x + all.width as i32 > 80);
+    }
+
+    #[test]
+    fn test_glyph_provider() {
+        use crate::custom_glyphs::CustomGlyph;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let red = [255, 0, 0, 255].repeat(12 * 10);
+        renderer.set_glyph_provider(move |c, _| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            match c {
+                '\u{e000}' => Some(CustomGlyph { advance: 14.0, ..CustomGlyph::new(12, 10, red.clone()) }),
+                'x' => Some(CustomGlyph { replaces_font: true, ..CustomGlyph::new(12, 10, red.clone()) }),
+                // the font has an 'a', so this never gets used
+                'a' => Some(CustomGlyph::new(12, 10, red.clone())),
+                _ => None,
+            }
+        });
+
+        // the emote pushes the text after it along by its advance
+        let options = DrawOptions::default();
+        let plain = renderer.measure_string_with_options("ab", 20.0, &options).width;
+        assert_eq!(renderer.measure_string_with_options("a\u{e000}b", 20.0, &options).width, plain + 14.0);
+
+        // and it's drawn as-is on the baseline, not in the colour of the text
+        let mut surface = TestSurface::new(100, 40);
+        renderer.draw_string("\u{e000}", 5.0, 5.0, 20.0, white, &mut surface);
+        let baseline = 5 + renderer.line_metrics(20.0).ascent.ceil() as usize;
+        assert_eq!(surface.inked_rows(0, 100), Some((baseline - 10, baseline - 1)));
+        let pixel = ((baseline - 5) * 100 + 10) * 4;
+        assert_eq!(&surface.data[pixel..pixel + 4], &[255, 0, 0, 255]);
+
+        // the provider only got asked about each character once
+        let asked = calls.load(Ordering::SeqCst);
+        renderer.draw_string("a\u{e000}b", 5.0, 5.0, 20.0, white, &mut surface);
+        assert_eq!(calls.load(Ordering::SeqCst), asked);
+
+        // 'x' is taken over even though the font has it, 'a' isn't
+        let mut x = TestSurface::new(100, 40);
+        renderer.draw_string("x", 5.0, 5.0, 20.0, white, &mut x);
+        assert_eq!(x.data, surface_with(&mut renderer, "\u{e000}"));
+        let mut a = TestSurface::new(100, 40);
+        renderer.draw_string("a", 5.0, 5.0, 20.0, white, &mut a);
+        assert!(a.data.chunks_exact(4).any(|pixel| pixel[3] > 0));
+        assert!(!a.data.chunks_exact(4).any(|pixel| pixel == [255, 0, 0, 255]));
+
+        fn surface_with(renderer: &mut TextRenderer<TestSurface, TestSurface>, string: &str) -> Vec<u8> {
+            let mut surface = TestSurface::new(100, 40);
+            renderer.draw_string(string, 5.0, 5.0, 20.0, TextColour::new_rgb(255, 255, 255), &mut surface);
+            surface.data
+        }
+    }
+
+    #[test]
+    fn test_text_transform() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let upper = DrawOptions { text_transform: TextTransform::Uppercase, ..DrawOptions::default() };
+        let lower = DrawOptions { text_transform: TextTransform::Lowercase, ..DrawOptions::default() };
+
+        // transformed text draws the same as if it had been written that way
+        let (mut transformed, mut written) = (TestSurface::new(200, 40), TestSurface::new(200, 40));
+        renderer.draw_string_with_options("Hello", 5.0, 5.0, 20.0, white, &upper, &mut transformed);
+        renderer.draw_string("HELLO", 5.0, 5.0, 20.0, white, &mut written);
+        assert_eq!(transformed.data, written.data);
+        let (mut transformed, mut written) = (TestSurface::new(200, 40), TestSurface::new(200, 40));
+        renderer.draw_string_with_options("Hello", 5.0, 5.0, 20.0, white, &lower, &mut transformed);
+        renderer.draw_string("hello", 5.0, 5.0, 20.0, white, &mut written);
+        assert_eq!(transformed.data, written.data);
+
+        // "ß" turns into two glyphs, both of which still point at its two bytes
+        let prepared = renderer.prepare_text("straße", 0.0, 0.0, 20.0, white, &upper);
+        assert_eq!(prepared.glyph_count(), 7);
+        assert_eq!(prepared.glyph_byte_range(4), Some(4..6));
+        assert_eq!(prepared.glyph_byte_range(5), Some(4..6));
+        assert_eq!(prepared.glyph_byte_range(6), Some(6..7));
+        assert_eq!(prepared.glyph_index_for_byte(6), Some(6));
+        assert_eq!(prepared.metrics().lines[0].byte_range, 0..7);
+        assert_eq!(renderer.measure_string_with_options("straße", 20.0, &upper).width, renderer.measure_string_with_options("STRASSE", 20.0, &DrawOptions::default()).width);
+
+        // small caps are shorter capitals on the same baseline, and take up less room
+        let small_caps = DrawOptions { text_transform: TextTransform::SmallCaps, ..DrawOptions::default() };
+        let prepared = renderer.prepare_text("Hh", 0.0, 0.0, 40.0, white, &small_caps);
+        assert_eq!(prepared.glyph_char(1), Some('h'));
+        let (big, small) = (prepared.glyph_bounds(0).unwrap(), prepared.glyph_bounds(1).unwrap());
+        assert!(small.height < big.height);
+        assert_eq!(small.y + small.height as i32, big.y + big.height as i32);
+        assert!(prepared.metrics().width < renderer.measure_string_with_options("HH", 40.0, &DrawOptions::default()).width);
+    }
+
+    #[test]
+    #[cfg(feature = "ttf-parser")]
+    fn test_decoration_metrics() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        // FreeMono has 1000 units per em, an underline at -125 that's 50 thick and a strikeout at 258 that's 49 thick
+        let metrics = renderer.decoration_metrics(40.0);
+        assert_eq!(metrics.underline_offset, -5.0);
+        assert_eq!(metrics.underline_thickness, 2.0);
+        assert!((metrics.strikeout_offset - 10.32).abs() < 0.001);
+        assert!((metrics.strikeout_thickness - 1.96).abs() < 0.001);
+        assert_eq!(renderer.decoration_metrics(20.0).underline_offset, -2.5);
+
+        // underlines go where the font says, unless the options say otherwise
+        let baseline = renderer.line_metrics(40.0).ascent.ceil() as usize;
+        let white = TextColour::new_rgb(255, 255, 255);
+        let underline = Underline { range: 0..1, style: UnderlineStyle::Solid, colour: white };
+        let mut underlined = |decoration_metrics| {
+            let options = DrawOptions { underlines: vec![underline.clone()], decoration_metrics, ..DrawOptions::default() };
+            let mut surface = TestSurface::new(60, 80);
+            renderer.draw_string_with_options(" ", 0.0, 0.0, 40.0, white, &options, &mut surface);
+            surface.inked_rows(0, 60).unwrap()
+        };
+        assert_eq!(underlined(None), (baseline + 5, baseline + 6));
+        let custom = DecorationMetrics { underline_offset: -10.0, underline_thickness: 4.0, ..metrics };
+        assert_eq!(underlined(Some(custom)), (baseline + 10, baseline + 13));
+    }
+
+    #[test]
+    fn test_warm_async() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let handle = renderer.warm_async("hello world".chars(), 20.0, white);
+        // duplicates and the space (which the font does have a glyph for) only count once
+        assert_eq!(handle.total(), "helo wrd".len());
+        assert_eq!(handle.progress(), 0.0);
+        while !handle.is_finished() {
+            renderer.pump_warm(&handle, std::time::Duration::from_millis(1));
+            std::thread::yield_now();
+        }
+        assert_eq!(handle.progress(), 1.0);
+
+        // everything that was warmed gets drawn straight from the cache, the same as if it had been rasterized there and then
+        let mut warmed = TestSurface::new(200, 40);
+        renderer.draw_string("hello world!", 5.0, 5.0, 20.0, white, &mut warmed);
+        assert_eq!(renderer.metrics().rasterizations, 1);
+        let mut cold = TestSurface::new(200, 40);
+        TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap().draw_string("hello world!", 5.0, 5.0, 20.0, white, &mut cold);
+        assert_eq!(warmed.data, cold.data);
+
+        // warming what's already cached has nothing left to do
+        assert!(renderer.warm_async("hello".chars(), 20.0, white).is_finished());
+    }
+
+    #[test]
+    fn test_line_hook() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let options = DrawOptions { max_width: Some(120.0), ..DrawOptions::default() };
+        let string = "one\n\nthree four five six";
+        let mut surface = TestSurface::new(160, 120);
+        let mut lines = Vec::new();
+        renderer.draw_string_with_line_hook(string, 4.0, 2.0, 16.0, white, &options, LineExtent::MaxWidth, &mut surface, |line, surface| {
+            // stripes behind every other line
+            if line.index % 2 == 1 {
+                for y in line.rect.y as usize..line.rect.y as usize + line.rect.height {
+                    for x in line.rect.x as usize..line.rect.x as usize + line.rect.width {
+                        surface.data[(y * 160 + x) * 4..][..4].copy_from_slice(&[0, 0, 255, 255]);
+                    }
+                }
+            }
+            lines.push(line);
+        });
+
+        // the empty line gets a call too, and the rects stack up without any gaps
+        let metrics = renderer.measure_string_with_options(string, 16.0, &options);
+        assert_eq!(lines.len(), metrics.lines.len());
+        assert!(lines.len() > 3);
+        assert_eq!(lines[1].byte_range, metrics.lines[1].byte_range);
+        assert_eq!(lines[0].rect.y, 2);
+        for (line, next) in lines.iter().zip(lines.iter().skip(1)) {
+            assert_eq!(line.rect.y + line.rect.height as i32, next.rect.y);
+            assert_eq!((line.rect.x, line.rect.width), (4, 120));
+        }
+
+        // the text is drawn over whatever the hook drew
+        let stripe = &lines[3];
+        let row = stripe.rect.y as usize..stripe.rect.y as usize + stripe.rect.height;
+        let pixels = row.flat_map(|y| (4..124).map(move |x| (y * 160 + x) * 4)).map(|i| &surface.data[i..i + 4]).collect::<Vec<_>>();
+        assert!(pixels.iter().any(|pixel| *pixel == [0, 0, 255, 255]));
+        assert!(pixels.iter().any(|pixel| pixel[0] > 128));
+
+        // with `LineExtent::Line` the empty line's rect has nothing in it
+        let mut widths = Vec::new();
+        renderer.draw_string_with_line_hook(string, 4.0, 2.0, 16.0, white, &options, LineExtent::Line, &mut surface, |line, _| widths.push(line.rect.width));
+        assert_eq!(widths[1], 0);
+        assert_eq!(widths[0], metrics.lines[0].width.ceil() as usize);
+    }
+
+    #[test]
+    fn test_colour_manipulation() {
+        // (colour, lighten(0.2), darken(0.2), saturate(0.2), desaturate(0.2), desaturate(1.0))
+        let table = [
+            ("800000", "e60000", "1a0000", "800000", "730d0d", "404040"),
+            ("3366cc", "85a3e0", "1f3d7a", "195de6", "4c6eb3", "808080"),
+            ("777777", "aaaaaa", "444444", "8f5f5f", "777777", "777777"),
+            ("ffcc00", "ffe066", "997a00", "ffcc00", "e6bd19", "808080"),
+        ];
+        for (colour, lighter, darker, saturated, desaturated, gray) in table {
+            let colour = TextColour::from_hex(colour);
+            assert_eq!(colour.lighten(0.2), TextColour::from_hex(lighter), "lighten {:?}", colour);
+            assert_eq!(colour.darken(0.2), TextColour::from_hex(darker), "darken {:?}", colour);
+            assert_eq!(colour.saturate(0.2), TextColour::from_hex(saturated), "saturate {:?}", colour);
+            assert_eq!(colour.desaturate(0.2), TextColour::from_hex(desaturated), "desaturate {:?}", colour);
+            assert_eq!(colour.desaturate(1.0), TextColour::from_hex(gray), "desaturate fully {:?}", colour);
+        }
+
+        // clamping rather than wrapping, and alpha coming along for the ride
+        let faded = TextColour::new(51, 102, 204, 100);
+        assert_eq!(faded.lighten(5.0), TextColour::new(255, 255, 255, 100));
+        assert_eq!(faded.darken(5.0), TextColour::new(0, 0, 0, 100));
+        assert_eq!(faded.invert(), TextColour::new(204, 153, 51, 100));
+        assert_eq!(faded.with_alpha(255).desaturate(0.2).lighten(0.1).with_alpha(7).a, 7);
+
+        // WCAG contrast
+        let (black, white) = (TextColour::new_rgb(0, 0, 0), TextColour::new_rgb(255, 255, 255));
+        assert!((black.contrast_ratio(&white) - 21.0).abs() < 0.001);
+        assert!((white.contrast_ratio(&white) - 1.0).abs() < 0.001);
+        let gray = TextColour::from_hex("777777");
+        assert!((gray.contrast_ratio(&white) - 4.478).abs() < 0.001);
+        assert_eq!(TextColour::best_on(gray), black);
+        assert_eq!(TextColour::best_on(TextColour::from_hex("800000")), white);
+        assert_eq!(TextColour::best_on(TextColour::from_hex("ffcc00")), black);
+    }
+
+    #[test]
+    fn test_monospaced_alignment() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let cell = 10usize;
+        let columns = |surface: &TestSurface, from: usize, to: usize| {
+            (0..40).flat_map(|y| surface.data[(y * 200 + from) * 4..(y * 200 + to) * 4].to_vec()).collect::<Vec<u8>>()
+        };
+
+        // the last two cells come out the same however long the number gets, and nothing goes past the anchor
+        let mut drawn = Vec::new();
+        for score in ["99", "999", "1099"] {
+            let mut surface = TestSurface::new(200, 40);
+            renderer.draw_string_monospaced_aligned(score, 150.0, 5.0, 20.0, white, Align::Right, &mut surface);
+            assert!(surface.data.chunks_exact(4).enumerate().all(|(i, pixel)| i % 200 < 150 || pixel[3] == 0));
+            drawn.push(columns(&surface, 150 - cell * 2, 150));
+        }
+        assert!(drawn[0].iter().any(|byte| *byte > 0));
+        assert!(drawn.iter().all(|cells| *cells == drawn[0]));
+
+        // centered text starts half of its cells to the left, wide characters counting twice
+        for string in ["0000", "a\u{4e00}b"] {
+            let (mut centered, mut left) = (TestSurface::new(200, 40), TestSurface::new(200, 40));
+            renderer.draw_string_monospaced_aligned(string, 100.0, 5.0, 20.0, white, Align::Center, &mut centered);
+            renderer.draw_string_monospaced(string, 80.0, 5.0, 20.0, white, &mut left);
+            assert_eq!(centered.data, left.data);
+        }
+    }
+
+    #[test]
+    fn test_measure_string() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let metrics = renderer.measure_string("hai\nworld", 20.0);
+        assert_eq!(metrics.line_count(), 2);
+        let advance = renderer.glyph_metrics('a', 20.0).unwrap().advance;
+        assert!((metrics.lines[0].width - advance * 3.0).abs() < 0.5);
+        assert!((metrics.lines[1].width - advance * 5.0).abs() < 0.5);
+        assert_eq!(metrics.width, metrics.lines[1].width);
+
+        // same size as what draw_string ends up drawing
+        let mut surface = TestSurface::new(200, 100);
+        let bounds = renderer.draw_string_with_options("hai\nworld", 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &DrawOptions::default(), &mut surface);
+        assert_eq!((metrics.width, metrics.height), (bounds.width, bounds.height));
+    }
+
+    #[test]
+    fn test_line_spacing() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let spaced = DrawOptions { line_spacing: Some(50.0), ..Default::default() };
+        let metrics = renderer.measure_string_with_options("A\nA\nA", 20.0, &spaced);
+        assert_eq!(metrics.lines.iter().map(|line| line.y).collect::<Vec<_>>(), vec![0.0, 50.0, 100.0]);
+        assert!(metrics.lines.iter().all(|line| line.height == 50.0));
+        assert_eq!(metrics.height, 150.0);
+        let plain = renderer.measure_string("A\nA\nA", 20.0);
+        assert_eq!(metrics.lines[0].baseline, plain.lines[0].baseline);
+
+        // every line is drawn the same, just further apart
+        let mut surface = TestSurface::new(50, 150);
+        renderer.draw_string_with_options("A\nA\nA", 0.0, 0.0, 20.0, TextColour::new_rgb(255, 255, 255), &spaced, &mut surface);
+        let rows = |from: usize| surface.data[from * 50 * 4..(from + 50) * 50 * 4].to_vec();
+        assert!(rows(0).iter().any(|byte| *byte > 0));
+        assert_eq!(rows(0), rows(50));
+        assert_eq!(rows(0), rows(100));
+
+        // lines can't be less than 0 pixels tall, and NaN is the same as not setting it
+        let white = TextColour::new_rgb(255, 255, 255);
+        for options in [
+            DrawOptions { line_spacing: Some(-100.0), ..Default::default() },
+            DrawOptions { line_height_scale: -2.0, ..Default::default() },
+        ] {
+            let metrics = renderer.measure_string_with_options("A\nA\nA", 20.0, &options);
+            assert!(metrics.lines.iter().all(|line| line.height == 0.0 && line.y == 0.0));
+            assert_eq!(metrics.height, 0.0);
+            for string in ["\u{200B}", " ", "A\nA"] {
+                let (rendered, _) = renderer.render_to_surface(string, 20.0, white, &options);
+                assert!(rendered.height < 100);
+            }
+        }
+        for options in [
+            DrawOptions { line_spacing: Some(f32::NAN), ..Default::default() },
+            DrawOptions { line_height_scale: f32::NAN, ..Default::default() },
+        ] {
+            assert_eq!(renderer.measure_string_with_options("A\nA\nA", 20.0, &options), renderer.measure_string("A\nA\nA", 20.0));
+        }
+    }
+
+    #[test]
+    fn test_draw_paragraph() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let mut surface = TestSurface::new(200, 200);
+        let rect = Rect::new(20, 10, 100, 60);
+        let text = "the quick brown fox jumps over the lazy dog and keeps on running";
+        let bounds = renderer.draw_paragraph(text, rect, 20.0, TextColour::new_rgb(255, 255, 255), &DrawOptions::default(), &mut surface);
+        assert_eq!((bounds.x, bounds.y), (20.0, 10.0));
+        assert!(bounds.width <= 100.0 && bounds.height <= 60.0);
+        assert!(bounds.truncated && bounds.bytes_drawn < text.len());
+
+        // nothing ends up outside of the rect
+        assert!(surface.data.chunks_exact(4).enumerate().all(|(i, pixel)| {
+            let (x, y) = (i % 200, i / 200);
+            pixel[3] == 0 || ((20..120).contains(&x) && (10..70).contains(&y))
+        }));
+        let (top, bottom) = surface.inked_rows(0, 200).unwrap();
+        assert!(top >= 10 && bottom > 10 + renderer.line_height(20.0) as usize);
+    }
+
+    #[test]
+    fn test_align() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        let centered = DrawOptions { align: Align::Center, ..Default::default() };
+        let metrics = renderer.measure_string_with_options("hai\nworld", 20.0, &centered);
+        assert_eq!(metrics.lines.iter().map(|line| line.x).collect::<Vec<_>>(), vec![advance, 0.0]);
+        assert_eq!(metrics.width, advance * 5.0);
+
+        // the short line is drawn the same, just moved over
+        let (mut left, mut center) = (TestSurface::new(200, 60), TestSurface::new(200, 60));
+        renderer.draw_string_with_options("hai\nworld", 0.0, 0.0, 20.0, white, &DrawOptions::default(), &mut left);
+        renderer.draw_string_with_options("hai\nworld", 0.0, 0.0, 20.0, white, &centered, &mut center);
+        let line_height = metrics.lines[0].height as usize;
+        let first_line = |surface: &TestSurface, from: usize| {
+            (0..line_height).flat_map(|y| surface.data[(y * 200 + from) * 4..(y * 200 + from + 100) * 4].to_vec()).collect::<Vec<u8>>()
+        };
+        assert!(first_line(&left, 0).iter().any(|byte| *byte > 0));
+        assert_eq!(first_line(&left, 0), first_line(&center, advance as usize));
+
+        // with a max_width, lines go against its right edge and the text takes up all of it
+        let right = DrawOptions { align: Align::Right, max_width: Some(200.0), ..Default::default() };
+        let metrics = renderer.measure_string_with_options("hai\nworld", 20.0, &right);
+        assert_eq!(metrics.width, 200.0);
+        assert!(metrics.lines.iter().all(|line| line.x + line.width == 200.0));
+        let mut surface = TestSurface::new(200, 60);
+        renderer.draw_string_with_options("hai\nworld", 0.0, 0.0, 20.0, white, &right, &mut surface);
+        assert!(surface.data.chunks_exact(4).enumerate().all(|(i, pixel)| pixel[3] == 0 || i % 200 >= 200 - advance as usize * 5));
+        assert!(surface.inked_rows(200 - advance as usize, 200).is_some());
+    }
+
+    #[test]
+    fn test_vertical_align() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let rect = Rect::new(0, 0, 200, 100);
+        let line_height = renderer.measure_string("hai", 20.0).height;
+        let mut drawn = Vec::new();
+        for vertical_align in [VerticalAlign::Top, VerticalAlign::Middle, VerticalAlign::Bottom] {
+            let options = DrawOptions { vertical_align, ..Default::default() };
+            let mut surface = TestSurface::new(200, 100);
+            let bounds = renderer.draw_paragraph("hai", rect, 20.0, white, &options, &mut surface);
+            assert_eq!(bounds.height, if vertical_align == VerticalAlign::Top { line_height } else { 100.0 });
+            drawn.push(surface.inked_rows(0, 200).unwrap());
+        }
+        let moved = |i: usize| drawn[i].0 - drawn[0].0;
+        assert_eq!(moved(1), ((100.0 - line_height) / 2.0).floor() as usize);
+        assert_eq!(moved(2), (100.0 - line_height) as usize);
+        assert!(drawn.iter().all(|(top, bottom)| bottom - top == drawn[0].1 - drawn[0].0));
+
+        // without a max_height there's nothing to align within
+        let options = DrawOptions { vertical_align: VerticalAlign::Bottom, ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options("hai", 20.0, &options), renderer.measure_string("hai", 20.0));
+    }
+
+    #[test]
+    fn test_justify() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        let text = "aa bb cc dd ee ff\nab cd";
+        let left = DrawOptions { max_width: Some(advance * 8.0 + 18.0), ..Default::default() };
+        let justified = DrawOptions { align: Align::Justify, ..left.clone() };
+        let metrics = renderer.measure_string_with_options(text, 20.0, &justified);
+        let ranges = metrics.lines.iter().map(|line| line.byte_range.clone()).collect::<Vec<_>>();
+        assert_eq!(ranges, vec![0..9, 9..18, 18..23]);
+        assert_eq!(metrics.lines[0].width, advance * 8.0 + 18.0);
+
+        // the 18 spare pixels of the first line get split between its two gaps, the rest end paragraphs and don't move
+        let (left, justified) = (renderer.prepare_text(text, 0.0, 0.0, 20.0, white, &left), renderer.prepare_text(text, 0.0, 0.0, 20.0, white, &justified));
+        let moved = (0..left.glyph_count()).map(|i| justified.glyph_bounds(i).unwrap().x - left.glyph_bounds(i).unwrap().x).collect::<Vec<_>>();
+        assert_eq!(moved[..9], [0, 0, 0, 9, 9, 9, 18, 18, 18]);
+        assert!(moved[9..].iter().all(|moved| *moved == 0));
+    }
+
+    #[test]
+    fn test_draw_text_in_rect() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let text = "the quick brown fox jumps over the lazy dog";
+        let line_height = renderer.measure_string("a", 20.0).height;
+
+        // everything fits in a big enough rect
+        let mut surface = TestSurface::new(200, 200);
+        let overflow = renderer.draw_text_in_rect(text, Rect::new(10, 10, 180, 180), 20.0, white, &DrawOptions::default(), &mut surface);
+        assert!(!overflow.overflowed());
+        assert_eq!((overflow.overflow_x, overflow.overflow_y), (0.0, 0.0));
+        assert_eq!(overflow.bounds.bytes_drawn, text.len());
+
+        // in a short one, the line that pokes out the bottom is drawn up to the edge and reported
+        let mut surface = TestSurface::new(200, 200);
+        let rect = Rect::new(10, 10, 120, line_height as usize + 5);
+        let overflow = renderer.draw_text_in_rect(text, rect, 20.0, white, &DrawOptions::default(), &mut surface);
+        let metrics = renderer.measure_string_with_options(text, 20.0, &DrawOptions { max_width: Some(120.0), ..Default::default() });
+        assert!(overflow.overflowed());
+        assert_eq!(overflow.clipped_from, Some(metrics.lines[1].byte_range.start));
+        assert_eq!(overflow.overflow_y, metrics.height - rect.height as f32);
+        assert_eq!(overflow.overflow_x, 0.0);
+        let (top, bottom) = surface.inked_rows(0, 200).unwrap();
+        assert!(top >= 10 && bottom == 10 + rect.height - 1);
+        assert!(surface.data.chunks_exact(4).enumerate().all(|(i, pixel)| pixel[3] == 0 || (10..130).contains(&(i % 200))));
+    }
+
+    #[test]
+    fn test_draw_string_truncated() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        let ellipsis = renderer.char_advance('\u{2026}', 20.0);
+
+        let mut surface = TestSurface::new(300, 50);
+        let bounds = renderer.draw_string_truncated("hello world, this\nis long", 0.0, 0.0, advance * 8.0, 20.0, white, &DrawOptions::default(), &mut surface);
+        assert!(bounds.truncated);
+        assert_eq!(bounds.bytes_drawn, 7);
+        assert_eq!(bounds.width, advance * 7.0 + ellipsis);
+        assert_eq!(bounds.height, renderer.measure_string("a", 20.0).height);
+        assert!(surface.inked_rows(bounds.width.ceil() as usize, 300).is_none());
+
+        // a string that fits is drawn as it is
+        let mut truncated = TestSurface::new(300, 50);
+        let mut plain = TestSurface::new(300, 50);
+        let bounds = renderer.draw_string_truncated("hello", 0.0, 0.0, advance * 8.0, 20.0, white, &DrawOptions::default(), &mut truncated);
+        renderer.draw_string_with_options("hello", 0.0, 0.0, 20.0, white, &DrawOptions::default(), &mut plain);
+        assert!(!bounds.truncated);
+        assert_eq!(truncated.data, plain.data);
+    }
+
+    #[test]
+    fn test_letter_spacing() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        let plain = renderer.prepare_text("hai wo", 0.0, 0.0, 20.0, white, &DrawOptions::default());
+        for letter_spacing in [3.0, -2.0] {
+            let options = DrawOptions { letter_spacing, ..Default::default() };
+            assert_eq!(renderer.measure_string_with_options("hai wo", 20.0, &options).width, (advance + letter_spacing) * 6.0);
+            let spaced = renderer.prepare_text("hai wo", 0.0, 0.0, 20.0, white, &options);
+            for i in 0..6 {
+                let (spaced, plain) = (spaced.glyph_bounds(i).unwrap(), plain.glyph_bounds(i).unwrap());
+                assert_eq!((spaced.x - plain.x, spaced.y), ((letter_spacing * i as f32) as i32, plain.y));
+            }
+        }
+
+        // wrapping takes the extra space into account
+        let options = DrawOptions { letter_spacing: 10.0, max_width: Some((advance + 10.0) * 4.0), ..Default::default() };
+        let metrics = renderer.measure_string_with_options("hai wo", 20.0, &options);
+        assert_eq!(metrics.lines.len(), 2);
+    }
+
+    #[test]
+    fn test_word_spacing() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        let options = DrawOptions { word_spacing: 8.0, letter_spacing: 1.0, ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options("ab cd\u{a0}e", 20.0, &options).width, (advance + 1.0) * 7.0 + 16.0);
+
+        // only the characters after a space move over
+        let plain = renderer.prepare_text("ab cd ef", 0.0, 0.0, 20.0, white, &DrawOptions::default());
+        let spaced = renderer.prepare_text("ab cd ef", 0.0, 0.0, 20.0, white, &DrawOptions { word_spacing: 8.0, ..Default::default() });
+        let moved = (0..8).map(|i| spaced.glyph_bounds(i).unwrap().x - plain.glyph_bounds(i).unwrap().x).collect::<Vec<_>>();
+        assert_eq!(moved, vec![0, 0, 0, 8, 8, 8, 16, 16]);
+    }
+
+    #[test]
+    fn test_line_height_scale() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let plain = renderer.measure_string("a\nb\nc", 20.0);
+        let height = plain.lines[0].height;
+        for scale in [1.5, 2.0] {
+            let options = DrawOptions { line_height_scale: scale, ..Default::default() };
+            let metrics = renderer.measure_string_with_options("a\nb\nc", 20.0, &options);
+            let scaled = (height * scale).round();
+            assert_eq!(metrics.lines.iter().map(|line| line.y).collect::<Vec<_>>(), vec![0.0, scaled, scaled * 2.0]);
+            assert_eq!(metrics.lines[1].baseline - metrics.lines[1].y, plain.lines[1].baseline - plain.lines[1].y);
+            assert_eq!(metrics.height, scaled * 3.0);
+        }
+
+        // line_spacing wins
+        let options = DrawOptions { line_height_scale: 2.0, line_spacing: Some(30.0), ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options("a\nb\nc", 20.0, &options).height, 90.0);
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        let renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let advance = renderer.char_advance('a', 20.0);
+        // where the pen was before every glyph after a tab, going by where the glyph ended up
+        let pens = |string: &str, tab_stops: TabStops| {
+            let options = DrawOptions { tab_stops: Some(tab_stops), ..Default::default() };
+            let prepared = renderer.prepare_text(string, 0.0, 0.0, 20.0, white, &options);
+            let alone = renderer.prepare_text("x", 0.0, 0.0, 20.0, white, &DrawOptions::default()).glyph_bounds(0).unwrap().x;
+            string.chars().enumerate().filter(|(i, _)| *i > 0 && string.chars().nth(i - 1) == Some('\t'))
+                .map(|(i, _)| (prepared.glyph_bounds(i).unwrap().x - alone) as f32)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(pens("x\tx", TabStops::Spaces(4)), vec![advance * 4.0]);
+        assert_eq!(pens("xxxxx\tx", TabStops::Spaces(4)), vec![advance * 8.0]);
+        // stops start again on every line
+        assert_eq!(pens("xxxxx\nx\tx", TabStops::Spaces(4)), vec![advance * 4.0]);
+        assert_eq!(pens("x\tx", TabStops::Every(50.0)), vec![50.0]);
+        // past the last column a tab is as wide as a space
+        assert_eq!(pens("x\tx\tx\tx", TabStops::Columns(vec![50.0, 100.0])), vec![50.0, 100.0, 100.0 + advance * 2.0]);
+
+        // tabs count towards the width
+        let options = DrawOptions { tab_stops: Some(TabStops::Every(50.0)), ..Default::default() };
+        assert_eq!(renderer.measure_string_with_options("x\tx", 20.0, &options).width, 50.0 + advance);
+    }
+
+    #[test]
+    fn test_draw_string_baseline() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        // the bottoms of capitals sit on the baseline, whatever size they are
+        let mut bottoms = Vec::new();
+        for size in [12.0, 24.0, 48.0] {
+            let mut surface = TestSurface::new(200, 100);
+            renderer.draw_string_baseline("HI", 10.0, 80.0, size, white, &mut surface);
+            let (top, bottom) = surface.inked_rows(0, 200).unwrap();
+            assert!((top as f32) < 80.0 - size / 3.0);
+            bottoms.push(bottom);
+        }
+        assert!(bottoms.iter().all(|bottom| (78..=80).contains(bottom)), "{:?}", bottoms);
+    }
+
+    #[test]
+    fn test_surface_extents() {
+        // a surface that knows its size and panics if anything gets pasted outside of it
+        struct StrictSurface {
+            width: usize,
+            height: usize,
+            pasted: usize,
+        }
+        impl PasteSurface<TestSurface> for StrictSurface {
+            fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, _data: &TestSurface) {
+                assert!(x + width <= self.width && y + height <= self.height, "pasted ({}, {}) {}x{} outside of the surface", x, y, width, height);
+                self.pasted += 1;
+            }
+
+            fn paste_clipped(&mut self, x: usize, y: usize, _src_x: usize, _src_y: usize, width: usize, height: usize, data: &TestSurface) {
+                self.paste(x, y, width, height, data);
+            }
+
+            fn width(&self) -> Option<usize> {
+                Some(self.width)
+            }
+
+            fn height(&self) -> Option<usize> {
+                Some(self.height)
+            }
+        }
+
+        let mut renderer = TextRenderer::<TestSurface, StrictSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let mut surface = StrictSurface { width: 100, height: 50, pasted: 0 };
+        // hanging off the right and bottom edges, only the visible parts get pasted
+        let options = DrawOptions {
+            selection: Some(Selection { range: 0..5, background: TextColour::new_rgb(0, 0, 255), foreground: None }),
+            ..Default::default()
+        };
+        renderer.draw_string_with_options("hello world", 60.0, 30.0, 24.0, white, &options, &mut surface);
+        assert!(surface.pasted > 0);
+        // and nothing is pasted at all when it's completely past them
+        let pasted = surface.pasted;
+        renderer.draw_string_with_options("hello world", 100.0, 0.0, 24.0, white, &options, &mut surface);
+        renderer.draw_string_with_options("hello world", 0.0, 50.0, 24.0, white, &options, &mut surface);
+        renderer.draw_char('W', 120.0, 0.0, 24.0, white, &mut surface);
+        assert_eq!(surface.pasted, pasted);
+    }
+
+    #[test]
+    fn test_set_clip() {
+        let mut renderer = TextRenderer::<TestSurface, TestSurface>::load("FreeMono.ttf").unwrap();
+        let white = TextColour::new_rgb(255, 255, 255);
+        let text = "scrolling\npanel text";
+        let panel = Rect::new(20, 10, 60, 25);
+
+        // the renderer's clip does the same as passing it to every draw call
+        let (mut a, mut b) = (TestSurface::new(200, 100), TestSurface::new(200, 100));
+        renderer.draw_string_with_options(text, 5.0, 0.0, 24.0, white, &DrawOptions { clip: Some(panel), ..Default::default() }, &mut a);
+        renderer.set_clip(Some(panel));
+        assert_eq!(renderer.clip(), Some(panel));
+        renderer.draw_string(text, 5.0, 0.0, 24.0, white, &mut b);
+        assert!(a.data.iter().any(|byte| *byte != 0));
+        assert_eq!(a.data, b.data);
+        assert!(b.data.chunks_exact(4).enumerate().all(|(i, pixel)| pixel[3] == 0 || ((20..80).contains(&(i % 200)) && (10..35).contains(&(i / 200)))));
+
+        // and only what's inside of both clips gets drawn
+        let mut c = TestSurface::new(200, 100);
+        renderer.draw_string_with_options(text, 5.0, 0.0, 24.0, white, &DrawOptions { clip: Some(Rect::new(50, 0, 200, 100)), ..Default::default() }, &mut c);
+        assert!(c.data.chunks_exact(4).enumerate().all(|(i, pixel)| pixel[3] == 0 || (50..80).contains(&(i % 200))));
+        assert!(c.inked_rows(50, 80).is_some());
+
+        // until it's taken away again
+        renderer.set_clip(None);
+        let (mut d, mut e) = (TestSurface::new(200, 100), TestSurface::new(200, 100));
+        renderer.draw_string(text, 5.0, 0.0, 24.0, white, &mut d);
+        renderer.draw_string_with_options(text, 5.0, 0.0, 24.0, white, &DrawOptions::default(), &mut e);
+        assert_eq!(d.data, e.data);
+        assert!(d.inked_rows(0, 20).is_some());
+
+        // pre-rendering isn't clipped, even with a clip nowhere near the text
+        let (unclipped, _) = renderer.render_to_surface(text, 24.0, white, &DrawOptions::default());
+        renderer.set_clip(Some(Rect::new(150, 80, 10, 10)));
+        let (rendered, _) = renderer.render_to_surface(text, 24.0, white, &DrawOptions::default());
+        assert!(unclipped.data.iter().any(|byte| *byte != 0));
+        assert_eq!(rendered.data, unclipped.data);
+
+        // cached strings are rendered whole and only clipped when they're pasted, so the clip doesn't stick to them
+        renderer.set_clip(Some(panel));
+        let mut f = TestSurface::new(200, 100);
+        renderer.draw_string_cached("panel", text, 5.0, 0.0, 24.0, white, &DrawOptions::default(), &mut f);
+        assert_eq!(f.data, b.data);
+        renderer.set_clip(None);
+        let mut g = TestSurface::new(200, 100);
+        renderer.draw_string_cached("panel", text, 5.0, 0.0, 24.0, white, &DrawOptions::default(), &mut g);
+        assert_eq!(g.data, d.data);
+    }
+}
@@ -0,0 +1,161 @@
+//! A tiny markup language for styled strings, so you can write
+//! `"Pick up the {color=#ff0000}red key{/color} and press {b}E{/b}"` instead of building `TextSpan`s by hand.
+//! Supported tags:
+//! - `{color=#rrggbb}` / `{colour=#rrggbbaa}` ... `{/color}`
+//! - `{size=24}` ... `{/size}` (in pixels)
+//! - `{sup}` ... `{/sup}` and `{sub}` ... `{/sub}` for superscript and subscript
+//! - `{b}` ... `{/b}` and `{i}` ... `{/i}`, which are accepted but don't do anything yet
+//!
+//! Tags can be nested, but have to be closed in the opposite order they were opened.
+//! Use `{{` and `}}` for literal braces.
+
+use crate::colours::TextColour;
+use crate::metrics::DrawnBounds;
+use crate::options::DrawOptions;
+use crate::spans::{BaselineShift, TextSpan};
+use crate::{PasteSurface, StoreSurface, TextRenderer};
+
+/// Everything that can go wrong while parsing markup. Offsets are byte offsets into the source string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkupError {
+    /// A `{` without a matching `}`.
+    UnclosedBrace(usize),
+    /// A `}` that isn't closing a tag (use `}}` for a literal one).
+    StrayBrace(usize),
+    /// A tag that isn't one of the supported ones, or one with a value it can't understand.
+    InvalidTag { offset: usize, tag: String },
+    /// A closing tag that doesn't match the most recently opened tag.
+    MismatchedClose { offset: usize, tag: String },
+    /// A tag that was still open at the end of the string.
+    UnclosedTag { offset: usize, tag: String },
+}
+
+/// The style applied to the text between tags.
+#[derive(Debug, Clone, Copy, Default)]
+struct Style {
+    colour: Option<TextColour>,
+    size: Option<f32>,
+    baseline_shift: BaselineShift,
+}
+
+/// Parses markup into spans.
+/// If `strict` is `false`, tags left open at the end just apply to the rest of the string, and any other
+/// mistake gives back a single span with the raw, unparsed source so it's at least visible what went wrong.
+/// If `strict` is `true`, every mistake is returned as an error.
+pub fn parse_markup(source: &str, strict: bool) -> Result<Vec<TextSpan>, MarkupError> {
+    match parse(source) {
+        Ok((_, Some(unclosed))) if strict => Err(unclosed),
+        Ok((spans, _)) => Ok(spans),
+        Err(error) if strict => Err(error),
+        Err(_) => Ok(vec![TextSpan::new(source)]),
+    }
+}
+
+/// Internal function that does the actual parsing.
+/// Also returns an `UnclosedTag` error for the outermost tag that was never closed, if there was one.
+fn parse(source: &str) -> Result<(Vec<TextSpan>, Option<MarkupError>), MarkupError> {
+    let mut spans = Vec::new();
+    let mut text = String::new();
+    let mut style = Style::default();
+    // (offset, name, style before the tag)
+    let mut stack: Vec<(usize, String, Style)> = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek().map(|(_, c)| *c) == Some('}') => {
+                chars.next();
+                text.push('}');
+            }
+            '}' => return Err(MarkupError::StrayBrace(offset)),
+            '{' => {
+                let end = source[offset..].find('}').ok_or(MarkupError::UnclosedBrace(offset))? + offset;
+                let tag = &source[offset + 1..end];
+                while chars.peek().is_some_and(|(i, _)| *i <= end) {
+                    chars.next();
+                }
+                flush(&mut spans, &mut text, style);
+                if let Some(name) = tag.strip_prefix('/') {
+                    let name = normalize(name);
+                    match stack.pop() {
+                        Some((_, open, previous)) if open == name => style = previous,
+                        _ => return Err(MarkupError::MismatchedClose { offset, tag: name.to_string() }),
+                    }
+                } else {
+                    let (name, value) = match tag.split_once('=') {
+                        Some((name, value)) => (normalize(name), Some(value.trim())),
+                        None => (normalize(tag), None),
+                    };
+                    let invalid = || MarkupError::InvalidTag { offset, tag: tag.to_string() };
+                    stack.push((offset, name.to_string(), style));
+                    match (name, value) {
+                        ("colour", Some(value)) => style.colour = Some(parse_colour(value).ok_or_else(invalid)?),
+                        ("size", Some(value)) => style.size = Some(value.parse::<f32>().ok().filter(|size| *size > 0.0).ok_or_else(invalid)?),
+                        ("sup", None) => style.baseline_shift = BaselineShift::Superscript,
+                        ("sub", None) => style.baseline_shift = BaselineShift::Subscript,
+                        ("b", None) | ("i", None) => {}
+                        _ => return Err(invalid()),
+                    }
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    flush(&mut spans, &mut text, style);
+    Ok((spans, stack.into_iter().next().map(|(offset, tag, _)| MarkupError::UnclosedTag { offset, tag })))
+}
+
+/// Internal function to turn the text collected so far into a span.
+fn flush(spans: &mut Vec<TextSpan>, text: &mut String, style: Style) {
+    if text.is_empty() {
+        return;
+    }
+    spans.push(TextSpan {
+        text: std::mem::take(text),
+        size: style.size,
+        colour: style.colour,
+        baseline_shift: style.baseline_shift,
+        font: 0,
+    });
+}
+
+/// Internal function so that `color` and `colour` are the same tag.
+fn normalize(name: &str) -> &str {
+    match name.trim() {
+        "color" => "colour",
+        name => name,
+    }
+}
+
+/// Internal function to parse `#rrggbb` or `#rrggbbaa` without panicking on garbage.
+fn parse_colour(value: &str) -> Option<TextColour> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 => Some(TextColour::from_hex(hex)),
+        8 => Some(TextColour::from_hex_with_alpha(hex)),
+        _ => None,
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Parses `source` as markup (not strictly, see `parse_markup`) and draws it with `draw_spans`.
+    /// `base_size` and `base_colour` are used for any text that isn't inside a `size`/`colour` tag.
+    pub fn draw_markup(
+        &mut self,
+        source: &str,
+        x: f32,
+        y: f32,
+        base_size: f32,
+        base_colour: TextColour,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let spans = parse_markup(source, false).unwrap_or_else(|_| vec![TextSpan::new(source)]);
+        self.draw_spans(&spans, x, y, base_size, base_colour, &DrawOptions::default(), surface)
+    }
+}
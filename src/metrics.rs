@@ -0,0 +1,183 @@
+use crate::Rect;
+use std::ops::Range;
+use std::time::Duration;
+
+/// A snapshot of the renderer's glyph cache counters, returned by `TextRenderer::metrics`.
+/// Useful for figuring out whether slow text is coming from the cache or from rasterizing over and over.
+/// The counters only ever go up, call `TextRenderer::reset_metrics` if you want to measure a single frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderMetrics {
+    /// Number of glyph lookups that were served straight from the cache.
+    pub cache_hits: u64,
+    /// Number of glyph lookups that weren't in the cache.
+    pub cache_misses: u64,
+    /// Number of glyphs that were actually rasterized by fontdue.
+    pub rasterizations: u64,
+    /// Total time spent rasterizing glyphs (including building the `StoreSurface` for them).
+    pub rasterization_time: Duration,
+}
+
+/// Vertical metrics of the font at a given size, returned by `TextRenderer::line_metrics`.
+/// All values are in pixels, with the baseline at 0 and positive values going up.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LineMetrics {
+    /// How far above the baseline the font's glyphs reach. Usually positive.
+    pub ascent: f32,
+    /// How far below the baseline the font's glyphs reach. Usually negative.
+    pub descent: f32,
+    /// The gap the font designer wants between the descent of one line and the ascent of the next.
+    pub line_gap: f32,
+    /// The distance between two baselines, `ascent - descent + line_gap`.
+    pub line_height: f32,
+}
+
+/// Where lines drawn through or under text go at a given size, returned by `TextRenderer::decoration_metrics`.
+/// All values are in pixels, with the baseline at 0 and positive values going up, same as `LineMetrics`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DecorationMetrics {
+    /// Where the top of an underline goes. Usually negative.
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    /// Where the top of a line striking through the text goes. Usually positive, around half the x-height.
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+}
+
+/// Metrics of a single glyph at a given size, returned by `TextRenderer::glyph_metrics`.
+/// All values are in pixels. Bearings are measured from the pen position on the baseline.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GlyphMetrics {
+    /// How far the pen moves to the right after this glyph.
+    pub advance: f32,
+    /// Distance from the pen position to the left edge of the inked box.
+    pub left_bearing: f32,
+    /// Distance from the baseline up to the top edge of the inked box.
+    pub top_bearing: f32,
+    /// Width of the inked box. Zero for glyphs that don't draw anything, like spaces.
+    pub width: f32,
+    /// Height of the inked box. Zero for glyphs that don't draw anything, like spaces.
+    pub height: f32,
+    /// Width of the bitmap that would be rasterized for this glyph.
+    pub bitmap_width: usize,
+    /// Height of the bitmap that would be rasterized for this glyph.
+    pub bitmap_height: usize,
+}
+
+/// Information about a single laid out line of text.
+/// With `Direction::Vertical` every "line" is a column, `width` is its length (top to bottom),
+/// `height` is its thickness, `y` is how far it is from the right edge of the first column,
+/// and `baseline` is the centre line of the column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineInfo {
+    /// The bytes of the source string that ended up on this line.
+    /// This includes any whitespace or newline that the line was broken at.
+    pub byte_range: Range<usize>,
+    /// How wide the line is in pixels. Trailing whitespace isn't counted.
+    pub width: f32,
+    /// How tall the line is in pixels, from the top of this line to the top of the next.
+    pub height: f32,
+    /// How far the start of this line is from the left of the text. This is always 0 unless `DrawOptions::align` moved it.
+    pub x: f32,
+    /// How far down the top of this line is from the top of the first line.
+    pub y: f32,
+    /// How far down the baseline of this line is from the top of the first line.
+    pub baseline: f32,
+    /// The number of glyphs on this line (including whitespace).
+    pub glyph_count: usize,
+}
+
+/// A line that's about to be drawn, passed to the hook of `TextRenderer::draw_string_with_line_hook`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawnLine {
+    /// Which line this is, starting from 0 at the top.
+    pub index: usize,
+    /// The bytes of the source string on this line, same as `LineInfo::byte_range`.
+    pub byte_range: Range<usize>,
+    /// Where the line is on the surface, the full height of the line. The rects of neighbouring lines always meet
+    /// exactly, so filling every other one gives unbroken stripes.
+    pub rect: Rect,
+    /// Where the baseline of the line is on the surface.
+    pub baseline: f32,
+}
+
+/// The size of a string as it would be drawn, returned by `TextRenderer::measure_string`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextMetrics {
+    /// Width of the widest line in pixels.
+    pub width: f32,
+    /// Height of all of the lines together in pixels.
+    pub height: f32,
+    /// Every line of text, top to bottom, with the same line breaks that drawing would use.
+    pub lines: Vec<LineInfo>,
+    /// Whether some lines were left out because of `DrawOptions::max_lines` or `DrawOptions::max_height`.
+    pub truncated: bool,
+}
+
+impl TextMetrics {
+    /// How many lines there are, same as `lines.len()`.
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+/// Where a string ended up after being drawn, returned by `TextRenderer::draw_string_with_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawnBounds {
+    /// Left edge of the drawn text.
+    pub x: f32,
+    /// Top edge of the drawn text (the top of the first line, even with `Origin::Baseline`).
+    pub y: f32,
+    /// Width of the widest drawn line.
+    pub width: f32,
+    /// Height of all of the drawn lines together.
+    pub height: f32,
+    /// Whether some of the text wasn't drawn because of `DrawOptions::max_lines` or `DrawOptions::max_height`.
+    pub truncated: bool,
+    /// How many glyphs of the string were drawn (not counting an ellipsis, but counting whitespace).
+    pub glyphs_drawn: usize,
+    /// How many bytes from the start of the string were drawn. Everything after this was cut off.
+    pub bytes_drawn: usize,
+}
+
+/// How well text drawn with `TextRenderer::draw_text_in_rect` fit into its rect.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextOverflow {
+    /// Where the text ended up, including the parts that got clipped off.
+    pub bounds: DrawnBounds,
+    /// How many pixels past the right of the rect the text went, or 0 if it didn't.
+    /// Lines are wrapped at the rect's width, so this only happens when a word can't be broken up to fit.
+    pub overflow_x: f32,
+    /// How many pixels past the bottom of the rect the text went, or 0 if it didn't.
+    pub overflow_y: f32,
+    /// The byte offset of the start of the first line that got clipped (even just a little), or `None` if every line fit.
+    pub clipped_from: Option<usize>,
+}
+
+impl TextOverflow {
+    /// Whether any of the text didn't fit into the rect.
+    pub fn overflowed(&self) -> bool {
+        self.clipped_from.is_some()
+    }
+}
+
+/// What happened when drawing a single character, returned by `TextRenderer::draw_char`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CharDrawResult {
+    /// How far to move the pen to the right before drawing the next character (rounded up to a whole pixel,
+    /// same as laid out strings do).
+    pub advance: f32,
+    /// The part of the surface the glyph was actually pasted onto,
+    /// or `None` if nothing was drawn (whitespace, or a glyph that's entirely off the surface).
+    pub ink: Option<Rect>,
+    /// Whether the font has a glyph for the character. If it doesn't, the font's "missing glyph" box is drawn instead.
+    pub found: bool,
+}
+
+/// The result of `TextRenderer::fit_size`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FittedSize {
+    /// The biggest size that fits, or the smallest size that was allowed if nothing fits.
+    pub size: f32,
+    /// `false` if the text doesn't fit even at the smallest allowed size.
+    pub fits: bool,
+}
@@ -0,0 +1,222 @@
+//! Internal module, multi-channel signed distance fields worked out from glyph outlines, for `TextRenderer::glyph_msdf`.
+//! This does the same thing msdfgen does: the edges of every contour get a colour (a mix of red, green and blue)
+//! that changes at every sharp corner, and each channel holds the distance to the nearest edge with that channel
+//! in its colour. The edges either side of a corner only share one channel, so the median of the three stays sharp
+//! at the corner however far it's scaled up, where a single channel would round it off.
+
+use crate::outline::{GlyphPath, PathCommand};
+
+const RED: u8 = 1;
+const GREEN: u8 = 2;
+const BLUE: u8 = 4;
+const CYAN: u8 = GREEN | BLUE;
+const MAGENTA: u8 = RED | BLUE;
+const YELLOW: u8 = RED | GREEN;
+const WHITE: u8 = RED | GREEN | BLUE;
+
+/// How sharp a join between two edges has to be to count as a corner, as the sine of the angle (msdfgen uses 3 radians).
+const CORNER_THRESHOLD: f32 = 0.141;
+
+/// How many straight pieces curves are split into when measuring distances to them.
+const QUAD_PIECES: usize = 8;
+const CUBIC_PIECES: usize = 12;
+
+type Point = (f32, f32);
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn normalize(a: Point) -> Point {
+    let length = dot(a, a).sqrt();
+    if length == 0.0 { (0.0, 0.0) } else { (a.0 / length, a.1 / length) }
+}
+
+/// Internal struct, one edge of a contour (a line or a curve from the font) as a list of points along it.
+struct Segment {
+    points: Vec<Point>,
+}
+
+impl Segment {
+    fn start_direction(&self) -> Point {
+        self.points.windows(2).map(|pair| normalize(sub(pair[1], pair[0]))).find(|&d| d != (0.0, 0.0)).unwrap_or((0.0, 0.0))
+    }
+
+    fn end_direction(&self) -> Point {
+        self.points.windows(2).rev().map(|pair| normalize(sub(pair[1], pair[0]))).find(|&d| d != (0.0, 0.0)).unwrap_or((0.0, 0.0))
+    }
+}
+
+/// Internal struct, a straight piece of a segment along with the colour of its segment.
+/// The first and last pieces of a segment carry on past their ends as straight lines when measuring
+/// pseudo-distances, so the channels either side of a corner meet at the corner instead of going round it.
+struct Piece {
+    a: Point,
+    b: Point,
+    colour: u8,
+    extend_start: bool,
+    extend_end: bool,
+}
+
+/// Internal function to split a path into contours of segments, leaving out any that don't go anywhere.
+fn contours(path: &GlyphPath) -> Vec<Vec<Segment>> {
+    let mut contours = Vec::new();
+    let mut contour: Vec<Segment> = Vec::new();
+    let (mut start, mut current) = ((0.0, 0.0), (0.0, 0.0));
+    fn close(contour: &mut Vec<Segment>, current: Point, start: Point, contours: &mut Vec<Vec<Segment>>) {
+        if current != start {
+            contour.push(Segment { points: vec![current, start] });
+        }
+        if !contour.is_empty() {
+            contours.push(std::mem::take(contour));
+        }
+    }
+    for command in &path.commands {
+        match *command {
+            PathCommand::MoveTo(x, y) => {
+                close(&mut contour, current, start, &mut contours);
+                start = (x, y);
+                current = start;
+            }
+            PathCommand::LineTo(x, y) => {
+                if (x, y) != current {
+                    contour.push(Segment { points: vec![current, (x, y)] });
+                }
+                current = (x, y);
+            }
+            PathCommand::QuadTo(x1, y1, x, y) => {
+                let points = (0..=QUAD_PIECES).map(|i| {
+                    let t = i as f32 / QUAD_PIECES as f32;
+                    let u = 1.0 - t;
+                    (u * u * current.0 + 2.0 * u * t * x1 + t * t * x, u * u * current.1 + 2.0 * u * t * y1 + t * t * y)
+                }).collect();
+                contour.push(Segment { points });
+                current = (x, y);
+            }
+            PathCommand::CubicTo(x1, y1, x2, y2, x, y) => {
+                let points = (0..=CUBIC_PIECES).map(|i| {
+                    let t = i as f32 / CUBIC_PIECES as f32;
+                    let u = 1.0 - t;
+                    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                    (a * current.0 + b * x1 + c * x2 + d * x, a * current.1 + b * y1 + c * y2 + d * y)
+                }).collect();
+                contour.push(Segment { points });
+                current = (x, y);
+            }
+            PathCommand::Close => {
+                close(&mut contour, current, start, &mut contours);
+                current = start;
+            }
+        }
+    }
+    close(&mut contour, current, start, &mut contours);
+    contours
+}
+
+/// Internal function to colour the edges of a contour and split them into pieces.
+/// Smooth contours are white all the way round, a contour with one corner (a teardrop) is split into thirds
+/// so the corner still has two colours meeting at it, and otherwise the colour changes at every corner,
+/// with the last stretch picking a third colour if needed so it doesn't match the first.
+fn colour_contour(segments: &[Segment], pieces: &mut Vec<Piece>) {
+    let count = segments.len();
+    let is_corner = |a: Point, b: Point| dot(a, b) <= 0.0 || cross(a, b).abs() > CORNER_THRESHOLD;
+    let corners: Vec<usize> = (0..count)
+        .filter(|&i| is_corner(segments[(i + count - 1) % count].end_direction(), segments[i].start_direction()))
+        .collect();
+    let first = corners.first().copied().unwrap_or(0);
+    let mut segment_colours = vec![WHITE; count];
+    if corners.len() > 1 {
+        let mut corner = 0;
+        for step in 0..count {
+            let i = (first + step) % count;
+            if corner + 1 < corners.len() && i == corners[corner + 1] {
+                corner += 1;
+            }
+            segment_colours[i] = if corners.len() % 2 == 1 && corner == corners.len() - 1 {
+                MAGENTA
+            } else if corner % 2 == 0 {
+                CYAN
+            } else {
+                YELLOW
+            };
+        }
+    }
+    let total: usize = segments.iter().map(|segment| segment.points.len() - 1).sum();
+    let mut index = 0;
+    for step in 0..count {
+        let segment = &segments[(first + step) % count];
+        let last = segment.points.len() - 2;
+        for (i, pair) in segment.points.windows(2).enumerate() {
+            let colour = if corners.len() == 1 {
+                [MAGENTA, WHITE, YELLOW][index * 3 / total]
+            } else {
+                segment_colours[(first + step) % count]
+            };
+            pieces.push(Piece { a: pair[0], b: pair[1], colour, extend_start: i == 0, extend_end: i == last });
+            index += 1;
+        }
+    }
+}
+
+/// Internal function to work out a multi-channel signed distance field for `path` (in pixels relative to the pen,
+/// y going down), `width` by `height` pixels with its top left corner `left` pixels right of the pen and `top` pixels
+/// above the baseline. Three bytes per pixel, encoded the same way as a single channel field reaching `spread` pixels.
+pub(crate) fn multi_distance_field(path: &GlyphPath, left: f32, top: f32, width: usize, height: usize, spread: usize, flipped: bool) -> Vec<u8> {
+    let mut pieces = Vec::new();
+    for contour in contours(path) {
+        colour_contour(&contour, &mut pieces);
+    }
+    // which side of an edge is inside depends on which way round the outer contours go
+    let area: f32 = pieces.iter().map(|piece| cross(piece.a, piece.b)).sum();
+    let orientation = if area < 0.0 { -1.0 } else { 1.0 };
+    let mut field = Vec::with_capacity(width * height * 3);
+    for row in 0..height {
+        let row = if flipped { height - 1 - row } else { row };
+        for column in 0..width {
+            let p = (left + column as f32 + 0.5, row as f32 + 0.5 - top);
+            // per channel: distance, how far off square to the edge it is (for ties at shared endpoints), piece, where along it
+            let mut nearest = [(f32::MAX, f32::MAX, 0usize, 0.0f32); 3];
+            for (index, piece) in pieces.iter().enumerate() {
+                let ab = sub(piece.b, piece.a);
+                let t = dot(sub(p, piece.a), ab) / dot(ab, ab).max(f32::EPSILON);
+                let q = if t <= 0.0 { piece.a } else if t >= 1.0 { piece.b } else { (piece.a.0 + ab.0 * t, piece.a.1 + ab.1 * t) };
+                let distance = dot(sub(p, q), sub(p, q)).sqrt();
+                let squareness = if t > 0.0 && t < 1.0 { 0.0 } else { dot(normalize(ab), normalize(sub(p, q))).abs() };
+                for (channel, best) in nearest.iter_mut().enumerate() {
+                    if piece.colour & (1 << channel) != 0 && (distance < best.0 || (distance == best.0 && squareness < best.1)) {
+                        *best = (distance, squareness, index, t);
+                    }
+                }
+            }
+            for &(distance, _, index, t) in &nearest {
+                let value = if distance == f32::MAX {
+                    -(spread as f32)
+                } else {
+                    let piece = &pieces[index];
+                    let direction = normalize(sub(piece.b, piece.a));
+                    let sign = if cross(direction, sub(p, piece.a)) * orientation < 0.0 { -1.0 } else { 1.0 };
+                    let mut signed = distance * sign;
+                    // past the ends of an edge, use the distance to the edge carried on in a straight line
+                    let end = if t < 0.0 && piece.extend_start { Some(piece.a) } else if t > 1.0 && piece.extend_end { Some(piece.b) } else { None };
+                    if let Some(end) = end {
+                        let pseudo = cross(direction, sub(p, end)) * orientation;
+                        if pseudo.abs() <= distance {
+                            signed = pseudo;
+                        }
+                    }
+                    signed
+                };
+                field.push((128.0 + value / spread as f32 * 127.0).floor().clamp(0.0, 255.0) as u8);
+            }
+        }
+    }
+    field
+}
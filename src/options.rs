@@ -0,0 +1,586 @@
+use crate::colours::TextColour;
+#[cfg(feature = "hyphenation")]
+use crate::hyphenate::Language;
+use crate::metrics::DecorationMetrics;
+use crate::Rect;
+use std::ops::Range;
+
+/// Where the `y` coordinate given to a draw call sits relative to the drawn text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Origin {
+    /// `y` is the top of the first line of text. This is what `draw_string` has always done.
+    #[default]
+    TopLeft,
+    /// `y` is the baseline of the first line of text, like HTML canvas or Cairo.
+    /// Text of different sizes drawn at the same `y` will share a baseline.
+    Baseline,
+}
+
+/// Which part of a line of text ends up at the `x` given to `TextRenderer::draw_string_monospaced_aligned`,
+/// or how lines are lined up with each other, see `DrawOptions::align`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Spreads the words of every line out so it reaches both edges, except for the last line of every paragraph
+    /// (or a line with only one word on it), which stays on the left.
+    /// Only used by `DrawOptions::align`, it's the same as `Left` anywhere else.
+    Justify,
+}
+
+/// Where text goes up and down within `DrawOptions::max_height`, see `DrawOptions::vertical_align`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VerticalAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Which point of the text's bounds ends up at the `x`/`y` given to a draw call, see `DrawOptions::anchor`.
+/// The bounds are the measured size of the whole block of text (every line), like `measure_string_with_options` returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    TopCenter,
+    TopRight,
+    CenterLeft,
+    Center,
+    CenterRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+impl Anchor {
+    /// How far across and down the bounds the anchor point is, from 0.0 (left/top) to 1.0 (right/bottom).
+    pub fn fractions(self) -> (f32, f32) {
+        let x = match self {
+            Anchor::TopLeft | Anchor::CenterLeft | Anchor::BottomLeft => 0.0,
+            Anchor::TopCenter | Anchor::Center | Anchor::BottomCenter => 0.5,
+            Anchor::TopRight | Anchor::CenterRight | Anchor::BottomRight => 1.0,
+        };
+        let y = match self {
+            Anchor::TopLeft | Anchor::TopCenter | Anchor::TopRight => 0.0,
+            Anchor::CenterLeft | Anchor::Center | Anchor::CenterRight => 0.5,
+            Anchor::BottomLeft | Anchor::BottomCenter | Anchor::BottomRight => 1.0,
+        };
+        (x, y)
+    }
+}
+
+/// How lines are broken up when they don't fit in `DrawOptions::max_width`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapStyle {
+    /// Break lines between words (using the unicode line breaking rules).
+    /// A single word that's longer than the whole line gets broken between letters instead of overflowing.
+    #[default]
+    Word,
+    /// Break lines after whichever letter hits the edge. Good for URLs, hashes and file paths.
+    Letter,
+}
+
+/// What a `DrawOptions::line_breaks` callback says about breaking a line between two characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakOpportunity {
+    /// The line can be broken here if it needs to be.
+    Allowed,
+    /// The line is never broken here, even if that means it ends up wider than `max_width`.
+    Forbidden,
+    /// The line is always broken here, like after a newline.
+    Mandatory,
+}
+
+/// A function that decides whether a line can be broken between `prev` and `next`.
+/// Returning `None` leaves it up to the normal rules for the `WrapStyle`.
+pub type LineBreakFn = fn(prev: char, next: char) -> Option<BreakOpportunity>;
+
+/// How `TextRenderer::wrap_string` breaks lines. These mean the same as the matching fields of `DrawOptions`.
+#[derive(Debug, Clone, Copy)]
+pub struct WrapOptions {
+    /// How lines get broken up when they're too long.
+    pub style: WrapStyle,
+    /// Whether newline characters start a new line. Defaults to `true`.
+    pub hard_breaks: bool,
+    /// Extra line breaking rules, see `DrawOptions::line_breaks`.
+    pub line_breaks: Option<LineBreakFn>,
+    /// Which language to hyphenate words in, see `DrawOptions::hyphenation`.
+    #[cfg(feature = "hyphenation")]
+    pub hyphenation: Option<Language>,
+}
+
+impl Default for WrapOptions {
+    fn default() -> Self {
+        Self {
+            style: WrapStyle::default(),
+            hard_breaks: true,
+            line_breaks: None,
+            #[cfg(feature = "hyphenation")]
+            hyphenation: None,
+        }
+    }
+}
+
+/// Which way the y axis of the surface points, set with `TextRendererBuilder::coordinate_system`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CoordinateSystem {
+    /// (0, 0) is the top left of the surface, and glyph bitmaps start with their top row.
+    #[default]
+    PositiveYDown,
+    /// (0, 0) is the bottom left of the surface (like OpenGL), and glyph bitmaps start with their bottom row.
+    /// Text is laid out exactly the same, just mirrored: the `y` given to a draw call is still where the top of the text
+    /// (or its baseline, with `Origin::Baseline`) goes, but the text goes down towards smaller y from there,
+    /// and `paste` is given the bottom left corner of each glyph. Clip rects, `DrawnBounds` and `PreparedText` rects
+    /// are all in the same coordinates, with a rect's `y` being its bottom edge.
+    /// Glyph outlines and SVG output don't touch the surface, so they're always y-down.
+    PositiveYUp,
+}
+
+/// How glyphs are kept in the glyph cache, set with `TextRendererBuilder::glyph_cache_format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GlyphCacheFormat {
+    /// Every glyph is cached as a `StoreSurface` that's already the right colour, once for every colour it's drawn in.
+    /// Pasting is as cheap as it gets, but drawing in lots of colours means lots of copies of every glyph.
+    #[default]
+    Rgba,
+    /// Every glyph is cached once as one byte of coverage per pixel, whatever colour it's drawn in,
+    /// and the colour is only applied when it's pasted (see `PasteSurface::paste_mask`).
+    /// Uses a quarter of the memory for single colour text (and much less than that for multi colour text),
+    /// at the cost of colouring the glyph every time it's drawn.
+    Alpha,
+    /// Like `Alpha`, but glyphs are rasterized at three times the horizontal resolution and cached as three bytes of
+    /// coverage per pixel, one for each of the red, green and blue subpixels (see `TextRendererBuilder::subpixel_order`).
+    /// They're pasted with `PasteSurface::paste_lcd`, which has to blend every channel with its own coverage for this to
+    /// look any sharper. Only worth it on low DPI LCD screens drawn to directly: anywhere else (or scaled, rotated,
+    /// or onto a see-through surface) the edges of glyphs look coloured. Outlined and blurred text is drawn like `Alpha`,
+    /// and glyphs snap to whole pixels whatever `TextRendererBuilder::subpixel_positions` is.
+    Lcd,
+}
+
+/// The order of the subpixels of each pixel on the screen, from left to right, for `GlyphCacheFormat::Lcd`.
+/// Set it with `TextRendererBuilder::subpixel_order`, most screens are `Rgb`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SubpixelOrder {
+    #[default]
+    Rgb,
+    Bgr,
+}
+
+/// Whether the glyph cache keeps the raw RGBA bytes every glyph's `StoreSurface` was made from,
+/// set with `TextRendererBuilder::cache_policy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CachePolicy {
+    /// Keep the raw bytes alive alongside the surface, for backends whose `StoreSurface` borrows them
+    /// (like SDL2 surfaces made with `from_data`). This is what the cache has always done.
+    #[default]
+    KeepRawBytes,
+    /// Only keep the `StoreSurface`, for backends that copy the bytes (or upload them somewhere) when they're made.
+    /// Roughly halves how much memory the glyph cache takes up.
+    SurfaceOnly,
+}
+
+/// Which way lines of text run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    /// Left to right lines, stacked top to bottom.
+    #[default]
+    Horizontal,
+    /// Top to bottom columns, stacked right to left, like traditional CJK text.
+    /// Characters are advanced by the font's vertical metrics if it has them, or the line height if it doesn't.
+    /// Latin characters are stacked upright rather than rotated.
+    /// In this mode `max_height` wraps columns (like `max_width` wraps lines), `max_lines` limits the number of columns,
+    /// `max_width` and `ellipsis` aren't used, and `origin` is always treated as `Origin::TopLeft`.
+    Vertical,
+}
+
+/// Changes the case of text as it's laid out, see `DrawOptions::text_transform`.
+/// Characters are mapped one at a time with Rust's Unicode case mappings, so nothing locale specific happens
+/// (a Turkish "i" still turns into "I", not "İ") and a final sigma doesn't get its own lowercase form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextTransform {
+    /// The text is drawn as it is.
+    #[default]
+    None,
+    Uppercase,
+    Lowercase,
+    /// Lowercase letters are drawn as capitals shrunk down by `DrawOptions::small_caps_scale`,
+    /// sitting on the same baseline as the full size capitals around them.
+    SmallCaps,
+}
+
+/// How glyphs are combined with whatever is already on the surface. Passed to `PasteSurface::paste_blended`,
+/// and `sext::blit` has software implementations of all of them for plain RGBA buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BlendMode {
+    /// Glyph pixels overwrite the surface, including the transparent ones around the edges of the glyph.
+    Replace,
+    /// Glyphs are composited over the surface using their alpha, so overlapping glyphs and text drawn over
+    /// other things look right. This is what text should look like, so it's the default.
+    #[default]
+    AlphaOver,
+    /// Glyph colours are added onto the surface.
+    Additive,
+    /// The surface is multiplied by the glyph colours, which only ever darkens it. Good for tinted watermarks.
+    Multiply,
+    /// The inverse of the surface is multiplied by the inverse of the glyph colours, which only ever lightens it.
+    Screen,
+}
+
+/// How the inside of glyphs is drawn, see `DrawOptions::fill_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FillMode {
+    /// Glyphs are filled in like normal.
+    #[default]
+    Solid,
+    /// Glyphs are hollow: only a ring `thickness` pixels thick around the inside of their edges is drawn.
+    /// Glyphs don't get any bigger, so this lays out exactly like solid text. Parts of a glyph thinner than
+    /// twice the thickness have no inside to hollow out, so they stay solid.
+    Outline { thickness: f32 },
+    /// Same as `Outline`, but `thickness` is a fraction of the size of the text (so `0.05` is 2.4 pixels at 48px),
+    /// for outlines that keep their weight whatever size the text is drawn at.
+    OutlineRelative { thickness: f32 },
+}
+
+impl FillMode {
+    /// How many pixels thick the outline is for text at `size` pixels, or `None` for `FillMode::Solid`.
+    /// Outlines are never thinner than one pixel, so they don't disappear at small sizes.
+    pub fn outline_thickness(&self, size: f32) -> Option<f32> {
+        match *self {
+            FillMode::Solid => None,
+            FillMode::Outline { thickness } => Some(thickness.max(1.0)),
+            FillMode::OutlineRelative { thickness } => Some((thickness * size).max(1.0)),
+        }
+    }
+}
+
+/// Part of the string to draw as selected, see `DrawOptions::selection`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    /// The bytes of the string that are selected. Glyphs are selected if the character they were made from starts in here.
+    pub range: Range<usize>,
+    /// Filled behind the selected glyphs, from the pen position before the first one to after the advance of the last one
+    /// (so selected spaces are highlighted too), the full height of the line.
+    pub background: TextColour,
+    /// If set, selected glyphs are drawn in this colour instead of their normal one.
+    pub foreground: Option<TextColour>,
+}
+
+/// What an `Underline` looks like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnderlineStyle {
+    /// A plain line.
+    #[default]
+    Solid,
+    /// Square dots with gaps as wide as the dots.
+    Dotted,
+    /// Dashes with short gaps between them.
+    Dashed,
+    /// A wavy line, like the squiggle spellcheckers put under misspelled words.
+    Wavy,
+    /// Two thin lines.
+    Double,
+}
+
+/// A line drawn under part of the string, see `DrawOptions::underlines`.
+/// How thick the line is and how far below the baseline it goes come from `TextRenderer::decoration_metrics`
+/// (or `DrawOptions::decoration_metrics`), and how big the pattern of the patterned styles is scales with the font size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Underline {
+    /// The bytes of the string to underline. Glyphs are underlined if the character they were made from starts in here,
+    /// and the line goes from the pen position before the first one to after the advance of the last one on each line.
+    pub range: Range<usize>,
+    pub style: UnderlineStyle,
+    /// The colour of the line, which doesn't have to be the colour of the text.
+    pub colour: TextColour,
+}
+
+/// Where tabs move the pen to, see `DrawOptions::tab_stops`. Stops are measured from the left of the line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TabStops {
+    /// A stop every this many spaces (as wide as a space in the tab's font and size), like a terminal or code editor.
+    Spaces(u32),
+    /// A stop every this many pixels.
+    Every(f32),
+    /// Stops at these pixel columns, smallest first. Tabs past the last one are as wide as a space.
+    Columns(Vec<f32>),
+}
+
+impl TabStops {
+    /// Where the first stop after `pen` is, given how wide a space is. A tab right on a stop goes to the next one.
+    pub fn next_stop(&self, pen: f32, space: f32) -> f32 {
+        let every = |interval: f32| if interval > 0.0 { ((pen / interval).floor() + 1.0) * interval } else { pen + space };
+        match self {
+            TabStops::Spaces(spaces) => every(*spaces as f32 * space),
+            TabStops::Every(interval) => every(*interval),
+            TabStops::Columns(columns) => columns.iter().copied().find(|column| *column > pen).unwrap_or(pen + space),
+        }
+    }
+}
+
+/// Markers drawn over whitespace so it can be seen, like the "show invisibles" toggle of a text editor.
+/// See `DrawOptions::show_whitespace`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShowWhitespace {
+    /// Drawn over spaces (and any other whitespace that isn't a tab or newline). Defaults to a centered dot (U+00B7).
+    pub space: char,
+    /// Drawn where tabs are. Defaults to an arrow (U+2192).
+    pub tab: char,
+    /// Drawn at the end of lines that end with a newline. Defaults to a pilcrow (U+00B6).
+    /// Lines that were only wrapped because they were too long don't get one.
+    pub newline: char,
+    /// The colour of the markers. `None` (the default) uses the colour of the text at 40% alpha.
+    pub colour: Option<TextColour>,
+}
+
+impl Default for ShowWhitespace {
+    fn default() -> Self {
+        Self {
+            space: '\u{b7}',
+            tab: '\u{2192}',
+            newline: '\u{b6}',
+            colour: None,
+        }
+    }
+}
+
+/// How wide the rects given to the hook of `TextRenderer::draw_string_with_line_hook` are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineExtent {
+    /// From the left of the text to `DrawOptions::max_width`, or to the right of the widest line if there isn't one,
+    /// so every line's rect is the same width.
+    #[default]
+    MaxWidth,
+    /// Only as wide as the line itself (see `LineInfo::width`), so an empty line gets an empty rect.
+    Line,
+}
+
+/// Where text drawn with `TextRenderer::draw_string_on_path` sits compared to the path.
+/// "Above" is to the left of the direction the path goes in, so text on a circle drawn clockwise is on the outside of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathPlacement {
+    /// The baseline is on the path, so the text sits on top of it.
+    #[default]
+    Above,
+    /// The path goes through the middle of the text, halfway between the ascent and descent.
+    Centered,
+    /// The top of the text (its ascent) is on the path, so the text hangs underneath it.
+    Below,
+}
+
+/// What happens to glyphs of text drawn with `TextRenderer::draw_string_on_path` that don't fit on the path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathOverflow {
+    /// Glyphs past either end of the path aren't drawn.
+    #[default]
+    Drop,
+    /// Glyphs past either end of the path carry on in a straight line from the end they went past.
+    Extend,
+}
+
+/// Settings for `TextRenderer::draw_string_on_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PathTextOptions {
+    pub placement: PathPlacement,
+    /// How many pixels along the path the start of the text is.
+    pub start_offset: f32,
+    pub overflow: PathOverflow,
+    /// How glyphs are blended onto the surface.
+    pub blend_mode: BlendMode,
+}
+
+/// Where and how a single glyph gets drawn, passed to (and returned from) the callback of `TextRenderer::draw_string_fx`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlyphDrawParams {
+    /// Where the left edge of the glyph's bitmap goes.
+    pub x: f32,
+    /// Where the top edge of the glyph's bitmap goes, in the same coordinates as the `y` given to the draw call
+    /// (so for `CoordinateSystem::PositiveYUp`, bigger is higher up).
+    pub y: f32,
+    /// Multiplied into the alpha of the colour, from 0.0 (not drawn at all) to 1.0 (drawn normally).
+    /// This works like `DrawOptions::opacity`, so it doesn't touch the glyph cache.
+    pub alpha: f32,
+}
+
+/// Extra settings for the `_with_options` family of draw calls.
+/// Everything defaults to the behaviour of the plain draw calls, so you can just set what you need:
+/// `DrawOptions { origin: Origin::Baseline, ..Default::default() }`
+#[derive(Debug, Clone)]
+pub struct DrawOptions {
+    /// What the `x`/`y` coordinates of the draw call refer to.
+    pub origin: Origin,
+    /// Which point of the text's bounds goes at `x`/`y`, so you don't have to measure the text first to
+    /// right align or center it. This is added on top of `origin`, so you'd normally only change one of them.
+    pub anchor: Anchor,
+    /// If set, nothing will be drawn outside of this rect. Glyphs that are partially inside of it are cut off
+    /// and passed to `PasteSurface::paste_clipped`.
+    pub clip: Option<Rect>,
+    /// How glyphs are blended onto the surface.
+    pub blend_mode: BlendMode,
+    /// How see-through everything drawn is, from 0.0 (invisible, nothing is pasted at all) to 1.0 (the default).
+    /// This is multiplied into the alpha of every glyph as it's pasted (see `PasteSurface::paste_mask`) rather than
+    /// being baked into the glyph cache, so fading text in and out every frame doesn't fill the cache with copies of it.
+    pub opacity: f32,
+    /// Whether glyphs are filled in or hollow. Hollow glyphs are cached separately for every thickness they're drawn at.
+    /// Custom glyphs (see `TextRenderer::set_glyph_provider`) and whitespace markers are always drawn as they are.
+    pub fill_mode: FillMode,
+    /// If set, glyphs are blurred by this many pixels, for glows and soft shadows: draw the text blurred in the glow colour,
+    /// then draw it again sharp on top. Blurred glyphs are bigger than sharp ones by the radius on every side so none of
+    /// the blur gets cut off, but they stay centred on where the sharp glyph would be, and the layout doesn't change at all.
+    /// Every radius gets its own copy of every glyph in the glyph cache, so don't animate it.
+    pub blur: Option<f32>,
+    /// Whether text is laid out in horizontal lines or vertical columns.
+    pub direction: Direction,
+    /// If set, lines longer than this many pixels get wrapped onto the next line.
+    pub max_width: Option<f32>,
+    /// How every line is lined up within the text: within `max_width` if it's set, or else within the widest line.
+    /// If `max_width` is set and this isn't `Align::Left`, the text counts as being `max_width` wide when it's measured
+    /// (and for `anchor`). Doesn't apply to `Direction::Vertical` text.
+    pub align: Align,
+    /// How lines get broken up when they're longer than `max_width`.
+    pub wrap_style: WrapStyle,
+    /// Whether newline characters start a new line. Defaults to `true`.
+    pub wrap_hard_breaks: bool,
+    /// If set, this is asked about every pair of neighbouring characters before the normal rules are,
+    /// so you can add break opportunities (like after the dots in `com.example.AccountManager`), forbid them
+    /// (like before closing punctuation in CJK text) or force them. Words that don't fit on a line at all are
+    /// still broken between letters, unless the callback returned `BreakOpportunity::Forbidden` there.
+    /// Soft hyphens (U+00AD) in the text are always break opportunities: they take up no space and aren't drawn,
+    /// unless the line is broken at one, in which case a hyphen is drawn at the end of the line.
+    /// Neither of these apply to `Direction::Vertical` text.
+    pub line_breaks: Option<LineBreakFn>,
+    /// If set (and `max_width` is too), words can be hyphenated where this language's patterns allow it, with a hyphen
+    /// drawn at the end of the line. A word is only split if breaking at the last space before it would leave the line
+    /// less than three quarters full, so most lines still end between words. The hyphens aren't part of the string:
+    /// they get an empty byte range in `PreparedText`, and line byte ranges still cover the string with no gaps.
+    /// Doesn't apply to `Direction::Vertical` text. Needs the `hyphenation` feature.
+    #[cfg(feature = "hyphenation")]
+    pub hyphenation: Option<Language>,
+    /// If set, every line is this many pixels tall (from one baseline to the next) instead of the height the font
+    /// recommends (see `TextRenderer::line_height`), for text that's spaced out more or packed tighter.
+    /// Each line's glyphs stay where they were relative to its baseline. Doesn't apply to `Direction::Vertical` text.
+    /// Anything below `0.0` is treated as `0.0` (every line on top of each other), and NaN is treated as `None`.
+    pub line_spacing: Option<f32>,
+    /// How tall every line is compared to the height the font recommends, so `1.5` gives one and a half times the usual
+    /// leading and `2.0` double spaces the text. Lines are rounded to whole pixels. `line_spacing` wins if it's set too.
+    /// Defaults to `1.0`. Doesn't apply to `Direction::Vertical` text.
+    /// Lines can't end up less than 0 pixels tall, so anything below `0.0` works like `0.0`, and NaN works like `1.0`.
+    pub line_height_scale: f32,
+    /// If set, only this many lines are drawn.
+    pub max_lines: Option<usize>,
+    /// If set, only lines that fit completely within this many pixels (from the top of the text) are drawn.
+    /// If not even one line fits, nothing is drawn.
+    pub max_height: Option<f32>,
+    /// Where the lines go within `max_height`, if it's set (and with `max_height` not set, this doesn't do anything).
+    /// Like with `align`, if this isn't `VerticalAlign::Top` the text counts as being `max_height` tall when it's measured.
+    /// `Origin::Baseline` still refers to where the first line's baseline would be if the text was at the top.
+    /// Doesn't apply to `Direction::Vertical` text.
+    pub vertical_align: VerticalAlign,
+    /// If `true` and some lines got cut off by `max_lines`/`max_height`, the last drawn line ends with "…"
+    /// (or "..." if the font doesn't have "…"). Characters are removed from the end of the line so it
+    /// still fits within `max_width`.
+    pub ellipsis: bool,
+    /// If set, this part of the string is drawn as selected, with one background rect for each line it's on.
+    /// An empty range doesn't draw anything. Selections aren't drawn for `Direction::Vertical` text.
+    pub selection: Option<Selection>,
+    /// Lines to draw under parts of the string, drawn after the selection and before the glyphs.
+    /// Underlines aren't drawn for `Direction::Vertical` text.
+    pub underlines: Vec<Underline>,
+    /// If set, underlines use these instead of the font's own `TextRenderer::decoration_metrics`.
+    /// They're used as they are for every underline, whatever size its text is.
+    pub decoration_metrics: Option<DecorationMetrics>,
+    /// If set, whitespace is drawn with a marker on top of it. Markers don't change the layout at all, they're drawn
+    /// centered over the whitespace they stand for (or right where it is, for tabs and newlines that don't take up any room).
+    /// If the font doesn't have one of the marker characters, `.`, `>` or `$` are drawn instead.
+    /// Markers aren't drawn for `Direction::Vertical` text.
+    pub show_whitespace: Option<ShowWhitespace>,
+    /// How big superscript and subscript spans are compared to the text they're in. Defaults to `0.65`.
+    pub script_scale: f32,
+    /// Changes the case of the text before it's laid out, so the string you pass in doesn't have to be.
+    /// Byte offsets (in metrics, selections, underlines and hit testing) still point into the string you passed in,
+    /// and measuring gives the size of the transformed text.
+    pub text_transform: TextTransform,
+    /// How big the shrunk capitals of `TextTransform::SmallCaps` are compared to the text they're in. Defaults to `0.75`.
+    pub small_caps_scale: f32,
+    /// Extra space in pixels added after every character, so positive values spread text out and negative ones squeeze it
+    /// together. It's added to the character's advance (so measuring, wrapping and selections all take it into account),
+    /// the glyphs themselves aren't changed and don't get cached again. Rounding this to whole pixels keeps glyphs sharp.
+    /// Doesn't apply to `Direction::Vertical` text.
+    pub letter_spacing: f32,
+    /// Extra space in pixels added after every space (and no-break space), on top of `letter_spacing`. Positive values spread
+    /// words out and negative ones bring them closer together, without changing the spacing within words.
+    /// Doesn't apply to `Direction::Vertical` text.
+    pub word_spacing: f32,
+    /// If set, tabs move the pen to the next tab stop, so the text after them lines up in columns. Otherwise tabs take up no room at all.
+    /// Stops are measured from the start of the line the tab is on, which for wrapped lines is the start of the line before it
+    /// was wrapped (after the last newline), so code listings should be drawn without a `max_width`.
+    /// Doesn't apply to `Direction::Vertical` text.
+    pub tab_stops: Option<TabStops>,
+    /// If `true`, every ASCII digit takes up as much room as the widest one in its font at its size, with the narrower ones
+    /// centred in that space, so columns of numbers line up and don't wiggle as they change. Everything else keeps its own width.
+    /// Measuring takes this into account too. Doesn't apply to `Direction::Vertical` text.
+    pub tabular_numbers: bool,
+}
+
+impl Default for DrawOptions {
+    fn default() -> Self {
+        Self {
+            origin: Origin::default(),
+            anchor: Anchor::default(),
+            clip: None,
+            blend_mode: BlendMode::default(),
+            opacity: 1.0,
+            fill_mode: FillMode::default(),
+            blur: None,
+            direction: Direction::default(),
+            max_width: None,
+            align: Align::default(),
+            wrap_style: WrapStyle::default(),
+            wrap_hard_breaks: true,
+            line_breaks: None,
+            #[cfg(feature = "hyphenation")]
+            hyphenation: None,
+            line_spacing: None,
+            line_height_scale: 1.0,
+            max_lines: None,
+            max_height: None,
+            vertical_align: VerticalAlign::default(),
+            ellipsis: false,
+            selection: None,
+            underlines: Vec::new(),
+            decoration_metrics: None,
+            show_whitespace: None,
+            script_scale: 0.65,
+            text_transform: TextTransform::default(),
+            small_caps_scale: 0.75,
+            letter_spacing: 0.0,
+            word_spacing: 0.0,
+            tab_stops: None,
+            tabular_numbers: false,
+        }
+    }
+}
+
+/// Settings for how the font itself is loaded, passed to `TextRendererBuilder`.
+/// These can't be changed after the renderer is created, so the glyph cache never has to worry about them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontOptions {
+    /// The pixel size the font's outlines are optimized for. Sizes close to this rasterize best.
+    /// Defaults to `40.0`, so if you're mostly drawing small UI text you might want to lower it.
+    pub scale: f32,
+    /// Which font to use if the file is a font collection (.ttc). Defaults to `0`.
+    /// Loading fails with `TextRendererError::InvalidCollectionIndex` if the file doesn't have a font at this index
+    /// (anything but `0` for files that aren't collections).
+    pub collection_index: u32,
+}
+
+impl Default for FontOptions {
+    fn default() -> Self {
+        Self {
+            scale: 40.0,
+            collection_index: 0,
+        }
+    }
+}
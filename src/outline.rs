@@ -0,0 +1,163 @@
+//! Glyph outlines as vector paths, enabled with the `ttf-parser` feature.
+//! fontdue only gives us bitmaps, so the outlines are read straight from the font with `ttf-parser`.
+//! Composite glyphs (like accented letters built out of several pieces) come out with their pieces already in place.
+
+use crate::custom_glyphs::CUSTOM_FONT;
+use crate::layout::Glyph;
+use crate::options::DrawOptions;
+use crate::{PasteSurface, StoreSurface, TextRenderer};
+use ttf_parser::{Face, GlyphId, OutlineBuilder};
+
+/// A single drawing command of a `GlyphPath`. Coordinates are in pixels, with y going down like everywhere else.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    /// Starts a new contour at (x, y).
+    MoveTo(f32, f32),
+    /// A straight line to (x, y).
+    LineTo(f32, f32),
+    /// A quadratic bézier curve with control point (x1, y1), ending at (x, y).
+    QuadTo(f32, f32, f32, f32),
+    /// A cubic bézier curve with control points (x1, y1) and (x2, y2), ending at (x, y).
+    CubicTo(f32, f32, f32, f32, f32, f32),
+    /// Closes the current contour with a line back to where it started.
+    Close,
+}
+
+/// The outline of a glyph, returned by `TextRenderer::glyph_outline`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GlyphPath {
+    pub commands: Vec<PathCommand>,
+}
+
+impl GlyphPath {
+    /// Returns the same path moved over by (`dx`, `dy`).
+    pub fn translated(&self, dx: f32, dy: f32) -> GlyphPath {
+        let commands = self.commands.iter().map(|command| match *command {
+            PathCommand::MoveTo(x, y) => PathCommand::MoveTo(x + dx, y + dy),
+            PathCommand::LineTo(x, y) => PathCommand::LineTo(x + dx, y + dy),
+            PathCommand::QuadTo(x1, y1, x, y) => PathCommand::QuadTo(x1 + dx, y1 + dy, x + dx, y + dy),
+            PathCommand::CubicTo(x1, y1, x2, y2, x, y) => PathCommand::CubicTo(x1 + dx, y1 + dy, x2 + dx, y2 + dy, x + dx, y + dy),
+            PathCommand::Close => PathCommand::Close,
+        }).collect();
+        GlyphPath { commands }
+    }
+
+    /// Returns the (min x, min y, max x, max y) of all of the points in the path (including control points),
+    /// or `None` if the path is empty.
+    pub fn bounds(&self) -> Option<(f32, f32, f32, f32)> {
+        let points = self.commands.iter().flat_map(|command| match *command {
+            PathCommand::MoveTo(x, y) | PathCommand::LineTo(x, y) => vec![(x, y)],
+            PathCommand::QuadTo(x1, y1, x, y) => vec![(x1, y1), (x, y)],
+            PathCommand::CubicTo(x1, y1, x2, y2, x, y) => vec![(x1, y1), (x2, y2), (x, y)],
+            PathCommand::Close => vec![],
+        });
+        points.fold(None, |bounds, (x, y)| match bounds {
+            None => Some((x, y, x, y)),
+            Some((min_x, min_y, max_x, max_y)) => Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))),
+        })
+    }
+}
+
+/// A glyph's outline moved to where it ends up in a laid out string, returned by `TextRenderer::string_outlines`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutlinedGlyph {
+    /// The byte offset of the character this glyph was made from.
+    pub byte_offset: usize,
+    /// The character this glyph was made from.
+    pub c: char,
+    pub path: GlyphPath,
+}
+
+/// Internal struct that collects `ttf-parser`'s outline callbacks into a `GlyphPath`, scaling and flipping as it goes.
+struct PathBuilder {
+    scale: f32,
+    path: GlyphPath,
+}
+
+impl PathBuilder {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        (x * self.scale, -y * self.scale)
+    }
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.path.commands.push(PathCommand::MoveTo(x, y));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.path.commands.push(PathCommand::LineTo(x, y));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let ((x1, y1), (x, y)) = (self.point(x1, y1), self.point(x, y));
+        self.path.commands.push(PathCommand::QuadTo(x1, y1, x, y));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ((x1, y1), (x2, y2), (x, y)) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.path.commands.push(PathCommand::CubicTo(x1, y1, x2, y2, x, y));
+    }
+
+    fn close(&mut self) {
+        self.path.commands.push(PathCommand::Close);
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Returns the outline of a single character at the given pixel size, relative to the pen position on the baseline.
+    /// Returns `None` if the font doesn't have a glyph for the character, and an empty path for glyphs
+    /// that don't draw anything (like spaces).
+    pub fn glyph_outline(&self, c: char, size: f32) -> Option<GlyphPath> {
+        let glyph_index = self.font.lookup_glyph_index(c);
+        if glyph_index == 0 {
+            return None;
+        }
+        self.glyph_outline_indexed(0, glyph_index, size)
+    }
+
+    /// Lays out a string the same way `draw_string_with_options` would and returns the outline of every glyph
+    /// that draws something, already moved to where it would be drawn.
+    /// Glyphs are placed exactly where their bitmaps would go, so the outlines line up with what's drawn.
+    pub fn string_outlines(&self, string: &str, x: f32, y: f32, size: f32, options: &DrawOptions) -> Vec<OutlinedGlyph> {
+        let text = self.layout_text(string, size, options);
+        let (dx, dy) = text.origin_offset(options);
+        let (x, y) = (x + dx, y + dy);
+        text.glyphs.iter().filter_map(|glyph| {
+            Some(OutlinedGlyph {
+                byte_offset: glyph.byte_offset,
+                c: glyph.parent,
+                path: self.laid_out_glyph_outline(glyph, x, y)?,
+            })
+        }).collect()
+    }
+
+    /// Internal function to get the outline of a laid out glyph, moved to where its bitmap would be drawn
+    /// if the text's top left was at (`x`, `y`). Returns `None` for glyphs that don't draw anything,
+    /// and for custom glyphs (see `set_glyph_provider`) which don't have an outline.
+    pub(crate) fn laid_out_glyph_outline(&self, glyph: &Glyph, x: f32, y: f32) -> Option<GlyphPath> {
+        if glyph.char_data.is_whitespace() || glyph.char_data.is_control() || glyph.font_index == CUSTOM_FONT {
+            return None;
+        }
+        let metrics = self.font_at(glyph.font_index).metrics_indexed(glyph.key.glyph_index, glyph.key.px);
+        let path = self.glyph_outline_indexed(glyph.font_index, glyph.key.glyph_index, glyph.key.px)?;
+        let pen_x = x + glyph.x - metrics.bounds.xmin;
+        let baseline = y + glyph.y + metrics.bounds.height + metrics.bounds.ymin;
+        Some(path.translated(pen_x, baseline))
+    }
+
+    /// Internal function to get the outline of a glyph by its index in one of the renderer's fonts.
+    pub(crate) fn glyph_outline_indexed(&self, font_index: usize, glyph_index: u16, size: f32) -> Option<GlyphPath> {
+        let (font_data, collection_index) = self.font_data_at(font_index);
+        let face = Face::parse(font_data, collection_index).ok()?;
+        let mut builder = PathBuilder {
+            scale: size / face.units_per_em() as f32,
+            path: GlyphPath::default(),
+        };
+        // glyphs without an outline (spaces) are fine, they just have an empty path
+        face.outline_glyph(GlyphId(glyph_index), &mut builder);
+        Some(builder.path)
+    }
+}
@@ -0,0 +1,260 @@
+//! Drawing text along a path, like the lettering around the edge of a badge.
+//! Paths are flattened into short straight pieces as they're built, so finding the point (and direction) some distance
+//! along one is just a search through the lengths of the pieces.
+//! Glyphs are rotated to follow the path, and every rotation of every glyph is cached as a coverage mask
+//! (in steps of 1/360 of a turn) so drawing the same badge again doesn't rotate anything.
+
+use crate::colours::TextColour;
+use crate::custom_glyphs::CUSTOM_FONT;
+use crate::options::{CoordinateSystem, DrawOptions, PathOverflow, PathPlacement, PathTextOptions};
+use crate::{adjust_coverage, rasterize_mask, CacheKey, GlyphCache, GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer};
+use fontdue::layout::GlyphRasterConfig;
+use std::f32::consts::TAU;
+use std::sync::{Arc, PoisonError};
+use std::time::Instant;
+
+/// How many different angles a glyph can be rotated to.
+const ROTATION_STEPS: u16 = 360;
+
+/// A path for `TextRenderer::draw_string_on_path` to put text along, made of straight lines, cubic bézier curves and circular arcs.
+/// Coordinates are in pixels, the same as the `x` and `y` of every other draw call, and angles are in radians going from
+/// the positive x axis towards the positive y axis (so clockwise on a surface with y going down).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path {
+    points: Vec<(f32, f32)>,
+    /// How far along the path every point is.
+    distances: Vec<f32>,
+}
+
+impl Path {
+    /// Starts a path at (`x`, `y`).
+    pub fn new(x: f32, y: f32) -> Self {
+        Path { points: vec![(x, y)], distances: vec![0.0] }
+    }
+
+    /// A path going through every one of `points` in a straight line.
+    pub fn polyline(points: &[(f32, f32)]) -> Self {
+        let mut path = Path::default();
+        for (x, y) in points {
+            path.push(*x, *y);
+        }
+        path
+    }
+
+    /// A path around part of a circle centered on (`cx`, `cy`), from `start_angle` to `end_angle`.
+    /// If `end_angle` is smaller than `start_angle`, the arc goes the other way around.
+    pub fn arc(cx: f32, cy: f32, radius: f32, start_angle: f32, end_angle: f32) -> Self {
+        Path::default().arc_to(cx, cy, radius, start_angle, end_angle)
+    }
+
+    /// Adds a straight line from the end of the path to (`x`, `y`).
+    pub fn line_to(mut self, x: f32, y: f32) -> Self {
+        self.push(x, y);
+        self
+    }
+
+    /// Adds a cubic bézier curve from the end of the path to (`x`, `y`), with control points (`x1`, `y1`) and (`x2`, `y2`).
+    pub fn cubic_to(mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) -> Self {
+        let Some(&(x0, y0)) = self.points.last() else { return self.line_to(x, y) };
+        // the curve is never longer than its control points, so this keeps every piece at most about 2 pixels long
+        let hull = [(x0, y0), (x1, y1), (x2, y2), (x, y)].windows(2).map(|pair| distance(pair[0], pair[1])).sum::<f32>();
+        let pieces = ((hull / 2.0).ceil() as usize).clamp(4, 1024);
+        for i in 1..=pieces {
+            let t = i as f32 / pieces as f32;
+            let u = 1.0 - t;
+            let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+            self.push(a * x0 + b * x1 + c * x2 + d * x, a * y0 + b * y1 + c * y2 + d * y);
+        }
+        self
+    }
+
+    /// Adds part of a circle, the same as `Path::arc`. If the path doesn't end where the arc starts, a straight line joins them.
+    pub fn arc_to(mut self, cx: f32, cy: f32, radius: f32, start_angle: f32, end_angle: f32) -> Self {
+        let sweep = end_angle - start_angle;
+        let pieces = ((sweep.abs() * radius.abs() / 2.0).ceil() as usize).clamp(8, 4096);
+        for i in 0..=pieces {
+            let angle = start_angle + sweep * i as f32 / pieces as f32;
+            self.push(cx + radius * angle.cos(), cy + radius * angle.sin());
+        }
+        self
+    }
+
+    /// How long the path is, in pixels.
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    /// Returns the point `distance` pixels along the path, and the angle the path is going in there.
+    /// Returns `None` if the distance is before the start or past the end of the path, or the path has no length.
+    pub fn point_at(&self, distance: f32) -> Option<(f32, f32, f32)> {
+        self.sample(distance, false)
+    }
+
+    /// Internal function to add a point onto the end of the path, skipping it if it's in the same place as the last one.
+    fn push(&mut self, x: f32, y: f32) {
+        match self.points.last() {
+            None => self.distances.push(0.0),
+            Some(&last) => {
+                let length = distance(last, (x, y));
+                if length < 1e-4 {
+                    return;
+                }
+                self.distances.push(self.length() + length);
+            }
+        }
+        self.points.push((x, y));
+    }
+
+    /// Internal function for `point_at`. If `extend` is set, distances before the start or past the end of the path
+    /// carry on in a straight line from the first or last piece of it.
+    pub(crate) fn sample(&self, distance: f32, extend: bool) -> Option<(f32, f32, f32)> {
+        if self.points.len() < 2 || (!extend && !(0.0..=self.length()).contains(&distance)) {
+            return None;
+        }
+        // the piece that `distance` is on (or the closest one to it)
+        let piece = self.distances.partition_point(|d| *d <= distance).clamp(1, self.points.len() - 1) - 1;
+        let ((x0, y0), (x1, y1)) = (self.points[piece], self.points[piece + 1]);
+        let t = (distance - self.distances[piece]) / (self.distances[piece + 1] - self.distances[piece]);
+        Some((x0 + (x1 - x0) * t, y0 + (y1 - y0) * t, (y1 - y0).atan2(x1 - x0)))
+    }
+}
+
+/// Internal function, the distance between two points.
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// Internal struct, a glyph's coverage mask after being rotated, cached in `GlyphCache::rotated_map`.
+#[derive(Debug, Clone)]
+pub(crate) struct RotatedMask {
+    /// Shared with the cache, so drawing a cached glyph doesn't copy it.
+    pub(crate) mask: Arc<[u8]>,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// Where the top left corner of the unrotated glyph ended up, relative to the top left of `mask` (with y going down).
+    origin: (f32, f32),
+}
+
+/// Internal function to rotate a coverage mask by `angle` (clockwise with y going down) around its top left corner,
+/// sampling it bilinearly so the edges stay smooth.
+fn rotate_mask(mask: &[u8], width: usize, height: usize, angle: f32) -> RotatedMask {
+    let (sin, cos) = angle.sin_cos();
+    let rotate = |x: f32, y: f32| (x * cos - y * sin, x * sin + y * cos);
+    let corners = [rotate(0.0, 0.0), rotate(width as f32, 0.0), rotate(0.0, height as f32), rotate(width as f32, height as f32)];
+    let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min).floor();
+    let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min).floor();
+    let max_x = corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max).ceil();
+    let max_y = corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max).ceil();
+    let (rotated_width, rotated_height) = ((max_x - min_x) as usize, (max_y - min_y) as usize);
+    let coverage = |x: i32, y: i32| {
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 {
+            0.0
+        } else {
+            mask[y as usize * width + x as usize] as f32
+        }
+    };
+    let mut rotated = Vec::with_capacity(rotated_width * rotated_height);
+    for y in 0..rotated_height {
+        for x in 0..rotated_width {
+            // back into the unrotated glyph, then between the centres of the 4 pixels around that point
+            let (px, py) = (min_x + x as f32 + 0.5, min_y + y as f32 + 0.5);
+            let (sx, sy) = (px * cos + py * sin - 0.5, -px * sin + py * cos - 0.5);
+            let (x0, y0) = (sx.floor(), sy.floor());
+            let (fx, fy) = (sx - x0, sy - y0);
+            let (x0, y0) = (x0 as i32, y0 as i32);
+            let top = coverage(x0, y0) * (1.0 - fx) + coverage(x0 + 1, y0) * fx;
+            let bottom = coverage(x0, y0 + 1) * (1.0 - fx) + coverage(x0 + 1, y0 + 1) * fx;
+            rotated.push((top * (1.0 - fy) + bottom * fy).round() as u8);
+        }
+    }
+    RotatedMask { mask: rotated.into(), width: rotated_width, height: rotated_height, origin: (-min_x, -min_y) }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Draws a string along a path, with every glyph centered on the point of the path that's as far along it as the middle
+    /// of the glyph is along the string, and turned to follow the direction the path goes in there.
+    /// The string is drawn on one line, so newlines don't do anything. See `PathTextOptions` for where the text sits
+    /// compared to the path and what happens to glyphs that don't fit on it.
+    /// Returns the area of the surface that was drawn over, or `None` if nothing was.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_on_path(
+        &mut self,
+        string: &str,
+        path: &Path,
+        size: f32,
+        colour: TextColour,
+        surface: &mut A,
+        options: &PathTextOptions
+    ) -> Option<Rect> {
+        let text = self.layout_text(string, size, &DrawOptions { wrap_hard_breaks: false, ..DrawOptions::default() });
+        let line = text.metrics.lines.first()?;
+        let metrics = self.line_metrics(size);
+        // how far above the baseline the path goes through the text
+        let rise = match options.placement {
+            PathPlacement::Above => 0.0,
+            PathPlacement::Centered => (metrics.ascent + metrics.descent) / 2.0,
+            PathPlacement::Below => metrics.ascent,
+        };
+        let on_path = line.baseline - rise;
+        let mut drawn: Option<Rect> = None;
+        for glyph in text.glyphs.iter() {
+            if glyph.width == 0 || glyph.height == 0 || glyph.font_index == CUSTOM_FONT {
+                continue;
+            }
+            let centre = self.pen_x(glyph) + self.glyph_advance(glyph) / 2.0;
+            let Some((x, y, angle)) = path.sample(options.start_offset + centre, options.overflow == PathOverflow::Extend) else { continue };
+            // everything from here is in y-down layout coordinates, see `layout_y`
+            let (y, angle) = (self.layout_y(y), match self.coordinate_system {
+                CoordinateSystem::PositiveYDown => angle,
+                CoordinateSystem::PositiveYUp => -angle,
+            });
+            let step = ((angle / TAU * ROTATION_STEPS as f32).round() as i32).rem_euclid(ROTATION_STEPS as i32) as u16;
+            let rotated = self.get_rotated_mask(glyph.font_index, glyph.key, glyph.width, glyph.height, step);
+            // where the glyph's top left corner goes, turned around the point on the path
+            let (sin, cos) = (step as f32 / ROTATION_STEPS as f32 * TAU).sin_cos();
+            let (dx, dy) = (glyph.x - centre, glyph.y - on_path);
+            let (left, top) = (x + dx * cos - dy * sin - rotated.origin.0, y + dx * sin + dy * cos - rotated.origin.1);
+            let dst = self.surface_rect(Rect::new(left.floor() as i32, top.floor() as i32, rotated.width, rotated.height));
+            let Some(visible) = self.visible_rect(surface, dst, None) else { continue };
+            let mask = match self.gamma {
+                Some(gamma) => {
+                    let mut adjusted = rotated.mask.to_vec();
+                    adjust_coverage(&mut adjusted, colour, gamma);
+                    adjusted.into()
+                }
+                None => rotated.mask,
+            };
+            self.paste_coverage(surface, dst, visible, &mask, rotated.width, colour, options.blend_mode);
+            self.mark_dirty(Some(visible));
+            drawn = Some(drawn.map_or(visible, |drawn| drawn.union(&visible)));
+        }
+        drawn
+    }
+
+    /// Internal function to get a glyph rotated by `step` 360ths of a turn from either the cache or the font.
+    /// For `CoordinateSystem::PositiveYUp` the mask is stored upside down, same as every other cached glyph.
+    pub(crate) fn get_rotated_mask(&mut self, font_index: usize, glyph: GlyphRasterConfig, width: usize, height: usize, step: u16) -> RotatedMask {
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = CacheKey { font: font_index, size: glyph.px.to_bits(), gamma: None, flipped, outline: None, blur: None, subpixel: 0 };
+        {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            let cached = glyph_caches.get(&key).and_then(|glyph_cache| glyph_cache.rotated_map.get(&(glyph.glyph_index, step)));
+            if let Some(cached) = cached {
+                self.metrics.cache_hits += 1;
+                return cached.clone();
+            }
+        }
+        self.metrics.cache_misses += 1;
+        let start = Instant::now();
+        let mut rotated = rotate_mask(&rasterize_mask(self.font_at(font_index), glyph, false, GlyphEffects::default()), width, height, step as f32 / ROTATION_STEPS as f32 * TAU);
+        if flipped && rotated.width > 0 {
+            rotated.mask = rotated.mask.chunks_exact(rotated.width).rev().flatten().copied().collect();
+        }
+        self.metrics.rasterizations += 1;
+        self.metrics.rasterization_time += start.elapsed();
+        let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+        let glyph_cache = glyph_caches.entry(key).or_insert_with(|| GlyphCache::new(glyph.px));
+        glyph_cache.rotated_map.insert((glyph.glyph_index, step), rotated.clone());
+        rotated
+    }
+}
@@ -0,0 +1,85 @@
+//! tiny-skia pixmaps as a backend, enabled with the `tiny-skia` feature.
+//! Glyphs are stored as `tiny_skia::Pixmap`s and drawn with `Pixmap::draw_pixmap`, so text is composited by tiny-skia
+//! itself and sits in a vector scene just like anything else drawn into it. Pixmaps are premultiplied RGBA, so build
+//! the renderer with `TextRendererBuilder::premultiplied_alpha(true)` (and leave the pixel order at `PixelOrder::Rgba`).
+
+use crate::colours::{PixelOrder, TextColour};
+use crate::options::BlendMode;
+use crate::{coloured_pixels, PasteSurface, StoreSurface};
+use tiny_skia::{IntRect, IntSize, Pixmap, PixmapPaint, Transform};
+
+/// Internal function to pick the tiny-skia blend mode for a `BlendMode`.
+fn skia_blend_mode(mode: BlendMode) -> tiny_skia::BlendMode {
+    match mode {
+        BlendMode::Replace => tiny_skia::BlendMode::Source,
+        BlendMode::AlphaOver => tiny_skia::BlendMode::SourceOver,
+        BlendMode::Additive => tiny_skia::BlendMode::Plus,
+        BlendMode::Multiply => tiny_skia::BlendMode::Multiply,
+        BlendMode::Screen => tiny_skia::BlendMode::Screen,
+    }
+}
+
+impl StoreSurface for Pixmap {
+    /// Pixmaps can't be empty, so glyphs with nothing in them (like spaces) get a single transparent pixel instead.
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        IntSize::from_wh(width as u32, height as u32)
+            .and_then(|size| Pixmap::from_vec(data.to_vec(), size))
+            .unwrap_or_else(|| Pixmap::new(1, 1).unwrap())
+    }
+}
+
+impl PasteSurface<Pixmap> for Pixmap {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Pixmap) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Pixmap) {
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn width(&self) -> Option<usize> {
+        Some(Pixmap::width(self) as usize)
+    }
+
+    fn height(&self) -> Option<usize> {
+        Some(Pixmap::height(self) as usize)
+    }
+
+    fn new_empty(width: usize, height: usize) -> Self {
+        Pixmap::new(width.max(1) as u32, height.max(1) as u32).unwrap()
+    }
+
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Pixmap, mode: BlendMode) {
+        let paint = PixmapPaint { blend_mode: skia_blend_mode(mode), ..PixmapPaint::default() };
+        let (x, y) = (dst_x as i32, dst_y as i32);
+        if src_x == 0 && src_y == 0 && width >= Pixmap::width(data) as usize && height >= Pixmap::height(data) as usize {
+            self.draw_pixmap(x, y, data.as_ref(), &paint, Transform::identity(), None);
+            return;
+        }
+        // only part of the glyph is visible, and draw_pixmap can only draw all of it
+        let visible = IntRect::from_xywh(src_x as i32, src_y as i32, width as u32, height as u32).and_then(|rect| data.clone_rect(rect));
+        if let Some(visible) = visible {
+            self.draw_pixmap(x, y, visible.as_ref(), &paint, Transform::identity(), None);
+        }
+    }
+
+    /// Pixmaps have to be premultiplied, so the mask is always coloured that way, whatever the renderer is set to.
+    fn paste_mask(
+        &mut self,
+        dst_x: usize,
+        dst_y: usize,
+        src_x: usize,
+        src_y: usize,
+        width: usize,
+        height: usize,
+        mask: &[u8],
+        mask_width: usize,
+        colour: TextColour,
+        pixel_order: PixelOrder,
+        mode: BlendMode
+    ) {
+        let data = coloured_pixels(mask, colour, pixel_order, true);
+        let bitmap = Pixmap::from_raw_mask(mask_width, mask.len() / mask_width.max(1), &data, colour);
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, &bitmap, mode);
+    }
+}
@@ -0,0 +1,172 @@
+use crate::colours::TextColour;
+use crate::metrics::TextMetrics;
+use crate::options::{BlendMode, DrawOptions};
+use crate::spans::TextSpan;
+use crate::{GlyphEffects, PasteSurface, Rect, StoreSurface, TextRenderer};
+use crate::layout::Glyph;
+use std::ops::Range;
+
+/// A string that has already been laid out by `TextRenderer::prepare_text` (or built up with a `TextLayoutBuilder`).
+/// Drawing one of these (with `TextRenderer::draw_prepared` or `TextRenderer::draw_prefix`) skips layout entirely,
+/// so every glyph ends up in exactly the same place every time you draw it.
+/// Handy for typewriter style text where you reveal a few more characters every frame.
+#[derive(Debug, Clone)]
+pub struct PreparedText {
+    pub(crate) glyphs: Vec<Glyph>,
+    pub(crate) colour: TextColour,
+    /// The colour of every run the text was laid out from, indexed by the glyphs' user data.
+    pub(crate) run_colours: Vec<TextColour>,
+    pub(crate) clip: Option<Rect>,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) opacity: f32,
+    pub(crate) effects: GlyphEffects,
+    pub(crate) metrics: TextMetrics,
+    /// Where the top of the text is on the surface.
+    pub(crate) top: f32,
+    /// The pen position before and after every glyph, on the surface.
+    pub(crate) pens: Vec<(f32, f32)>,
+    /// How many glyphs came from the string, anything after that is an ellipsis.
+    pub(crate) source_glyphs: usize,
+    /// The indices of hyphens added by `DrawOptions::hyphenation`, which aren't in the string either.
+    pub(crate) inserted_glyphs: Vec<usize>,
+    /// Whether this was prepared for `CoordinateSystem::PositiveYUp`, in which case `glyphs` and `top`
+    /// are still in y-down layout coordinates and have to be flipped on the way out.
+    pub(crate) flipped: bool,
+}
+
+impl PreparedText {
+    /// The number of glyphs that were laid out.
+    /// Keep in mind that this counts whitespace and control characters too, as they still take up a glyph slot.
+    pub fn glyph_count(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// The area of the surface that the glyph at `index` will be pasted to, or `None` if the index is out of range.
+    /// Whitespace glyphs have an empty rect.
+    pub fn glyph_bounds(&self, index: usize) -> Option<Rect> {
+        self.glyphs.get(index).map(|glyph| Rect {
+            x: glyph.x as i32,
+            y: if self.flipped { -(glyph.y as i32) - glyph.height as i32 } else { glyph.y as i32 },
+            width: glyph.width,
+            height: glyph.height,
+        })
+    }
+
+    /// The character that the glyph at `index` was made from, or `None` if the index is out of range.
+    pub fn glyph_char(&self, index: usize) -> Option<char> {
+        self.glyphs.get(index).map(|glyph| glyph.parent)
+    }
+
+    /// The bytes of the source string that the glyph at `index` was made from, or `None` if the index is out of range.
+    /// For text built with a `TextLayoutBuilder`, offsets are into all of the pushed pieces joined together.
+    /// A glyph added for an ellipsis doesn't stand for any of the string, so it gets an empty range where the string was cut off.
+    /// Same for a hyphen added by `DrawOptions::hyphenation`, which gets an empty range at the start of the next line.
+    pub fn glyph_byte_range(&self, index: usize) -> Option<Range<usize>> {
+        let glyph = self.glyphs.get(index)?;
+        if index >= self.source_glyphs || self.inserted_glyphs.contains(&index) {
+            return Some(glyph.byte_offset..glyph.byte_offset);
+        }
+        Some(glyph.byte_offset..glyph.byte_offset + glyph.parent.len_utf8())
+    }
+
+    /// The byte offset in the source string of the character that the glyph at `index` was made from,
+    /// or `None` if the index is out of range. Same as the start of `glyph_byte_range`.
+    pub fn byte_for_glyph(&self, index: usize) -> Option<usize> {
+        self.glyph_byte_range(index).map(|range| range.start)
+    }
+
+    /// The index of the glyph made from the character that `byte` is part of (it doesn't have to be the first byte of it).
+    /// Returns `None` if no glyph was made from that character, like when `byte` is past the end of the string,
+    /// the character was cut off by `max_lines`/`max_height`, or it was an unbroken soft hyphen.
+    /// Hyphens added by `DrawOptions::hyphenation` are never returned.
+    pub fn glyph_index_for_byte(&self, byte: usize) -> Option<usize> {
+        (0..self.source_glyphs.min(self.glyphs.len())).filter(|i| !self.inserted_glyphs.contains(i)).find(|&i| {
+            let glyph = &self.glyphs[i];
+            (glyph.byte_offset..glyph.byte_offset + glyph.parent.len_utf8()).contains(&byte)
+        })
+    }
+
+    /// The colour that this text will be drawn in (apart from any pieces of a `TextLayoutBuilder` that set their own).
+    pub fn colour(&self) -> TextColour {
+        self.colour
+    }
+
+    /// The size and lines of the text, same as `TextRenderer::measure_string_with_options` would give.
+    /// Like there, everything is relative to the top left of the text.
+    pub fn metrics(&self) -> &TextMetrics {
+        &self.metrics
+    }
+
+    /// Returns the byte offset of the character under (`x`, `y`) on the surface, or `None` if there isn't one there.
+    /// A character covers the full height of its line, from the pen position before it to after its advance,
+    /// so this works for spaces too. Always returns `None` for vertical text.
+    pub fn hit_test(&self, x: f32, y: f32) -> Option<usize> {
+        let (top, y) = (self.top, if self.flipped { -y } else { y });
+        let mut start = 0;
+        for line in self.metrics.lines.iter() {
+            let end = (start + line.glyph_count).min(self.glyphs.len());
+            if (top + line.y..top + line.y + line.height).contains(&y) {
+                return (start..end)
+                    .find(|&i| (self.pens[i].0..self.pens[i].1).contains(&x))
+                    .map(|i| self.glyphs[i].byte_offset);
+            }
+            start = end;
+        }
+        None
+    }
+}
+
+/// Builds up a `PreparedText` piece by piece, for when the pieces come from different places
+/// (like a chat message made of a username, the message itself and a timestamp).
+/// Start one with `TextRenderer::begin_layout`, then push pieces onto the end:
+/// `renderer.begin_layout(0.0, 0.0, 16.0, white, &DrawOptions::default()).push_styled("bob", 16.0, blue).push_str(": hi").finish()`
+/// All of the pieces are laid out together when `finish` is called, so they wrap and sit on a shared baseline
+/// just like `TextRenderer::draw_spans` would.
+pub struct TextLayoutBuilder<'a, G, A> {
+    renderer: &'a TextRenderer<G, A>,
+    x: f32,
+    y: f32,
+    size: f32,
+    colour: TextColour,
+    options: DrawOptions,
+    spans: Vec<TextSpan>,
+}
+
+impl<'a, G, A> TextLayoutBuilder<'a, G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    pub(crate) fn new(renderer: &'a TextRenderer<G, A>, x: f32, y: f32, size: f32, colour: TextColour, options: DrawOptions) -> Self {
+        Self { renderer, x, y, size, colour, options, spans: Vec::new() }
+    }
+
+    /// Adds some text in the size and colour that were given to `begin_layout`.
+    pub fn push_str(&mut self, text: &str) -> &mut Self {
+        self.push_span(TextSpan::new(text))
+    }
+
+    /// Adds some text in its own size and colour.
+    pub fn push_styled(&mut self, text: &str, size: f32, colour: TextColour) -> &mut Self {
+        self.push_span(TextSpan::new(text).with_size(size).with_colour(colour))
+    }
+
+    /// Adds any span, for things like superscripts.
+    pub fn push_span(&mut self, span: TextSpan) -> &mut Self {
+        // nothing to lay out, and fontdue skips empty runs anyway
+        if !span.text.is_empty() {
+            self.spans.push(span);
+        }
+        self
+    }
+
+    /// Starts a new line. This is a newline character, so it only works if `DrawOptions::wrap_hard_breaks` is on.
+    pub fn push_break(&mut self) -> &mut Self {
+        self.push_str("\n")
+    }
+
+    /// Lays out everything that was pushed, ready to be drawn with `TextRenderer::draw_prepared`.
+    pub fn finish(&self) -> PreparedText {
+        let renderer = self.renderer;
+        let runs = self.spans.iter().map(|span| renderer.span_run(span, self.size, &self.options)).collect::<Vec<_>>();
+        let text = renderer.layout_rich(&runs, self.size, &self.options);
+        let run_colours = self.spans.iter().map(|span| span.colour.unwrap_or(self.colour)).collect();
+        renderer.prepare_laid_out(&text, self.x, self.y, self.colour, run_colours, &self.options)
+    }
+}
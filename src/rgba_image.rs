@@ -0,0 +1,66 @@
+//! `image::RgbaImage` as a backend, enabled with the `image` feature.
+//! With `TextRenderer<RgbaImage, RgbaImage>` text can be drawn straight into an image (or rendered into a new one with
+//! `render_to_surface`) and then saved with `RgbaImage::save`, which is handy for command line tools and tests.
+//! Images are plain RGBA, so leave the pixel order at the default `PixelOrder::Rgba`.
+
+use crate::blit;
+use crate::colours::{PixelOrder, TextColour};
+use crate::options::BlendMode;
+use crate::{PasteSurface, StoreSurface};
+use image::RgbaImage;
+
+impl StoreSurface for RgbaImage {
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        RgbaImage::from_raw(width as u32, height as u32, data.to_vec())
+            .unwrap_or_else(|| RgbaImage::new(width as u32, height as u32))
+    }
+}
+
+impl PasteSurface<RgbaImage> for RgbaImage {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &RgbaImage) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &RgbaImage) {
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn width(&self) -> Option<usize> {
+        Some(self.dimensions().0 as usize)
+    }
+
+    fn height(&self) -> Option<usize> {
+        Some(self.dimensions().1 as usize)
+    }
+
+    fn new_empty(width: usize, height: usize) -> Self {
+        RgbaImage::new(width as u32, height as u32)
+    }
+
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &RgbaImage, mode: BlendMode) {
+        let (dst_w, dst_h) = (self.dimensions().0 as usize, self.dimensions().1 as usize);
+        let src_w = data.dimensions().0 as usize;
+        blit::paste_rgba(self, dst_w, dst_h, dst_x, dst_y, src_x, src_y, width, height, data, src_w, mode);
+    }
+
+    fn paste_lcd(
+        &mut self,
+        dst_x: usize,
+        dst_y: usize,
+        src_x: usize,
+        src_y: usize,
+        width: usize,
+        height: usize,
+        coverage: &[u8],
+        mask_width: usize,
+        colour: TextColour,
+        pixel_order: PixelOrder,
+        _mode: BlendMode
+    ) {
+        let pitch = self.dimensions().0 as usize * 4;
+        let pixels: &mut [u8] = self;
+        let dst = &mut pixels[dst_y * pitch + dst_x * 4..];
+        let coverage = &coverage[(src_y * mask_width + src_x) * 3..];
+        blit::blend_lcd(dst, pitch, coverage, mask_width * 3, width, height, colour, pixel_order);
+    }
+}
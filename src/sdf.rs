@@ -0,0 +1,161 @@
+//! Glyphs as signed distance fields, for scaling and rotating text on the GPU without rasterizing it again.
+//! Every pixel of a field holds how far it is from the edge of the glyph instead of how much of it is covered,
+//! so a shader can sample it at any size (or angle) and threshold it at 0.5 to get sharp edges back.
+//! Fields are worked out from the glyph's rasterized coverage and cached alongside the normal glyph bitmaps.
+//! With the `ttf-parser` feature there's also `TextRenderer::glyph_msdf`, which works out a multi-channel field
+//! from the glyph's outline instead, keeping corners sharp (the shader takes the median of the three channels).
+
+use crate::metrics::GlyphMetrics;
+use crate::options::CoordinateSystem;
+use crate::{rasterize_mask, CacheKey, GlyphCache, GlyphEffects, PasteSurface, StoreSurface, TextRenderer};
+use fontdue::layout::GlyphRasterConfig;
+use std::sync::PoisonError;
+use std::time::Instant;
+
+/// A glyph as a signed distance field, returned by `TextRenderer::glyph_sdf` (and `TextRenderer::glyph_msdf`).
+/// To draw it `scale` times bigger than the size it was generated at, draw the field `scale` times bigger too,
+/// with its top left `left * scale` to the right of the pen and `top * scale` above the baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphSdf {
+    /// Metrics of the glyph at the size the field was generated at, the same as `TextRenderer::glyph_metrics` gives.
+    pub metrics: GlyphMetrics,
+    /// How many pixels the field reaches past the edge of the glyph, inside and out.
+    /// The field is this many pixels bigger than the glyph's bitmap on every side, so nothing gets cut off.
+    pub spread: usize,
+    /// How far to the right of the pen position the left edge of the field goes.
+    pub left: f32,
+    /// How far above the baseline the top edge of the field goes.
+    pub top: f32,
+    pub width: usize,
+    pub height: usize,
+    /// How many bytes each pixel has, 1 for a normal field and 3 (red, green, blue) for a multi-channel one.
+    pub channels: usize,
+    /// `channels` bytes per pixel, top row first (bottom row first for `CoordinateSystem::PositiveYUp`, like every other glyph).
+    /// 128 is right on the edge of the glyph, 255 is `spread` or more pixels inside of it and 0 is `spread` or more outside.
+    /// For a multi-channel field that goes for the median of the three channels rather than each of them.
+    pub data: Vec<u8>,
+}
+
+/// Internal function to turn a coverage mask `width` pixels wide into a signed distance field reaching `spread` pixels
+/// each way, which is `spread` pixels bigger on every side. Pixels at least half covered count as inside, the distance
+/// from one is to the middle of the nearest pixel on the other side (less half a pixel, as the edge is between them),
+/// and pixels right on the edge use their coverage instead so antialiasing isn't thrown away.
+fn distance_field(mask: &[u8], width: usize, spread: usize) -> (usize, usize, Vec<u8>) {
+    let height = mask.len().checked_div(width).unwrap_or(0);
+    let (padded_width, padded_height) = (width + spread * 2, height + spread * 2);
+    let coverage = |x: isize, y: isize| -> u8 {
+        let (x, y) = (x - spread as isize, y - spread as isize);
+        if x < 0 || y < 0 || x >= width as isize || y >= height as isize { 0 } else { mask[y as usize * width + x as usize] }
+    };
+    let reach = spread as isize + 1;
+    let mut field = Vec::with_capacity(padded_width * padded_height);
+    for y in 0..padded_height as isize {
+        for x in 0..padded_width as isize {
+            let here = coverage(x, y);
+            let inside = here >= 128;
+            let mut nearest = f32::MAX;
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    if (coverage(x + dx, y + dy) >= 128) != inside {
+                        nearest = nearest.min(((dx * dx + dy * dy) as f32).sqrt());
+                    }
+                }
+            }
+            let distance = if nearest <= 1.0 {
+                // next to the edge, so coverage says how far over it this pixel is
+                here as f32 / 255.0 - 0.5
+            } else if inside {
+                nearest - 0.5
+            } else {
+                0.5 - nearest
+            };
+            // rounding down keeps everything outside of the edge below 128, however close it is
+            field.push((128.0 + distance / spread as f32 * 127.0).floor().clamp(0.0, 255.0) as u8);
+        }
+    }
+    (padded_width, padded_height, field)
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Generates a signed distance field for a single character at `size` pixels, reaching `spread` pixels either side
+    /// of the edge of the glyph (at least 1). A bigger `size` gives sharper corners when scaled up, and a bigger `spread`
+    /// leaves more room for outlines, glows and shadows in the shader. Fields are cached, so asking again is cheap.
+    /// Returns `None` if the font doesn't have a glyph for the character, or `size` isn't one that can be drawn.
+    pub fn glyph_sdf(&mut self, c: char, size: f32, spread: usize) -> Option<GlyphSdf> {
+        self.distance_field_glyph(c, size, spread, 1)
+    }
+
+    /// Generates a multi-channel signed distance field for a single character, the same way msdfgen does.
+    /// Works just like `glyph_sdf`, but the field comes from the glyph's outline and has 3 channels,
+    /// so corners stay sharp instead of being rounded off however far it's scaled up.
+    #[cfg(feature = "ttf-parser")]
+    pub fn glyph_msdf(&mut self, c: char, size: f32, spread: usize) -> Option<GlyphSdf> {
+        self.distance_field_glyph(c, size, spread, 3)
+    }
+
+    /// Internal function to get the field with `channels` channels for a glyph, from the cache if it's there.
+    fn distance_field_glyph(&mut self, c: char, size: f32, spread: usize, channels: usize) -> Option<GlyphSdf> {
+        self.check_size(size).ok()?;
+        let metrics = self.glyph_metrics(c, size)?;
+        let spread = spread.max(1);
+        let glyph_index = self.font.lookup_glyph_index(c);
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = CacheKey { font: 0, size: size.to_bits(), gamma: None, flipped, outline: None, blur: None, subpixel: 0 };
+        {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            let cached = glyph_caches.get(&key).and_then(|glyph_cache| glyph_cache.sdf_map.get(&(glyph_index, spread, channels)));
+            if let Some(cached) = cached {
+                self.metrics.cache_hits += 1;
+                return Some(cached.clone());
+            }
+        }
+        self.metrics.cache_misses += 1;
+        let start = Instant::now();
+        // the bitmap sits on whole pixels, which isn't quite where the bearings are
+        let bitmap = self.font.metrics_indexed(glyph_index, size);
+        let left = (bitmap.xmin - spread as i32) as f32;
+        let top = (bitmap.ymin + bitmap.height as i32 + spread as i32) as f32;
+        let (width, height, data) = match channels {
+            #[cfg(feature = "ttf-parser")]
+            3 => self.multi_distance_field_glyph(glyph_index, size, spread, left, top, metrics.bitmap_width, metrics.bitmap_height, flipped),
+            _ => {
+                let config = GlyphRasterConfig { glyph_index, px: size, font_hash: self.font.file_hash() };
+                let mask = match metrics.bitmap_width * metrics.bitmap_height {
+                    0 => Vec::new(),
+                    _ => rasterize_mask(&self.font, config, flipped, GlyphEffects::default()),
+                };
+                distance_field(&mask, metrics.bitmap_width, spread)
+            }
+        };
+        let sdf = GlyphSdf { metrics, spread, left, top, width, height, channels, data };
+        self.metrics.rasterizations += 1;
+        self.metrics.rasterization_time += start.elapsed();
+        let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+        let glyph_cache = glyph_caches.entry(key).or_insert_with(|| GlyphCache::new(size));
+        glyph_cache.sdf_map.insert((glyph_index, spread, channels), sdf.clone());
+        Some(sdf)
+    }
+
+    /// Internal function to work out a multi-channel field from a glyph's outline, covering the same pixels as
+    /// the single channel field would.
+    #[cfg(feature = "ttf-parser")]
+    #[allow(clippy::too_many_arguments)]
+    fn multi_distance_field_glyph(
+        &self,
+        glyph_index: u16,
+        size: f32,
+        spread: usize,
+        left: f32,
+        top: f32,
+        bitmap_width: usize,
+        bitmap_height: usize,
+        flipped: bool
+    ) -> (usize, usize, Vec<u8>) {
+        let (width, height) = (bitmap_width + spread * 2, bitmap_height + spread * 2);
+        let data = match self.glyph_outline_indexed(0, glyph_index, size) {
+            Some(path) => crate::msdf::multi_distance_field(&path, left, top, width, height, spread, flipped),
+            None => vec![0; width * height * 3],
+        };
+        (width, height, data)
+    }
+}
@@ -0,0 +1,147 @@
+//! SDL2 surfaces (and window canvases) as a backend, enabled with the `sdl2` feature.
+//! Glyphs are stored as `sdl2::surface::Surface`s, and can be pasted onto another surface or straight onto a
+//! `Canvas<Window>`, so `TextRenderer<Surface<'static>, Surface>` (or `TextRenderer<Surface<'static>, Canvas<Window>>`)
+//! works with no glue code at all. Glyph surfaces are `PixelFormatEnum::RGBA32`, which is what the default
+//! `PixelOrder::Rgba` gives, so leave the pixel order alone with this backend.
+
+use crate::blit;
+use crate::colours::TextColour;
+use crate::options::BlendMode;
+use crate::{PasteSurface, StoreSurface};
+use log::debug;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode as SdlBlendMode, Canvas};
+use sdl2::surface::{Surface, SurfaceRef};
+use sdl2::video::Window;
+
+/// Internal function to pick the SDL blend mode closest to a `BlendMode`.
+/// SDL doesn't have a screen mode, so that's drawn as a normal alpha blend.
+fn sdl_blend_mode(mode: BlendMode) -> SdlBlendMode {
+    match mode {
+        BlendMode::Replace => SdlBlendMode::None,
+        BlendMode::AlphaOver => SdlBlendMode::Blend,
+        BlendMode::Additive => SdlBlendMode::Add,
+        BlendMode::Multiply => SdlBlendMode::Mul,
+        BlendMode::Screen => {
+            debug!("SDL has no screen blend mode, using alpha blending instead");
+            SdlBlendMode::Blend
+        }
+    }
+}
+
+/// Internal function to check whether a surface is a plain 4 bytes per pixel buffer with the alpha last,
+/// which `sext::blit` can work on directly.
+fn is_plain_rgba(surface: &Surface) -> bool {
+    matches!(surface.pixel_format_enum(), PixelFormatEnum::RGBA32 | PixelFormatEnum::BGRA32)
+        && surface.pitch() as usize == SurfaceRef::width(surface) as usize * 4
+}
+
+impl StoreSurface for Surface<'static> {
+    fn from_raw_mask(width: usize, height: usize, data: &[u8], _colour: TextColour) -> Self {
+        let mut surface = Surface::new(width as u32, height as u32, PixelFormatEnum::RGBA32)
+            .expect("SDL couldn't create a glyph surface");
+        // a brand new surface can always have its blend mode set
+        let _ = surface.set_blend_mode(SdlBlendMode::Blend);
+        // empty surfaces don't have any pixels to lock
+        if width * height == 0 {
+            return surface;
+        }
+        let pitch = surface.pitch() as usize;
+        surface.with_lock_mut(|pixels| {
+            for (row, line) in data.chunks_exact(width * 4).take(height).enumerate() {
+                pixels[row * pitch..row * pitch + width * 4].copy_from_slice(line);
+            }
+        });
+        surface
+    }
+}
+
+impl PasteSurface<Surface<'static>> for Surface<'_> {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Surface<'static>) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Surface<'static>) {
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn width(&self) -> Option<usize> {
+        Some(SurfaceRef::width(self) as usize)
+    }
+
+    fn height(&self) -> Option<usize> {
+        Some(SurfaceRef::height(self) as usize)
+    }
+
+    /// Surfaces in the same 4 byte format as the glyphs are blended with `sext::blit`, so every mode comes out exactly
+    /// like the software backends. Anything else is left to SDL's own blitting, which converts between formats
+    /// but only has the modes SDL has.
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Surface<'static>, mode: BlendMode) {
+        if width * height == 0 {
+            return;
+        }
+        if is_plain_rgba(self) && self.pixel_format_enum() == data.pixel_format_enum() && is_plain_rgba(data) {
+            let (dst_w, dst_h) = (SurfaceRef::width(self) as usize, SurfaceRef::height(self) as usize);
+            let src_w = SurfaceRef::width(data) as usize;
+            data.with_lock(|src| {
+                self.with_lock_mut(|dst| {
+                    blit::paste_rgba(dst, dst_w, dst_h, dst_x, dst_y, src_x, src_y, width, height, src, src_w, mode);
+                });
+            });
+            return;
+        }
+        let src_rect = Rect::new(src_x as i32, src_y as i32, width as u32, height as u32);
+        let dst_rect = Rect::new(dst_x as i32, dst_y as i32, width as u32, height as u32);
+        let result = match sdl_blend_mode(mode) {
+            SdlBlendMode::Blend => data.blit(src_rect, self, dst_rect),
+            // glyph surfaces are shared by the cache, so other modes get set on a copy
+            sdl_mode => data.convert_format(data.pixel_format_enum()).and_then(|mut copy| {
+                copy.set_blend_mode(sdl_mode)?;
+                copy.blit(src_rect, self, dst_rect)
+            }),
+        };
+        if let Err(e) = result {
+            debug!("couldn't blit glyph: {}", e);
+        }
+    }
+}
+
+/// Pastes glyphs straight onto a window's canvas, turning each one into a texture as it's drawn. That's simple
+/// but not fast, so for lots of text render it to a `Surface` first (see `TextRenderer::render_to_surface`)
+/// and make one texture out of that instead.
+impl PasteSurface<Surface<'static>> for Canvas<Window> {
+    fn paste(&mut self, x: usize, y: usize, width: usize, height: usize, data: &Surface<'static>) {
+        self.paste_blended(x, y, 0, 0, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn paste_clipped(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Surface<'static>) {
+        self.paste_blended(dst_x, dst_y, src_x, src_y, width, height, data, BlendMode::AlphaOver);
+    }
+
+    fn width(&self) -> Option<usize> {
+        self.output_size().ok().map(|(width, _)| width as usize)
+    }
+
+    fn height(&self) -> Option<usize> {
+        self.output_size().ok().map(|(_, height)| height as usize)
+    }
+
+    fn paste_blended(&mut self, dst_x: usize, dst_y: usize, src_x: usize, src_y: usize, width: usize, height: usize, data: &Surface<'static>, mode: BlendMode) {
+        if width * height == 0 {
+            return;
+        }
+        let texture_creator = self.texture_creator();
+        let result = texture_creator.create_texture_from_surface(data).map_err(|e| e.to_string()).and_then(|mut texture| {
+            texture.set_blend_mode(sdl_blend_mode(mode));
+            self.copy(
+                &texture,
+                Rect::new(src_x as i32, src_y as i32, width as u32, height as u32),
+                Rect::new(dst_x as i32, dst_y as i32, width as u32, height as u32),
+            )
+        });
+        if let Err(e) = result {
+            debug!("couldn't draw glyph texture: {}", e);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Complex script shaping through `rustybuzz`, enabled with the `shaping` feature.
+//! fontdue's layout maps every character to exactly one glyph, which is fine for latin text but means
+//! arabic comes out with isolated letter forms and indic conjuncts come out wrong.
+//! Shaped glyphs still go through the normal glyph cache, keyed by glyph index.
+
+use crate::colours::TextColour;
+use crate::metrics::DrawnBounds;
+use crate::options::{DrawOptions, Origin};
+use crate::layout::{Glyph, GlyphData};
+use crate::{GlyphEffects, PasteSurface, StoreSurface, TextRenderer};
+use fontdue::layout::{CharacterData, GlyphPosition, GlyphRasterConfig};
+use rustybuzz::{Face, UnicodeBuffer};
+
+/// A single glyph after shaping, returned by `TextRenderer::shape_string`.
+/// All positions are in pixels, relative to the pen position at the start of the string on the baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShapedGlyph {
+    /// The index of the glyph in the font. This is *not* a character, as shaping can turn one character into
+    /// several glyphs or several characters into one.
+    pub glyph_id: u16,
+    /// The byte offset into the source string of the first character this glyph was made from.
+    /// Several glyphs can share a cluster (and glyphs for right-to-left text come out in visual order),
+    /// so use this for caret positioning and hit testing rather than the glyph's index.
+    pub cluster: usize,
+    /// Where the pen was (plus any shaping offset) when this glyph was placed.
+    pub x: f32,
+    /// Vertical shaping offset of this glyph, positive is up.
+    pub y: f32,
+    /// How far the pen moved after this glyph.
+    pub advance: f32,
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Runs a string through rustybuzz and returns the positioned glyphs, in visual order.
+    /// The string is treated as a single line, newlines aren't given any special treatment.
+    pub fn shape_string(&self, string: &str, size: f32) -> Vec<ShapedGlyph> {
+        let face = match Face::from_slice(&self.font_data, self.collection_index) {
+            Some(face) => face,
+            None => return Vec::new(),
+        };
+        let scale = size / face.units_per_em() as f32;
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(string);
+        buffer.guess_segment_properties();
+        let shaped = rustybuzz::shape(&face, &[], buffer);
+
+        let mut pen = 0.0;
+        shaped.glyph_infos().iter().zip(shaped.glyph_positions()).map(|(info, position)| {
+            let glyph = ShapedGlyph {
+                glyph_id: info.glyph_id as u16,
+                cluster: info.cluster as usize,
+                x: pen + position.x_offset as f32 * scale,
+                y: position.y_offset as f32 * scale,
+                advance: position.x_advance as f32 * scale,
+            };
+            pen += glyph.advance;
+            glyph
+        }).collect()
+    }
+
+    /// Same as `draw_string_with_options`, but the string is shaped with rustybuzz first so scripts that need
+    /// contextual forms, ligatures or reordering come out right.
+    /// Only `origin`, `anchor`, `clip`, `blend_mode` and `opacity` are used from the options for now, shaped text is always a single line.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_shaped(
+        &mut self,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds {
+        let shaped = self.shape_string(string, size);
+        let line = self.line_metrics(size);
+        // same rounding that fontdue's layout uses, so shaped and unshaped text sit at the same height
+        let baseline = line.ascent.ceil();
+        let (width, height) = (shaped.iter().map(|glyph| glyph.advance).sum::<f32>(), line.line_height.ceil());
+        let (anchor_x, anchor_y) = options.anchor.fractions();
+        let x = x - width * anchor_x;
+        let y = match options.origin {
+            Origin::TopLeft => self.layout_y(y),
+            Origin::Baseline => self.layout_y(y) - baseline,
+        } - height * anchor_y;
+        let glyphs = shaped.iter().map(|glyph| self.shaped_glyph_position(string, glyph, size, baseline)).collect::<Vec<_>>();
+        self.draw_glyphs(&glyphs, x, y, options.clip, options.blend_mode, options.opacity, GlyphEffects::from_options(options), surface, |_| colour);
+        DrawnBounds {
+            x,
+            y: self.layout_y(y),
+            width,
+            height,
+            truncated: false,
+            glyphs_drawn: glyphs.len(),
+            bytes_drawn: string.len(),
+        }
+    }
+
+    /// Internal function to turn a shaped glyph into the same `GlyphPosition` fontdue's layout would produce,
+    /// so it can go through the normal drawing and caching code.
+    fn shaped_glyph_position(&self, string: &str, glyph: &ShapedGlyph, size: f32, baseline: f32) -> Glyph {
+        let metrics = self.font.metrics_indexed(glyph.glyph_id, size);
+        let parent = string[glyph.cluster..].chars().next().unwrap_or(' ');
+        GlyphPosition {
+            key: GlyphRasterConfig {
+                glyph_index: glyph.glyph_id,
+                px: size,
+                font_hash: self.font.file_hash(),
+            },
+            font_index: 0,
+            parent,
+            x: (glyph.x + metrics.bounds.xmin).floor(),
+            y: (-metrics.bounds.height - metrics.bounds.ymin - glyph.y).floor() + baseline,
+            width: metrics.width,
+            height: metrics.height,
+            byte_offset: glyph.cluster,
+            char_data: CharacterData::classify(parent, glyph.glyph_id),
+            user_data: GlyphData::default(),
+        }
+    }
+}
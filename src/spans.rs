@@ -0,0 +1,63 @@
+use crate::colours::TextColour;
+
+/// Moves a span's baseline up or down relative to the text around it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BaselineShift {
+    /// Sits on the same baseline as everything else.
+    #[default]
+    None,
+    /// Scaled down by `DrawOptions::script_scale` and raised by a fraction of the ascent, like the 2 in "x²".
+    Superscript,
+    /// Scaled down by `DrawOptions::script_scale` and lowered by a fraction of the descent, like the 2 in "H₂O".
+    Subscript,
+    /// Moved up by this many pixels (or down if negative), without changing the size.
+    Exact(f32),
+}
+
+/// A piece of a string with its own styling, for drawing with `TextRenderer::draw_spans`.
+/// Anything that isn't set falls back to what was passed to the draw call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSpan {
+    pub text: String,
+    /// Pixel size of this span. Superscripts and subscripts are scaled down from this.
+    pub size: Option<f32>,
+    pub colour: Option<TextColour>,
+    pub baseline_shift: BaselineShift,
+    /// Which of the renderer's fonts this span is drawn in. 0 is the renderer's own font,
+    /// anything else is one added with `TextRenderer::add_font`.
+    pub font: usize,
+}
+
+impl TextSpan {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            size: None,
+            colour: None,
+            baseline_shift: BaselineShift::None,
+            font: 0,
+        }
+    }
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = Some(size);
+        self
+    }
+    pub fn with_colour(mut self, colour: TextColour) -> Self {
+        self.colour = Some(colour);
+        self
+    }
+    pub fn with_baseline_shift(mut self, baseline_shift: BaselineShift) -> Self {
+        self.baseline_shift = baseline_shift;
+        self
+    }
+    pub fn with_font(mut self, font: usize) -> Self {
+        self.font = font;
+        self
+    }
+    pub fn superscript(text: &str) -> Self {
+        Self::new(text).with_baseline_shift(BaselineShift::Superscript)
+    }
+    pub fn subscript(text: &str) -> Self {
+        Self::new(text).with_baseline_shift(BaselineShift::Subscript)
+    }
+}
@@ -0,0 +1,198 @@
+//! Caching whole strings as single pre-rendered surfaces, for labels that don't change from frame to frame.
+//! The first `TextRenderer::draw_string_cached` for a key renders the string with `render_to_surface`,
+//! and every call after that is one paste of that surface instead of laying out and pasting every glyph.
+
+use crate::colours::TextColour;
+use crate::metrics::DrawnBounds;
+use crate::options::{Anchor, DrawOptions, Origin};
+use crate::{paste_visible, PasteSurface, Rect, StoreSurface, TextRenderer};
+use std::collections::HashMap;
+
+/// How many bytes of pre-rendered strings are kept around by default (8 MiB), see `TextRenderer::set_string_cache_budget`.
+pub const DEFAULT_STRING_CACHE_BUDGET: usize = 8 * 1024 * 1024;
+
+/// What a cached string is stored under. Anything that uniquely names a label works,
+/// so `"score"`, `String::from("score")` and `42u64` can all be passed straight to `draw_string_cached`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum StringCacheKey {
+    Name(String),
+    Id(u64),
+}
+
+impl From<&str> for StringCacheKey {
+    fn from(name: &str) -> Self {
+        StringCacheKey::Name(name.to_string())
+    }
+}
+
+impl From<String> for StringCacheKey {
+    fn from(name: String) -> Self {
+        StringCacheKey::Name(name)
+    }
+}
+
+impl From<u64> for StringCacheKey {
+    fn from(id: u64) -> Self {
+        StringCacheKey::Id(id)
+    }
+}
+
+/// Internal struct, a string that's been rendered and what it was rendered with.
+#[derive(Clone)]
+struct CachedString<A> {
+    string: String,
+    /// `f32::to_bits` of the pixel size.
+    size: u32,
+    colour: TextColour,
+    surface: A,
+    /// Where the text is in `surface`, see `render_to_surface`.
+    bounds: DrawnBounds,
+    /// How far `origin` and `anchor` move the text, see `LaidOutText::origin_offset`.
+    origin_offset: (f32, f32),
+    width: usize,
+    height: usize,
+    /// The value of `StringCache::clock` the last time this was drawn.
+    last_used: u64,
+}
+
+impl<A> CachedString<A> {
+    /// Roughly how much memory the surface takes up, assuming 4 bytes per pixel.
+    fn bytes(&self) -> usize {
+        self.width * self.height * 4
+    }
+}
+
+/// Internal struct, the pre-rendered strings of a single `TextRenderer`. Unlike the glyph cache this isn't shared
+/// between clones, as the keys only mean something to whatever is drawing with that renderer.
+#[derive(Clone)]
+pub(crate) struct StringCache<A> {
+    entries: HashMap<StringCacheKey, CachedString<A>>,
+    budget: usize,
+    used: usize,
+    /// Goes up by one on every draw, so the least recently used string is the one with the lowest `last_used`.
+    clock: u64,
+}
+
+impl<A> Default for StringCache<A> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            budget: DEFAULT_STRING_CACHE_BUDGET,
+            used: 0,
+            clock: 0,
+        }
+    }
+}
+
+impl<A> StringCache<A> {
+    fn remove(&mut self, key: &StringCacheKey) -> bool {
+        match self.entries.remove(key) {
+            Some(entry) => {
+                self.used -= entry.bytes();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Throws out the least recently used strings until `extra` more bytes fit in the budget.
+    fn evict(&mut self, extra: usize) {
+        while self.used + extra > self.budget {
+            let oldest = self.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(key, _)| key.clone());
+            match oldest {
+                Some(key) => self.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Draws a string the same way `draw_string_with_options` would, but keeps the rendered result around under `key`
+    /// so drawing it again is a single paste. If the string, size or colour under `key` has changed since it was cached,
+    /// it's rendered again. Changing any other options doesn't do that, so call `invalidate_string` if you do.
+    /// The cached surface is pasted with `options.blend_mode` (and cut off by `options.clip`), so text drawn with
+    /// `BlendMode::Replace` replaces the whole rect the string covers rather than just its glyphs.
+    /// Strings too big to ever fit in the budget are drawn normally without being cached, and so is anything drawn
+    /// with an `options.opacity` below 1.0 (the cached surface has the colour baked in).
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_string_cached(
+        &mut self,
+        key: impl Into<StringCacheKey>,
+        string: &str,
+        x: f32,
+        y: f32,
+        size: f32,
+        colour: TextColour,
+        options: &DrawOptions,
+        surface: &mut A
+    ) -> DrawnBounds where A: StoreSurface + PasteSurface<A> {
+        if options.opacity < 1.0 {
+            let text = self.layout_text(string, size, options);
+            return self.draw_laid_out(&text, x, y, options, surface, |_| colour);
+        }
+        let key = key.into();
+        self.string_cache.clock += 1;
+        let clock = self.string_cache.clock;
+        let fresh = self.string_cache.entries.get(&key)
+            .is_some_and(|entry| entry.string == string && entry.size == size.to_bits() && entry.colour == colour);
+        if !fresh {
+            self.string_cache.remove(&key);
+            let render_options = DrawOptions { origin: Origin::TopLeft, anchor: Anchor::TopLeft, clip: None, ..options.clone() };
+            let text = self.layout_text(string, size, &render_options);
+            let ink = text.ink_bounds();
+            if ink.width * ink.height * 4 > self.string_cache.budget {
+                return self.draw_laid_out(&text, x, y, options, surface, |_| colour);
+            }
+            let (rendered, bounds) = self.render_laid_out(&text, colour, &render_options);
+            let entry = CachedString {
+                string: string.to_string(),
+                size: size.to_bits(),
+                colour,
+                surface: rendered,
+                bounds,
+                origin_offset: text.origin_offset(options),
+                width: ink.width,
+                height: ink.height,
+                last_used: clock,
+            };
+            self.string_cache.evict(entry.bytes());
+            self.string_cache.used += entry.bytes();
+            self.string_cache.entries.insert(key.clone(), entry);
+        }
+
+        // the origin moves the text in layout coordinates, so it has to be flipped for y-up surfaces
+        let (dx, dy) = self.string_cache.entries[&key].origin_offset;
+        let (x, y) = (x + dx, self.layout_y(self.layout_y(y) + dy));
+        let entry = &self.string_cache.entries[&key];
+        let dst = Rect::new((x - entry.bounds.x).floor() as i32, (y - entry.bounds.y).floor() as i32, entry.width, entry.height);
+        let visible = self.visible_rect::<A, A>(surface, dst, options.clip);
+        let entry = self.string_cache.entries.get_mut(&key).unwrap();
+        entry.last_used = clock;
+        if let Some(visible) = visible {
+            paste_visible(surface, dst, visible, &entry.surface, options.blend_mode);
+        }
+        let bounds = DrawnBounds { x, y, ..entry.bounds };
+        self.mark_dirty(visible);
+        bounds
+    }
+
+    /// Throws away the cached rendering of the string under `key`, so the next `draw_string_cached` renders it again.
+    /// Returns `false` if nothing was cached under `key`.
+    pub fn invalidate_string(&mut self, key: impl Into<StringCacheKey>) -> bool {
+        self.string_cache.remove(&key.into())
+    }
+
+    /// Throws away every cached string.
+    pub fn clear_string_cache(&mut self) {
+        self.string_cache.entries.clear();
+        self.string_cache.used = 0;
+    }
+
+    /// Sets roughly how many bytes of pre-rendered strings are kept (counting 4 bytes per pixel),
+    /// throwing out the least recently drawn ones once there's too many. Defaults to `DEFAULT_STRING_CACHE_BUDGET`.
+    pub fn set_string_cache_budget(&mut self, bytes: usize) {
+        self.string_cache.budget = bytes;
+        self.string_cache.evict(0);
+    }
+}
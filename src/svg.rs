@@ -0,0 +1,66 @@
+//! Exporting laid out text as an SVG document, enabled with the `ttf-parser` feature (as it needs glyph outlines).
+//! Every glyph becomes its own `<path>`, so the result is completely self contained and scales without getting blurry.
+
+use crate::colours::TextColour;
+use crate::layout::LaidOutText;
+use crate::options::DrawOptions;
+use crate::outline::PathCommand;
+use crate::spans::TextSpan;
+use crate::{PasteSurface, StoreSurface, TextRenderer};
+use std::fmt::Write;
+
+/// Internal function to write a number without a pile of useless decimals.
+fn num(value: f32) -> f32 {
+    (value * 100.0).round() / 100.0 + 0.0
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Lays out a string the same way `draw_string_with_options` would and returns it as an SVG document.
+    /// The document is exactly as big as the measured text (see `measure_string_with_options`),
+    /// with the top left of the text at (0, 0). `origin` and `anchor` from the options aren't used.
+    pub fn render_to_svg(&self, string: &str, size: f32, colour: TextColour, options: &DrawOptions) -> String {
+        let text = self.layout_text(string, size, options);
+        self.svg_document(&text, |_| colour)
+    }
+
+    /// Same as `render_to_svg`, but for styled spans like `draw_spans`.
+    pub fn render_spans_to_svg(&self, spans: &[TextSpan], size: f32, colour: TextColour, options: &DrawOptions) -> String {
+        let runs = spans.iter().map(|span| self.span_run(span, size, options)).collect::<Vec<_>>();
+        let text = self.layout_rich(&runs, size, options);
+        self.svg_document(&text, |run| spans.get(run).and_then(|span| span.colour).unwrap_or(colour))
+    }
+
+    /// Internal function to write out the SVG for some laid out text. `colour_fn` gets the run index of every glyph.
+    fn svg_document(&self, text: &LaidOutText, colour_fn: impl Fn(usize) -> TextColour) -> String {
+        let (width, height) = (text.metrics.width.ceil(), text.metrics.height.ceil());
+        let mut svg = String::new();
+        // writing to a String can't fail, so all of the results are ignored
+        let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#);
+        for glyph in text.glyphs.iter() {
+            let path = match self.laid_out_glyph_outline(glyph, 0.0, 0.0) {
+                Some(path) if !path.commands.is_empty() => path,
+                _ => continue,
+            };
+            let colour = colour_fn(glyph.user_data.run);
+            let _ = write!(svg, r##"<path fill="#{:02x}{:02x}{:02x}""##, colour.r, colour.g, colour.b);
+            if colour.a != 255 {
+                let _ = write!(svg, r#" fill-opacity="{}""#, num(colour.a as f32 / 255.0));
+            }
+            svg.push_str(r#" d=""#);
+            for command in path.commands.iter() {
+                let _ = match *command {
+                    PathCommand::MoveTo(x, y) => write!(svg, "M{} {}", num(x), num(y)),
+                    PathCommand::LineTo(x, y) => write!(svg, "L{} {}", num(x), num(y)),
+                    PathCommand::QuadTo(x1, y1, x, y) => write!(svg, "Q{} {} {} {}", num(x1), num(y1), num(x), num(y)),
+                    PathCommand::CubicTo(x1, y1, x2, y2, x, y) => {
+                        write!(svg, "C{} {} {} {} {} {}", num(x1), num(y1), num(x2), num(y2), num(x), num(y))
+                    }
+                    PathCommand::Close => write!(svg, "Z"),
+                };
+            }
+            svg.push_str("\"/>\n");
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
@@ -0,0 +1,90 @@
+//! Loading installed fonts by their family name, enabled with the `system-fonts` feature.
+//! Fonts are found with `fontdb`, which looks wherever the platform keeps them (and through fontconfig on linux).
+//! Looking through every installed font takes a while, so it's only done once, the first time a family is asked for.
+
+use crate::builder::TextRendererBuilder;
+use crate::options::FontOptions;
+use crate::{PasteSurface, StoreSurface, TextRenderer, TextRendererError};
+use fontdb::{Database, Family, Query, Stretch, Style, Weight};
+use std::sync::OnceLock;
+
+/// Which face of a family to pick, see `TextRenderer::from_family_styled`.
+/// The closest match is used if the family doesn't have exactly this one, the same way CSS picks one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontStyle {
+    /// From 100 (thin) to 900 (black). 400 is regular and 700 is bold. Defaults to `400`.
+    pub weight: u16,
+    /// Whether to pick an italic (or oblique) face. Defaults to `false`.
+    pub italic: bool,
+}
+
+impl FontStyle {
+    /// The regular face.
+    pub const REGULAR: Self = FontStyle { weight: 400, italic: false };
+    /// The bold face.
+    pub const BOLD: Self = FontStyle { weight: 700, italic: false };
+    /// The italic face.
+    pub const ITALIC: Self = FontStyle { weight: 400, italic: true };
+}
+
+impl Default for FontStyle {
+    fn default() -> Self {
+        Self::REGULAR
+    }
+}
+
+/// Internal function to get every installed font, looking for them the first time it's called.
+fn system_fonts() -> &'static Database {
+    static DATABASE: OnceLock<Database> = OnceLock::new();
+    DATABASE.get_or_init(|| {
+        let mut database = Database::new();
+        database.load_system_fonts();
+        database
+    })
+}
+
+/// Internal function to find the face of `family` closest to `style` in `database`, and get its raw data and index in
+/// its file. `family` can also be one of the generic CSS families (`serif`, `sans-serif`, `monospace`, `cursive` or
+/// `fantasy`), which go to whatever the platform uses for them.
+pub(crate) fn find_face(database: &Database, family: &str, style: FontStyle) -> Option<(Vec<u8>, u32)> {
+    let family = match family {
+        "serif" => Family::Serif,
+        "sans-serif" => Family::SansSerif,
+        "monospace" => Family::Monospace,
+        "cursive" => Family::Cursive,
+        "fantasy" => Family::Fantasy,
+        name => Family::Name(name),
+    };
+    let query = Query {
+        families: &[family],
+        weight: Weight(style.weight),
+        stretch: Stretch::Normal,
+        style: if style.italic { Style::Italic } else { Style::Normal },
+    };
+    let id = database.query(&query)?;
+    database.with_face_data(id, |data, index| (data.to_vec(), index))
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Creates a `TextRenderer` with the regular face of an installed font family, like `"DejaVu Sans"`,
+    /// or one of the generic families `"serif"`, `"sans-serif"` and `"monospace"`.
+    /// Returns `TextRendererError::FontNotFound` if the family isn't installed or couldn't be loaded.
+    /// The first call looks through every installed font, which can take a moment.
+    pub fn from_family(family: &str) -> Result<Self, TextRendererError> {
+        Self::from_family_styled(family, FontStyle::default())
+    }
+
+    /// Same as `from_family`, but for the face of the family closest to `style`.
+    pub fn from_family_styled(family: &str, style: FontStyle) -> Result<Self, TextRendererError> {
+        let (font_data, collection_index) = find_face(system_fonts(), family, style).ok_or(TextRendererError::FontNotFound)?;
+        TextRendererBuilder::from_bytes(font_data).collection_index(collection_index).build()
+    }
+
+    /// Same as `add_font`, but for the face of an installed font family closest to `style` (see `from_family`).
+    /// Handy for adding fallback fonts (see `set_fallback_fonts`) without having to ship them.
+    pub fn add_family(&mut self, family: &str, style: FontStyle, options: &FontOptions) -> Result<usize, TextRendererError> {
+        let (font_data, collection_index) = find_face(system_fonts(), family, style).ok_or(TextRendererError::FontNotFound)?;
+        self.add_font_from_bytes(font_data, &FontOptions { collection_index, ..*options })
+    }
+}
+
@@ -0,0 +1,46 @@
+// draws glyphs out of a sext glyph atlas, one instance per glyph, see src/gpu.rs
+
+struct Uniforms {
+    screen_size: vec2<f32>,
+    y_up: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> uniforms: Uniforms;
+@group(0) @binding(1) var atlas: texture_2d<f32>;
+@group(0) @binding(2) var atlas_sampler: sampler;
+
+struct Instance {
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv: vec4<f32>,
+    @location(3) colour: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) colour: vec4<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, instance: Instance) -> VertexOutput {
+    // a triangle strip of 4 vertices, going (0, 0), (1, 0), (0, 1), (1, 1)
+    let corner = vec2<f32>(f32(vertex & 1u), f32(vertex >> 1u));
+    let pixel = instance.position + corner * instance.size;
+    var ndc = pixel / uniforms.screen_size * 2.0 - 1.0;
+    if uniforms.y_up == 0u {
+        ndc.y = -ndc.y;
+    }
+    var out: VertexOutput;
+    out.position = vec4<f32>(ndc, 0.0, 1.0);
+    out.uv = mix(instance.uv.xy, instance.uv.zw, corner);
+    out.colour = instance.colour;
+    return out;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(atlas, atlas_sampler, input.uv).r;
+    return vec4<f32>(input.colour.rgb, input.colour.a * coverage);
+}
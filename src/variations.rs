@@ -0,0 +1,338 @@
+//! Variable fonts, enabled with the `ttf-parser` feature.
+//! fontdue can only draw a variable font at its default axis values, so picking an instance (see
+//! `TextRendererBuilder::variation` and `TextRenderer::add_font_variation`) writes out a new, static font:
+//! every glyph's outline and advance is read with `ttf-parser` at the chosen axis values and stored as a plain
+//! TrueType glyph, and the tables that made it variable are left out. Everything else about the font stays the same.
+//! Only outlines and advances change, so things the font varies through other tables (like `MVAR` line metrics)
+//! stay at their defaults, and hinting is dropped as the instructions were written for the default outlines.
+
+use crate::options::FontOptions;
+use crate::{PasteSurface, StoreSurface, TextRenderer, TextRendererError};
+use ttf_parser::{Face, GlyphId, OutlineBuilder, Tag};
+
+/// One of the axes a variable font can be varied along, see `TextRenderer::variation_axes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    /// The axis' tag, like `"wght"` for weight or `"wdth"` for width.
+    pub tag: String,
+    /// What the font calls the axis, if it has a name for it.
+    pub name: Option<String>,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+}
+
+/// A set of axis values the font designer gave a name, like "Light" or "Bold Condensed".
+/// See `TextRenderer::named_instances`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NamedInstance {
+    pub name: String,
+    /// The value of every axis, as (tag, value).
+    pub coordinates: Vec<(String, f32)>,
+}
+
+/// Tables that make the font variable, or that describe it in a way that's wrong for an instance of it.
+const DROPPED_TABLES: [&[u8; 4]; 17] = [
+    b"glyf", b"loca", b"hmtx", b"hdmx", b"gvar", b"fvar", b"avar", b"HVAR", b"MVAR", b"VVAR", b"STAT", b"cvar",
+    b"CFF ", b"CFF2", b"fpgm", b"prep", b"DSIG",
+];
+
+/// Internal function to turn a tag like `"wght"` into a `Tag`, padding short ones with spaces like OpenType does.
+fn parse_tag(tag: &str) -> Option<Tag> {
+    let bytes = tag.as_bytes();
+    if bytes.is_empty() || bytes.len() > 4 || !tag.is_ascii() {
+        return None;
+    }
+    let mut padded = [b' '; 4];
+    padded[..bytes.len()].copy_from_slice(bytes);
+    Some(Tag::from_bytes(&padded))
+}
+
+/// Internal function to get a name from the font's `name` table, if it has one that can be read.
+fn face_name(face: &Face, name_id: u16) -> Option<String> {
+    face.names().into_iter().filter(|name| name.name_id == name_id).find_map(|name| name.to_string())
+}
+
+/// Internal function to list the axes of a font.
+fn axes(face: &Face) -> Vec<VariationAxis> {
+    face.variation_axes().into_iter().map(|axis| VariationAxis {
+        tag: axis.tag.to_string(),
+        name: face_name(face, axis.name_id),
+        min: axis.min_value,
+        default: axis.def_value,
+        max: axis.max_value,
+    }).collect()
+}
+
+/// Internal function to list the named instances of a font. ttf-parser doesn't read these, so the `fvar` table
+/// is read here: a header, the axes, then every instance as a name ID, some flags and a 16.16 value for every axis.
+fn instances(face: &Face) -> Vec<NamedInstance> {
+    let Some(fvar) = face.raw_face().table(Tag::from_bytes(b"fvar")) else { return Vec::new() };
+    let read_u16 = |at: usize| fvar.get(at..at + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize);
+    let (Some(axes_offset), Some(axis_count), Some(axis_size), Some(instance_count), Some(instance_size)) =
+        (read_u16(4), read_u16(8), read_u16(10), read_u16(12), read_u16(14)) else { return Vec::new() };
+    let tags = axes(face).into_iter().map(|axis| axis.tag).collect::<Vec<_>>();
+    let instances_offset = axes_offset + axis_count * axis_size;
+    (0..instance_count).filter_map(|i| {
+        let record = fvar.get(instances_offset + i * instance_size..instances_offset + (i + 1) * instance_size)?;
+        let name = face_name(face, u16::from_be_bytes([record[0], record[1]]))?;
+        let coordinates = tags.iter().enumerate().map(|(axis, tag)| {
+            let value = record.get(4 + axis * 4..8 + axis * 4)?;
+            Some((tag.clone(), i32::from_be_bytes([value[0], value[1], value[2], value[3]]) as f32 / 65536.0))
+        }).collect::<Option<Vec<_>>>()?;
+        Some(NamedInstance { name, coordinates })
+    }).collect()
+}
+
+/// Internal struct, collects a glyph's outline as TrueType contours of (x, y, on curve) points, in font units.
+#[derive(Default)]
+struct ContourBuilder {
+    contours: Vec<Vec<(i16, i16, bool)>>,
+    /// Where the pen is, for turning cubic curves into quadratic ones.
+    pen: (f32, f32),
+}
+
+impl ContourBuilder {
+    fn push(&mut self, x: f32, y: f32, on_curve: bool) {
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push((x.round() as i16, y.round() as i16, on_curve));
+        }
+        if on_curve {
+            self.pen = (x, y);
+        }
+    }
+}
+
+impl OutlineBuilder for ContourBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(Vec::new());
+        self.push(x, y, true);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(x, y, true);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.push(x1, y1, false);
+        self.push(x, y, true);
+    }
+
+    // TrueType only has quadratic curves, so cubic ones (from CFF2 fonts) are split into four quadratic ones
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (x0, y0) = self.pen;
+        let point = |t: f32| {
+            let u = 1.0 - t;
+            (u * u * u * x0 + 3.0 * u * u * t * x1 + 3.0 * u * t * t * x2 + t * t * t * x,
+             u * u * u * y0 + 3.0 * u * u * t * y1 + 3.0 * u * t * t * y2 + t * t * t * y)
+        };
+        let tangent = |t: f32| {
+            let u = 1.0 - t;
+            (3.0 * (u * u * (x1 - x0) + 2.0 * u * t * (x2 - x1) + t * t * (x - x2)),
+             3.0 * (u * u * (y1 - y0) + 2.0 * u * t * (y2 - y1) + t * t * (y - y2)))
+        };
+        for i in 0..4 {
+            let (t0, t1) = (i as f32 / 4.0, (i + 1) as f32 / 4.0);
+            let ((sx, sy), (ex, ey)) = (point(t0), point(t1));
+            let ((dx0, dy0), (dx1, dy1)) = (tangent(t0), tangent(t1));
+            // the control point is the average of where the tangents at both ends say it should be
+            let step = t1 - t0;
+            let control = ((sx + dx0 * step / 2.0 + ex - dx1 * step / 2.0) / 2.0, (sy + dy0 * step / 2.0 + ey - dy1 * step / 2.0) / 2.0);
+            self.push(control.0, control.1, false);
+            self.push(ex, ey, true);
+        }
+    }
+
+    fn close(&mut self) {
+        // TrueType contours close themselves, so a last point that's back at the start isn't needed
+        if let Some(contour) = self.contours.last_mut() {
+            if contour.len() > 1 && contour.first() == contour.last() {
+                contour.pop();
+            }
+        }
+    }
+}
+
+/// Internal function to encode contours as a simple TrueType glyph, returning it and its bounding box.
+/// Glyphs without any contours are empty. Every coordinate is stored as a 16 bit delta to keep this simple.
+fn encode_glyph(contours: &[Vec<(i16, i16, bool)>]) -> (Vec<u8>, Option<[i16; 4]>) {
+    let points = contours.iter().flatten().collect::<Vec<_>>();
+    if points.is_empty() {
+        return (Vec::new(), None);
+    }
+    let bounds = points.iter().fold([i16::MAX, i16::MAX, i16::MIN, i16::MIN], |[x_min, y_min, x_max, y_max], (x, y, _)| {
+        [x_min.min(*x), y_min.min(*y), x_max.max(*x), y_max.max(*y)]
+    });
+    let mut data = Vec::new();
+    data.extend((contours.len() as i16).to_be_bytes());
+    bounds.iter().for_each(|value| data.extend(value.to_be_bytes()));
+    let mut end = 0;
+    for contour in contours.iter().filter(|contour| !contour.is_empty()) {
+        end += contour.len();
+        data.extend((end as u16 - 1).to_be_bytes());
+    }
+    // no instructions
+    data.extend(0u16.to_be_bytes());
+    data.extend(points.iter().map(|(_, _, on_curve)| *on_curve as u8));
+    for axis in 0..2 {
+        let mut previous = 0i16;
+        for (x, y, _) in points.iter() {
+            let value = if axis == 0 { *x } else { *y };
+            data.extend(value.wrapping_sub(previous).to_be_bytes());
+            previous = value;
+        }
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+    (data, Some(bounds))
+}
+
+/// Internal function to add up a table the way the OpenType table directory wants it checksummed.
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).map(|chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u32::from_be_bytes(word)
+    }).fold(0, u32::wrapping_add)
+}
+
+/// Internal function to write out a static instance of a variable font, at the given (tag, value) axis values
+/// (axes that aren't given stay at their defaults). Fails if the font doesn't have one of the axes,
+/// which fonts that aren't variable never do.
+pub(crate) fn instance_font(font_data: &[u8], collection_index: u32, variations: &[(&str, f32)]) -> Result<Vec<u8>, TextRendererError> {
+    let mut face = Face::parse(font_data, collection_index).map_err(|reason| TextRendererError::FontParseError(reason.to_string()))?;
+    let axes = axes(&face);
+    for (tag, value) in variations {
+        let unknown = || TextRendererError::UnknownVariation(tag.to_string());
+        let tag = parse_tag(tag).filter(|tag| axes.iter().any(|axis| axis.tag == tag.to_string())).ok_or_else(unknown)?;
+        face.set_variation(tag, *value).ok_or_else(unknown)?;
+    }
+
+    // every glyph at the new axis values
+    let mut glyf = Vec::new();
+    let mut loca = vec![0u32];
+    let mut hmtx = Vec::new();
+    let mut font_bounds: Option<[i16; 4]> = None;
+    let mut advance_max = 0;
+    for glyph_id in 0..face.number_of_glyphs() {
+        let mut builder = ContourBuilder::default();
+        face.outline_glyph(GlyphId(glyph_id), &mut builder);
+        let (data, bounds) = encode_glyph(&builder.contours);
+        glyf.extend(data);
+        loca.push(glyf.len() as u32);
+        let advance = face.glyph_hor_advance(GlyphId(glyph_id)).unwrap_or(0);
+        advance_max = advance_max.max(advance);
+        hmtx.extend(advance.to_be_bytes());
+        hmtx.extend(bounds.map_or(0, |[x_min, ..]| x_min).to_be_bytes());
+        if let Some([x_min, y_min, x_max, y_max]) = bounds {
+            font_bounds = Some(match font_bounds {
+                Some([a, b, c, d]) => [a.min(x_min), b.min(y_min), c.max(x_max), d.max(y_max)],
+                None => [x_min, y_min, x_max, y_max],
+            });
+        }
+    }
+
+    // everything else is copied over, with the few fields that depend on the glyphs fixed up
+    let raw_face = face.raw_face();
+    let mut tables = Vec::new();
+    for record in raw_face.table_records {
+        if DROPPED_TABLES.iter().any(|tag| Tag::from_bytes(tag) == record.tag) {
+            continue;
+        }
+        let mut data = raw_face.table(record.tag)
+            .ok_or_else(|| TextRendererError::FontParseError(format!("the {} table is out of bounds", record.tag)))?
+            .to_vec();
+        match &record.tag.to_bytes() {
+            b"head" if data.len() >= 54 => {
+                data[8..12].copy_from_slice(&[0; 4]);
+                let bounds = font_bounds.unwrap_or_default();
+                bounds.iter().enumerate().for_each(|(i, value)| data[36 + i * 2..38 + i * 2].copy_from_slice(&value.to_be_bytes()));
+                // long loca offsets
+                data[50..52].copy_from_slice(&1u16.to_be_bytes());
+            }
+            b"hhea" if data.len() >= 36 => {
+                data[10..12].copy_from_slice(&advance_max.to_be_bytes());
+                data[34..36].copy_from_slice(&face.number_of_glyphs().to_be_bytes());
+            }
+            _ => {}
+        }
+        tables.push((record.tag.to_bytes(), data));
+    }
+    tables.push((*b"glyf", glyf));
+    tables.push((*b"loca", loca.iter().flat_map(|offset| offset.to_be_bytes()).collect()));
+    tables.push((*b"hmtx", hmtx));
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    // then the table directory, and every table after it on a 4 byte boundary
+    let table_count = tables.len() as u16;
+    let entry_selector = 15 - table_count.leading_zeros() as u16;
+    let search_range = (1 << entry_selector) * 16;
+    let mut font = Vec::new();
+    font.extend(0x00010000u32.to_be_bytes());
+    for value in [table_count, search_range, entry_selector, table_count * 16 - search_range] {
+        font.extend(value.to_be_bytes());
+    }
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = None;
+    for (tag, data) in tables.iter() {
+        if tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend(tag);
+        font.extend(checksum(data).to_be_bytes());
+        font.extend((offset as u32).to_be_bytes());
+        font.extend((data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables.iter() {
+        font.extend(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    Ok(font)
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Lists the axes the font at `font_index` can be varied along (see `add_font_variation`).
+    /// Fonts that aren't variable don't have any.
+    pub fn variation_axes(&self, font_index: usize) -> Vec<VariationAxis> {
+        let (font_data, collection_index) = self.font_data_at(font_index);
+        Face::parse(font_data, collection_index).map_or(Vec::new(), |face| axes(&face))
+    }
+
+    /// Lists the named instances of the font at `font_index`, like "Light" or "Bold" (see `add_named_instance`).
+    pub fn named_instances(&self, font_index: usize) -> Vec<NamedInstance> {
+        let (font_data, collection_index) = self.font_data_at(font_index);
+        Face::parse(font_data, collection_index).map_or(Vec::new(), |face| instances(&face))
+    }
+
+    /// Adds the variable font at `font_index` again as a font of its own, with its axes set to `variations`
+    /// (as (tag, value), like `("wght", 700.0)`; values are clamped to the axis' range and anything not given stays
+    /// at its default), and returns the new font's index. Glyphs are cached separately for every font index,
+    /// so every instance you add gets its own glyphs. Only `options.scale` is used.
+    /// Returns `TextRendererError::UnknownVariation` if the font doesn't have one of the axes (or isn't variable).
+    pub fn add_font_variation(&mut self, font_index: usize, variations: &[(&str, f32)], options: &FontOptions) -> Result<usize, TextRendererError> {
+        let (font_data, collection_index) = self.font_data_at(font_index);
+        let instance = instance_font(font_data, collection_index, variations)?;
+        self.add_font_from_bytes(instance, &FontOptions { collection_index: 0, ..*options })
+    }
+
+    /// Same as `add_font_variation`, but with the axis values of one of the font's named instances
+    /// (see `named_instances`). Returns `TextRendererError::UnknownVariation` if there isn't one called `name`.
+    pub fn add_named_instance(&mut self, font_index: usize, name: &str, options: &FontOptions) -> Result<usize, TextRendererError> {
+        let instance = self.named_instances(font_index).into_iter().find(|instance| instance.name == name)
+            .ok_or_else(|| TextRendererError::UnknownVariation(name.to_string()))?;
+        let variations = instance.coordinates.iter().map(|(tag, value)| (tag.as_str(), *value)).collect::<Vec<_>>();
+        self.add_font_variation(font_index, &variations, options)
+    }
+}
+
+/// Internal function to find the axis values of a named instance in raw font data, for `TextRendererBuilder`.
+pub(crate) fn named_instance_coordinates(font_data: &[u8], collection_index: u32, name: &str) -> Option<Vec<(String, f32)>> {
+    let face = Face::parse(font_data, collection_index).ok()?;
+    instances(&face).into_iter().find(|instance| instance.name == name).map(|instance| instance.coordinates)
+}
@@ -0,0 +1,152 @@
+//! Filling the glyph cache in the background, so the first frames that draw a lot of text don't stall on rasterizing it.
+//! `TextRenderer::warm_async` rasterizes glyphs on a thread of its own, and `TextRenderer::pump_warm` moves whatever
+//! is done into the cache a little at a time (on your thread, as `StoreSurface::from_raw_mask` may not be safe to call
+//! from anywhere else). Drawing a glyph before it's been warmed rasterizes it straight away like normal.
+
+use crate::colours::TextColour;
+use crate::options::{CachePolicy, CoordinateSystem, GlyphCacheFormat};
+use crate::{cache_glyph, rasterize_lcd, rasterize_mask, CacheKey, CachedGlyph, GlyphCache, GlyphEffects, PasteSurface, StoreSurface, TextRenderer};
+use fontdue::layout::GlyphRasterConfig;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+/// Internal struct, a glyph that's been rasterized on the warming thread and is waiting to go in the cache.
+struct WarmedGlyph {
+    glyph_index: u16,
+    width: usize,
+    height: usize,
+    /// Coloured RGBA pixels, or just coverage for `GlyphCacheFormat::Alpha` (three bytes of it per pixel for `GlyphCacheFormat::Lcd`).
+    pixels: Vec<u8>,
+}
+
+/// Glyphs being warmed in the background, returned by `TextRenderer::warm_async`.
+/// Pass it to `TextRenderer::pump_warm` every frame until `is_finished` says it's done.
+/// Dropping it stops the warming thread after the glyph it's working on.
+pub struct WarmHandle {
+    receiver: Receiver<WarmedGlyph>,
+    key: CacheKey,
+    colour: TextColour,
+    format: GlyphCacheFormat,
+    total: usize,
+    /// How many glyphs `pump_warm` has dealt with, shared so it only needs `&WarmHandle`.
+    done: AtomicUsize,
+}
+
+impl WarmHandle {
+    /// How many glyphs are being warmed. Characters that were already cached (or that the font doesn't have) aren't counted.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// How many glyphs have made it into the cache so far.
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+
+    /// How far along warming is, from 0.0 to 1.0, for a loading bar.
+    pub fn progress(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.done() as f32 / self.total as f32
+    }
+
+    /// Whether every glyph has made it into the cache.
+    pub fn is_finished(&self) -> bool {
+        self.done() >= self.total
+    }
+}
+
+impl<G, A> TextRenderer<G, A> where A: PasteSurface<G>, G: StoreSurface, G: Clone {
+    /// Starts rasterizing the glyphs for `chars` at `size` pixels in `colour` on a new thread, to be put in the glyph cache
+    /// by `pump_warm`. Only the renderer's own font is used, and only the gamma, coordinate system and cache format it has
+    /// right now are warmed for. Characters that are already cached are skipped, and so is everything if `size` isn't
+    /// one that can be drawn (see `check_size`).
+    pub fn warm_async(&self, chars: impl IntoIterator<Item = char>, size: f32, colour: TextColour) -> WarmHandle {
+        let format = self.glyph_cache_format;
+        let flipped = self.coordinate_system == CoordinateSystem::PositiveYUp;
+        let key = match format {
+            GlyphCacheFormat::Rgba => self.surface_cache_key(0, size, GlyphEffects::default()),
+            GlyphCacheFormat::Alpha | GlyphCacheFormat::Lcd => CacheKey { font: 0, size: size.to_bits(), gamma: None, flipped, outline: None, blur: None, subpixel: 0 },
+        };
+        let mut glyph_indices = Vec::new();
+        if self.check_size(size).is_ok() {
+            let glyph_caches = self.glyph_caches.read().unwrap_or_else(PoisonError::into_inner);
+            let cached = glyph_caches.get(&key);
+            for c in chars {
+                let glyph_index = self.font.lookup_glyph_index(c);
+                let already_cached = cached.is_some_and(|glyph_cache| match format {
+                    GlyphCacheFormat::Rgba => glyph_cache.surface_map.get(&colour).is_some_and(|colour_map| colour_map.contains_key(&glyph_index)),
+                    GlyphCacheFormat::Alpha => glyph_cache.mask_map.contains_key(&glyph_index),
+                    GlyphCacheFormat::Lcd => glyph_cache.lcd_map.contains_key(&glyph_index),
+                });
+                if glyph_index != 0 && !already_cached && !glyph_indices.contains(&glyph_index) {
+                    glyph_indices.push(glyph_index);
+                }
+            }
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let total = glyph_indices.len();
+        let (font, gamma, pixel_order, premultiplied) = (self.font.clone(), self.gamma, self.pixel_order, self.premultiplied_alpha);
+        let subpixel_order = self.subpixel_order;
+        std::thread::spawn(move || {
+            for glyph_index in glyph_indices {
+                let config = GlyphRasterConfig { glyph_index, px: size, font_hash: font.file_hash() };
+                let metrics = font.metrics_indexed(glyph_index, size);
+                let pixels = match format {
+                    GlyphCacheFormat::Rgba => {
+                        let mask = rasterize_mask(&font, config, flipped, GlyphEffects::default());
+                        cache_glyph(mask, colour, gamma, pixel_order, premultiplied, |_| ()).0
+                    }
+                    GlyphCacheFormat::Alpha => rasterize_mask(&font, config, flipped, GlyphEffects::default()),
+                    GlyphCacheFormat::Lcd => rasterize_lcd(&font, config, flipped, subpixel_order),
+                };
+                let warmed = WarmedGlyph { glyph_index, width: metrics.width, height: metrics.height, pixels };
+                // the handle was dropped, so nobody wants the rest
+                if sender.send(warmed).is_err() {
+                    return;
+                }
+            }
+        });
+        WarmHandle { receiver, key, colour, format, total, done: AtomicUsize::new(0) }
+    }
+
+    /// Puts glyphs that `warm_async` has finished rasterizing into the glyph cache, until they run out or `budget` is used up
+    /// (at least one glyph goes in per call, if one's ready). Returns how many glyphs went in.
+    /// Meant to be called once a frame, with however much of the frame you can spare.
+    pub fn pump_warm(&mut self, handle: &WarmHandle, budget: Duration) -> usize {
+        let start = Instant::now();
+        let mut pumped = 0;
+        // the channel being empty and the thread being done (or gone) both just mean there's nothing more for now
+        while let Ok(warmed) = handle.receiver.try_recv() {
+            let mut glyph_caches = self.glyph_caches.write().unwrap_or_else(PoisonError::into_inner);
+            let glyph_cache = glyph_caches.entry(handle.key).or_insert_with(|| GlyphCache::new(f32::from_bits(handle.key.size)));
+            // anything drawn while it was being warmed is already in there, which is just as good
+            match handle.format {
+                GlyphCacheFormat::Rgba => {
+                    let colour_map = glyph_cache.surface_map.entry(handle.colour).or_default();
+                    colour_map.entry(warmed.glyph_index).or_insert_with(|| CachedGlyph {
+                        surface: G::from_raw_mask(warmed.width, warmed.height, &warmed.pixels, handle.colour),
+                        pixels: warmed.width * warmed.height,
+                        raw: (self.cache_policy == CachePolicy::KeepRawBytes).then_some(warmed.pixels),
+                    });
+                }
+                GlyphCacheFormat::Alpha => {
+                    glyph_cache.mask_map.entry(warmed.glyph_index).or_insert(warmed.pixels.into());
+                }
+                GlyphCacheFormat::Lcd => {
+                    glyph_cache.lcd_map.entry(warmed.glyph_index).or_insert(warmed.pixels.into());
+                }
+            }
+            handle.done.fetch_add(1, Ordering::Relaxed);
+            pumped += 1;
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+        pumped
+    }
+}